@@ -0,0 +1,650 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Finality by approvals of the Micc authorities, a lightweight alternative to GRANDPA for small
+//! sets of authorities.
+//!
+//! Every authority signs an approval of the blocks it imports and gossips it to the others. Once
+//! more than two thirds of the authorities of its parent approved a block, the block is finalized
+//! with the approvals as its [`MiccJustification`].
+//!
+//! Approvals are given in voting rounds, and a justification holds the approvals of a single
+//! round. Within a round, an authority never approves a block that doesn't descend from the last
+//! one it approved, unless that one was left behind by the finalized chain. Once the approvals of
+//! a round are split between the branches of a fork so that none of them can gather enough, the
+//! authorities move to the next round and approve their best block again, so that siblings
+//! approved by different authorities don't stall finality. Two conflicting blocks can't be
+//! finalized unless more than a third of the authorities misbehave.
+
+use std::{
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+	fmt::Debug,
+	marker::PhantomData,
+	sync::Arc,
+};
+
+use codec::{Codec, Decode, DecodeAll, Encode};
+use futures::prelude::*;
+use log::{debug, info, warn};
+use prometheus_endpoint::Registry;
+use sc_client_api::{backend::AuxStore, Backend, BlockchainEvents, Finalizer};
+use sc_network::{
+	config::{NonReservedPeerMode, SetConfig},
+	peer_store::PeerStoreProvider,
+	service::NotificationMetrics,
+	NetworkBackend, NotificationService, ProtocolName,
+};
+use sc_network_gossip::{
+	GossipEngine, Network, Syncing, TopicNotification, ValidationResult, Validator,
+	ValidatorContext,
+};
+use sc_network_types::PeerId;
+use sp_api::ProvideRuntimeApi;
+use sp_application_crypto::AppPublic;
+use sp_blockchain::{Error as ClientError, HeaderBackend, HeaderMetadata};
+use sp_consensus::BlockOrigin;
+use sp_consensus_micc::{AuthorityIndex, MiccApi, MICC, MICC_ENGINE_ID};
+use sp_core::{
+	crypto::{ByteArray, Pair},
+	hexdisplay::HexDisplay,
+};
+use sp_keystore::KeystorePtr;
+use sp_runtime::traits::{Block as BlockT, Hash as HashT, Header, NumberFor, One};
+
+use crate::{
+	authorities,
+	authority_sets::SharedAuthoritySets,
+	justification::{approval_threshold, sign_approval, verify_approval, MiccJustification},
+	AuthorityId, CompatibilityMode, LOG_TARGET,
+};
+
+/// The voting round and the last block approved in it by the local authority key.
+const LAST_APPROVED_KEY: &[u8] = b"micc_last_approved";
+
+/// Approvals are small, the notifications are capped well above their size.
+const MAX_APPROVAL_NOTIFICATION_SIZE: u64 = 16 * 1024;
+
+/// Approvals of blocks further ahead of the best block than this are dropped.
+const MAX_APPROVAL_LEAD: u32 = 256;
+
+/// Blocks not imported yet may have approvals waiting for them, up to this many blocks.
+const MAX_PENDING_BLOCKS: usize = 1024;
+
+/// The name of the approvals gossip protocol of the chain with `genesis_hash`.
+pub fn approvals_protocol_name(genesis_hash: &[u8]) -> ProtocolName {
+	format!("/{}/micc/approvals/1", HexDisplay::from(&genesis_hash)).into()
+}
+
+/// The configuration of the approvals gossip protocol, to add to the network configuration.
+pub fn approvals_peers_set_config<B: BlockT, N: NetworkBackend<B, B::Hash>>(
+	protocol_name: ProtocolName,
+	metrics: NotificationMetrics,
+	peer_store_handle: Arc<dyn PeerStoreProvider>,
+) -> (N::NotificationProtocolConfig, Box<dyn NotificationService>) {
+	N::notification_config(
+		protocol_name,
+		Vec::new(),
+		MAX_APPROVAL_NOTIFICATION_SIZE,
+		None,
+		SetConfig {
+			in_peers: 0,
+			out_peers: 0,
+			reserved_nodes: Vec::new(),
+			non_reserved_mode: NonReservedPeerMode::Deny,
+		},
+		metrics,
+		peer_store_handle,
+	)
+}
+
+/// The approval of a block by an authority of its parent.
+#[derive(Debug, Clone, Encode, Decode)]
+struct Approval<H, N, S> {
+	hash: H,
+	number: N,
+	round: u32,
+	authority_index: AuthorityIndex,
+	signature: S,
+}
+
+type ApprovalOf<B, P> = Approval<<B as BlockT>::Hash, NumberFor<B>, <P as Pair>::Signature>;
+
+/// Every approval is gossiped under the same topic.
+fn approvals_topic<B: BlockT>() -> B::Hash {
+	<<B::Header as Header>::Hashing as HashT>::hash(b"micc-approvals")
+}
+
+/// The cache of the authority sets used to check approvals.
+type AuthoritySetsOf<B, P> = SharedAuthoritySets<<B as BlockT>::Hash, NumberFor<B>, AuthorityId<P>>;
+
+/// Check `approval` of the imported block `header`, returning the size of the authority set of its
+/// parent.
+fn check_approval<B, C, P>(
+	client: &C,
+	authority_sets: Option<&AuthoritySetsOf<B, P>>,
+	header: &B::Header,
+	approval: &ApprovalOf<B, P>,
+) -> Result<usize, String>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + HeaderMetadata<B, Error = ClientError>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+{
+	let authorities = authorities(
+		client,
+		*header.parent_hash(),
+		*header.number(),
+		&CompatibilityMode::None,
+		authority_sets,
+	)
+	.map_err(|e| e.to_string())?;
+	let authority = authorities
+		.authorities
+		.get(approval.authority_index as usize)
+		.ok_or_else(|| format!("approval by unknown authority {}", approval.authority_index))?;
+
+	if *header.number() != approval.number ||
+		!verify_approval::<_, P>(&approval.hash, approval.round, &approval.signature, authority)
+	{
+		return Err(format!("bad approval by authority {}", approval.authority_index))
+	}
+
+	Ok(authorities.len())
+}
+
+/// Drops approvals that are malformed, forged or no longer needed before they are propagated.
+struct ApprovalValidator<B: BlockT, C, P: Pair> {
+	client: Arc<C>,
+	authority_sets: Option<AuthoritySetsOf<B, P>>,
+	_phantom: PhantomData<fn() -> (B, P)>,
+}
+
+impl<B, C, P> Validator<B> for ApprovalValidator<B, C, P>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = ClientError>
+		+ Send
+		+ Sync,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	fn validate(
+		&self,
+		_context: &mut dyn ValidatorContext<B>,
+		sender: &PeerId,
+		mut data: &[u8],
+	) -> ValidationResult<B::Hash> {
+		let Ok(approval) = ApprovalOf::<B, P>::decode(&mut data) else {
+			return ValidationResult::Discard
+		};
+
+		let info = self.client.info();
+		if approval.number <= info.finalized_number ||
+			approval.number > info.best_number + MAX_APPROVAL_LEAD.into()
+		{
+			return ValidationResult::Discard
+		}
+
+		// Approvals of blocks not imported yet are checked once they are.
+		if let Ok(Some(header)) = self.client.header(approval.hash) {
+			let authority_sets = self.authority_sets.as_ref();
+			if let Err(e) =
+				check_approval::<B, C, P>(&*self.client, authority_sets, &header, &approval)
+			{
+				debug!(target: LOG_TARGET, "Dropping approval from {}: {}", sender, e);
+				return ValidationResult::Discard
+			}
+		}
+
+		ValidationResult::ProcessAndKeep(approvals_topic::<B>())
+	}
+
+	fn message_expired<'a>(&'a self) -> Box<dyn FnMut(B::Hash, &[u8]) -> bool + 'a> {
+		let finalized_number = self.client.info().finalized_number;
+		Box::new(move |_topic, mut data| {
+			ApprovalOf::<B, P>::decode(&mut data)
+				.map_or(true, |approval| approval.number <= finalized_number)
+		})
+	}
+}
+
+/// Parameters of [`run_approval_voter`].
+pub struct ApprovalVoterParams<B: BlockT, C, N, S, A> {
+	/// The client to interact with the chain.
+	pub client: Arc<C>,
+	/// Cache of the authority sets, looked up before asking the runtime.
+	///
+	/// Should be the cache the block import records changes in, see [`crate::MiccBlockImport`].
+	pub authority_sets: Option<SharedAuthoritySets<B::Hash, NumberFor<B>, A>>,
+	/// The keystore holding the authority key, `None` to only collect the approvals of others.
+	pub keystore: Option<KeystorePtr>,
+	/// The network to gossip approvals with.
+	pub network: N,
+	/// The sync service, telling who the peers are.
+	pub sync: S,
+	/// The notification service of the protocol configured by [`approvals_peers_set_config`].
+	pub notification_service: Box<dyn NotificationService>,
+	/// The name of the protocol, see [`approvals_protocol_name`].
+	pub protocol_name: ProtocolName,
+	/// The prometheus registry.
+	pub prometheus_registry: Option<Registry>,
+}
+
+/// The approvals of a block collected so far.
+struct Collected<N, S> {
+	number: N,
+	authorities: usize,
+	approvals: BTreeMap<AuthorityIndex, S>,
+}
+
+struct ApprovalVoter<B: BlockT, BE, C, P: Pair> {
+	client: Arc<C>,
+	authority_sets: Option<AuthoritySetsOf<B, P>>,
+	keystore: Option<KeystorePtr>,
+	/// The current voting round.
+	round: u32,
+	/// The last block approved by the local authority key in the current round.
+	last_approved: Option<(B::Hash, NumberFor<B>)>,
+	/// The approvals of imported blocks by block and round, all of them checked.
+	collected: HashMap<(B::Hash, u32), Collected<NumberFor<B>, P::Signature>>,
+	/// The blocks approved by each authority, by round from the current one.
+	votes: BTreeMap<u32, HashMap<AuthorityIndex, HashSet<(B::Hash, NumberFor<B>)>>>,
+	/// The approvals of blocks not imported yet.
+	pending: HashMap<B::Hash, Vec<ApprovalOf<B, P>>>,
+	_phantom: PhantomData<fn() -> BE>,
+}
+
+impl<B, BE, C, P> ApprovalVoter<B, BE, C, P>
+where
+	B: BlockT,
+	BE: Backend<B>,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = ClientError>
+		+ Finalizer<B, BE>
+		+ AuxStore,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: AppPublic + Codec + Debug + Clone,
+	P::Signature: Codec + TryFrom<Vec<u8>> + Clone,
+{
+	/// Approve the imported block `header` with the local authority key, if there is one and the
+	/// block extends the last block it approved.
+	fn approve(&mut self, header: &B::Header) -> Result<Option<ApprovalOf<B, P>>, String> {
+		let Some(keystore) = &self.keystore else { return Ok(None) };
+		let hash = header.hash();
+		let number = *header.number();
+		let info = self.client.info();
+		if number <= info.finalized_number {
+			return Ok(None)
+		}
+
+		let base = match self.last_approved {
+			Some(last_approved) if last_approved.1 > info.finalized_number => last_approved,
+			_ => (info.finalized_hash, info.finalized_number),
+		};
+		let extends_base = number > base.1 &&
+			sc_client_api::utils::is_descendent_of::<B, _>(&*self.client, None)(&base.0, &hash)
+				.map_err(|e| e.to_string())?;
+		if !extends_base {
+			debug!(target: LOG_TARGET, "Not approving {:?}, it doesn't extend {:?}", hash, base.0);
+			return Ok(None)
+		}
+
+		let authorities = authorities(
+			&*self.client,
+			*header.parent_hash(),
+			number,
+			&CompatibilityMode::None,
+			self.authority_sets.as_ref(),
+		)
+		.map_err(|e| e.to_string())?;
+		let local = authorities.authorities.iter().enumerate().find(|(_, authority)| {
+			keystore.has_keys(&[(authority.to_raw_vec(), MICC)])
+		});
+		let Some((authority_index, public)) = local else { return Ok(None) };
+
+		// Recorded before signing, so that a restart never approves a conflicting block.
+		self.client
+			.insert_aux(&[(LAST_APPROVED_KEY, &(self.round, (hash, number)).encode()[..])], &[])
+			.map_err(|e| e.to_string())?;
+		self.last_approved = Some((hash, number));
+
+		let signature = sign_approval::<_, P>(&hash, self.round, public, keystore)
+			.map_err(|e| e.to_string())?;
+		Ok(Some(Approval {
+			hash,
+			number,
+			round: self.round,
+			authority_index: authority_index as AuthorityIndex,
+			signature,
+		}))
+	}
+
+	/// Move to the next round if the approvals of the current one can't finalize any block, and
+	/// approve the best block in it.
+	///
+	/// All the approvals of the current round extend the finalized block through one of its
+	/// children. A block can only gather the approvals of the authorities that approved blocks
+	/// through the same child, or didn't approve any block yet, so the round is over once no child
+	/// of the finalized block gathers enough of them.
+	fn next_round(&mut self) -> Result<Option<ApprovalOf<B, P>>, String> {
+		let info = self.client.info();
+		let authorities = authorities(
+			&*self.client,
+			info.finalized_hash,
+			info.finalized_number + One::one(),
+			&CompatibilityMode::None,
+			self.authority_sets.as_ref(),
+		)
+		.map_err(|e| e.to_string())?
+		.len();
+
+		let mut branches = HashMap::<B::Hash, BTreeSet<AuthorityIndex>>::new();
+		let mut undecided: BTreeSet<_> = (0..authorities as AuthorityIndex).collect();
+		for (index, approved) in self.votes.get(&self.round).into_iter().flatten() {
+			for (hash, _) in approved.iter().filter(|(_, number)| *number > info.finalized_number)
+			{
+				undecided.remove(index);
+				let route = sp_blockchain::tree_route(&*self.client, info.finalized_hash, *hash)
+					.map_err(|e| e.to_string())?;
+				// Blocks left behind by the finalized chain can't be finalized anymore.
+				if let (true, Some(branch)) = (route.retracted().is_empty(), route.enacted().first())
+				{
+					branches.entry(branch.hash).or_default().insert(*index);
+				}
+			}
+		}
+		// Approvals of blocks not imported yet may be on any branch.
+		for approval in self.pending.values().flatten().filter(|a| a.round == self.round) {
+			undecided.insert(approval.authority_index);
+		}
+
+		let most_approvals = branches
+			.values()
+			.map(|approved| approved.union(&undecided).count())
+			.max()
+			.unwrap_or(undecided.len());
+		if most_approvals >= approval_threshold(authorities) {
+			return Ok(None)
+		}
+
+		self.round += 1;
+		self.last_approved = None;
+		self.votes = self.votes.split_off(&self.round);
+		info!(
+			target: LOG_TARGET,
+			"Approvals split between {} branches, moving to round {}",
+			branches.len(),
+			self.round,
+		);
+
+		match self.client.header(info.best_hash).map_err(|e| e.to_string())? {
+			Some(best) => self.approve(&best),
+			None => Ok(None),
+		}
+	}
+
+	/// Collect `approval`, finalizing its block once it has enough of them.
+	fn note_approval(&mut self, approval: ApprovalOf<B, P>) {
+		if approval.number <= self.client.info().finalized_number {
+			return
+		}
+
+		let header = match self.client.header(approval.hash) {
+			Ok(Some(header)) => header,
+			_ => {
+				let known = self.pending.contains_key(&approval.hash);
+				if known || self.pending.len() < MAX_PENDING_BLOCKS {
+					self.pending.entry(approval.hash).or_default().push(approval);
+				}
+				return
+			},
+		};
+
+		let authority_sets = self.authority_sets.as_ref();
+		let authorities = match check_approval::<B, C, P>(
+			&*self.client,
+			authority_sets,
+			&header,
+			&approval,
+		) {
+			Ok(authorities) => authorities,
+			Err(e) => {
+				debug!(target: LOG_TARGET, "Dropping approval of {:?}: {}", approval.hash, e);
+				return
+			},
+		};
+
+		if approval.round >= self.round {
+			let votes = self.votes.entry(approval.round).or_default();
+			let approved = votes.entry(approval.authority_index).or_default();
+			approved.insert((approval.hash, approval.number));
+		}
+
+		let key = (approval.hash, approval.round);
+		let collected = self.collected.entry(key).or_insert_with(|| Collected {
+			number: approval.number,
+			authorities,
+			approvals: BTreeMap::new(),
+		});
+		collected.approvals.insert(approval.authority_index, approval.signature);
+
+		self.try_finalize(key);
+	}
+
+	/// Check the approvals waiting for the block `hash`, now imported.
+	fn note_imported(&mut self, hash: B::Hash) {
+		for approval in self.pending.remove(&hash).unwrap_or_default() {
+			self.note_approval(approval);
+		}
+	}
+
+	fn try_finalize(&mut self, (hash, round): (B::Hash, u32)) {
+		let Some(collected) = self.collected.get(&(hash, round)) else { return };
+		if collected.approvals.len() < approval_threshold(collected.authorities) {
+			return
+		}
+
+		let justification = MiccJustification {
+			round,
+			approvals: collected
+				.approvals
+				.iter()
+				.map(|(index, signature)| (*index, signature.clone()))
+				.collect(),
+		};
+		let justification = (MICC_ENGINE_ID, justification.encode());
+		match self.client.finalize_block(hash, Some(justification), true) {
+			Ok(()) => info!(
+				target: LOG_TARGET,
+				"Finalized #{} ({:?}) with {} approvals of round {}",
+				collected.number,
+				hash,
+				collected.approvals.len(),
+				round,
+			),
+			Err(e) => warn!(target: LOG_TARGET, "Failed to finalize {:?}: {}", hash, e),
+		}
+		self.prune();
+	}
+
+	/// Forget the approvals of finalized blocks.
+	fn prune(&mut self) {
+		let finalized_number = self.client.info().finalized_number;
+		self.collected.retain(|_, collected| collected.number > finalized_number);
+		for votes in self.votes.values_mut() {
+			for approved in votes.values_mut() {
+				approved.retain(|(_, number)| *number > finalized_number);
+			}
+			votes.retain(|_, approved| !approved.is_empty());
+		}
+		self.pending.retain(|_, approvals| {
+			approvals.retain(|approval| approval.number > finalized_number);
+			!approvals.is_empty()
+		});
+	}
+}
+
+/// Run the approval voter: approve the blocks imported with the local authority key, gossip the
+/// approvals and finalize the blocks approved by enough authorities.
+///
+/// The returned future should be run as an essential task, like the GRANDPA voter.
+pub fn run_approval_voter<B, BE, C, P, N, S>(
+	ApprovalVoterParams {
+		client,
+		authority_sets,
+		keystore,
+		network,
+		sync,
+		notification_service,
+		protocol_name,
+		prometheus_registry,
+	}: ApprovalVoterParams<B, C, N, S, AuthorityId<P>>,
+) -> Result<impl Future<Output = ()> + Send, ClientError>
+where
+	B: BlockT,
+	BE: Backend<B> + 'static,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = ClientError>
+		+ BlockchainEvents<B>
+		+ Finalizer<B, BE>
+		+ AuxStore
+		+ Send
+		+ Sync
+		+ 'static,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair + 'static,
+	P::Public: AppPublic + Codec + Debug + Clone,
+	P::Signature: Codec + TryFrom<Vec<u8>> + Clone + Send,
+	N: Network<B> + Send + Clone + 'static,
+	S: Syncing<B> + Send + Clone + 'static,
+{
+	let (round, last_approved) = match client.get_aux(LAST_APPROVED_KEY)? {
+		None => (0, None),
+		Some(encoded) => {
+			let (round, last_approved) = <(u32, _)>::decode_all(&mut &encoded[..])
+				// Recorded before approvals had rounds.
+				.or_else(|_| Decode::decode(&mut &encoded[..]).map(|last| (0, last)))
+				.map_err(|e| {
+					ClientError::Backend(format!("Last Micc approval is corrupted: {}", e))
+				})?;
+			(round, Some(last_approved))
+		},
+	};
+
+	let validator = Arc::new(ApprovalValidator::<B, C, P> {
+		client: client.clone(),
+		authority_sets: authority_sets.clone(),
+		_phantom: PhantomData,
+	});
+	let mut gossip = GossipEngine::new(
+		network,
+		sync,
+		notification_service,
+		protocol_name,
+		validator,
+		prometheus_registry.as_ref(),
+	);
+	let topic = approvals_topic::<B>();
+	let mut approvals = gossip.messages_for(topic).fuse();
+	let mut imports = client.import_notification_stream().fuse();
+	let mut finality = client.finality_notification_stream().fuse();
+
+	let mut voter = ApprovalVoter::<B, BE, C, P> {
+		client,
+		authority_sets,
+		keystore,
+		round,
+		last_approved,
+		collected: HashMap::new(),
+		votes: BTreeMap::new(),
+		pending: HashMap::new(),
+		_phantom: PhantomData,
+	};
+
+	Ok(async move {
+		loop {
+			futures::select! {
+				notification = imports.next() => {
+					let Some(notification) = notification else { break };
+					// Blocks of the initial sync are old news, their justifications come with them.
+					if notification.origin == BlockOrigin::NetworkInitialSync {
+						continue
+					}
+
+					voter.note_imported(notification.hash);
+					match voter.approve(&notification.header) {
+						Ok(Some(approval)) => {
+							gossip.gossip_message(topic, approval.encode(), false);
+							voter.note_approval(approval);
+						},
+						Ok(None) => {},
+						Err(e) => warn!(
+							target: LOG_TARGET,
+							"Failed to approve {:?}: {}",
+							notification.hash,
+							e,
+						),
+					}
+				},
+				message = approvals.next() => {
+					let Some(TopicNotification { message, .. }) = message else { break };
+					let Ok(approval) = ApprovalOf::<B, P>::decode(&mut &message[..]) else {
+						continue
+					};
+					let round = approval.round;
+					voter.note_approval(approval);
+					if round != voter.round {
+						continue
+					}
+
+					match voter.next_round() {
+						Ok(Some(approval)) => {
+							gossip.gossip_message(topic, approval.encode(), false);
+							voter.note_approval(approval);
+						},
+						Ok(None) => {},
+						Err(e) => warn!(
+							target: LOG_TARGET,
+							"Failed to check the approvals of round {}: {}",
+							voter.round,
+							e,
+						),
+					}
+				},
+				notification = finality.next() => {
+					if notification.is_none() {
+						break
+					}
+					voter.prune();
+				},
+				_ = (&mut gossip).fuse() => break,
+			}
+		}
+
+		warn!(target: LOG_TARGET, "Micc approval voter stopped");
+	})
+}
@@ -0,0 +1,265 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Client-side cache of the authority sets of all forks.
+//!
+//! Every block that changes the authority set or the slot author selector deposits a
+//! [`ConsensusLog`](sp_consensus_micc::ConsensusLog) which takes effect from its children on. The
+//! block import follows these logs and records the resulting [`SlotAuthorities`] in a tree of
+//! blocks, so the authorities of a block are those of its closest recorded ancestor. The tree is
+//! persisted in the aux store and pruned on finality.
+//!
+//! Only executed blocks are recorded. Blocks imported without execution, e.g. while warp or gap
+//! syncing, may hide changes, so the first executed block without a recorded ancestor is recorded
+//! with the authorities read from the runtime.
+//!
+//! The [`AuthorshipMode`](sp_consensus_micc::AuthorshipMode) is set in the chain spec and no log
+//! changes it, so the recorded sets carry the mode read along with the first of them.
+
+use std::sync::{Arc, Mutex};
+
+use codec::{Decode, Encode};
+use fork_tree::ForkTree;
+use sc_client_api::backend::AuxStore;
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+
+use crate::{standalone::SlotAuthorities, LOG_TARGET};
+
+const AUTHORITY_SETS_KEY: &[u8] = b"micc_authority_sets";
+
+type Tree<H, N, A> = ForkTree<H, N, SlotAuthorities<A>>;
+
+/// The authority sets of all unfinalized forks, keyed by the blocks enacting them.
+///
+/// Shared between the [`MiccBlockImport`](crate::MiccBlockImport), which records the changes, and
+/// the verifier and slot worker, which look authorities up before asking the runtime.
+pub struct SharedAuthoritySets<H, N, A> {
+	tree: Arc<Mutex<Tree<H, N, A>>>,
+	import_lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl<H, N, A> Clone for SharedAuthoritySets<H, N, A> {
+	fn clone(&self) -> Self {
+		Self { tree: self.tree.clone(), import_lock: self.import_lock.clone() }
+	}
+}
+
+impl<H: Decode, N: Decode, A: Decode> SharedAuthoritySets<H, N, A> {
+	/// Load the authority sets from the aux store, starting empty on a fresh node.
+	pub fn load<C: AuxStore>(client: &C) -> ClientResult<Self> {
+		let tree = match client.get_aux(AUTHORITY_SETS_KEY)? {
+			None => ForkTree::new(),
+			Some(encoded) => Tree::decode(&mut &encoded[..]).map_err(|e| {
+				ClientError::Backend(format!("Micc authority sets are corrupted: {}", e))
+			})?,
+		};
+
+		Ok(Self { tree: Arc::new(Mutex::new(tree)), import_lock: Default::default() })
+	}
+}
+
+impl<H, N, A> SharedAuthoritySets<H, N, A>
+where
+	H: Clone + PartialEq + std::fmt::Debug,
+	N: Ord + Clone,
+	A: Clone,
+{
+	/// The authorities of the children of `parent_hash`, if a recorded block is an ancestor of
+	/// them.
+	pub fn authorities_for_child_of<F, E>(
+		&self,
+		parent_hash: &H,
+		parent_number: &N,
+		is_descendent_of: &F,
+	) -> Option<SlotAuthorities<A>>
+	where
+		F: Fn(&H, &H) -> Result<bool, E>,
+		E: std::error::Error,
+	{
+		let tree = self.tree.lock().expect("never poisoned; qed");
+
+		// The tree only searches strict ancestors, but a change at the parent applies too.
+		if let Some((_, _, authorities)) = tree.iter().find(|(hash, _, _)| *hash == parent_hash) {
+			return Some(authorities.clone())
+		}
+
+		match tree.find_node_where(parent_hash, parent_number, is_descendent_of, &|_| true) {
+			Ok(node) => node.map(|node| node.data.clone()),
+			Err(e) => {
+				log::debug!(
+					target: LOG_TARGET,
+					"Failed to look up the authorities of a child of {:?}: {}",
+					parent_hash,
+					e,
+				);
+				None
+			},
+		}
+	}
+}
+
+impl<H, N, A> SharedAuthoritySets<H, N, A>
+where
+	H: Clone + PartialEq + std::fmt::Debug + Encode,
+	N: Ord + Clone + Encode,
+	A: Clone + Encode,
+{
+	/// Serialize the imports recording changes, so the tree written along with a block contains
+	/// the changes of all blocks imported before it.
+	pub(crate) async fn lock_import(&self) -> tokio::sync::MutexGuard<'_, ()> {
+		self.import_lock.lock().await
+	}
+
+	/// A copy of the recorded changes, to [`Self::restore`] should the block recording a change
+	/// fail to import.
+	pub(crate) fn snapshot(&self) -> AuthoritySetsSnapshot<H, N, A> {
+		AuthoritySetsSnapshot(self.tree.lock().expect("never poisoned; qed").clone())
+	}
+
+	/// Restore the changes recorded when `snapshot` was taken.
+	pub(crate) fn restore(&self, snapshot: AuthoritySetsSnapshot<H, N, A>) {
+		*self.tree.lock().expect("never poisoned; qed") = snapshot.0;
+	}
+
+	/// Record that the descendants of `hash` use `authorities`, after dropping the forks which
+	/// don't include the `finalized` block.
+	///
+	/// Returns the aux store entries to write along with the block. If the block can't be
+	/// recorded, the cache is cleared rather than left answering with an outdated set.
+	pub(crate) fn record<F, E>(
+		&self,
+		hash: H,
+		number: N,
+		authorities: SlotAuthorities<A>,
+		(finalized_hash, finalized_number): (&H, &N),
+		is_descendent_of: &F,
+	) -> Vec<(Vec<u8>, Vec<u8>)>
+	where
+		F: Fn(&H, &H) -> Result<bool, E>,
+		E: std::error::Error,
+	{
+		let mut tree = self.tree.lock().expect("never poisoned; qed");
+
+		if let Err(e) = tree.prune(finalized_hash, finalized_number, is_descendent_of, &|_| true) {
+			log::debug!(target: LOG_TARGET, "Failed to prune the authority sets: {}", e);
+		}
+
+		match tree.import(hash.clone(), number, authorities, is_descendent_of) {
+			Ok(_) | Err(fork_tree::Error::Duplicate) => {},
+			Err(e) => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Failed to record the authorities enacted by {:?}, clearing the cache: {}",
+					hash,
+					e,
+				);
+				*tree = ForkTree::new();
+			},
+		}
+
+		vec![(AUTHORITY_SETS_KEY.to_vec(), tree.encode())]
+	}
+}
+
+/// The changes recorded in [`SharedAuthoritySets`] at some point, see
+/// [`SharedAuthoritySets::snapshot`].
+pub(crate) struct AuthoritySetsSnapshot<H, N, A>(Tree<H, N, A>);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_consensus_micc::{AuthorshipMode, SlotAuthorSelector};
+	use std::convert::Infallible;
+
+	// 0 - a1 - a2 - a3
+	//       \
+	//        b2
+	fn parent(hash: &str) -> Option<&'static str> {
+		match hash {
+			"a1" => Some("0"),
+			"a2" | "b2" => Some("a1"),
+			"a3" => Some("a2"),
+			_ => None,
+		}
+	}
+
+	fn is_descendent_of(base: &&str, block: &&str) -> Result<bool, Infallible> {
+		let mut current = parent(block);
+		while let Some(hash) = current {
+			if hash == *base {
+				return Ok(true)
+			}
+			current = parent(hash);
+		}
+		Ok(false)
+	}
+
+	fn empty() -> SharedAuthoritySets<&'static str, u32, u64> {
+		SharedAuthoritySets {
+			tree: Arc::new(Mutex::new(ForkTree::new())),
+			import_lock: Default::default(),
+		}
+	}
+
+	#[test]
+	fn authorities_follow_the_recorded_changes_of_each_fork() {
+		let sets = empty();
+		let first = SlotAuthorities::round_robin(vec![1, 2]);
+		let second = SlotAuthorities {
+			authorities: vec![3],
+			selector: SlotAuthorSelector::RoundRobin,
+			mode: AuthorshipMode::Strict,
+		};
+
+		sets.record("a1", 1, first.clone(), (&"0", &0), &is_descendent_of);
+		sets.record("a2", 2, second.clone(), (&"0", &0), &is_descendent_of);
+
+		let child_of = |hash, number| sets.authorities_for_child_of(&hash, &number, &is_descendent_of);
+		assert_eq!(child_of("0", 0), None);
+		assert_eq!(child_of("a1", 1), Some(first.clone()));
+		assert_eq!(child_of("a2", 2), Some(second.clone()));
+		assert_eq!(child_of("a3", 3), Some(second));
+		assert_eq!(child_of("b2", 2), Some(first));
+	}
+
+	#[test]
+	fn finality_prunes_other_forks() {
+		let sets = empty();
+		let fork = SlotAuthorities::round_robin(vec![4]);
+		sets.record("a1", 1, SlotAuthorities::round_robin(vec![1]), (&"0", &0), &is_descendent_of);
+		sets.record("b2", 2, fork, (&"0", &0), &is_descendent_of);
+		sets.record("a3", 3, SlotAuthorities::round_robin(vec![3]), (&"a2", &2), &is_descendent_of);
+
+		assert!(sets.tree.lock().unwrap().iter().all(|(hash, _, _)| *hash != "b2"));
+	}
+
+	#[test]
+	fn restoring_a_snapshot_forgets_the_changes_recorded_since() {
+		let sets = empty();
+		let first = SlotAuthorities::round_robin(vec![1]);
+		sets.record("a1", 1, first.clone(), (&"0", &0), &is_descendent_of);
+
+		let snapshot = sets.snapshot();
+		sets.record("a2", 2, SlotAuthorities::round_robin(vec![2]), (&"0", &0), &is_descendent_of);
+		sets.restore(snapshot);
+
+		let child_of = |hash, number| sets.authorities_for_child_of(&hash, &number, &is_descendent_of);
+		assert_eq!(child_of("a2", 2), Some(first.clone()));
+		assert_eq!(child_of("a3", 3), Some(first));
+	}
+}
@@ -0,0 +1,270 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Backing off authoring while finality lags, measured in time rather than in slots.
+//!
+//! [`sc_consensus_slots::BackoffAuthoringOnFinalizedHeadLagging`] compares slot numbers, which
+//! assumes a block every slot. Blocks authored on demand can be minutes or hours apart, so here the
+//! lag is the time elapsed since the slot of the last finalized block, and the backoff is a minimum
+//! age of the chain head before building on top of it.
+//!
+//! Only the unfinalized blocks carrying transactions count toward the backlog, heartbeats on an
+//! idle chain don't make authoring back off.
+
+use std::{marker::PhantomData, sync::Arc, time::Duration};
+
+use codec::{Codec, Compact, Decode, Encode};
+use sc_client_api::BlockBackend;
+use sc_consensus_slots::BackoffAuthoringBlocksStrategy;
+use sc_transaction_pool_api::TransactionPool;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus_micc::MiccApi;
+use sp_consensus_slots::Slot;
+use sp_core::crypto::Pair;
+use sp_runtime::traits::{Block as BlockT, Header, NumberFor, One};
+
+use crate::{standalone, AuthorityId};
+
+/// Unfinalized blocks further down the chain than this are assumed to carry no transactions.
+const MAX_SCANNED_BLOCKS: u32 = 4096;
+
+/// Whether `extrinsic` is a transaction rather than an inherent, telling from the version byte
+/// following the length prefix of its encoding, which marks signed and general transactions.
+///
+/// Bare transactions, such as unsigned ones, are counted as inherents.
+fn is_transaction<E: Encode>(extrinsic: &E) -> bool {
+	let encoded = extrinsic.encode();
+	let mut input = &encoded[..];
+	match (Compact::<u32>::decode(&mut input), input.first()) {
+		(Ok(_), Some(version)) => version & 0b1100_0000 != 0,
+		_ => false,
+	}
+}
+
+/// Configuration of [`BackoffAuthoringOnFinalityAge`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FinalityAgeBackoffConfig {
+	/// Never back off while the last finalized block is younger than this.
+	pub age_slack: Duration,
+	/// Never back off while there are no more unfinalized blocks carrying transactions than this.
+	pub unfinalized_slack: u32,
+	/// The longest the chain head has to wait before a block is built on top of it, regardless of
+	/// how far finality lags.
+	pub max_interval: Duration,
+}
+
+impl Default for FinalityAgeBackoffConfig {
+	fn default() -> Self {
+		Self {
+			age_slack: Duration::from_secs(60),
+			unfinalized_slack: 8,
+			max_interval: Duration::from_secs(300),
+		}
+	}
+}
+
+impl FinalityAgeBackoffConfig {
+	/// The age the chain head must reach before authoring on top of it, `None` if authoring
+	/// shouldn't wait at all.
+	///
+	/// The wait grows with the lag of finality past [`Self::age_slack`], at half its pace.
+	pub(crate) fn required_head_age(
+		&self,
+		finalized_age: Duration,
+		unfinalized: u32,
+	) -> Option<Duration> {
+		if unfinalized <= self.unfinalized_slack || finalized_age <= self.age_slack {
+			return None
+		}
+
+		Some(((finalized_age - self.age_slack) / 2).min(self.max_interval))
+	}
+}
+
+/// Backs off authoring while the last finalized block is old and unfinalized blocks carrying
+/// transactions pile up.
+///
+/// Heartbeats, the blocks authored while the transaction pool has nothing ready, are never held
+/// back: they carry no transactions and they are what finality needs to catch up on an idle chain.
+/// Neither do they count toward the unfinalized blocks.
+pub struct BackoffAuthoringOnFinalityAge<B, C, P, TExPool> {
+	client: Arc<C>,
+	pool: Arc<TExPool>,
+	config: FinalityAgeBackoffConfig,
+	_phantom: PhantomData<fn() -> (B, P)>,
+}
+
+impl<B, C, P, TExPool> BackoffAuthoringOnFinalityAge<B, C, P, TExPool> {
+	/// Back off authoring on top of the chain of `client` according to `config`, unless `pool` has
+	/// no ready transactions.
+	pub fn new(client: Arc<C>, pool: Arc<TExPool>, config: FinalityAgeBackoffConfig) -> Self {
+		Self { client, pool, config, _phantom: PhantomData }
+	}
+}
+
+impl<B, C, P, TExPool> BackoffAuthoringOnFinalityAge<B, C, P, TExPool>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + BlockBackend<B>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec,
+	P::Signature: Codec,
+{
+	/// The number of blocks carrying transactions from the chain head down to the last finalized
+	/// block, counted up to `limit`.
+	fn unfinalized_transaction_blocks(
+		&self,
+		chain_head_number: NumberFor<B>,
+		finalized_number: NumberFor<B>,
+		limit: u32,
+	) -> u32 {
+		let Ok(Some(mut hash)) = self.client.hash(chain_head_number) else { return 0 };
+		let mut number = chain_head_number;
+		let mut counted = 0;
+		for _ in 0..MAX_SCANNED_BLOCKS {
+			if number <= finalized_number || counted >= limit {
+				break
+			}
+			let Ok(Some(body)) = self.client.block_body(hash) else { break };
+			if body.iter().any(is_transaction) {
+				counted += 1;
+			}
+			let Ok(Some(header)) = self.client.header(hash) else { break };
+			hash = *header.parent_hash();
+			number = *header.number() - One::one();
+		}
+
+		counted
+	}
+
+	/// The slot of the last finalized block, or of the first block while only genesis is
+	/// finalized.
+	fn finalized_slot(&self, finalized_number: NumberFor<B>) -> Option<Slot> {
+		let hash = self.client.hash(finalized_number.max(One::one())).ok().flatten()?;
+		let header = self.client.header(hash).ok().flatten()?;
+
+		standalone::find_pre_digest::<B, P::Signature>(&header).ok()
+	}
+}
+
+impl<B, C, P, TExPool> BackoffAuthoringBlocksStrategy<NumberFor<B>>
+	for BackoffAuthoringOnFinalityAge<B, C, P, TExPool>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + BlockBackend<B>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec,
+	P::Signature: Codec,
+	TExPool: TransactionPool<Block = B>,
+{
+	fn should_backoff(
+		&self,
+		chain_head_number: NumberFor<B>,
+		chain_head_slot: Slot,
+		finalized_number: NumberFor<B>,
+		slot_now: Slot,
+		logging_target: &str,
+	) -> bool {
+		if self.pool.status().ready == 0 {
+			return false
+		}
+
+		let unfinalized = self.unfinalized_transaction_blocks(
+			chain_head_number,
+			finalized_number,
+			self.config.unfinalized_slack.saturating_add(1),
+		);
+		if unfinalized <= self.config.unfinalized_slack {
+			return false
+		}
+
+		let Some(finalized_slot) = self.finalized_slot(finalized_number) else { return false };
+		let schedule = match standalone::slot_duration_schedule_at::<AuthorityId<P>, B, C>(
+			&*self.client,
+			self.client.info().best_hash,
+		) {
+			Ok(schedule) => schedule,
+			Err(e) => {
+				log::warn!(
+					target: logging_target,
+					"Could not fetch the slot duration schedule, not backing off: {}",
+					e,
+				);
+				return false
+			},
+		};
+
+		let now = schedule.slot_start(slot_now);
+		let age_since = |slot| Duration::from_millis(now.saturating_sub(schedule.slot_start(slot)));
+		let finalized_age = age_since(finalized_slot);
+		let Some(required) = self.config.required_head_age(finalized_age, unfinalized) else {
+			return false
+		};
+
+		if age_since(chain_head_slot) < required {
+			log::info!(
+				target: logging_target,
+				"Backing off claiming new slot for block authorship: finality is lagging by {:?} \
+				 and {} blocks.",
+				finalized_age,
+				unfinalized,
+			);
+			true
+		} else {
+			false
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn only_signed_and_general_extrinsics_are_transactions() {
+		// Length prefixed, with the version byte first.
+		let extrinsic = |version: u8| sp_runtime::OpaqueExtrinsic::from_bytes(&[8, version, 0]);
+
+		assert!(!is_transaction(&extrinsic(0b0000_0100).unwrap()));
+		assert!(is_transaction(&extrinsic(0b1000_0100).unwrap()));
+		assert!(is_transaction(&extrinsic(0b0100_0101).unwrap()));
+	}
+
+	#[test]
+	fn backs_off_only_once_both_slacks_are_exceeded() {
+		let config = FinalityAgeBackoffConfig {
+			age_slack: Duration::from_secs(60),
+			unfinalized_slack: 8,
+			max_interval: Duration::from_secs(300),
+		};
+
+		assert_eq!(config.required_head_age(Duration::from_secs(600), 8), None);
+		assert_eq!(config.required_head_age(Duration::from_secs(60), 100), None);
+		assert_eq!(
+			config.required_head_age(Duration::from_secs(100), 9),
+			Some(Duration::from_secs(20)),
+		);
+		assert_eq!(
+			config.required_head_age(Duration::from_secs(3600), 9),
+			Some(Duration::from_secs(300)),
+		);
+	}
+}
@@ -0,0 +1,211 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Micc specific block import, wrapping the block import of the node.
+
+use std::{fmt::Debug, sync::Arc};
+
+use crate::{
+	authority_sets::SharedAuthoritySets,
+	clock_drift::{ClockDriftMonitor, TimestampReader},
+	standalone::SlotAuthorities,
+	CompatibilityMode, LOG_TARGET,
+};
+use codec::Codec;
+use sc_client_api::utils::is_descendent_of;
+use sc_consensus::{BlockCheckParams, BlockImport, BlockImportParams, ImportResult};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::{HeaderBackend, HeaderMetadata};
+use sp_consensus_micc::{ConsensusLog, MiccApi, MICC_ENGINE_ID};
+use sp_runtime::{
+	generic::OpaqueDigestItemId,
+	traits::{Block as BlockT, Header, NumberFor, One, Saturating},
+};
+
+/// A block import recording the authority set changes of imported blocks in the
+/// [`SharedAuthoritySets`] before handing them to the wrapped block import.
+///
+/// It can also feed the timestamps of imported blocks to a [`ClockDriftMonitor`].
+pub struct MiccBlockImport<B: BlockT, I, C, A> {
+	inner: I,
+	client: Arc<C>,
+	authority_sets: SharedAuthoritySets<B::Hash, NumberFor<B>, A>,
+	clock_drift: Option<(ClockDriftMonitor, TimestampReader<B>)>,
+}
+
+impl<B: BlockT, I: Clone, C, A> Clone for MiccBlockImport<B, I, C, A> {
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone(),
+			client: self.client.clone(),
+			authority_sets: self.authority_sets.clone(),
+			clock_drift: self.clock_drift.clone(),
+		}
+	}
+}
+
+impl<B: BlockT, I, C, A> MiccBlockImport<B, I, C, A> {
+	/// Wrap `inner`, recording authority set changes in `authority_sets`.
+	pub fn new(
+		inner: I,
+		client: Arc<C>,
+		authority_sets: SharedAuthoritySets<B::Hash, NumberFor<B>, A>,
+	) -> Self {
+		Self { inner, client, authority_sets, clock_drift: None }
+	}
+
+	/// Report the timestamps of imported blocks, as read by `timestamp`, to `monitor`.
+	pub fn with_clock_drift(
+		mut self,
+		monitor: ClockDriftMonitor,
+		timestamp: TimestampReader<B>,
+	) -> Self {
+		self.clock_drift = Some((monitor, timestamp));
+		self
+	}
+}
+
+impl<B, I, C, A> MiccBlockImport<B, I, C, A>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + HeaderMetadata<B, Error = sp_blockchain::Error>,
+	C::Api: MiccApi<B, A>,
+	A: Codec + Debug + Clone,
+{
+	/// The authorities enacted by `header` for its descendants, if they need to be recorded.
+	fn enacted_authorities(&self, header: &B::Header) -> Option<SlotAuthorities<A>> {
+		let mut new_authorities = None;
+		let mut new_selector = None;
+		for log in header.digest().logs() {
+			match log.try_to::<ConsensusLog<A>>(OpaqueDigestItemId::Consensus(&MICC_ENGINE_ID)) {
+				Some(ConsensusLog::AuthoritiesChange(authorities)) =>
+					new_authorities = Some(authorities),
+				Some(ConsensusLog::SlotAuthorSelectorChange(selector)) =>
+					new_selector = Some(selector),
+				_ => {},
+			}
+		}
+
+		let parent_hash = *header.parent_hash();
+		let known = self.authority_sets.authorities_for_child_of(
+			&parent_hash,
+			&header.number().saturating_sub(One::one()),
+			&is_descendent_of(&*self.client, None),
+		);
+		if new_authorities.is_none() && new_selector.is_none() && known.is_some() {
+			return None
+		}
+
+		let mut authorities = match known {
+			Some(authorities) => authorities,
+			None => match crate::authorities(
+				&*self.client,
+				parent_hash,
+				*header.number(),
+				&CompatibilityMode::None,
+				None,
+			) {
+				Ok(authorities) => authorities,
+				Err(e) => {
+					log::debug!(
+						target: LOG_TARGET,
+						"Not caching the authorities of {:?}, runtime lookup failed: {}",
+						parent_hash,
+						e,
+					);
+					return None
+				},
+			},
+		};
+		if let Some(new_authorities) = new_authorities {
+			authorities.authorities = new_authorities;
+		}
+		if let Some(new_selector) = new_selector {
+			authorities.selector = new_selector;
+		}
+
+		Some(authorities)
+	}
+}
+
+#[async_trait::async_trait]
+impl<B, I, C, A> BlockImport<B> for MiccBlockImport<B, I, C, A>
+where
+	B: BlockT,
+	I: BlockImport<B> + Send + Sync,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ Send
+		+ Sync,
+	C::Api: MiccApi<B, A>,
+	A: Codec + Debug + Clone + Send + Sync,
+{
+	type Error = I::Error;
+
+	async fn check_block(&self, block: BlockCheckParams<B>) -> Result<ImportResult, Self::Error> {
+		self.inner.check_block(block).await
+	}
+
+	async fn import_block(
+		&self,
+		mut block: BlockImportParams<B>,
+	) -> Result<ImportResult, Self::Error> {
+		let hash = block.post_hash();
+
+		// Blocks imported without execution are not recorded, see `authority_sets`.
+		let mut snapshot = None;
+		let _import_lock = if block.with_state() || block.state_action.skip_execution_checks() {
+			None
+		} else {
+			let guard = self.authority_sets.lock_import().await;
+			if let Some(authorities) = self.enacted_authorities(&block.header) {
+				snapshot = Some(self.authority_sets.snapshot());
+				let info = self.client.info();
+				let aux = self.authority_sets.record(
+					hash,
+					*block.header.number(),
+					authorities,
+					(&info.finalized_hash, &info.finalized_number),
+					&is_descendent_of(&*self.client, Some((hash, *block.header.parent_hash()))),
+				);
+				block.auxiliary.extend(aux.into_iter().map(|(key, value)| (key, Some(value))));
+			}
+			Some(guard)
+		};
+
+		// Forget the change of a block that didn't make it into the chain.
+		let result = self.inner.import_block(block).await;
+		if !matches!(result, Ok(ImportResult::Imported(_))) {
+			if let Some(snapshot) = snapshot {
+				self.authority_sets.restore(snapshot);
+			}
+		}
+		let result = result?;
+
+		if let (ImportResult::Imported(_), Some((monitor, timestamp))) =
+			(&result, &self.clock_drift)
+		{
+			if let Some(timestamp) = timestamp(hash) {
+				monitor.note_imported(hash.as_ref(), timestamp);
+			}
+		}
+
+		Ok(result)
+	}
+}
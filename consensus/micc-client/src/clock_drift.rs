@@ -0,0 +1,392 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Detection of local clock drift against the timestamps of blocks authored by peers.
+//!
+//! Micc derives the slot from the local system time, so a skewed clock breaks both authoring and
+//! verification without any obvious symptom. For every block received from the network we record
+//! the difference between the local time at which it arrived and the timestamp its author put into
+//! it. The median of these samples per author, and the median over authors, estimate how far the
+//! local clock is off.
+//!
+//! Samples include propagation and proposing time, so a node with a perfect clock still sees a
+//! small positive drift.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use codec::Decode;
+use prometheus_endpoint::{register, Counter, Gauge, PrometheusError, Registry, F64, U64};
+use sc_client_api::{backend::Backend, StorageProvider};
+use sp_core::{hashing::twox_128, hexdisplay::HexDisplay, storage::StorageKey};
+use sp_runtime::traits::Block as BlockT;
+
+use crate::LOG_TARGET;
+
+/// Maximum number of received blocks waiting to be imported that are tracked.
+const MAX_PENDING: usize = 256;
+
+/// Maximum number of authors tracked, the least recently seen one is forgotten first.
+const MAX_AUTHORS: usize = 256;
+
+/// Reads the timestamp set by the block author from the state of an imported block.
+pub type TimestampReader<B> = Arc<dyn Fn(<B as BlockT>::Hash) -> Option<u64> + Send + Sync>;
+
+/// Read block timestamps from the storage of `pallet_timestamp`, deployed as `pallet_name` in
+/// `construct_runtime!`.
+///
+/// Warns the first time a block has no timestamp there, which usually means `pallet_name` is not
+/// the name the runtime gives the pallet and no drift can be measured.
+pub fn pallet_timestamp_reader<B, C, BE>(client: Arc<C>, pallet_name: &str) -> TimestampReader<B>
+where
+	B: BlockT,
+	C: StorageProvider<B, BE> + Send + Sync + 'static,
+	BE: Backend<B> + 'static,
+{
+	let pallet_name = pallet_name.to_owned();
+	let key = StorageKey([twox_128(pallet_name.as_bytes()), twox_128(b"Now")].concat());
+	let warned = AtomicBool::new(false);
+
+	Arc::new(move |hash| {
+		let timestamp = client
+			.storage(hash, &key)
+			.ok()
+			.flatten()
+			.and_then(|data| u64::decode(&mut &data.0[..]).ok());
+		if timestamp.is_none() && !warned.swap(true, Ordering::Relaxed) {
+			log::warn!(
+				target: LOG_TARGET,
+				"No timestamp in the storage of pallet {} at {:?}, the clock drift can't be \
+				 measured",
+				pallet_name,
+				hash,
+			);
+		}
+
+		timestamp
+	})
+}
+
+/// Configuration of the [`ClockDriftMonitor`].
+#[derive(Debug, Clone)]
+pub struct ClockDriftConfig {
+	/// Refuse to author while the estimated local drift is larger than this, in either direction.
+	pub max_drift: Duration,
+	/// Number of most recent samples kept per author.
+	pub samples_per_author: usize,
+	/// Minimum number of samples an author needs to count towards the aggregate estimate.
+	pub min_samples: usize,
+}
+
+impl Default for ClockDriftConfig {
+	fn default() -> Self {
+		Self { max_drift: Duration::from_secs(5), samples_per_author: 32, min_samples: 3 }
+	}
+}
+
+/// Drift estimated from the blocks of a single author.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuthorClockDrift {
+	/// The author, hex encoded.
+	pub author: String,
+	/// Median of the local arrival time minus the block timestamp, in milliseconds.
+	pub drift_ms: i64,
+	/// Number of samples the estimate is based on.
+	pub samples: usize,
+}
+
+/// Current estimate of the local clock drift.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockDriftEstimate {
+	/// Median of the per-author estimates with enough samples, in milliseconds.
+	///
+	/// Positive values mean the local clock is ahead of the block authors. `None` until enough
+	/// blocks from peers have been imported.
+	pub drift_ms: Option<i64>,
+	/// The drift above which the node refuses to author, in milliseconds.
+	pub max_drift_ms: u64,
+	/// Per-author estimates.
+	pub authors: Vec<AuthorClockDrift>,
+}
+
+/// The local clock drifted too far to safely author blocks.
+#[derive(Debug, thiserror::Error)]
+#[error(
+	"local clock is {drift_ms}ms off the timestamps of blocks from peers (limit {max_drift_ms}ms); \
+	 check the system clock and its NTP synchronisation"
+)]
+pub struct ClockDriftError {
+	/// The estimated drift, in milliseconds.
+	pub drift_ms: i64,
+	/// The configured limit, in milliseconds.
+	pub max_drift_ms: u64,
+}
+
+#[derive(Default)]
+struct AuthorSamples {
+	samples: VecDeque<i64>,
+	last_seen: u64,
+}
+
+#[derive(Default)]
+struct DriftState {
+	pending: VecDeque<(Vec<u8>, Vec<u8>, SystemTime)>,
+	authors: HashMap<Vec<u8>, AuthorSamples>,
+	imported: u64,
+}
+
+#[derive(Clone)]
+struct Metrics {
+	drift: Gauge<F64>,
+	samples: Counter<U64>,
+	refusals: Counter<U64>,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			drift: register(
+				Gauge::new(
+					"micc_clock_drift_milliseconds",
+					"Estimated drift of the local clock against block timestamps from peers",
+				)?,
+				registry,
+			)?,
+			samples: register(
+				Counter::new(
+					"micc_clock_drift_samples_total",
+					"Number of imported blocks used to estimate the local clock drift",
+				)?,
+				registry,
+			)?,
+			refusals: register(
+				Counter::new(
+					"micc_clock_drift_authoring_refusals_total",
+					"Number of slots not authored because of local clock drift",
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// Tracks the drift of the local clock against the timestamps of blocks imported from peers.
+///
+/// The verifier notes when a block arrives and who authored it, the block import reads the
+/// author's timestamp once the block is imported, and the block authoring consults
+/// [`Self::check`] before claiming a slot.
+#[derive(Clone)]
+pub struct ClockDriftMonitor {
+	state: Arc<Mutex<DriftState>>,
+	config: ClockDriftConfig,
+	metrics: Option<Metrics>,
+}
+
+impl ClockDriftMonitor {
+	/// Create a new monitor, registering its metrics if a registry is given.
+	pub fn new(
+		config: ClockDriftConfig,
+		registry: Option<&Registry>,
+	) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			state: Default::default(),
+			config,
+			metrics: registry.map(Metrics::register).transpose()?,
+		})
+	}
+
+	/// Note that the block `hash` authored by `author` was received from the network at
+	/// `received_at`.
+	pub(crate) fn note_received(&self, hash: &[u8], author: &[u8], received_at: SystemTime) {
+		let mut state = self.state.lock().expect("never poisoned; qed");
+		if state.pending.len() >= MAX_PENDING {
+			state.pending.pop_front();
+		}
+		state.pending.push_back((hash.to_vec(), author.to_vec(), received_at));
+	}
+
+	/// Note that the block `hash` carrying `timestamp` (in milliseconds) was imported.
+	///
+	/// Blocks that weren't noted as received from the network are ignored.
+	pub(crate) fn note_imported(&self, hash: &[u8], timestamp: u64) {
+		let mut state = self.state.lock().expect("never poisoned; qed");
+		let Some(position) = state.pending.iter().position(|(pending, ..)| pending == hash) else {
+			return
+		};
+		let (_, author, received_at) =
+			state.pending.remove(position).expect("position was just found; qed");
+
+		let received_at =
+			received_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as i64;
+		let drift = received_at.saturating_sub(timestamp as i64);
+
+		state.imported += 1;
+		let imported = state.imported;
+		if !state.authors.contains_key(&author) && state.authors.len() >= MAX_AUTHORS {
+			let stale = state
+				.authors
+				.iter()
+				.min_by_key(|(_, samples)| samples.last_seen)
+				.map(|(author, _)| author.clone());
+			if let Some(stale) = stale {
+				state.authors.remove(&stale);
+			}
+		}
+
+		let entry = state.authors.entry(author).or_default();
+		if entry.samples.len() >= self.config.samples_per_author {
+			entry.samples.pop_front();
+		}
+		entry.samples.push_back(drift);
+		entry.last_seen = imported;
+
+		let aggregate = self.aggregate(&state);
+		drop(state);
+
+		if let Some(metrics) = &self.metrics {
+			metrics.samples.inc();
+			if let Some(aggregate) = aggregate {
+				metrics.drift.set(aggregate as f64);
+			}
+		}
+	}
+
+	fn aggregate(&self, state: &DriftState) -> Option<i64> {
+		let per_author = state
+			.authors
+			.values()
+			.filter(|author| author.samples.len() >= self.config.min_samples)
+			.map(|author| median(author.samples.iter().copied().collect()))
+			.collect::<Vec<_>>();
+
+		(!per_author.is_empty()).then(|| median(per_author))
+	}
+
+	/// The current drift estimate.
+	pub fn estimate(&self) -> ClockDriftEstimate {
+		let state = self.state.lock().expect("never poisoned; qed");
+
+		let mut authors = state
+			.authors
+			.iter()
+			.filter(|(_, author)| !author.samples.is_empty())
+			.map(|(author, samples)| AuthorClockDrift {
+				author: format!("0x{}", HexDisplay::from(&&author[..])),
+				drift_ms: median(samples.samples.iter().copied().collect()),
+				samples: samples.samples.len(),
+			})
+			.collect::<Vec<_>>();
+		authors.sort_by(|a, b| a.author.cmp(&b.author));
+
+		ClockDriftEstimate {
+			drift_ms: self.aggregate(&state),
+			max_drift_ms: self.config.max_drift.as_millis() as u64,
+			authors,
+		}
+	}
+
+	/// Check whether the local clock is close enough to the network to author blocks.
+	pub fn check(&self) -> Result<(), ClockDriftError> {
+		let max_drift_ms = self.config.max_drift.as_millis() as u64;
+		let aggregate = self.aggregate(&self.state.lock().expect("never poisoned; qed"));
+
+		match aggregate {
+			Some(drift_ms) if drift_ms.unsigned_abs() > max_drift_ms => {
+				if let Some(metrics) = &self.metrics {
+					metrics.refusals.inc();
+				}
+				Err(ClockDriftError { drift_ms, max_drift_ms })
+			},
+			_ => Ok(()),
+		}
+	}
+}
+
+fn median(mut values: Vec<i64>) -> i64 {
+	values.sort_unstable();
+	let middle = values.len() / 2;
+	if values.len() % 2 == 0 {
+		((values[middle - 1] as i128 + values[middle] as i128) / 2) as i64
+	} else {
+		values[middle]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn now_ms() -> u64 {
+		SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64
+	}
+
+	fn import(monitor: &ClockDriftMonitor, hash: u8, author: u8, author_ahead_ms: i64) {
+		monitor.note_received(&[hash], &[author], SystemTime::now());
+		monitor.note_imported(&[hash], (now_ms() as i64 + author_ahead_ms) as u64);
+	}
+
+	#[test]
+	fn no_estimate_without_enough_samples() {
+		let monitor = ClockDriftMonitor::new(Default::default(), None).unwrap();
+		import(&monitor, 1, 1, -60_000);
+		import(&monitor, 2, 1, -60_000);
+
+		assert_eq!(monitor.estimate().drift_ms, None);
+		assert!(monitor.check().is_ok());
+
+		// blocks we didn't see arriving, such as our own, are ignored.
+		monitor.note_imported(&[3], 0);
+		assert_eq!(monitor.estimate().authors[0].samples, 2);
+	}
+
+	#[test]
+	fn refuses_to_author_when_behind_the_network() {
+		let config = ClockDriftConfig { max_drift: Duration::from_secs(5), ..Default::default() };
+		let monitor = ClockDriftMonitor::new(config, None).unwrap();
+
+		// two authors consistently 10 seconds ahead of us, one outlier.
+		for i in 0..3 {
+			import(&monitor, i, 1, 10_000);
+			import(&monitor, 10 + i, 2, 10_000);
+			import(&monitor, 20 + i, 3, -100_000);
+		}
+
+		let drift = monitor.estimate().drift_ms.unwrap();
+		assert!((-10_100..=-9_900).contains(&drift), "{drift}");
+		assert_eq!(monitor.check().unwrap_err().max_drift_ms, 5_000);
+	}
+
+	#[test]
+	fn accepts_small_drift() {
+		let monitor = ClockDriftMonitor::new(Default::default(), None).unwrap();
+		for i in 0..5 {
+			import(&monitor, i, 1, -300);
+		}
+
+		assert!(monitor.check().is_ok());
+		assert_eq!(monitor.estimate().authors.len(), 1);
+	}
+}
@@ -0,0 +1,451 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Sizing the collection windows of the event-driven production from the blocks it authored.
+//!
+//! The [`CollectionController`] aims at a p95 latency between the import of a transaction in the
+//! pool and the import of the block including it, and at a fill ratio of the blocks. Both are
+//! measured from the blocks authored locally by [`measure_authored_blocks`]. The collection window
+//! shrinks while the latency is above target, and grows while it is below and the blocks are
+//! emptier than targeted. The number of ready transactions producing a block right away follows
+//! the size of the transactions, so that such a block is filled to target.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	hash::Hash,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use codec::Encode;
+use futures::StreamExt;
+use prometheus_endpoint::{register, Gauge, PrometheusError, Registry, F64, U64};
+use sc_client_api::{BlockBackend, BlockchainEvents};
+use sc_transaction_pool_api::{InPoolTransaction, TransactionPool};
+use sp_consensus::BlockOrigin;
+use sp_runtime::traits::Block as BlockT;
+
+use crate::{
+	event_driven::{CollectionConfig, EventDrivenConfig},
+	promotion::PROMOTION_CHECK_INTERVAL,
+};
+
+/// Number of inclusion latencies the p95 is computed over.
+const LATENCY_SAMPLES: usize = 256;
+/// Maximum number of transaction arrivals remembered until their inclusion.
+const MAX_ARRIVALS: usize = 65_536;
+/// Weight of the last block in the averages of the fill ratio and transaction size.
+const SMOOTHING: f64 = 0.2;
+/// Bounds of the factor the window is scaled by after each block.
+const MIN_STEP: f64 = 0.5;
+const MAX_STEP: f64 = 1.25;
+
+/// Targets of a [`CollectionController`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CollectionTargets {
+	/// The p95 of the time between the import of a transaction and the import of the block
+	/// including it.
+	pub p95_inclusion_latency: Duration,
+	/// The share of [`Self::block_capacity`] the authored blocks should fill.
+	pub fill_ratio: f64,
+	/// The encoded size of a full block, in bytes: the length the `BlockLength` of the runtime
+	/// allows the normal dispatch class.
+	pub block_capacity: usize,
+}
+
+impl Default for CollectionTargets {
+	fn default() -> Self {
+		Self {
+			p95_inclusion_latency: Duration::from_secs(3),
+			fill_ratio: 0.5,
+			// The share of 5 MiB blocks open to normal transactions, as in the template runtime.
+			block_capacity: 5 * 1024 * 1024 * 3 / 4,
+		}
+	}
+}
+
+#[derive(Debug)]
+struct ControllerState {
+	window: Duration,
+	batch_threshold: usize,
+	latencies: VecDeque<Duration>,
+	fill_ratio: Option<f64>,
+	transaction_size: Option<f64>,
+}
+
+impl ControllerState {
+	fn new(collection: &CollectionConfig) -> Self {
+		Self {
+			window: (collection.min_collection_time + collection.max_collection_time) / 2,
+			batch_threshold: collection.max_batch_size,
+			latencies: VecDeque::with_capacity(LATENCY_SAMPLES),
+			fill_ratio: None,
+			transaction_size: None,
+		}
+	}
+
+	fn p95(&self) -> Option<Duration> {
+		let mut latencies = self.latencies.iter().copied().collect::<Vec<_>>();
+		latencies.sort_unstable();
+
+		let index = (latencies.len() * 95).div_ceil(100).checked_sub(1)?;
+		latencies.get(index).copied()
+	}
+
+	/// Note a block including transactions after `latencies`, `transactions_size` bytes of them,
+	/// in a block of `block_size` bytes.
+	fn note_block(
+		&mut self,
+		latencies: Vec<Duration>,
+		transactions_size: usize,
+		block_size: usize,
+		targets: &CollectionTargets,
+	) {
+		let transactions = latencies.len();
+		for latency in latencies {
+			if self.latencies.len() >= LATENCY_SAMPLES {
+				self.latencies.pop_front();
+			}
+			self.latencies.push_back(latency);
+		}
+
+		let smooth = |average: Option<f64>, value: f64| {
+			Some(average.map_or(value, |average| average + SMOOTHING * (value - average)))
+		};
+		let fill_ratio = block_size as f64 / targets.block_capacity.max(1) as f64;
+		self.fill_ratio = smooth(self.fill_ratio, fill_ratio);
+		self.transaction_size =
+			smooth(self.transaction_size, transactions_size as f64 / transactions as f64);
+	}
+
+	/// Move the window and the batch threshold towards the targets.
+	fn adjust(&mut self, collection: &CollectionConfig, targets: &CollectionTargets) {
+		if let (Some(p95), Some(fill_ratio)) = (self.p95(), self.fill_ratio) {
+			let slack = targets.p95_inclusion_latency.as_secs_f64() / p95.as_secs_f64().max(0.001);
+			let step = if slack < 1.0 {
+				slack.max(MIN_STEP)
+			} else if fill_ratio < targets.fill_ratio {
+				slack.min(MAX_STEP)
+			} else {
+				1.0
+			};
+
+			self.window = self
+				.window
+				.mul_f64(step)
+				.clamp(collection.min_collection_time, collection.max_collection_time);
+		}
+
+		if let Some(transaction_size) = self.transaction_size {
+			let target_size = targets.fill_ratio * targets.block_capacity as f64;
+			self.batch_threshold = ((target_size / transaction_size.max(1.0)) as usize)
+				.clamp(1, collection.max_batch_size.max(1));
+		}
+	}
+}
+
+#[derive(Clone)]
+struct Metrics {
+	window: Gauge<U64>,
+	batch_threshold: Gauge<U64>,
+	p95_latency: Gauge<U64>,
+	fill_ratio: Gauge<F64>,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			window: register(
+				Gauge::new(
+					"micc_collection_window_milliseconds",
+					"Length of the collection windows opened for event-driven blocks",
+				)?,
+				registry,
+			)?,
+			batch_threshold: register(
+				Gauge::new(
+					"micc_collection_batch_threshold",
+					"Number of ready transactions producing an event-driven block right away",
+				)?,
+				registry,
+			)?,
+			p95_latency: register(
+				Gauge::new(
+					"micc_inclusion_latency_p95_milliseconds",
+					"p95 of the time from a transaction import to the import of the locally \
+					 authored block including it",
+				)?,
+				registry,
+			)?,
+			fill_ratio: register(
+				Gauge::new(
+					"micc_block_fill_ratio",
+					"Average share of the block capacity filled by the locally authored blocks",
+				)?,
+				registry,
+			)?,
+		})
+	}
+
+	fn report(&self, state: &ControllerState) {
+		self.window.set(state.window.as_millis() as u64);
+		self.batch_threshold.set(state.batch_threshold as u64);
+		if let Some(p95) = state.p95() {
+			self.p95_latency.set(p95.as_millis() as u64);
+		}
+		if let Some(fill_ratio) = state.fill_ratio {
+			self.fill_ratio.set(fill_ratio);
+		}
+	}
+}
+
+/// Sizes the collection windows of the event-driven production, moving their length and the
+/// batch threshold after each block noted, towards the [`CollectionTargets`].
+#[derive(Clone)]
+pub struct CollectionController {
+	state: Arc<Mutex<ControllerState>>,
+	collection: CollectionConfig,
+	targets: CollectionTargets,
+	metrics: Option<Metrics>,
+}
+
+impl CollectionController {
+	/// Create a controller within the bounds of `config`, registering its metrics if a registry
+	/// is given.
+	///
+	/// The window and batch threshold don't move if [`CollectionConfig::enable_adaptive_timing`]
+	/// is unset.
+	pub fn new(
+		config: &EventDrivenConfig,
+		registry: Option<&Registry>,
+	) -> Result<Self, PrometheusError> {
+		let state = ControllerState::new(&config.collection);
+		let metrics = registry.map(Metrics::register).transpose()?;
+		if let Some(metrics) = &metrics {
+			metrics.report(&state);
+		}
+
+		Ok(Self {
+			state: Arc::new(Mutex::new(state)),
+			collection: config.collection.clone(),
+			targets: config.targets,
+			metrics,
+		})
+	}
+
+	/// The length of the next collection window.
+	pub fn window(&self) -> Duration {
+		self.state.lock().expect("never poisoned; qed").window
+	}
+
+	/// The number of ready transactions producing a block without waiting for the window to
+	/// close.
+	pub fn batch_threshold(&self) -> usize {
+		self.state.lock().expect("never poisoned; qed").batch_threshold
+	}
+
+	/// Note a locally authored block of `block_size` bytes, including transactions imported
+	/// `latencies` before it, of `transactions_size` bytes overall.
+	pub fn note_block(
+		&self,
+		latencies: Vec<Duration>,
+		transactions_size: usize,
+		block_size: usize,
+	) {
+		if latencies.is_empty() {
+			return
+		}
+
+		let mut state = self.state.lock().expect("never poisoned; qed");
+		state.note_block(latencies, transactions_size, block_size, &self.targets);
+		if self.collection.enable_adaptive_timing {
+			state.adjust(&self.collection, &self.targets);
+		}
+
+		if let Some(metrics) = &self.metrics {
+			metrics.report(&state);
+		}
+	}
+}
+
+/// The time the transactions not included yet arrived in the pool.
+pub(crate) struct Arrivals<H> {
+	at: HashMap<H, Instant>,
+	order: VecDeque<H>,
+}
+
+impl<H> Default for Arrivals<H> {
+	fn default() -> Self {
+		Self { at: HashMap::new(), order: VecDeque::new() }
+	}
+}
+
+impl<H: Hash + Eq + Clone> Arrivals<H> {
+	/// Note that `hash` arrived at `at`, unless it arrived before.
+	pub(crate) fn note(&mut self, hash: H, at: Instant) {
+		if self.at.contains_key(&hash) {
+			return
+		}
+
+		if self.order.len() >= MAX_ARRIVALS {
+			if let Some(oldest) = self.order.pop_front() {
+				self.at.remove(&oldest);
+			}
+		}
+		self.at.insert(hash.clone(), at);
+		self.order.push_back(hash);
+	}
+
+	/// Take the time `hash` arrived at, if noted.
+	pub(crate) fn take(&mut self, hash: &H) -> Option<Instant> {
+		self.at.remove(hash)
+	}
+}
+
+/// Feed `controller` with the blocks authored locally, and the time their transactions were
+/// imported in `pool`, or first seen in its future queue.
+///
+/// Blocks without any transaction seen in the pool, such as heartbeats, are left out.
+pub async fn measure_authored_blocks<B, C, A>(
+	client: Arc<C>,
+	pool: Arc<A>,
+	controller: CollectionController,
+) where
+	B: BlockT,
+	C: BlockchainEvents<B> + BlockBackend<B>,
+	A: TransactionPool<Block = B>,
+{
+	let mut imports = pool.import_notification_stream();
+	let mut blocks = client.import_notification_stream();
+	let mut futures_check = tokio::time::interval(PROMOTION_CHECK_INTERVAL);
+	let mut arrivals = Arrivals::default();
+
+	loop {
+		tokio::select! {
+			Some(hash) = imports.next() => arrivals.note(hash, Instant::now()),
+			// Neither entering the future queue nor leaving it for the ready one is notified, the
+			// latency of such transactions counts from the first time they are seen there.
+			_ = futures_check.tick(), if pool.status().future > 0 => {
+				let now = Instant::now();
+				for tx in pool.futures() {
+					arrivals.note(tx.hash().clone(), now);
+				}
+			},
+			Some(block) = blocks.next() => {
+				if block.origin != BlockOrigin::Own {
+					continue
+				}
+				let Ok(Some(body)) = client.block_body(block.hash) else { continue };
+
+				let now = Instant::now();
+				let mut latencies = Vec::new();
+				let mut transactions_size = 0;
+				for extrinsic in &body {
+					if let Some(arrived) = arrivals.take(&pool.hash_of(extrinsic)) {
+						latencies.push(now.saturating_duration_since(arrived));
+						transactions_size += extrinsic.encoded_size();
+					}
+				}
+
+				controller.note_block(latencies, transactions_size, body.encoded_size());
+			},
+			else => return,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn collection() -> CollectionConfig {
+		CollectionConfig {
+			min_collection_time: Duration::from_millis(100),
+			max_collection_time: Duration::from_secs(2),
+			max_batch_size: 1000,
+			..Default::default()
+		}
+	}
+
+	fn targets() -> CollectionTargets {
+		CollectionTargets {
+			p95_inclusion_latency: Duration::from_secs(1),
+			fill_ratio: 0.5,
+			block_capacity: 100_000,
+		}
+	}
+
+	#[test]
+	fn window_follows_the_latency_and_fill_targets() {
+		let (collection, targets) = (collection(), targets());
+		let mut state = ControllerState::new(&collection);
+		assert_eq!(state.window, Duration::from_millis(1050));
+
+		// Too slow: the window shrinks, down to its minimum.
+		for _ in 0..20 {
+			state.note_block(vec![Duration::from_secs(4); 10], 1_000, 1_000, &targets);
+			state.adjust(&collection, &targets);
+		}
+		assert_eq!(state.window, collection.min_collection_time);
+
+		// Fast but mostly empty blocks: the window grows again, up to its maximum.
+		state.latencies.clear();
+		for _ in 0..40 {
+			state.note_block(vec![Duration::from_millis(200); 10], 1_000, 1_000, &targets);
+			state.adjust(&collection, &targets);
+		}
+		assert_eq!(state.window, collection.max_collection_time);
+
+		// Fast and full enough blocks: the window holds.
+		state.window = Duration::from_millis(500);
+		state.fill_ratio = None;
+		state.note_block(vec![Duration::from_millis(200); 10], 1_000, 80_000, &targets);
+		state.adjust(&collection, &targets);
+		assert_eq!(state.window, Duration::from_millis(500));
+	}
+
+	#[test]
+	fn batch_threshold_fills_blocks_to_target() {
+		let (collection, targets) = (collection(), targets());
+		let mut state = ControllerState::new(&collection);
+		assert_eq!(state.batch_threshold, 1000);
+
+		// 500 bytes per transaction, half of 100 kB fits 100 of them.
+		state.note_block(vec![Duration::from_millis(200); 4], 2_000, 2_000, &targets);
+		state.adjust(&collection, &targets);
+		assert_eq!(state.batch_threshold, 100);
+
+		// Tiny transactions: bounded by the configured maximum.
+		state.transaction_size = None;
+		state.note_block(vec![Duration::from_millis(200); 4], 4, 2_000, &targets);
+		state.adjust(&collection, &targets);
+		assert_eq!(state.batch_threshold, 1000);
+	}
+
+	#[test]
+	fn p95_of_the_latest_samples() {
+		let mut state = ControllerState::new(&collection());
+		assert_eq!(state.p95(), None);
+
+		state.latencies = (1..=100).map(Duration::from_millis).collect();
+		assert_eq!(state.p95(), Some(Duration::from_millis(95)));
+
+		state.latencies = vec![Duration::from_millis(7)].into();
+		assert_eq!(state.p95(), Some(Duration::from_millis(7)));
+	}
+}
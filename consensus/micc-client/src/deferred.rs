@@ -0,0 +1,508 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Holding back blocks whose slot is slightly ahead of the local clock.
+//!
+//! The import queue verifies one block at a time, so a block can't wait for its slot in the
+//! verifier without holding back every block behind it. The [`DeferringImportQueue`] checks the
+//! slot of the blocks handed to it instead, and keeps those slightly ahead aside, handing them to
+//! the import queue again once the local clock reaches their slot.
+//!
+//! The time the blocks broadcast by their authors reached the queue is kept in [`ArrivalTimes`],
+//! so that the clock drift measured by the verifier isn't hidden by the time they waited.
+
+use std::{
+	collections::{BTreeMap, HashMap},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use log::debug;
+use sc_consensus::import_queue::{
+	BasicQueue, ImportQueue, ImportQueueService, IncomingBlock, Link, RuntimeOrigin,
+};
+use sp_consensus::BlockOrigin;
+use sp_consensus_micc::SlotDurationSchedule;
+use sp_consensus_slots::Slot;
+use sp_runtime::{
+	traits::{Block as BlockT, NumberFor},
+	Justifications,
+};
+use tokio::sync::Notify;
+
+use crate::LOG_TARGET;
+
+/// How far ahead of the local clock the slot of an imported header may be.
+///
+/// Headers more than [`Self::accepted_slots`] ahead are deferred: they wait until the local clock
+/// catches up and are imported again. Only headers more than [`Self::deferred_slots`] ahead, or
+/// arriving while [`Self::max_deferred`] blocks are already waiting, are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DriftTolerance {
+	/// Number of slots a header may be ahead of the local slot and still be imported right away.
+	pub accepted_slots: u64,
+	/// Number of slots a header may be ahead of the local slot and still be deferred instead of
+	/// rejected. Values below [`Self::accepted_slots`] disable deferral.
+	pub deferred_slots: u64,
+	/// Maximum number of blocks waiting for their slot at the same time.
+	pub max_deferred: usize,
+}
+
+impl Default for DriftTolerance {
+	fn default() -> Self {
+		Self { accepted_slots: 1, deferred_slots: 4, max_deferred: 64 }
+	}
+}
+
+impl DriftTolerance {
+	/// Whether a header at `slot` should wait for the local clock, given the local `slot_now`.
+	pub(crate) fn should_defer(&self, slot: Slot, slot_now: Slot) -> bool {
+		slot > slot_now.saturating_add(self.accepted_slots) &&
+			slot <= slot_now.saturating_add(self.deferred_slots)
+	}
+}
+
+/// Number of arrival times kept at once, see [`ArrivalTimes`].
+const MAX_ARRIVALS: usize = 1024;
+/// Age after which an arrival time is dropped to make room for others, see [`ArrivalTimes`].
+const STALE_ARRIVAL: Duration = Duration::from_secs(60);
+
+/// When the blocks broadcast by their authors reached the [`DeferringImportQueue`], before any of
+/// them was held back, taken by the verifier.
+pub(crate) struct ArrivalTimes<H>(Arc<Mutex<HashMap<H, SystemTime>>>);
+
+impl<H> Clone for ArrivalTimes<H> {
+	fn clone(&self) -> Self {
+		Self(self.0.clone())
+	}
+}
+
+impl<H> Default for ArrivalTimes<H> {
+	fn default() -> Self {
+		Self(Default::default())
+	}
+}
+
+impl<H: std::hash::Hash + Eq> ArrivalTimes<H> {
+	/// Note that `hash` arrived at `at`, unless it arrived before, e.g. when deferred blocks are
+	/// handed to the queue again.
+	fn note(&self, hash: H, at: SystemTime) {
+		let mut arrivals = self.0.lock().expect("never poisoned; qed");
+		// Blocks dropped by the queue before reaching the verifier leave their time behind.
+		if arrivals.len() >= MAX_ARRIVALS {
+			arrivals
+				.retain(|_, noted| at.duration_since(*noted).unwrap_or_default() < STALE_ARRIVAL);
+		}
+		if arrivals.len() < MAX_ARRIVALS {
+			arrivals.entry(hash).or_insert(at);
+		}
+	}
+
+	/// When `hash` arrived, if it was noted.
+	pub(crate) fn take(&self, hash: &H) -> Option<SystemTime> {
+		self.0.lock().expect("never poisoned; qed").remove(hash)
+	}
+}
+
+/// Batches of blocks waiting for the local clock to reach their slot, ordered by release time.
+struct DeferredBlocks<B: BlockT> {
+	waiting: BTreeMap<(Instant, u64), (BlockOrigin, Vec<IncomingBlock<B>>)>,
+	next_id: u64,
+	len: usize,
+	max_deferred: usize,
+}
+
+impl<B: BlockT> DeferredBlocks<B> {
+	fn new(max_deferred: usize) -> Self {
+		Self { waiting: BTreeMap::new(), next_id: 0, len: 0, max_deferred }
+	}
+
+	/// Park `blocks` until `release_at`.
+	///
+	/// Returns the blocks back if there is no room left for them.
+	fn park(
+		&mut self,
+		release_at: Instant,
+		origin: BlockOrigin,
+		blocks: Vec<IncomingBlock<B>>,
+	) -> Result<(), Vec<IncomingBlock<B>>> {
+		if self.len + blocks.len() > self.max_deferred {
+			return Err(blocks)
+		}
+
+		self.len += blocks.len();
+		self.waiting.insert((release_at, self.next_id), (origin, blocks));
+		self.next_id += 1;
+		Ok(())
+	}
+
+	/// When the next batch is due, if any is waiting.
+	fn next_release(&self) -> Option<Instant> {
+		self.waiting.keys().next().map(|(release_at, _)| *release_at)
+	}
+
+	/// Take the batches due at `now`, in release order.
+	fn take_due(&mut self, now: Instant) -> Vec<(BlockOrigin, Vec<IncomingBlock<B>>)> {
+		let waiting = self.waiting.split_off(&(now, u64::MAX));
+		let due = std::mem::replace(&mut self.waiting, waiting);
+		self.len -= due.values().map(|(_, blocks)| blocks.len()).sum::<usize>();
+		due.into_values().collect()
+	}
+}
+
+/// How the slots of the blocks and of the local clock are found.
+pub(crate) struct SlotClock<B: BlockT> {
+	/// The slot of a header, `None` if it has none.
+	pub(crate) slot_of: Box<dyn Fn(&B::Header) -> Option<Slot> + Send + Sync>,
+	/// The slot duration schedule of the chain.
+	pub(crate) schedule: Box<dyn Fn() -> SlotDurationSchedule + Send + Sync>,
+}
+
+struct Deferral<B: BlockT> {
+	tolerance: DriftTolerance,
+	clock: SlotClock<B>,
+	blocks: Mutex<DeferredBlocks<B>>,
+	arrivals: ArrivalTimes<B::Hash>,
+	wake: Notify,
+}
+
+impl<B: BlockT> Deferral<B> {
+	/// Split `blocks` at the first one too far ahead of the local clock, returning it along with
+	/// the time it is due, if it should be deferred rather than rejected.
+	///
+	/// Every block descends from the previous one, so the blocks following a block ahead of the
+	/// local clock are ahead of it too.
+	fn split_early(
+		&self,
+		blocks: &[IncomingBlock<B>],
+		now_ms: u64,
+	) -> Option<(usize, Slot, Instant)> {
+		let schedule = (self.clock.schedule)();
+		let slot_now = schedule.slot_at(now_ms);
+		let accepted = slot_now.saturating_add(self.tolerance.accepted_slots);
+
+		let (index, slot) = blocks.iter().enumerate().find_map(|(index, block)| {
+			// Blocks imported from a state or without execution aren't checked against the clock.
+			if block.skip_execution || block.state.is_some() {
+				return None
+			}
+			let slot = (self.clock.slot_of)(block.header.as_ref()?)?;
+			(slot > accepted).then_some((index, slot))
+		})?;
+		if !self.tolerance.should_defer(slot, slot_now) {
+			return None
+		}
+
+		let accepted_from = slot.saturating_sub(self.tolerance.accepted_slots);
+		Some((index, slot, instant_at_unix_millis(schedule.slot_start(accepted_from))))
+	}
+}
+
+/// An [`ImportQueueService`] holding back the blocks slightly ahead of the local clock.
+struct DeferringService<B: BlockT> {
+	inner: Box<dyn ImportQueueService<B>>,
+	deferral: Arc<Deferral<B>>,
+}
+
+impl<B: BlockT> ImportQueueService<B> for DeferringService<B> {
+	fn import_blocks(&mut self, origin: BlockOrigin, mut blocks: Vec<IncomingBlock<B>>) {
+		let now = SystemTime::now();
+		if origin == BlockOrigin::NetworkBroadcast {
+			for block in &blocks {
+				self.deferral.arrivals.note(block.hash, now);
+			}
+		}
+
+		let now_ms = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+		if let Some((index, slot, release_at)) =
+			self.deferral.split_early(&blocks, now_ms as u64)
+		{
+			let early = blocks.split_off(index);
+			let hash = early[0].hash;
+			let parked = self.deferral.blocks.lock().expect("never poisoned; qed").park(
+				release_at,
+				origin,
+				early,
+			);
+			match parked {
+				Ok(()) => {
+					debug!(
+						target: LOG_TARGET,
+						"Deferring {:?} at slot {} for {:?}.",
+						hash,
+						slot,
+						release_at.saturating_duration_since(Instant::now()),
+					);
+					self.deferral.wake.notify_one();
+				},
+				// The verifier rejects them.
+				Err(early) => {
+					debug!(
+						target: LOG_TARGET,
+						"Not deferring {:?} at slot {}: too many blocks waiting for their slot.",
+						hash,
+						slot,
+					);
+					blocks.extend(early);
+				},
+			}
+		}
+
+		if !blocks.is_empty() {
+			self.inner.import_blocks(origin, blocks);
+		}
+	}
+
+	fn import_justifications(
+		&mut self,
+		who: RuntimeOrigin,
+		hash: B::Hash,
+		number: NumberFor<B>,
+		justifications: Justifications,
+	) {
+		self.inner.import_justifications(who, hash, number, justifications)
+	}
+}
+
+/// A [`BasicQueue`] holding back the blocks whose slot is ahead of the local clock within the
+/// [`DriftTolerance`], see the [module docs](self).
+pub struct DeferringImportQueue<B: BlockT> {
+	inner: BasicQueue<B>,
+	service: DeferringService<B>,
+}
+
+impl<B: BlockT> DeferringImportQueue<B> {
+	/// Defer the blocks handed to `inner`, returning the queue along with the task handing the
+	/// deferred blocks to it again once due, to spawn.
+	///
+	/// The arrival times of the blocks are noted in `arrivals`, for the verifier of `inner`.
+	pub(crate) fn new(
+		inner: BasicQueue<B>,
+		tolerance: DriftTolerance,
+		clock: SlotClock<B>,
+		arrivals: ArrivalTimes<B::Hash>,
+	) -> (Self, impl std::future::Future<Output = ()> + Send + 'static) {
+		let deferral = Arc::new(Deferral {
+			tolerance,
+			clock,
+			blocks: Mutex::new(DeferredBlocks::new(tolerance.max_deferred)),
+			arrivals,
+			wake: Notify::new(),
+		});
+		let service = DeferringService { inner: inner.service(), deferral: deferral.clone() };
+		let resubmit =
+			resubmit_deferred(DeferringService { inner: inner.service(), deferral });
+
+		(Self { inner, service }, resubmit)
+	}
+}
+
+#[async_trait::async_trait]
+impl<B: BlockT> ImportQueue<B> for DeferringImportQueue<B> {
+	fn service(&self) -> Box<dyn ImportQueueService<B>> {
+		Box::new(DeferringService {
+			inner: self.inner.service(),
+			deferral: self.service.deferral.clone(),
+		})
+	}
+
+	fn service_ref(&mut self) -> &mut dyn ImportQueueService<B> {
+		&mut self.service
+	}
+
+	fn poll_actions(&mut self, cx: &mut futures::task::Context, link: &dyn Link<B>) {
+		self.inner.poll_actions(cx, link)
+	}
+
+	async fn run(self, link: &dyn Link<B>) {
+		self.inner.run(link).await
+	}
+}
+
+/// Hand the deferred blocks to `service` again once due, deferring them again if the local clock
+/// is still behind.
+async fn resubmit_deferred<B: BlockT>(mut service: DeferringService<B>) {
+	loop {
+		let next_release =
+			service.deferral.blocks.lock().expect("never poisoned; qed").next_release();
+		match next_release {
+			Some(release_at) if release_at <= Instant::now() => {
+				let due = service
+					.deferral
+					.blocks
+					.lock()
+					.expect("never poisoned; qed")
+					.take_due(Instant::now());
+				for (origin, blocks) in due {
+					service.import_blocks(origin, blocks);
+				}
+			},
+			Some(release_at) => tokio::select! {
+				_ = tokio::time::sleep_until(release_at.into()) => {},
+				_ = service.deferral.wake.notified() => {},
+			},
+			None => service.deferral.wake.notified().await,
+		}
+	}
+}
+
+/// The [`Instant`] at which the wall clock reaches `unix_millis`, or now if it already has.
+fn instant_at_unix_millis(unix_millis: u64) -> Instant {
+	let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+	Instant::now() + Duration::from_millis(unix_millis).saturating_sub(now)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_consensus_slots::SlotDuration;
+	use sp_core::H256;
+	use sp_runtime::{
+		generic::{Block, Header},
+		traits::{BlakeTwo256, Header as _},
+		OpaqueExtrinsic,
+	};
+
+	#[test]
+	fn only_headers_inside_the_window_are_deferred() {
+		let tolerance = DriftTolerance { accepted_slots: 1, deferred_slots: 3, max_deferred: 1 };
+		let now = Slot::from(10);
+
+		assert!(!tolerance.should_defer(11.into(), now));
+		assert!(tolerance.should_defer(12.into(), now));
+		assert!(tolerance.should_defer(13.into(), now));
+		assert!(!tolerance.should_defer(14.into(), now));
+
+		let disabled = DriftTolerance { deferred_slots: 0, ..tolerance };
+		assert!(!disabled.should_defer(12.into(), now));
+	}
+
+	type TestBlock = Block<Header<u64, BlakeTwo256>, OpaqueExtrinsic>;
+
+	const SLOT_MS: u64 = 50;
+
+	fn block(number: u64) -> IncomingBlock<TestBlock> {
+		IncomingBlock {
+			hash: H256::from_low_u64_be(number),
+			header: Some(Header::new(
+				number,
+				Default::default(),
+				Default::default(),
+				Default::default(),
+				Default::default(),
+			)),
+			body: None,
+			indexed_body: None,
+			justifications: None,
+			origin: None,
+			allow_missing_state: false,
+			skip_execution: false,
+			import_existing: false,
+			state: None,
+		}
+	}
+
+	/// Blocks at the slot of their number.
+	fn deferral(max_deferred: usize) -> Arc<Deferral<TestBlock>> {
+		Arc::new(Deferral {
+			tolerance: DriftTolerance { accepted_slots: 1, deferred_slots: 4, max_deferred },
+			clock: SlotClock {
+				slot_of: Box::new(|header| Some(Slot::from(*header.number()))),
+				schedule: Box::new(|| {
+					SlotDurationSchedule::new(SlotDuration::from_millis(SLOT_MS))
+				}),
+			},
+			blocks: Mutex::new(DeferredBlocks::new(max_deferred)),
+			arrivals: Default::default(),
+			wake: Notify::new(),
+		})
+	}
+
+	/// Records the numbers of the blocks of every batch imported.
+	#[derive(Clone, Default)]
+	struct Imported(Arc<Mutex<Vec<Vec<u64>>>>);
+
+	impl Imported {
+		fn batches(&self) -> Vec<Vec<u64>> {
+			self.0.lock().unwrap().clone()
+		}
+	}
+
+	impl ImportQueueService<TestBlock> for Imported {
+		fn import_blocks(&mut self, _: BlockOrigin, blocks: Vec<IncomingBlock<TestBlock>>) {
+			let numbers = blocks.iter().map(|block| *block.header.as_ref().unwrap().number());
+			self.0.lock().unwrap().push(numbers.collect());
+		}
+
+		fn import_justifications(&mut self, _: RuntimeOrigin, _: H256, _: u64, _: Justifications) {
+		}
+	}
+
+	#[test]
+	fn blocks_are_split_at_the_first_one_to_defer() {
+		let deferral = deferral(8);
+		let now_ms = 10 * SLOT_MS;
+
+		let (index, slot, _) = deferral
+			.split_early(&[block(10), block(11), block(13), block(14)], now_ms)
+			.expect("13 is within the deferral window");
+		assert_eq!((index, slot), (2, 13.into()));
+
+		assert!(deferral.split_early(&[block(10), block(11)], now_ms).is_none());
+		assert!(deferral.split_early(&[block(10), block(15)], now_ms).is_none());
+	}
+
+	#[tokio::test]
+	async fn deferred_blocks_are_imported_once_due_without_holding_back_others() {
+		let imported = Imported::default();
+		let deferral = deferral(1);
+		let mut service =
+			DeferringService { inner: Box::new(imported.clone()), deferral: deferral.clone() };
+		tokio::spawn(resubmit_deferred(DeferringService {
+			inner: Box::new(imported.clone()),
+			deferral: deferral.clone(),
+		}));
+
+		let now_ms = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+		let slot_now = now_ms / SLOT_MS;
+		let blocks = vec![block(slot_now), block(slot_now + 3)];
+		service.import_blocks(BlockOrigin::NetworkBroadcast, blocks);
+		let handed_at = SystemTime::now();
+		assert_eq!(imported.batches(), vec![vec![slot_now]]);
+
+		// No room left to defer another block, which goes to the verifier right away.
+		service.import_blocks(BlockOrigin::NetworkBroadcast, vec![block(slot_now + 4)]);
+		assert_eq!(imported.batches(), vec![vec![slot_now], vec![slot_now + 4]]);
+
+		tokio::time::timeout(Duration::from_secs(1), async {
+			while imported.batches().len() < 3 {
+				tokio::time::sleep(Duration::from_millis(5)).await;
+			}
+		})
+		.await
+		.expect("the deferred block is imported once due");
+		assert_eq!(imported.batches()[2], vec![slot_now + 3]);
+		assert!(SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64 >=
+			(slot_now + 2) * SLOT_MS);
+
+		// The deferred block arrived when first handed to the queue, not when imported.
+		let deferred = block(slot_now + 3).hash;
+		assert!(deferral.arrivals.take(&deferred).is_some_and(|arrived| arrived <= handed_at));
+		assert_eq!(deferral.arrivals.take(&deferred), None);
+	}
+}
@@ -28,18 +28,31 @@ use futures::{
 use sc_transaction_pool_api::{InPoolTransaction, TransactionPool, TransactionPriority};
 use sp_runtime::traits::Block as BlockT;
 use std::{
+    fmt,
     marker::PhantomData,
     pin::Pin,
+    str::FromStr,
     sync::Arc,
     task::{Context, Poll},
     time::{Duration, Instant},
 };
 use log::{debug, info, warn};
 use tokio::time::{sleep, Sleep};
-use sc_consensus_slots::SlotTrigger;
+use sc_consensus_slots::{ProposalBudget, SlotDurationSource, SlotProportion, SlotTrigger};
+
+use crate::{
+    collection::{CollectionController, CollectionTargets},
+    ordering::TransactionArrivals,
+    promotion::{FuturePromotions, PromotedBundle, PROMOTION_CHECK_INTERVAL},
+    speculative::Speculation,
+};
 
 const LOG_TARGET: &str = "micc::event_driven";
 
+/// Bounds of the time the pool is polled for before subscribing again to its import notifications.
+const MIN_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESUBSCRIBE_DELAY: Duration = Duration::from_secs(60);
+
 /// Transaction pool events for event-driven block production.
 #[derive(Debug, Clone)]
 pub enum PoolEvent<Hash = TransactionHash> {
@@ -67,13 +80,11 @@ pub struct CollectionConfig {
     pub min_collection_time: Duration,
     /// Maximum collection time to wait for more transactions.
     pub max_collection_time: Duration,
-    /// Maximum number of transactions per block.
+    /// Maximum number of ready transactions waiting for the collection window to close.
     pub max_batch_size: usize,
     /// Priority threshold for immediate block production.
     pub priority_threshold: TransactionPriority,
-    /// Network load factor for dynamic timing (0.1 to 2.0).
-    pub network_load_factor: f64,
-    /// Enable adaptive collection windows based on transaction flow.
+    /// Let a [`CollectionController`] move the window length and batch size towards its targets.
     pub enable_adaptive_timing: bool,
 }
 
@@ -84,7 +95,6 @@ impl Default for CollectionConfig {
             max_collection_time: Duration::from_secs(2),
             max_batch_size: 1000,
             priority_threshold: TransactionPriority::MAX / 2, // High priority threshold for immediate production
-            network_load_factor: 1.0,
             enable_adaptive_timing: true,
         }
     }
@@ -99,8 +109,12 @@ pub struct EventDrivenConfig {
     pub empty_block_interval_ms: Option<u64>,
     /// Enable priority fast-track for high-priority transactions.
     pub enable_priority_fast_track: bool,
-    /// Historical transaction rate for adaptive timing.
-    pub transaction_rate_history_size: usize,
+    /// Targets of the [`CollectionController`] sizing the collection windows.
+    pub targets: CollectionTargets,
+    /// Proposal budget of the blocks produced immediately for high-priority transactions.
+    pub immediate_budget: ProposalBudget,
+    /// Proposal budget of the blocks produced for a batch of transactions.
+    pub batch_budget: ProposalBudget,
 }
 
 impl Default for EventDrivenConfig {
@@ -109,7 +123,44 @@ impl Default for EventDrivenConfig {
             collection: CollectionConfig::default(),
             empty_block_interval_ms: Some(3600000), // 1 hour
             enable_priority_fast_track: true,
-            transaction_rate_history_size: 10,
+            targets: CollectionTargets::default(),
+            immediate_budget: ProposalBudget::IMMEDIATE,
+            batch_budget: ProposalBudget::BATCH,
+        }
+    }
+}
+
+/// How blocks are requested from the slot worker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProductionMode {
+    /// Blocks are requested by the transactions, once their collection window closes, see
+    /// [`create_true_event_driven_stream`].
+    #[default]
+    EventDriven,
+    /// A block at every slot while transactions are ready, and an early one within the slot for
+    /// high-priority transactions, see [`create_hybrid_stream`].
+    Hybrid,
+}
+
+impl FromStr for ProductionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "event-driven" => Ok(Self::EventDriven),
+            "hybrid" => Ok(Self::Hybrid),
+            _ => Err(format!(
+                "`{s}` is not a production mode, expected `event-driven` or `hybrid`"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ProductionMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::EventDriven => write!(f, "event-driven"),
+            Self::Hybrid => write!(f, "hybrid"),
         }
     }
 }
@@ -162,6 +213,10 @@ where
     }
 
     /// Monitor transaction pool events in the background using true event-driven approach.
+    ///
+    /// Should the import notifications of the pool end, the pool is polled for a while before
+    /// subscribing to them again, the while doubling as long as the subscriptions keep ending
+    /// before notifying any import.
     async fn monitor_pool_events(
         pool: Arc<Pool>,
         sender: UnboundedSender<PoolEvent<<Pool as TransactionPool>::Hash>>,
@@ -170,124 +225,131 @@ where
     ) {
         info!(target: LOG_TARGET, "Starting true event-driven transaction pool monitoring");
         
-        // Get the import notification stream for immediate transaction notifications
-        let mut import_stream = pool.import_notification_stream();
-        let mut status_check_interval = tokio::time::interval(Duration::from_millis(500)); // Backup polling at 500ms
+        let mut resubscribe_delay = MIN_RESUBSCRIBE_DELAY;
+        'subscribe: loop {
+            // Get the import notification stream for immediate transaction notifications
+            let mut import_stream = pool.import_notification_stream();
+            let mut status_check_interval = tokio::time::interval(Duration::from_millis(500)); // Backup polling at 500ms
         
-        info!(target: LOG_TARGET, "Subscribing to transaction pool import events");
+            info!(target: LOG_TARGET, "Subscribing to transaction pool import events");
         
-        loop {
-            tokio::select! {
-                // PRIMARY: Immediate response to transaction import events
-                tx_hash = import_stream.next() => {
-                    match tx_hash {
-                        Some(hash) => {
-                            info!(target: LOG_TARGET, "Transaction imported to pool: {:?}", hash);
+            loop {
+                tokio::select! {
+                    // PRIMARY: Immediate response to transaction import events
+                    tx_hash = import_stream.next() => {
+                        match tx_hash {
+                            Some(hash) => {
+                                info!(target: LOG_TARGET, "Transaction imported to pool: {:?}", hash);
+                                resubscribe_delay = MIN_RESUBSCRIBE_DELAY;
                             
-                            // Send immediate notification of transaction added
-                            if sender.unbounded_send(PoolEvent::TransactionAdded(hash)).is_err() {
-                                warn!(target: LOG_TARGET, "Event sender closed, stopping pool monitoring");
-                                break;
-                            }
+                                // Send immediate notification of transaction added
+                                if sender.unbounded_send(PoolEvent::TransactionAdded(hash)).is_err() {
+                                    warn!(target: LOG_TARGET, "Event sender closed, stopping pool monitoring");
+                                    break 'subscribe;
+                                }
                             
-                            // Get current pool status and send update
-                            let status = pool.status();
-                            let ready_count = status.ready;
+                                // Get current pool status and send update
+                                let status = pool.status();
+                                let ready_count = status.ready;
                             
-                            // Update last status
-                            {
-                                let mut last = last_status.lock().unwrap();
-                                *last = ready_count;
-                            }
+                                // Update last status
+                                {
+                                    let mut last = last_status.lock().unwrap();
+                                    *last = ready_count;
+                                }
                             
-                            // Send pool ready event with current transaction count
-                            let event = if ready_count > 0 {
-                                PoolEvent::PoolReady(ready_count, None) // Priority detection would need deeper integration
-                            } else {
-                                PoolEvent::PoolEmpty
-                            };
+                                // Send pool ready event with current transaction count
+                                let event = if ready_count > 0 {
+                                    PoolEvent::PoolReady(ready_count, None) // Priority detection would need deeper integration
+                                } else {
+                                    PoolEvent::PoolEmpty
+                                };
                             
-                            if sender.unbounded_send(event).is_err() {
-                                warn!(target: LOG_TARGET, "Event sender closed, stopping pool monitoring");
+                                if sender.unbounded_send(event).is_err() {
+                                    warn!(target: LOG_TARGET, "Event sender closed, stopping pool monitoring");
+                                    break 'subscribe;
+                                }
+                            }
+                            None => {
+                                warn!(target: LOG_TARGET, "Transaction import stream ended, falling back to polling");
                                 break;
                             }
                         }
-                        None => {
-                            warn!(target: LOG_TARGET, "Transaction import stream ended, falling back to polling");
-                            break;
-                        }
                     }
-                }
                 
-                // BACKUP: Periodic status check to catch any missed events
-                _ = status_check_interval.tick() => {
-                    let status = pool.status();
-                    let ready_count = status.ready;
+                    // BACKUP: Periodic status check to catch any missed events
+                    _ = status_check_interval.tick() => {
+                        let status = pool.status();
+                        let ready_count = status.ready;
                     
-                    let last_count = {
-                        let mut last = last_status.lock().unwrap();
-                        let prev = *last;
-                        *last = ready_count;
-                        prev
-                    };
+                        let last_count = {
+                            let mut last = last_status.lock().unwrap();
+                            let prev = *last;
+                            *last = ready_count;
+                            prev
+                        };
                     
-                    // Only send update if status changed and we missed it
-                    if ready_count != last_count {
-                        debug!(target: LOG_TARGET, "Backup status check detected change: {} -> {} ready transactions", last_count, ready_count);
+                        // Only send update if status changed and we missed it
+                        if ready_count != last_count {
+                            debug!(target: LOG_TARGET, "Backup status check detected change: {} -> {} ready transactions", last_count, ready_count);
                         
-                        let event = if ready_count > 0 {
-                            PoolEvent::PoolReady(ready_count, None)
-                        } else {
-                            PoolEvent::PoolEmpty
-                        };
+                            let event = if ready_count > 0 {
+                                PoolEvent::PoolReady(ready_count, None)
+                            } else {
+                                PoolEvent::PoolEmpty
+                            };
                         
-                        if sender.unbounded_send(event).is_err() {
-                            warn!(target: LOG_TARGET, "Event sender closed, stopping pool monitoring");
-                            break;
+                            if sender.unbounded_send(event).is_err() {
+                                warn!(target: LOG_TARGET, "Event sender closed, stopping pool monitoring");
+                                break 'subscribe;
+                            }
                         }
                     }
                 }
             }
-        }
-        
-        // Fallback to polling-only mode if import stream fails
-        warn!(target: LOG_TARGET, "Falling back to polling-only transaction pool monitoring");
-        let mut polling_interval = tokio::time::interval(Duration::from_millis(100));
+            
+            // Fall back to polling until subscribing again to the import notifications
+            warn!(target: LOG_TARGET, "Polling the transaction pool for {:?} before subscribing again", resubscribe_delay);
+            let resubscribe_at = Instant::now() + resubscribe_delay;
+            resubscribe_delay = (resubscribe_delay * 2).min(MAX_RESUBSCRIBE_DELAY);
+            let mut polling_interval = tokio::time::interval(Duration::from_millis(100));
         
-        loop {
-            polling_interval.tick().await;
+            while Instant::now() < resubscribe_at {
+                polling_interval.tick().await;
             
-            let status = pool.status();
-            let ready_count = status.ready;
+                let status = pool.status();
+                let ready_count = status.ready;
             
-            let last_count = {
-                let mut last = last_status.lock().unwrap();
-                let prev = *last;
-                *last = ready_count;
-                prev
-            };
+                let last_count = {
+                    let mut last = last_status.lock().unwrap();
+                    let prev = *last;
+                    *last = ready_count;
+                    prev
+                };
             
-            if ready_count != last_count {
-                info!(target: LOG_TARGET, "Polling detected pool status change: {} -> {} ready transactions", last_count, ready_count);
+                if ready_count != last_count {
+                    info!(target: LOG_TARGET, "Polling detected pool status change: {} -> {} ready transactions", last_count, ready_count);
                 
-                if ready_count > last_count {
-                    // Create a default hash for the transaction added event when polling
-                    let default_hash = <Pool as TransactionPool>::Hash::default();
-                    if sender.unbounded_send(PoolEvent::TransactionAdded(default_hash)).is_err() {
-                        break;
+                    if ready_count > last_count {
+                        // Create a default hash for the transaction added event when polling
+                        let default_hash = <Pool as TransactionPool>::Hash::default();
+                        if sender.unbounded_send(PoolEvent::TransactionAdded(default_hash)).is_err() {
+                            break 'subscribe;
+                        }
                     }
-                }
                 
-                let event = if ready_count > 0 {
-                    PoolEvent::PoolReady(ready_count, None)
-                } else {
-                    PoolEvent::PoolEmpty
-                };
+                    let event = if ready_count > 0 {
+                        PoolEvent::PoolReady(ready_count, None)
+                    } else {
+                        PoolEvent::PoolEmpty
+                    };
                 
-                if sender.unbounded_send(event).is_err() {
-                    break;
+                    if sender.unbounded_send(event).is_err() {
+                        break 'subscribe;
+                    }
                 }
             }
+        
         }
         
         info!(target: LOG_TARGET, "Transaction pool event monitoring stopped");
@@ -299,90 +361,18 @@ where
 pub struct SmartCollectionWindow {
     /// Start time of the collection window.
     pub started_at: Instant,
-    /// Adaptive duration based on network conditions.
+    /// Duration of the window, as sized by the [`CollectionController`].
     pub duration: Duration,
     /// Number of transactions when window started.
     pub initial_tx_count: usize,
     /// Highest priority transaction in this window.
     pub highest_priority: Option<TransactionPriority>,
-    /// Network load factor at window start.
-    pub network_load: f64,
     /// Timer for the collection window.
     pub timer: Pin<Box<Sleep>>,
     /// Whether this window was triggered by a high-priority transaction.
     pub priority_triggered: bool,
 }
 
-/// Network load tracker for adaptive collection windows.
-#[derive(Debug)]
-pub struct NetworkLoadTracker {
-    /// Transaction timestamps for rate calculation.
-    transaction_history: Vec<Instant>,
-    /// Maximum history size.
-    max_history_size: usize,
-    /// Current transactions per second.
-    current_tps: f64,
-    /// Last calculation time.
-    last_calculation: Instant,
-}
-
-impl NetworkLoadTracker {
-    /// Create a new network load tracker with specified history size.
-    pub fn new(max_history_size: usize) -> Self {
-        Self {
-            transaction_history: Vec::new(),
-            max_history_size,
-            current_tps: 0.0,
-            last_calculation: Instant::now(),
-        }
-    }
-
-    /// Record a new transaction and calculate current TPS.
-    pub fn record_transaction(&mut self) -> f64 {
-        let now = Instant::now();
-        self.transaction_history.push(now);
-
-        // Keep only recent transactions within the last 10 seconds
-        let cutoff = now - Duration::from_secs(10);
-        self.transaction_history.retain(|&timestamp| timestamp > cutoff);
-
-        // Limit history size
-        if self.transaction_history.len() > self.max_history_size {
-            self.transaction_history.drain(0..self.transaction_history.len() - self.max_history_size);
-        }
-
-        // Calculate TPS if enough time has passed
-        if now.duration_since(self.last_calculation) > Duration::from_millis(500) {
-            self.current_tps = self.calculate_tps();
-            self.last_calculation = now;
-        }
-
-        self.current_tps
-    }
-
-    /// Get current transactions per second.
-    pub fn get_tps(&self) -> f64 {
-        self.current_tps
-    }
-
-    /// Calculate TPS from transaction history.
-    fn calculate_tps(&self) -> f64 {
-        if self.transaction_history.len() < 2 {
-            return 0.0;
-        }
-
-        let now = Instant::now();
-        let oldest = self.transaction_history[0];
-        let duration = now.duration_since(oldest).as_secs_f64();
-
-        if duration > 0.0 {
-            self.transaction_history.len() as f64 / duration
-        } else {
-            0.0
-        }
-    }
-}
-
 /// Legacy collection window for backwards compatibility.
 #[derive(Debug)]
 pub struct CollectionWindow {
@@ -397,19 +387,21 @@ pub struct CollectionWindow {
 }
 
 impl SmartCollectionWindow {
-    /// Create a new smart collection window with adaptive timing.
+    /// Create a new smart collection window lasting `duration`, or the minimum collection time
+    /// if triggered by a high-priority transaction.
     pub fn new(
         config: &CollectionConfig,
         initial_tx_count: usize,
         highest_priority: Option<TransactionPriority>,
-        network_load: f64,
+        duration: Duration,
         priority_triggered: bool,
     ) -> Self {
-        let duration = Self::calculate_adaptive_duration(config, network_load, highest_priority, priority_triggered);
+        let duration = if priority_triggered { config.min_collection_time } else { duration }
+            .clamp(config.min_collection_time, config.max_collection_time);
         
         info!(target: LOG_TARGET, 
-            "Creating smart collection window: duration={:?}, tx_count={}, priority={:?}, load={:.2}, priority_triggered={}", 
-            duration, initial_tx_count, highest_priority, network_load, priority_triggered
+            "Creating smart collection window: duration={:?}, tx_count={}, priority={:?}, priority_triggered={}", 
+            duration, initial_tx_count, highest_priority, priority_triggered
         );
 
         Self {
@@ -417,71 +409,11 @@ impl SmartCollectionWindow {
             duration,
             initial_tx_count,
             highest_priority,
-            network_load,
             timer: Box::pin(sleep(duration)),
             priority_triggered,
         }
     }
 
-    /// Calculate adaptive duration based on network conditions and priority.
-    fn calculate_adaptive_duration(
-        config: &CollectionConfig,
-        network_load: f64,
-        highest_priority: Option<TransactionPriority>,
-        priority_triggered: bool,
-    ) -> Duration {
-        let base_duration = if priority_triggered {
-            // Shorter window for priority transactions
-            config.min_collection_time
-        } else {
-            // Standard window duration
-            Duration::from_millis(
-                ((config.min_collection_time.as_millis() + config.max_collection_time.as_millis()) / 2) as u64
-            )
-        };
-
-        if !config.enable_adaptive_timing {
-            return base_duration.clamp(config.min_collection_time, config.max_collection_time);
-        }
-
-        // Adjust based on network load
-        let load_factor = if network_load > 5.0 {
-            // High load: shorter windows to process transactions faster
-            0.7
-        } else if network_load > 2.0 {
-            // Medium load: slightly shorter windows
-            0.85
-        } else if network_load < 0.5 {
-            // Low load: longer windows to batch more transactions
-            1.3
-        } else {
-            // Normal load
-            1.0
-        };
-
-        // Adjust for transaction priority
-        let priority_factor = if let Some(priority) = highest_priority {
-            if priority >= config.priority_threshold {
-                // Very high priority: immediate production
-                0.1
-            } else if priority > config.priority_threshold / 2 {
-                // High priority: shorter window
-                0.5
-            } else {
-                // Normal priority
-                1.0
-            }
-        } else {
-            1.0
-        };
-
-        let adjusted_duration = Duration::from_nanos(
-            (base_duration.as_nanos() as f64 * load_factor * priority_factor) as u64
-        );
-
-        adjusted_duration.clamp(config.min_collection_time, config.max_collection_time)
-    }
-
     /// Check if the collection window has expired.
     pub fn is_expired(&mut self) -> bool {
         matches!(self.timer.as_mut().poll(&mut Context::from_waker(&futures::task::noop_waker())), Poll::Ready(()))
@@ -545,7 +477,7 @@ pub struct SmartEventDrivenController<Block: BlockT> {
     empty_block_timer: Option<Pin<Box<Sleep>>>,
     pending_transactions: usize,
     last_block_time: Instant,
-    network_load_tracker: NetworkLoadTracker,
+    collection_controller: CollectionController,
     _phantom: PhantomData<Block>,
 }
 
@@ -560,8 +492,8 @@ pub struct EventDrivenController<Block: BlockT> {
 }
 
 impl<Block: BlockT> SmartEventDrivenController<Block> {
-    /// Create a new smart event-driven controller.
-    pub fn new(config: EventDrivenConfig) -> Self {
+    /// Create a new smart event-driven controller, sizing its windows with `collection_controller`.
+    pub fn new(config: EventDrivenConfig, collection_controller: CollectionController) -> Self {
         let empty_block_timer = config.empty_block_interval_ms.map(|interval_ms| {
             Box::pin(sleep(Duration::from_millis(interval_ms)))
         });
@@ -572,7 +504,7 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
             empty_block_timer,
             pending_transactions: 0,
             last_block_time: Instant::now(),
-            network_load_tracker: NetworkLoadTracker::new(config.transaction_rate_history_size),
+            collection_controller,
             _phantom: PhantomData,
         }
     }
@@ -581,14 +513,12 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
     pub fn handle_pool_event(&mut self, event: PoolEvent) -> BlockProductionTrigger {
         match event {
             PoolEvent::TransactionAdded(_) => {
-                let tps = self.network_load_tracker.record_transaction();
-                debug!(target: LOG_TARGET, "Transaction added, current TPS: {:.2}", tps);
+                debug!(target: LOG_TARGET, "Transaction added");
                 BlockProductionTrigger::None
             },
             
             PoolEvent::HighPriorityTransactionAdded(_, priority) => {
-                let tps = self.network_load_tracker.record_transaction();
-                info!(target: LOG_TARGET, "High priority transaction added: priority={}, TPS={:.2}", priority, tps);
+                info!(target: LOG_TARGET, "High priority transaction added: priority={}", priority);
                 
                 // Update existing window or trigger immediate production
                 if let Some(ref mut window) = self.collection_window {
@@ -601,19 +531,18 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
                     }
                 } else {
                     // Start new priority window
-                    return self.start_collection_window_for_priority(priority, tps);
+                    return self.start_collection_window_for_priority(priority);
                 }
                 
                 BlockProductionTrigger::None
             },
             
             PoolEvent::PoolReady(count, highest_priority) => {
-                let tps = self.network_load_tracker.record_transaction();
                 self.pending_transactions = count;
                 
                 // Start collection window if not already active
                 if self.collection_window.is_none() && count > 0 {
-                    info!(target: LOG_TARGET, "Starting collection window for {} transactions (TPS: {:.2})", count, tps);
+                    info!(target: LOG_TARGET, "Starting collection window for {} transactions", count);
                     
                     let priority_triggered = highest_priority
                         .map(|p| p >= self.config.collection.priority_threshold / 2)
@@ -623,7 +552,7 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
                         &self.config.collection,
                         count,
                         highest_priority,
-                        tps,
+                        self.collection_controller.window(),
                         priority_triggered,
                     ));
                     
@@ -663,7 +592,6 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
     fn start_collection_window_for_priority(
         &mut self, 
         priority: TransactionPriority, 
-        network_load: f64
     ) -> BlockProductionTrigger {
         // Immediate production for very high priority
         if priority >= self.config.collection.priority_threshold {
@@ -676,7 +604,7 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
             &self.config.collection,
             self.pending_transactions,
             Some(priority),
-            network_load,
+            self.collection_controller.window(),
             true, // priority_triggered
         ));
         
@@ -738,8 +666,9 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
     /// Check if we should produce a block immediately.
     fn should_produce_immediately(&self, tx_count: usize, highest_priority: Option<TransactionPriority>) -> bool {
         // Produce immediately if we have a large batch
-        if tx_count >= self.config.collection.max_batch_size {
-            info!(target: LOG_TARGET, "Large batch detected: {} >= {}", tx_count, self.config.collection.max_batch_size);
+        let batch_threshold = self.collection_controller.batch_threshold();
+        if tx_count >= batch_threshold {
+            info!(target: LOG_TARGET, "Large batch detected: {} >= {}", tx_count, batch_threshold);
             return true;
         }
         
@@ -761,11 +690,6 @@ impl<Block: BlockT> SmartEventDrivenController<Block> {
         
         false
     }
-    
-    /// Get current network load (TPS).
-    pub fn get_network_load(&self) -> f64 {
-        self.network_load_tracker.get_tps()
-    }
 }
 
 impl<Block: BlockT> EventDrivenController<Block> {
@@ -958,9 +882,27 @@ impl BlockProductionTrigger {
 
 /// Create a true event-driven stream using transaction pool import notifications.
 /// This replaces polling with immediate response to transaction imports.
+///
+/// The imports and triggers are recorded in `arrivals` if given, for the block proposer to order
+/// transactions by, see [`crate::OrderedPool`].
+///
+/// With a `speculation`, the block is prepared as soon as a collection window opens and sealed
+/// once it closes, see [`crate::SpeculativeProposerFactory`].
+///
+/// The collection windows are sized by `controller` if given, by the number of ready transactions
+/// otherwise.
+///
+/// Transactions promoted from the future queue to the ready one are triggers as well, their window
+/// opening when they were first seen in the future queue. A window is held open, for
+/// `max_collection_time` at most, while transactions recently seen in the future queue still wait
+/// there for senders of the ready transactions, so as not to cut the nonce chains of these senders
+/// in half.
 pub fn create_true_event_driven_stream<Block, Pool>(
     pool: Arc<Pool>,
     config: EventDrivenConfig,
+    arrivals: Option<TransactionArrivals<<Pool as TransactionPool>::Hash>>,
+    speculation: Option<Speculation>,
+    controller: Option<CollectionController>,
 ) -> Pin<Box<dyn Stream<Item = SlotTrigger> + Send + 'static>>
 where
     Block: sp_runtime::traits::Block,
@@ -972,16 +914,22 @@ where
     
     let pool_clone = pool.clone();
     let config_clone = config.clone();
+    let arrivals_clone = arrivals.clone();
+    let speculation_clone = speculation.clone();
+    let controller_clone = controller.clone();
     
     info!(target: LOG_TARGET, "Starting true event-driven transaction monitoring with import notifications");
     
     // Use unfold to create a true event-driven monitoring stream
     let initial_status = pool.status().ready; // Initialize with current pool status to avoid false positives
     Box::pin(futures::stream::unfold(
-        (pool.import_notification_stream(), initial_status, None::<Instant>, tokio::time::interval(Duration::from_millis(500))),
-        move |(mut import_stream, mut last_status, mut collection_timer, mut backup_interval)| {
+        (pool.import_notification_stream(), initial_status, None::<Instant>, tokio::time::interval(PROMOTION_CHECK_INTERVAL), FuturePromotions::default()),
+        move |(mut import_stream, mut last_status, mut collection_timer, mut backup_interval, mut promotions)| {
             let pool = pool_clone.clone();
             let config = config_clone.clone();
+            let arrivals = arrivals_clone.clone();
+            let speculation = speculation_clone.clone();
+            let controller = controller_clone.clone();
             
             async move {
                 tokio::select! {
@@ -990,7 +938,11 @@ where
                         match tx_hash {
                             Some(hash) => {
                                 info!(target: LOG_TARGET, "Transaction import detected: {:?}", hash);
+                                if let Some(arrivals) = &arrivals {
+                                    arrivals.record(hash);
+                                }
                                 
+                                let bundles = check_promotions(&*pool, &mut promotions, arrivals.as_ref());
                                 let status = pool.status();
                                 let ready_count = status.ready;
                                 last_status = ready_count;
@@ -1007,55 +959,81 @@ where
                                     if highest_priority >= config.collection.priority_threshold {
                                         info!(target: LOG_TARGET, "High-priority transaction detected (priority: {}), producing block immediately", highest_priority);
                                         collection_timer = None;
-                                        return Some((SlotTrigger::CreateBlock, (import_stream, last_status, collection_timer, backup_interval)));
+                                        return Some((SlotTrigger::CreateBlock(config.immediate_budget), (import_stream, last_status, collection_timer, backup_interval, promotions)));
                                     }
                                     
                                     // Start collection window if not already active (for non-high-priority transactions)
                                     if collection_timer.is_none() {
-                                        let collection_duration = calculate_collection_duration(&config, ready_count);
-                                        collection_timer = Some(Instant::now() + collection_duration);
+                                        let collection_duration = window_duration(&config, controller.as_ref(), ready_count);
+                                        let expires = window_opening(&bundles) + collection_duration;
+                                        collection_timer = Some(expires);
                                         info!(target: LOG_TARGET, "Starting collection window for {}ms with {} ready transactions (highest priority: {})", 
                                             collection_duration.as_millis(), ready_count, highest_priority);
                                         
                                         // Check if we should produce immediately for large batches
-                                        if ready_count >= config.collection.max_batch_size {
+                                        if ready_count >= batch_threshold(&config, controller.as_ref()) {
                                             info!(target: LOG_TARGET, "Large batch detected ({} transactions), producing block immediately", ready_count);
                                             collection_timer = None;
-                                            return Some((SlotTrigger::CreateBlock, (import_stream, last_status, collection_timer, backup_interval)));
+                                            return Some((SlotTrigger::CreateBlock(config.batch_budget), (import_stream, last_status, collection_timer, backup_interval, promotions)));
+                                        }
+
+                                        if let Some(speculation) = &speculation {
+                                            speculation.open();
+                                            let budget = prepare_budget(&config, expires.saturating_duration_since(Instant::now()));
+                                            return Some((SlotTrigger::Prepare(budget), (import_stream, last_status, collection_timer, backup_interval, promotions)));
                                         }
                                     }
                                 }
                                 
-                                return Some((SlotTrigger::NoAction, (import_stream, last_status, collection_timer, backup_interval)));
+                                return Some((SlotTrigger::NoAction, (import_stream, last_status, collection_timer, backup_interval, promotions)));
                             }
                             None => {
-                                warn!(target: LOG_TARGET, "Import stream ended, falling back to polling");
-                                // Fall through to backup polling
+                                // End the stream, for a `TriggerSupervisor` to start it again
+                                warn!(target: LOG_TARGET, "Import stream ended, ending the trigger stream");
+                                if let Some(speculation) = &speculation {
+                                    speculation.abandon();
+                                }
                             }
                         }
                     }
                     
                     // BACKUP: Periodic status check to catch any missed events
                     _ = backup_interval.tick() => {
+                        let bundles = check_promotions(&*pool, &mut promotions, arrivals.as_ref());
                         let status = pool.status();
                         let ready_count = status.ready;
                         
                         // Check if collection window has expired
                         if let Some(expire_time) = collection_timer {
-                            if Instant::now() >= expire_time {
+                            // Hold the window while transactions of its senders wait in the future queue
+                            // for the one they depend on, rather than cutting their nonce chain in half
+                            let now = Instant::now();
+                            let max_hold = config.collection.max_collection_time;
+                            let holding = now >= expire_time && now < expire_time + max_hold && {
+                                let window: Vec<_> = pool.ready().collect();
+                                promotions.waiting_within(now, max_hold, window.iter().flat_map(|tx| tx.provides()))
+                            };
+                            if holding && now >= expire_time {
+                                debug!(target: LOG_TARGET, "Holding the collection window for transactions waiting in the future queue");
+                            }
+
+                            if now >= expire_time && !holding {
                                 if ready_count > 0 {
                                     info!(target: LOG_TARGET, "Collection window expired, producing block with {} transactions", ready_count);
                                     collection_timer = None;
                                     last_status = ready_count;
-                                    return Some((SlotTrigger::CreateBlock, (import_stream, last_status, collection_timer, backup_interval)));
+                                    return Some((SlotTrigger::CreateBlock(config.batch_budget), (import_stream, last_status, collection_timer, backup_interval, promotions)));
                                 } else {
                                     collection_timer = None;
+                                    if let Some(speculation) = &speculation {
+                                        speculation.abandon();
+                                    }
                                 }
                             }
                         }
                         
                         // Detect status changes that might have been missed
-                        if ready_count != last_status {
+                        if ready_count != last_status || !bundles.is_empty() {
                             debug!(target: LOG_TARGET, "Backup check detected pool status change: {} -> {} ready transactions", last_status, ready_count);
                             last_status = ready_count;
                             
@@ -1069,33 +1047,55 @@ where
                                 
                                 if highest_priority >= config.collection.priority_threshold {
                                     info!(target: LOG_TARGET, "Backup check: High-priority transaction detected (priority: {}), producing block immediately", highest_priority);
-                                    return Some((SlotTrigger::CreateBlock, (import_stream, last_status, collection_timer, backup_interval)));
+                                    return Some((SlotTrigger::CreateBlock(config.immediate_budget), (import_stream, last_status, collection_timer, backup_interval, promotions)));
                                 }
                                 
-                                let collection_duration = calculate_collection_duration(&config, ready_count);
-                                collection_timer = Some(Instant::now() + collection_duration);
+                                let collection_duration = window_duration(&config, controller.as_ref(), ready_count);
+                                let expires = window_opening(&bundles) + collection_duration;
+                                collection_timer = Some(expires);
                                 info!(target: LOG_TARGET, "Backup check started collection window for {}ms (highest priority: {})", collection_duration.as_millis(), highest_priority);
+
+                                if let Some(speculation) = &speculation {
+                                    speculation.open();
+                                    let budget = prepare_budget(&config, expires.saturating_duration_since(Instant::now()));
+                                    return Some((SlotTrigger::Prepare(budget), (import_stream, last_status, collection_timer, backup_interval, promotions)));
+                                }
                             } else if ready_count == 0 && collection_timer.is_some() {
                                 info!(target: LOG_TARGET, "Backup check: pool became empty, canceling collection window");
                                 collection_timer = None;
+                                if let Some(speculation) = &speculation {
+                                    speculation.abandon();
+                                }
                             }
                         }
                         
-                        return Some((SlotTrigger::NoAction, (import_stream, last_status, collection_timer, backup_interval)));
+                        return Some((SlotTrigger::NoAction, (import_stream, last_status, collection_timer, backup_interval, promotions)));
                     }
                 }
                 
-                // This should not be reached, but included for completeness
+                // The import stream ended
                 None
             }
         }
-    ).filter_map(|trigger| async move {
-        match trigger {
-            SlotTrigger::CreateBlock => {
-                info!(target: LOG_TARGET, "Emitting CreateBlock trigger from true event-driven stream");
-                Some(SlotTrigger::CreateBlock)
+    ).filter_map(move |trigger| {
+        let arrivals = arrivals.clone();
+        let speculation = speculation.clone();
+        async move {
+            match trigger {
+                SlotTrigger::CreateBlock(budget) => {
+                    info!(target: LOG_TARGET, "Emitting CreateBlock trigger from true event-driven stream");
+                    if let Some(arrivals) = arrivals {
+                        arrivals.mark_trigger();
+                    }
+                    if let Some(speculation) = speculation {
+                        speculation.seal();
+                    }
+                    Some(SlotTrigger::CreateBlock(budget))
+                }
+                SlotTrigger::Prepare(budget) => Some(SlotTrigger::Prepare(budget)),
+                SlotTrigger::RuntimeRequired(budget) => Some(SlotTrigger::RuntimeRequired(budget)),
+                SlotTrigger::NoAction => None,
             }
-            SlotTrigger::NoAction => None,
         }
     }))
 }
@@ -1155,7 +1155,7 @@ where
                         if highest_priority >= config.collection.priority_threshold {
                             info!(target: LOG_TARGET, "High-priority transaction detected (priority: {}), producing block immediately", highest_priority);
                             collection_timer = None;
-                            return Some((SlotTrigger::CreateBlock, (last_status, collection_timer, interval)));
+                            return Some((SlotTrigger::CreateBlock(config.immediate_budget), (last_status, collection_timer, interval)));
                         }
                         
                         let collection_duration = calculate_collection_duration(&config, ready_count);
@@ -1167,7 +1167,7 @@ where
                         if ready_count >= config.collection.max_batch_size {
                             info!(target: LOG_TARGET, "Large batch detected ({} transactions), producing block immediately", ready_count);
                             collection_timer = None;
-                            return Some((SlotTrigger::CreateBlock, (last_status, collection_timer, interval)));
+                            return Some((SlotTrigger::CreateBlock(config.batch_budget), (last_status, collection_timer, interval)));
                         }
                     } else if ready_count == 0 && collection_timer.is_some() {
                         info!(target: LOG_TARGET, "Pool became empty, canceling collection window");
@@ -1181,7 +1181,7 @@ where
                         if ready_count > 0 {
                             info!(target: LOG_TARGET, "Collection window expired, producing block with {} transactions", ready_count);
                             collection_timer = None;
-                            return Some((SlotTrigger::CreateBlock, (last_status, collection_timer, interval)));
+                            return Some((SlotTrigger::CreateBlock(config.batch_budget), (last_status, collection_timer, interval)));
                         } else {
                             collection_timer = None;
                         }
@@ -1194,15 +1194,75 @@ where
         }
     ).filter_map(|trigger| async move {
         match trigger {
-            SlotTrigger::CreateBlock => {
+            SlotTrigger::CreateBlock(budget) => {
                 info!(target: LOG_TARGET, "Emitting CreateBlock trigger");
-                Some(SlotTrigger::CreateBlock)
+                Some(SlotTrigger::CreateBlock(budget))
             }
-            SlotTrigger::NoAction => None,
+            SlotTrigger::RuntimeRequired(budget) => Some(SlotTrigger::RuntimeRequired(budget)),
+            SlotTrigger::Prepare(_) | SlotTrigger::NoAction => None,
         }
     }))
 }
 
+/// Check the future queue of `pool` for promotions, recording the transactions first seen there in
+/// `arrivals`.
+fn check_promotions<Pool: TransactionPool>(
+    pool: &Pool,
+    promotions: &mut FuturePromotions<<Pool as TransactionPool>::Hash>,
+    arrivals: Option<&TransactionArrivals<<Pool as TransactionPool>::Hash>>,
+) -> Vec<PromotedBundle<<Pool as TransactionPool>::Hash>> {
+    if pool.status().future == 0 && promotions.is_empty() {
+        return Vec::new()
+    }
+
+    let futures = pool.futures();
+    if let Some(arrivals) = arrivals {
+        for tx in &futures {
+            arrivals.record(tx.hash().clone());
+        }
+    }
+
+    let bundles = promotions.refresh(&futures, pool.ready(), Instant::now());
+    for bundle in &bundles {
+        info!(
+            target: LOG_TARGET,
+            "{} transactions promoted from the future queue, first seen {:?} ago",
+            bundle.transactions.len(),
+            bundle.first_seen.elapsed(),
+        );
+    }
+    bundles
+}
+
+/// When a collection window opened for `bundles` starts: when their first transaction was seen,
+/// now if none was promoted.
+fn window_opening<H>(bundles: &[PromotedBundle<H>]) -> Instant {
+    bundles.iter().map(|bundle| bundle.first_seen).min().unwrap_or_else(Instant::now)
+}
+
+/// Budget of a block prepared while a collection window of `collection_duration` is open: the
+/// window itself, then the budget of a batch.
+fn prepare_budget(config: &EventDrivenConfig, collection_duration: Duration) -> ProposalBudget {
+    ProposalBudget {
+        proposing: collection_duration + config.batch_budget.proposing,
+        block_size_limit: config.batch_budget.block_size_limit,
+    }
+}
+
+/// Length of a collection window opened with `ready_count` transactions.
+fn window_duration(
+    config: &EventDrivenConfig,
+    controller: Option<&CollectionController>,
+    ready_count: usize,
+) -> Duration {
+    controller.map_or_else(|| calculate_collection_duration(config, ready_count), |c| c.window())
+}
+
+/// Number of ready transactions producing a block without waiting for the window to close.
+fn batch_threshold(config: &EventDrivenConfig, controller: Option<&CollectionController>) -> usize {
+    controller.map_or(config.collection.max_batch_size, |c| c.batch_threshold())
+}
+
 /// Calculate optimal collection window duration based on transaction count and configuration.
 fn calculate_collection_duration(config: &EventDrivenConfig, tx_count: usize) -> Duration {
     let base_duration = Duration::from_millis(1000); // 1 second
@@ -1223,10 +1283,11 @@ fn calculate_collection_duration(config: &EventDrivenConfig, tx_count: usize) ->
     duration.clamp(min_duration, max_duration)
 }
 
-/// Create a smart event-driven stream with adaptive collection windows.
+/// Create a smart event-driven stream with collection windows sized by `controller`.
 pub fn create_smart_event_driven_stream<Block, Pool>(
     pool: Arc<Pool>,
     config: EventDrivenConfig,
+    controller: CollectionController,
 ) -> Pin<Box<dyn Stream<Item = SlotTrigger> + Send + 'static>>
 where
     Block: sp_runtime::traits::Block,
@@ -1246,10 +1307,11 @@ where
     // Use unfold to create a smart monitoring stream
     let initial_status = pool.status().ready;
     Box::pin(futures::stream::unfold(
-        (initial_status, None::<SmartCollectionWindow>, interval, NetworkLoadTracker::new(config.transaction_rate_history_size)),
-        move |(mut last_status, mut collection_window, mut interval, mut load_tracker)| {
+        (initial_status, None::<SmartCollectionWindow>, interval),
+        move |(mut last_status, mut collection_window, mut interval)| {
             let pool = pool_clone.clone();
             let config = config_clone.clone();
+            let controller = controller.clone();
             
             async move {
                 // Wait for next tick
@@ -1259,17 +1321,10 @@ where
                 let status = pool.status();
                 let ready_count = status.ready;
                 
-                // Record transaction activity and calculate load
-                let current_tps = if ready_count != last_status {
-                    load_tracker.record_transaction()
-                } else {
-                    load_tracker.get_tps()
-                };
-                
                 // Detect status changes
                 if ready_count != last_status {
-                    info!(target: LOG_TARGET, "Pool status changed: {} -> {} ready transactions (TPS: {:.2})", 
-                        last_status, ready_count, current_tps);
+                    info!(target: LOG_TARGET, "Pool status changed: {} -> {} ready transactions", 
+                        last_status, ready_count);
                     last_status = ready_count;
                     
                     // Start smart collection window if we now have transactions and no active window
@@ -1284,7 +1339,7 @@ where
                             if priority >= config.collection.priority_threshold {
                                 info!(target: LOG_TARGET, "High-priority transaction detected (priority: {}), producing block immediately", priority);
                                 collection_window = None;
-                                return Some((SlotTrigger::CreateBlock, (last_status, collection_window, interval, load_tracker)));
+                                return Some((SlotTrigger::CreateBlock(config.immediate_budget), (last_status, collection_window, interval)));
                             }
                         }
                         
@@ -1292,16 +1347,16 @@ where
                             &config.collection,
                             ready_count,
                             highest_priority,
-                            current_tps,
+                            controller.window(),
                             false, // Not priority triggered by default
                         );
                         collection_window = Some(smart_window);
                         
                         // Check if we should produce immediately for large batches
-                        if ready_count >= config.collection.max_batch_size {
+                        if ready_count >= controller.batch_threshold() {
                             info!(target: LOG_TARGET, "Large batch detected ({} transactions), producing block immediately", ready_count);
                             collection_window = None;
-                            return Some((SlotTrigger::CreateBlock, (last_status, collection_window, interval, load_tracker)));
+                            return Some((SlotTrigger::CreateBlock(config.batch_budget), (last_status, collection_window, interval)));
                         }
                     } else if ready_count == 0 && collection_window.is_some() {
                         info!(target: LOG_TARGET, "Pool became empty, canceling smart collection window");
@@ -1314,11 +1369,11 @@ where
                     if window.is_expired() {
                         if ready_count > 0 {
                             info!(target: LOG_TARGET, 
-                                "Smart collection window expired after {:?}, producing block with {} transactions (priority: {:?}, load: {:.2})", 
-                                window.elapsed(), ready_count, window.highest_priority, window.network_load
+                                "Smart collection window expired after {:?}, producing block with {} transactions (priority: {:?})", 
+                                window.elapsed(), ready_count, window.highest_priority
                             );
                             collection_window = None;
-                            return Some((SlotTrigger::CreateBlock, (last_status, collection_window, interval, load_tracker)));
+                            return Some((SlotTrigger::CreateBlock(config.batch_budget), (last_status, collection_window, interval)));
                         } else {
                             collection_window = None;
                         }
@@ -1326,20 +1381,160 @@ where
                 }
                 
                 // Continue monitoring
-                Some((SlotTrigger::NoAction, (last_status, collection_window, interval, load_tracker)))
+                Some((SlotTrigger::NoAction, (last_status, collection_window, interval)))
             }
         }
     ).filter_map(|trigger| async move {
         match trigger {
-            SlotTrigger::CreateBlock => {
+            SlotTrigger::CreateBlock(budget) => {
                 info!(target: LOG_TARGET, "Emitting CreateBlock trigger from smart collection");
-                Some(SlotTrigger::CreateBlock)
+                Some(SlotTrigger::CreateBlock(budget))
             }
-            SlotTrigger::NoAction => None,
+            SlotTrigger::RuntimeRequired(budget) => Some(SlotTrigger::RuntimeRequired(budget)),
+            SlotTrigger::Prepare(_) | SlotTrigger::NoAction => None,
         }
     }))
 }
 
+/// The next import notified by `stream`, `None` once it ended, after which it is dropped and no
+/// import is ever notified.
+async fn next_import<S: Stream + Unpin>(stream: &mut Option<S>) -> Option<S::Item> {
+    let Some(imports) = stream else { return futures::future::pending().await };
+    let import = imports.next().await;
+    if import.is_none() {
+        warn!(target: LOG_TARGET, "Transaction pool import stream ended");
+        *stream = None;
+    }
+    import
+}
+
+/// The slots [`create_hybrid_stream`] already requested a block for.
+#[derive(Debug, Default)]
+struct HybridSlots {
+    /// End of the last slot a block was requested for.
+    requested_until: Option<Instant>,
+}
+
+impl HybridSlots {
+    /// Request a block at `now` for the slot ending at `slot_end`, unless one already was.
+    fn request(&mut self, now: Instant, slot_end: Instant) -> bool {
+        if self.requested_until.map_or(false, |until| now < until) {
+            return false
+        }
+
+        self.requested_until = Some(slot_end);
+        true
+    }
+}
+
+/// Create a hybrid stream, requesting a block at every slot while transactions are ready, and an
+/// early one within the slot for high-priority transactions.
+///
+/// The regular blocks are requested as the slots start and proposed within
+/// `block_proposal_slot_portion` of the slot, whose length `slot_durations` gives as it starts.
+/// Nothing is requested for the slots starting with an empty pool, the heartbeat of the slot worker
+/// covering idle chains. A ready transaction reaching the priority threshold of `config` requests
+/// the block of the current slot right away, within [`EventDrivenConfig::immediate_budget`],
+/// unless the slot already had one or ends before that budget runs out. Which of these slots the
+/// node owns is left to the slot worker, claiming them.
+///
+/// The stream never ends: should the import notifications of the pool end, it subscribes to them
+/// again at the next slot.
+///
+/// The imports and triggers are recorded in `arrivals` if given, see [`crate::OrderedPool`].
+pub fn create_hybrid_stream<Block, Pool, SD>(
+    pool: Arc<Pool>,
+    config: EventDrivenConfig,
+    slot_durations: Arc<SD>,
+    block_proposal_slot_portion: SlotProportion,
+    arrivals: Option<TransactionArrivals<<Pool as TransactionPool>::Hash>>,
+) -> Pin<Box<dyn Stream<Item = SlotTrigger> + Send + 'static>>
+where
+    Block: BlockT,
+    Pool: TransactionPool<Block = Block> + 'static,
+    SD: SlotDurationSource<Block> + ?Sized + 'static,
+{
+    const LOG_TARGET: &str = "micc::hybrid";
+
+    info!(target: LOG_TARGET, "Hybrid stream initialized with config: {:?}", config);
+
+    let proposal_portion = block_proposal_slot_portion.get();
+    let trigger_arrivals = arrivals.clone();
+    Box::pin(futures::stream::unfold(
+        (Some(pool.import_notification_stream()), HybridSlots::default()),
+        move |(mut import_stream, mut slots)| {
+            let pool = pool.clone();
+            let config = config.clone();
+            let slot_durations = slot_durations.clone();
+            let arrivals = arrivals.clone();
+
+            async move {
+                let trigger = tokio::select! {
+                    Some(hash) = next_import(&mut import_stream) => {
+                        if let Some(arrivals) = &arrivals {
+                            arrivals.record(hash);
+                        }
+
+                        let until_next_slot = slot_durations.time_until_next_slot();
+                        let highest_priority = pool.ready().map(|tx| *tx.priority()).max();
+                        let threshold = config.collection.priority_threshold;
+                        let urgent = config.enable_priority_fast_track &&
+                            highest_priority.map_or(false, |p| p >= threshold) &&
+                            until_next_slot >= config.immediate_budget.proposing;
+
+                        let now = Instant::now();
+                        if urgent && slots.request(now, now + until_next_slot) {
+                            info!(target: LOG_TARGET, "High-priority transaction detected (priority: {:?}), producing block within the slot", highest_priority);
+                            SlotTrigger::CreateBlock(config.immediate_budget)
+                        } else {
+                            SlotTrigger::NoAction
+                        }
+                    }
+                    _ = sleep(slot_durations.time_until_next_slot()) => {
+                        if import_stream.is_none() {
+                            debug!(target: LOG_TARGET, "Subscribing again to transaction pool imports");
+                            import_stream = Some(pool.import_notification_stream());
+                        }
+
+                        let ready_count = pool.status().ready;
+                        let now = Instant::now();
+                        // The slot just started, so it lasts until the next one.
+                        let slot_length = slot_durations.time_until_next_slot();
+                        if ready_count > 0 && slots.request(now, now + slot_length) {
+                            debug!(target: LOG_TARGET, "New slot with {} ready transactions, producing block", ready_count);
+                            SlotTrigger::CreateBlock(slot_budget(slot_length, proposal_portion))
+                        } else {
+                            SlotTrigger::NoAction
+                        }
+                    }
+                };
+
+                Some((trigger, (import_stream, slots)))
+            }
+        }
+    ).filter_map(move |trigger| {
+        let arrivals = trigger_arrivals.clone();
+        async move {
+            match trigger {
+                SlotTrigger::CreateBlock(budget) => {
+                    if let Some(arrivals) = arrivals {
+                        arrivals.mark_trigger();
+                    }
+                    Some(SlotTrigger::CreateBlock(budget))
+                }
+                SlotTrigger::RuntimeRequired(budget) => Some(SlotTrigger::RuntimeRequired(budget)),
+                SlotTrigger::Prepare(_) | SlotTrigger::NoAction => None,
+            }
+        }
+    }))
+}
+
+/// Budget of the blocks requested at the start of a slot lasting `slot_length`, proposed within
+/// `portion` of it.
+fn slot_budget(slot_length: Duration, portion: f32) -> ProposalBudget {
+    ProposalBudget { proposing: slot_length.mul_f32(portion), block_size_limit: None }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -1428,45 +1623,28 @@ mod tests {
         let config = CollectionConfig::default();
         
         // Test normal collection window
-        let window = SmartCollectionWindow::new(&config, 10, None, 1.0, false);
+        let window = SmartCollectionWindow::new(&config, 10, None, Duration::from_secs(1), false);
         assert_eq!(window.initial_tx_count, 10);
-        assert!(window.network_load > 0.0);
+        assert_eq!(window.duration, Duration::from_secs(1));
         assert!(!window.priority_triggered);
         
         // Test priority collection window
-        let priority_window = SmartCollectionWindow::new(&config, 5, Some(1000), 2.0, true);
+        let priority_window =
+            SmartCollectionWindow::new(&config, 5, Some(1000), Duration::from_secs(1), true);
         assert_eq!(priority_window.initial_tx_count, 5);
         assert_eq!(priority_window.highest_priority, Some(1000));
         assert!(priority_window.priority_triggered);
     }
 
-    #[test]
-    fn test_network_load_tracker() {
-        let mut tracker = NetworkLoadTracker::new(100);
-        
-        // Test initial state
-        assert_eq!(tracker.get_tps(), 0.0);
-        
-        // Test recording transactions
-        let tps = tracker.record_transaction();
-        assert!(tps >= 0.0);
-        
-        // Test multiple transactions
-        for _ in 0..5 {
-            tracker.record_transaction();
-        }
-        
-        let final_tps = tracker.get_tps();
-        assert!(final_tps >= 0.0);
-    }
-
     #[test]
     fn test_smart_event_driven_controller() {
         use sp_runtime::{generic::Header, traits::BlakeTwo256, testing::UncheckedExtrinsic};
         type TestBlock = sp_runtime::generic::Block<Header<u32, BlakeTwo256>, UncheckedExtrinsic>;
         
         let config = EventDrivenConfig::default();
-        let mut controller = SmartEventDrivenController::<TestBlock>::new(config);
+        let collection_controller = CollectionController::new(&config, None).unwrap();
+        let mut controller =
+            SmartEventDrivenController::<TestBlock>::new(config, collection_controller.clone());
         
         // Test normal transaction handling
         let trigger = controller.handle_pool_event(PoolEvent::PoolReady(10, None));
@@ -1479,9 +1657,9 @@ mod tests {
         ));
         assert_eq!(trigger, BlockProductionTrigger::ProduceImmediately);
         
-        // Test network load tracking
-        let load = controller.get_network_load();
-        assert!(load >= 0.0);
+        // Test the window is sized by the collection controller
+        assert_eq!(collection_controller.window(), Duration::from_millis(1250));
+        assert_eq!(collection_controller.batch_threshold(), 1000);
     }
 
     #[test]
@@ -1491,18 +1669,41 @@ mod tests {
             max_collection_time: Duration::from_millis(1000),
             max_batch_size: 500,
             priority_threshold: 1000,
-            network_load_factor: 1.0,
             enable_adaptive_timing: true,
         };
         
-        // Test high load scenario
-        let window = SmartCollectionWindow::new(&config, 20, None, 10.0, false);
+        // Test the window is kept within bounds
+        let window = SmartCollectionWindow::new(&config, 20, None, Duration::from_secs(10), false);
         assert!(window.duration >= config.min_collection_time);
         assert!(window.duration <= config.max_collection_time);
         
         // Test high priority scenario
-        let priority_window = SmartCollectionWindow::new(&config, 5, Some(1500), 1.0, true);
-        assert!(priority_window.duration >= config.min_collection_time);
+        let priority_window =
+            SmartCollectionWindow::new(&config, 5, Some(1500), Duration::from_secs(1), true);
+        assert_eq!(priority_window.duration, config.min_collection_time);
         assert!(priority_window.highest_priority == Some(1500));
     }
+
+    #[test]
+    fn test_hybrid_slots_request_one_block_per_slot() {
+        let start = Instant::now();
+        let slot = Duration::from_secs(6);
+        let mut slots = HybridSlots::default();
+
+        // An early block within the first slot, then nothing more until it ends
+        assert!(slots.request(start + Duration::from_secs(1), start + slot));
+        assert!(!slots.request(start + Duration::from_secs(5), start + slot));
+
+        // The next slot gets its regular block
+        assert!(slots.request(start + slot, start + slot * 2));
+        assert!(!slots.request(start + slot + Duration::from_secs(1), start + slot * 2));
+    }
+
+    #[test]
+    fn test_production_mode_parsing() {
+        for mode in [ProductionMode::EventDriven, ProductionMode::Hybrid] {
+            assert_eq!(mode.to_string().parse::<ProductionMode>(), Ok(mode));
+        }
+        assert!("slots".parse::<ProductionMode>().is_err());
+    }
 }
\ No newline at end of file
@@ -19,24 +19,29 @@
 //! Module implementing the logic for verifying and importing AuRa blocks.
 
 use crate::{
-	authorities, standalone::SealVerificationError, AuthorityId, CompatibilityMode, Error,
-	LOG_TARGET,
+	authorities,
+	authority_sets::SharedAuthoritySets,
+	clock_drift::ClockDriftMonitor,
+	deferred::{ArrivalTimes, DeferringImportQueue, DriftTolerance, SlotClock},
+	justification::verify_justification,
+	standalone::{SealVerificationError, SlotAuthorities},
+	AuthorityId, CompatibilityMode, Error, RuntimeSlotDurations, LOG_TARGET,
 };
-use codec::Codec;
+use codec::{Codec, Encode};
 use log::{debug, info, trace};
 use prometheus_endpoint::Registry;
 use sc_client_api::{backend::AuxStore, BlockOf, UsageProvider};
 use sc_consensus::{
 	block_import::{BlockImport, BlockImportParams, ForkChoiceStrategy},
-	import_queue::{BasicQueue, BoxJustificationImport, DefaultImportQueue, Verifier},
+	import_queue::{BasicQueue, BoxJustificationImport, Verifier},
 };
 use sc_consensus_slots::{check_equivocation, CheckedHeader, InherentDataProviderExt};
 use sc_telemetry::{telemetry, TelemetryHandle, CONSENSUS_DEBUG, CONSENSUS_TRACE};
 use sp_api::{ApiExt, ProvideRuntimeApi};
 use sp_block_builder::BlockBuilder as BlockBuilderApi;
-use sp_blockchain::HeaderBackend;
-use sp_consensus::Error as ConsensusError;
-use sp_consensus_micc::{inherents::MiccInherentData, MiccApi};
+use sp_blockchain::{HeaderBackend, HeaderMetadata};
+use sp_consensus::{BlockOrigin, Error as ConsensusError};
+use sp_consensus_micc::{inherents::MiccInherentData, MiccApi, MICC_ENGINE_ID};
 use sp_consensus_slots::Slot;
 use sp_core::crypto::Pair;
 use sp_inherents::{CreateInherentDataProviders, InherentDataProvider as _};
@@ -44,7 +49,7 @@ use sp_runtime::{
 	traits::{Block as BlockT, Header, NumberFor},
 	DigestItem,
 };
-use std::{fmt::Debug, marker::PhantomData, sync::Arc};
+use std::{fmt::Debug, marker::PhantomData, sync::Arc, time::SystemTime};
 
 /// check a header has been signed by the right key. If the slot is too far in the future, an error
 /// will be returned. If it's successful, returns the pre-header and the digest item
@@ -56,24 +61,26 @@ fn check_header<C, B: BlockT, P: Pair>(
 	slot_now: Slot,
 	header: B::Header,
 	hash: B::Hash,
-	authorities: &[AuthorityId<P>],
+	authorities: &SlotAuthorities<AuthorityId<P>>,
 	check_for_equivocation: CheckForEquivocation,
-) -> Result<CheckedHeader<B::Header, (Slot, DigestItem)>, Error<B>>
+) -> Result<CheckedHeader<B::Header, (Slot, DigestItem, AuthorityId<P>)>, Error<B>>
 where
 	P::Public: Codec,
 	P::Signature: Codec,
 	C: sc_client_api::backend::AuxStore,
 {
-	let check_result =
-		crate::standalone::check_header_slot_and_seal::<B, P>(slot_now, header, authorities);
+	let check_result = crate::standalone::check_header_slot_and_seal_with_mode::<B, P>(
+		slot_now,
+		header,
+		authorities,
+		authorities.mode,
+	);
 
 	match check_result {
-		Ok((header, slot, seal)) => {
-			let expected_author = crate::standalone::slot_author::<P>(slot, &authorities);
-			let should_equiv_check = check_for_equivocation.check_for_equivocation();
-			if let (true, Some(expected)) = (should_equiv_check, expected_author) {
+		Ok((header, slot, seal, author)) => {
+			if check_for_equivocation.check_for_equivocation() {
 				if let Some(equivocation_proof) =
-					check_equivocation(client, slot_now, slot, &header, expected)
+					check_equivocation(client, slot_now, slot, &header, &author)
 						.map_err(Error::Client)?
 				{
 					info!(
@@ -86,7 +93,7 @@ where
 				}
 			}
 
-			Ok(CheckedHeader::Checked(header, (slot, seal)))
+			Ok(CheckedHeader::Checked(header, (slot, seal, author)))
 		},
 		Err(SealVerificationError::Deferred(header, slot)) =>
 			Ok(CheckedHeader::Deferred(header, slot)),
@@ -99,22 +106,31 @@ where
 }
 
 /// A verifier for Micc blocks.
-pub struct MiccVerifier<C, P, CIDP, N> {
+pub struct MiccVerifier<C, P: Pair, CIDP, N, H> {
 	client: Arc<C>,
 	create_inherent_data_providers: CIDP,
 	check_for_equivocation: CheckForEquivocation,
 	telemetry: Option<TelemetryHandle>,
 	compatibility_mode: CompatibilityMode<N>,
+	drift_tolerance: DriftTolerance,
+	clock_drift: Option<ClockDriftMonitor>,
+	authority_sets: Option<SharedAuthoritySets<H, N, AuthorityId<P>>>,
+	micc_finality: bool,
+	arrivals: Option<ArrivalTimes<H>>,
 	_phantom: PhantomData<fn() -> P>,
 }
 
-impl<C, P, CIDP, N> MiccVerifier<C, P, CIDP, N> {
+impl<C, P: Pair, CIDP, N, H> MiccVerifier<C, P, CIDP, N, H> {
 	pub(crate) fn new(
 		client: Arc<C>,
 		create_inherent_data_providers: CIDP,
 		check_for_equivocation: CheckForEquivocation,
 		telemetry: Option<TelemetryHandle>,
 		compatibility_mode: CompatibilityMode<N>,
+		drift_tolerance: DriftTolerance,
+		clock_drift: Option<ClockDriftMonitor>,
+		authority_sets: Option<SharedAuthoritySets<H, N, AuthorityId<P>>>,
+		micc_finality: bool,
 	) -> Self {
 		Self {
 			client,
@@ -122,12 +138,24 @@ impl<C, P, CIDP, N> MiccVerifier<C, P, CIDP, N> {
 			check_for_equivocation,
 			telemetry,
 			compatibility_mode,
+			drift_tolerance,
+			clock_drift,
+			authority_sets,
+			micc_finality,
+			arrivals: None,
 			_phantom: PhantomData,
 		}
 	}
+
+	/// Measure the clock drift from the time the blocks reached the [`DeferringImportQueue`]
+	/// noted in `arrivals`, rather than from the time they are verified.
+	pub(crate) fn with_arrivals(mut self, arrivals: ArrivalTimes<H>) -> Self {
+		self.arrivals = Some(arrivals);
+		self
+	}
 }
 
-impl<C, P, CIDP, N> MiccVerifier<C, P, CIDP, N>
+impl<C, P: Pair, CIDP, N, H> MiccVerifier<C, P, CIDP, N, H>
 where
 	CIDP: Send,
 {
@@ -163,9 +191,14 @@ where
 }
 
 #[async_trait::async_trait]
-impl<B: BlockT, C, P, CIDP> Verifier<B> for MiccVerifier<C, P, CIDP, NumberFor<B>>
+impl<B: BlockT, C, P, CIDP> Verifier<B> for MiccVerifier<C, P, CIDP, NumberFor<B>, B::Hash>
 where
-	C: ProvideRuntimeApi<B> + Send + Sync + sc_client_api::backend::AuxStore,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ Send
+		+ Sync
+		+ sc_client_api::backend::AuxStore,
 	C::Api: BlockBuilderApi<B> + MiccApi<B, AuthorityId<P>> + ApiExt<B>,
 	P: Pair,
 	P::Public: Codec + Debug,
@@ -190,12 +223,19 @@ where
 		}
 
 		let hash = block.header.hash();
+		// Blocks held back until their slot arrived before they reach the verifier.
+		let received_at = self
+			.arrivals
+			.as_ref()
+			.and_then(|arrivals| arrivals.take(&hash))
+			.unwrap_or_else(SystemTime::now);
 		let parent_hash = *block.header.parent_hash();
 		let authorities = authorities(
 			self.client.as_ref(),
 			parent_hash,
 			*block.header.number(),
 			&self.compatibility_mode,
+			self.authority_sets.as_ref(),
 		)
 		.map_err(|e| format!("Could not fetch authorities at {:?}: {}", parent_hash, e))?;
 
@@ -212,20 +252,20 @@ where
 
 		let slot_now = create_inherent_data_providers.slot();
 
-		// we add one to allow for some small drift.
-		// FIXME #1019 in the future, alter this queue to allow deferring of
-		// headers
+		// Headers slightly ahead of our clock are held back by the `DeferringImportQueue` until
+		// their slot, those reaching the verifier early are rejected.
 		let checked_header = check_header::<C, B, P>(
 			&self.client,
-			slot_now + 1,
+			slot_now.saturating_add(self.drift_tolerance.accepted_slots),
 			block.header,
 			hash,
-			&authorities[..],
+			&authorities,
 			self.check_for_equivocation,
 		)
 		.map_err(|e| e.to_string())?;
+
 		match checked_header {
-			CheckedHeader::Checked(pre_header, (slot, seal)) => {
+			CheckedHeader::Checked(pre_header, (slot, seal, author)) => {
 				// if the body is passed through, we need to use the runtime
 				// to check that the internally-set timestamp in the inherents
 				// actually matches the slot set in the seal.
@@ -256,6 +296,13 @@ where
 					block.body = Some(inner_body);
 				}
 
+				// Only blocks gossiped at the time they are authored tell us about our clock.
+				if let (BlockOrigin::NetworkBroadcast, Some(clock_drift)) =
+					(block.origin, &self.clock_drift)
+				{
+					clock_drift.note_received(hash.as_ref(), &author.encode(), received_at);
+				}
+
 				trace!(target: LOG_TARGET, "Checked {:?}; importing.", pre_header);
 				telemetry!(
 					self.telemetry;
@@ -264,11 +311,31 @@ where
 					"pre_header" => ?pre_header,
 				);
 
+				block.fork_choice = Some(
+					crate::select_chain::fork_choice::<B, C, P>(
+						&*self.client,
+						&pre_header,
+						hash,
+						authorities.mode,
+						&self.compatibility_mode,
+						self.authority_sets.as_ref(),
+					)
+					.map_err(|e| e.to_string())?,
+				);
 				block.header = pre_header;
 				block.post_digests.push(seal);
-				block.fork_choice = Some(ForkChoiceStrategy::LongestChain);
 				block.post_hash = Some(hash);
 
+				let justification = block
+					.justifications
+					.as_ref()
+					.and_then(|justifications| justifications.get(MICC_ENGINE_ID));
+				if let (true, Some(justification)) = (self.micc_finality, justification) {
+					verify_justification::<_, P>(&hash, justification, &authorities.authorities)
+						.map_err(|e| format!("Bad Micc justification of {:?}: {}", hash, e))?;
+					block.finalized = true;
+				}
+
 				Ok(block)
 			},
 			CheckedHeader::Deferred(a, b) => {
@@ -312,7 +379,7 @@ impl Default for CheckForEquivocation {
 }
 
 /// Parameters of [`import_queue`].
-pub struct ImportQueueParams<'a, Block: BlockT, I, C, S, CIDP> {
+pub struct ImportQueueParams<'a, Block: BlockT, I, C, S, CIDP, A> {
 	/// The block import to use.
 	pub block_import: I,
 	/// The justification import.
@@ -321,6 +388,12 @@ pub struct ImportQueueParams<'a, Block: BlockT, I, C, S, CIDP> {
 	pub client: Arc<C>,
 	/// Something that can create the inherent data providers.
 	pub create_inherent_data_providers: CIDP,
+	/// The slot duration schedule of the runtime, timing the blocks held back until their slot. A
+	/// cache of its own if `None`.
+	///
+	/// Should be the cache the authoring and `create_inherent_data_providers` look the schedule up
+	/// in, so that the runtime is only asked once per change.
+	pub slot_durations: Option<Arc<RuntimeSlotDurations<Block, C, A>>>,
 	/// The spawner to spawn background tasks.
 	pub spawner: &'a S,
 	/// The prometheus registry.
@@ -333,22 +406,47 @@ pub struct ImportQueueParams<'a, Block: BlockT, I, C, S, CIDP> {
 	///
 	/// If in doubt, use `Default::default()`.
 	pub compatibility_mode: CompatibilityMode<NumberFor<Block>>,
+	/// How far ahead of the local clock imported headers may be.
+	///
+	/// If in doubt, use `Default::default()`.
+	pub drift_tolerance: DriftTolerance,
+	/// Monitor of the local clock drift, fed with the blocks received from the network.
+	///
+	/// Only useful if the block import reports imported blocks to it as well, see
+	/// [`crate::MiccBlockImport::with_clock_drift`].
+	pub clock_drift: Option<ClockDriftMonitor>,
+	/// Cache of the authority sets, looked up before asking the runtime.
+	///
+	/// Should be the cache the block import records changes in, see [`crate::MiccBlockImport`].
+	pub authority_sets: Option<SharedAuthoritySets<Block::Hash, NumberFor<Block>, A>>,
+	/// Finalize the blocks carrying a valid Micc justification, see [`crate::justification`].
+	///
+	/// Only for chains finalized by Micc justifications rather than another finality gadget.
+	pub micc_finality: bool,
 }
 
 /// Start an import queue for the Micc consensus algorithm.
+///
+/// Blocks slightly ahead of the local clock wait for their slot in the returned queue, see
+/// [`DeferringImportQueue`].
 pub fn import_queue<P, Block, I, C, S, CIDP>(
 	ImportQueueParams {
 		block_import,
 		justification_import,
 		client,
 		create_inherent_data_providers,
+		slot_durations,
 		spawner,
 		registry,
 		check_for_equivocation,
 		telemetry,
 		compatibility_mode,
-	}: ImportQueueParams<Block, I, C, S, CIDP>,
-) -> Result<DefaultImportQueue<Block>, sp_consensus::Error>
+		drift_tolerance,
+		clock_drift,
+		authority_sets,
+		micc_finality,
+	}: ImportQueueParams<Block, I, C, S, CIDP, AuthorityId<P>>,
+) -> Result<DeferringImportQueue<Block>, sp_consensus::Error>
 where
 	Block: BlockT,
 	C::Api: BlockBuilderApi<Block> + MiccApi<Block, AuthorityId<P>> + ApiExt<Block>,
@@ -359,7 +457,8 @@ where
 		+ Sync
 		+ AuxStore
 		+ UsageProvider<Block>
-		+ HeaderBackend<Block>,
+		+ HeaderBackend<Block>
+		+ HeaderMetadata<Block, Error = sp_blockchain::Error>,
 	I: BlockImport<Block, Error = ConsensusError> + Send + Sync + 'static,
 	P: Pair + 'static,
 	P::Public: Codec + Debug,
@@ -368,19 +467,50 @@ where
 	CIDP: CreateInherentDataProviders<Block, ()> + Sync + Send + 'static,
 	CIDP::InherentDataProviders: InherentDataProviderExt + Send + Sync,
 {
-	let verifier = build_verifier::<P, _, _, _>(BuildVerifierParams {
-		client,
+	let arrivals = ArrivalTimes::default();
+	let verifier = build_verifier::<P, _, _, _, _>(BuildVerifierParams {
+		client: client.clone(),
 		create_inherent_data_providers,
 		check_for_equivocation,
 		telemetry,
 		compatibility_mode,
-	});
-
-	Ok(BasicQueue::new(verifier, Box::new(block_import), justification_import, spawner, registry))
+		drift_tolerance,
+		clock_drift,
+		authority_sets,
+		micc_finality,
+	})
+	.with_arrivals(arrivals.clone());
+
+	let slot_durations = match slot_durations {
+		Some(slot_durations) => slot_durations,
+		None => {
+			let slot_duration = crate::slot_duration::<AuthorityId<P>, _, _>(&*client)
+				.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+			Arc::new(RuntimeSlotDurations::new(client.clone(), slot_duration))
+		},
+	};
+	let clock = SlotClock {
+		slot_of: Box::new(|header| {
+			crate::standalone::find_pre_digest::<Block, P::Signature>(header).ok()
+		}),
+		schedule: Box::new(move || slot_durations.schedule_at(client.info().best_hash)),
+	};
+
+	let basic_queue =
+		BasicQueue::new(verifier, Box::new(block_import), justification_import, spawner, registry);
+	let (import_queue, resubmit_deferred) =
+		DeferringImportQueue::new(basic_queue, drift_tolerance, clock, arrivals);
+	spawner.spawn_essential(
+		"micc-deferred-blocks",
+		Some("block-import"),
+		Box::pin(resubmit_deferred),
+	);
+
+	Ok(import_queue)
 }
 
 /// Parameters of [`build_verifier`].
-pub struct BuildVerifierParams<C, CIDP, N> {
+pub struct BuildVerifierParams<C, CIDP, N, H, A> {
 	/// The client to interact with the chain.
 	pub client: Arc<C>,
 	/// Something that can create the inherent data providers.
@@ -393,23 +523,46 @@ pub struct BuildVerifierParams<C, CIDP, N> {
 	///
 	/// If in doubt, use `Default::default()`.
 	pub compatibility_mode: CompatibilityMode<N>,
+	/// How far ahead of the local clock imported headers may be.
+	///
+	/// If in doubt, use `Default::default()`.
+	pub drift_tolerance: DriftTolerance,
+	/// Monitor of the local clock drift, fed with the blocks received from the network.
+	///
+	/// Only useful if the block import reports imported blocks to it as well, see
+	/// [`crate::MiccBlockImport::with_clock_drift`].
+	pub clock_drift: Option<ClockDriftMonitor>,
+	/// Cache of the authority sets, looked up before asking the runtime.
+	///
+	/// Should be the cache the block import records changes in, see [`crate::MiccBlockImport`].
+	pub authority_sets: Option<SharedAuthoritySets<H, N, A>>,
+	/// Finalize the blocks carrying a valid Micc justification, see [`crate::justification`].
+	pub micc_finality: bool,
 }
 
 /// Build the [`MiccVerifier`]
-pub fn build_verifier<P, C, CIDP, N>(
+pub fn build_verifier<P: Pair, C, CIDP, N, H>(
 	BuildVerifierParams {
 		client,
 		create_inherent_data_providers,
 		check_for_equivocation,
 		telemetry,
 		compatibility_mode,
-	}: BuildVerifierParams<C, CIDP, N>,
-) -> MiccVerifier<C, P, CIDP, N> {
-	MiccVerifier::<_, P, _, _>::new(
+		drift_tolerance,
+		clock_drift,
+		authority_sets,
+		micc_finality,
+	}: BuildVerifierParams<C, CIDP, N, H, AuthorityId<P>>,
+) -> MiccVerifier<C, P, CIDP, N, H> {
+	MiccVerifier::<_, P, _, _, _>::new(
 		client,
 		create_inherent_data_providers,
 		check_for_equivocation,
 		telemetry,
 		compatibility_mode,
+		drift_tolerance,
+		clock_drift,
+		authority_sets,
+		micc_finality,
 	)
 }
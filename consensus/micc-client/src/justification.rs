@@ -0,0 +1,621 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Micc justifications: approvals of a block by more than two thirds of the authorities of its
+//! parent.
+//!
+//! Chains with a single authority finalize their blocks as they are authored, the author
+//! attaching its own approval, see [`instant_justification`]. Chains with several authorities
+//! collect the approvals of the others first, see [`crate::approvals`]. Nodes finalize a block
+//! once they checked its justification, either when importing the block or through the
+//! [`MiccJustificationImport`].
+//!
+//! Warp sync follows the justifications of the blocks changing the authorities, see
+//! [`MiccWarpSyncProvider`].
+
+use std::{
+	collections::BTreeSet,
+	fmt::Debug,
+	marker::PhantomData,
+	sync::{Arc, Mutex},
+};
+
+use codec::{Codec, Decode, Encode};
+use sc_client_api::{Backend, BlockBackend, Finalizer};
+use sc_consensus::JustificationImport;
+use sc_network_sync::strategy::warp::{EncodedProof, VerificationResult, WarpSyncProvider};
+use sp_api::ProvideRuntimeApi;
+use sp_application_crypto::{AppCrypto, AppPublic};
+use sp_blockchain::{HeaderBackend, HeaderMetadata};
+use sp_consensus::Error as ConsensusError;
+use sp_consensus_grandpa::{AuthorityList, SetId};
+use sp_consensus_micc::{AuthorityIndex, ConsensusLog, MiccApi, MICC_ENGINE_ID};
+use sp_core::crypto::{ByteArray, Pair};
+use sp_keystore::KeystorePtr;
+use sp_runtime::{
+	generic::OpaqueDigestItemId,
+	traits::{Block as BlockT, Header, NumberFor, One, Zero},
+	Justification, Justifications,
+};
+
+use crate::{
+	authorities, authority_sets::SharedAuthoritySets, standalone::SlotAuthorities, AuthorityId,
+	CompatibilityMode,
+};
+
+/// Separates the signatures of approvals from those of seals.
+const APPROVAL_CONTEXT: &[u8] = b"micc_finality";
+
+/// Warp sync proofs stop growing past this size, and are continued by another request.
+const MAX_WARP_SYNC_PROOF_SIZE: usize = 8 * 1024 * 1024;
+
+/// Warp sync proofs stop after this many headers looked through, and are continued by another
+/// request.
+const MAX_WARP_SYNC_HEADERS_SCANNED: u32 = 65_536;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The approvals finalizing a block, stored as its justification under [`MICC_ENGINE_ID`].
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct MiccJustification<Signature> {
+	/// The voting round all the approvals were given in, see [`crate::approvals`].
+	pub round: u32,
+	/// The approvals, by index of the authority in the authority set of the parent block.
+	pub approvals: Vec<(AuthorityIndex, Signature)>,
+}
+
+/// Errors of the [`MiccJustification`] checks.
+#[derive(Debug, thiserror::Error)]
+pub enum JustificationError {
+	/// The justification couldn't be decoded.
+	#[error("undecodable Micc justification")]
+	Undecodable,
+	/// An approval is signed by an index out of the authority set.
+	#[error("approval by unknown authority {0}")]
+	UnknownAuthority(AuthorityIndex),
+	/// An authority approved more than once.
+	#[error("several approvals by authority {0}")]
+	DuplicateApproval(AuthorityIndex),
+	/// An approval has a bad signature.
+	#[error("bad approval signature by authority {0}")]
+	BadSignature(AuthorityIndex),
+	/// Too few authorities approved.
+	#[error("{approvals} approvals out of the {needed} needed")]
+	NotEnoughApprovals {
+		/// The approvals of the justification.
+		approvals: usize,
+		/// The approvals needed.
+		needed: usize,
+	},
+}
+
+fn approval_payload<H: Encode>(hash: &H, round: u32) -> Vec<u8> {
+	(APPROVAL_CONTEXT, hash, round).encode()
+}
+
+/// The number of approvals finalizing a block with `authorities` authorities at its parent, more
+/// than two thirds of them.
+pub fn approval_threshold(authorities: usize) -> usize {
+	authorities * 2 / 3 + 1
+}
+
+/// The authority of `authorities`, if it is the only one.
+pub fn sole_authority<A>(authorities: &SlotAuthorities<A>) -> Option<&A> {
+	match &authorities.authorities[..] {
+		[authority] => Some(authority),
+		_ => None,
+	}
+}
+
+/// Sign the approval of the block `hash` in the voting `round` with the key `public`.
+pub fn sign_approval<H, P>(
+	hash: &H,
+	round: u32,
+	public: &P::Public,
+	keystore: &KeystorePtr,
+) -> Result<P::Signature, ConsensusError>
+where
+	H: Encode,
+	P: Pair,
+	P::Signature: TryFrom<Vec<u8>>,
+	P::Public: AppPublic,
+{
+	let signature = keystore
+		.sign_with(
+			<AuthorityId<P> as AppCrypto>::ID,
+			<AuthorityId<P> as AppCrypto>::CRYPTO_ID,
+			public.as_slice(),
+			&approval_payload(hash, round),
+		)
+		.map_err(|e| ConsensusError::CannotSign(format!("{}. Key: {:?}", e, public)))?
+		.ok_or_else(|| {
+			ConsensusError::CannotSign(format!("Could not find key in keystore. Key: {:?}", public))
+		})?;
+
+	signature
+		.clone()
+		.try_into()
+		.map_err(|_| ConsensusError::InvalidSignature(signature, public.to_raw_vec()))
+}
+
+/// Whether `signature` is the approval of the block `hash` in the voting `round` by `authority`.
+pub fn verify_approval<H, P>(
+	hash: &H,
+	round: u32,
+	signature: &P::Signature,
+	authority: &P::Public,
+) -> bool
+where
+	H: Encode,
+	P: Pair,
+{
+	P::verify(signature, approval_payload(hash, round), authority)
+}
+
+/// The justification of the block `hash` by `public`, the only authority of its parent.
+pub fn instant_justification<H, P>(
+	hash: &H,
+	public: &P::Public,
+	keystore: &KeystorePtr,
+) -> Result<Justification, ConsensusError>
+where
+	H: Encode,
+	P: Pair,
+	P::Signature: Codec + TryFrom<Vec<u8>>,
+	P::Public: AppPublic,
+{
+	let signature = sign_approval::<_, P>(hash, 0, public, keystore)?;
+
+	Ok((MICC_ENGINE_ID, MiccJustification { round: 0, approvals: vec![(0, signature)] }.encode()))
+}
+
+/// Check that `justification` finalizes the block `hash`, whose parent has `authorities`.
+pub fn verify_justification<H, P>(
+	hash: &H,
+	justification: &[u8],
+	authorities: &[P::Public],
+) -> Result<(), JustificationError>
+where
+	H: Encode,
+	P: Pair,
+	P::Signature: Codec,
+{
+	let MiccJustification { round, approvals } =
+		MiccJustification::<P::Signature>::decode(&mut &justification[..])
+			.map_err(|_| JustificationError::Undecodable)?;
+
+	let mut approved = BTreeSet::new();
+	for (index, signature) in &approvals {
+		let authority = authorities
+			.get(*index as usize)
+			.ok_or(JustificationError::UnknownAuthority(*index))?;
+		if !approved.insert(*index) {
+			return Err(JustificationError::DuplicateApproval(*index))
+		}
+		if !verify_approval::<_, P>(hash, round, signature, authority) {
+			return Err(JustificationError::BadSignature(*index))
+		}
+	}
+
+	let needed = approval_threshold(authorities.len());
+	if approved.len() < needed {
+		return Err(JustificationError::NotEnoughApprovals { approvals: approved.len(), needed })
+	}
+
+	Ok(())
+}
+
+/// Check the Micc justification of the block `header` against the authority set of its parent,
+/// looked up in `authority_sets` first.
+pub(crate) fn check_justification<B, C, P>(
+	client: &C,
+	authority_sets: Option<&SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+	header: &B::Header,
+	justification: &[u8],
+) -> Result<(), ConsensusError>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + HeaderMetadata<B, Error = sp_blockchain::Error>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	let hash = header.hash();
+	let authorities = authorities(
+		client,
+		*header.parent_hash(),
+		*header.number(),
+		&CompatibilityMode::None,
+		authority_sets,
+	)?;
+
+	verify_justification::<_, P>(&hash, justification, &authorities.authorities).map_err(|e| {
+		ConsensusError::ClientImport(format!("Bad Micc justification of {:?}: {}", hash, e))
+	})
+}
+
+/// Finalizes the blocks whose Micc justification arrives after the block itself, e.g. when
+/// requested from peers.
+pub struct MiccJustificationImport<B: BlockT, BE, C, P: Pair> {
+	client: Arc<C>,
+	authority_sets: Option<SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+	_phantom: PhantomData<fn() -> (B, BE, P)>,
+}
+
+impl<B: BlockT, BE, C, P: Pair> MiccJustificationImport<B, BE, C, P> {
+	/// Import justifications into `client`, checking them against the authorities cached in
+	/// `authority_sets` before asking the runtime.
+	///
+	/// `authority_sets` should be the cache the block import records changes in, see
+	/// [`crate::MiccBlockImport`].
+	pub fn new(
+		client: Arc<C>,
+		authority_sets: Option<SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+	) -> Self {
+		Self { client, authority_sets, _phantom: PhantomData }
+	}
+}
+
+impl<B: BlockT, BE, C, P: Pair> Clone for MiccJustificationImport<B, BE, C, P> {
+	fn clone(&self) -> Self {
+		Self {
+			client: self.client.clone(),
+			authority_sets: self.authority_sets.clone(),
+			_phantom: PhantomData,
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl<B, BE, C, P> JustificationImport<B> for MiccJustificationImport<B, BE, C, P>
+where
+	B: BlockT,
+	BE: Backend<B>,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ Finalizer<B, BE>
+		+ Send
+		+ Sync,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	type Error = ConsensusError;
+
+	async fn on_start(&mut self) -> Vec<(B::Hash, NumberFor<B>)> {
+		Vec::new()
+	}
+
+	async fn import_justification(
+		&mut self,
+		hash: B::Hash,
+		_number: NumberFor<B>,
+		justification: Justification,
+	) -> Result<(), Self::Error> {
+		if justification.0 != MICC_ENGINE_ID {
+			return Err(ConsensusError::ClientImport(format!(
+				"Justification of {:?} isn't a Micc justification",
+				hash,
+			)))
+		}
+
+		let header = self
+			.client
+			.header(hash)
+			.map_err(|e| ConsensusError::ClientImport(e.to_string()))?
+			.ok_or_else(|| ConsensusError::ClientImport(format!("No header for {:?}", hash)))?;
+		let authority_sets = self.authority_sets.as_ref();
+		check_justification::<B, C, P>(&*self.client, authority_sets, &header, &justification.1)?;
+
+		self.client
+			.finalize_block(hash, Some(justification), true)
+			.map_err(|e| ConsensusError::ClientImport(e.to_string()))
+	}
+}
+
+/// Warp sync proofs of chains finalized by Micc justifications.
+#[derive(Encode, Decode)]
+struct WarpSyncProof<H> {
+	/// Headers with their Micc justification, every header changing the authorities included.
+	fragments: Vec<(H, Vec<u8>)>,
+	/// Whether the last fragment is the latest finalized block.
+	is_finished: bool,
+}
+
+/// The authorities enacted by `header` for its descendants, if it changes them.
+fn enacted_authorities<A: Codec, H: Header>(header: &H) -> Option<Vec<A>> {
+	header.digest().logs().iter().find_map(|log| {
+		match log.try_to::<ConsensusLog<A>>(OpaqueDigestItemId::Consensus(&MICC_ENGINE_ID)) {
+			Some(ConsensusLog::AuthoritiesChange(authorities)) => Some(authorities),
+			_ => None,
+		}
+	})
+}
+
+/// A [`WarpSyncProvider`] for chains finalized by Micc justifications.
+///
+/// Proofs are the headers changing the authorities, each justified by the authorities they
+/// replace, followed by the latest finalized block. The GRANDPA authorities and set ids passed
+/// around by warp sync are left untouched, the authorities proofs are checked against are tracked
+/// here instead, starting from those of the genesis block.
+pub struct MiccWarpSyncProvider<B: BlockT, C, P: Pair> {
+	client: Arc<C>,
+	/// The last verified block and the authorities of its descendants.
+	verified: Mutex<Option<(NumberFor<B>, Vec<AuthorityId<P>>)>>,
+}
+
+impl<B: BlockT, C, P: Pair> MiccWarpSyncProvider<B, C, P> {
+	/// Serve and verify proofs with `client`.
+	pub fn new(client: Arc<C>) -> Self {
+		Self { client, verified: Mutex::new(None) }
+	}
+}
+
+impl<B, C, P> MiccWarpSyncProvider<B, C, P>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ BlockBackend<B>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	fn justification(&self, hash: B::Hash) -> Result<Option<Vec<u8>>, BoxError> {
+		Ok(self
+			.client
+			.justifications(hash)?
+			.and_then(|justifications| justifications.into_justification(MICC_ENGINE_ID)))
+	}
+
+	fn header_at(&self, number: NumberFor<B>) -> Result<(B::Hash, B::Header), BoxError> {
+		let hash = self
+			.client
+			.hash(number)?
+			.ok_or_else(|| format!("No finalized block at height {}", number))?;
+		let header =
+			self.client.header(hash)?.ok_or_else(|| format!("No header for {:?}", hash))?;
+
+		Ok((hash, header))
+	}
+
+	/// The authorities of the children of the genesis block.
+	fn genesis_authorities(&self) -> Result<(NumberFor<B>, Vec<AuthorityId<P>>), BoxError> {
+		let authorities = authorities(
+			&*self.client,
+			self.client.info().genesis_hash,
+			One::one(),
+			&CompatibilityMode::None,
+			None,
+		)?;
+
+		Ok((Zero::zero(), authorities.authorities))
+	}
+}
+
+impl<B, C, P> WarpSyncProvider<B> for MiccWarpSyncProvider<B, C, P>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ BlockBackend<B>
+		+ Send
+		+ Sync,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	fn generate(&self, start: B::Hash) -> Result<EncodedProof, BoxError> {
+		let start_number = self
+			.client
+			.number(start)?
+			.ok_or_else(|| format!("Unknown warp sync start {:?}", start))?;
+		let finalized_number = self.client.info().finalized_number;
+		if start_number >= finalized_number {
+			return Err(format!("Nothing finalized after {:?}", start).into())
+		}
+		if self.client.hash(start_number)? != Some(start) {
+			return Err(format!("Warp sync start {:?} isn't finalized", start).into())
+		}
+
+		let mut fragments = Vec::new();
+		let mut proof_size = 0;
+		let mut scanned = 0;
+		let mut number = start_number;
+		while number < finalized_number &&
+			proof_size < MAX_WARP_SYNC_PROOF_SIZE &&
+			scanned < MAX_WARP_SYNC_HEADERS_SCANNED
+		{
+			number += One::one();
+			scanned += 1;
+
+			let (hash, header) = self.header_at(number)?;
+			if enacted_authorities::<AuthorityId<P>, _>(&header).is_none() {
+				continue
+			}
+			let justification = self
+				.justification(hash)?
+				.ok_or_else(|| format!("No Micc justification of {:?}", hash))?;
+			proof_size += header.encoded_size() + justification.len();
+			fragments.push((header, justification));
+		}
+
+		// End on the last block looked through, or the latest one before it that is justified.
+		let last_fragment = fragments.last().map_or(start_number, |(header, _)| *header.number());
+		while number > last_fragment {
+			let (hash, header) = self.header_at(number)?;
+			if let Some(justification) = self.justification(hash)? {
+				fragments.push((header, justification));
+				break
+			}
+			number -= One::one();
+		}
+
+		if fragments.is_empty() {
+			return Err(format!("No Micc justification after {:?}", start).into())
+		}
+		let is_finished = fragments
+			.last()
+			.is_some_and(|(header, _)| *header.number() == finalized_number);
+
+		Ok(EncodedProof(WarpSyncProof { fragments, is_finished }.encode()))
+	}
+
+	fn verify(
+		&self,
+		proof: &EncodedProof,
+		set_id: SetId,
+		authorities: AuthorityList,
+	) -> Result<VerificationResult<B>, BoxError> {
+		let WarpSyncProof { fragments, is_finished } =
+			WarpSyncProof::<B::Header>::decode(&mut &proof.0[..])?;
+
+		let mut verified = self.verified.lock().expect("never poisoned; qed");
+		let (mut last_number, mut authorities_now) = match verified.clone() {
+			Some(verified) => verified,
+			None => self.genesis_authorities()?,
+		};
+
+		let mut justified = Vec::with_capacity(fragments.len());
+		for (header, justification) in fragments {
+			let hash = header.hash();
+			if *header.number() <= last_number {
+				return Err(format!("Warp sync proof goes back to {:?}", hash).into())
+			}
+			verify_justification::<_, P>(&hash, &justification, &authorities_now)
+				.map_err(|e| format!("Bad Micc justification of {:?}: {}", hash, e))?;
+
+			if let Some(enacted) = enacted_authorities::<AuthorityId<P>, _>(&header) {
+				authorities_now = enacted;
+			}
+			last_number = *header.number();
+			justified.push((header, Justifications::from((MICC_ENGINE_ID, justification))));
+		}
+
+		let (last_header, _) = justified.last().ok_or("Empty warp sync proof")?;
+		let last_header = last_header.clone();
+		*verified = Some((last_number, authorities_now));
+
+		Ok(if is_finished {
+			VerificationResult::Complete(set_id, authorities, last_header, justified)
+		} else {
+			VerificationResult::Partial(set_id, authorities, last_header.hash(), justified)
+		})
+	}
+
+	fn current_authorities(&self) -> AuthorityList {
+		AuthorityList::new()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_consensus_micc::{
+		sr25519::{AuthorityId, AuthorityPair, AuthoritySignature},
+		MICC,
+	};
+	use sp_core::H256;
+	use sp_keystore::testing::MemoryKeystore;
+
+	fn keys(keystore: &KeystorePtr, seeds: &[&str]) -> Vec<AuthorityId> {
+		seeds
+			.iter()
+			.map(|seed| keystore.sr25519_generate_new(MICC, Some(seed)).unwrap().into())
+			.collect()
+	}
+
+	#[test]
+	fn instant_justifications_are_bound_to_the_block_and_the_authority() {
+		let keystore: KeystorePtr = Arc::new(MemoryKeystore::new());
+		let authorities = keys(&keystore, &["//Alice", "//Bob"]);
+		let (alice, bob) = (&authorities[0], &authorities[1]);
+		let (block, other_block) = (H256::repeat_byte(1), H256::repeat_byte(2));
+		let verify = |hash: &H256, justification: &[u8], authority: &AuthorityId| {
+			verify_justification::<_, AuthorityPair>(hash, justification, &[authority.clone()])
+		};
+
+		let (engine_id, justification) =
+			instant_justification::<_, AuthorityPair>(&block, alice, &keystore).unwrap();
+		assert_eq!(engine_id, MICC_ENGINE_ID);
+
+		assert!(verify(&block, &justification, alice).is_ok());
+		assert!(verify(&other_block, &justification, alice).is_err());
+		assert!(verify(&block, &justification, bob).is_err());
+		assert!(verify(&block, b"garbage", alice).is_err());
+
+		// A seal of the block doesn't finalize it.
+		let seal = crate::standalone::seal::<_, AuthorityPair>(&block, alice, &keystore).unwrap();
+		let signature = AuthoritySignature::decode(&mut seal.as_seal().unwrap().1).unwrap();
+		let sealed = MiccJustification { round: 0, approvals: vec![(0, signature)] }.encode();
+		assert!(verify(&block, &sealed, alice).is_err());
+	}
+
+	#[test]
+	fn justifications_need_more_than_two_thirds_of_the_authorities() {
+		let keystore: KeystorePtr = Arc::new(MemoryKeystore::new());
+		let authorities = keys(&keystore, &["//Alice", "//Bob", "//Charlie", "//Dave"]);
+		let block = H256::repeat_byte(1);
+		let approval_in = |round, index: usize| {
+			let signature =
+				sign_approval::<_, AuthorityPair>(&block, round, &authorities[index], &keystore)
+					.unwrap();
+			(index as AuthorityIndex, signature)
+		};
+		let approval = |index| approval_in(0, index);
+		let verify_in = |round, approvals| {
+			let justification = MiccJustification { round, approvals }.encode();
+			verify_justification::<_, AuthorityPair>(&block, &justification, &authorities)
+		};
+		let verify = |approvals| verify_in(0, approvals);
+
+		assert_eq!(approval_threshold(4), 3);
+		assert!(matches!(
+			verify(vec![approval(0), approval(1)]),
+			Err(JustificationError::NotEnoughApprovals { approvals: 2, needed: 3 })
+		));
+		assert!(matches!(
+			verify(vec![approval(0), approval(1), approval(1)]),
+			Err(JustificationError::DuplicateApproval(1))
+		));
+		let (_, misplaced) = approval(3);
+		assert!(matches!(
+			verify(vec![approval(0), approval(1), (2, misplaced.clone())]),
+			Err(JustificationError::BadSignature(2))
+		));
+		assert!(matches!(
+			verify(vec![approval(0), approval(1), (4, misplaced)]),
+			Err(JustificationError::UnknownAuthority(4))
+		));
+		assert!(verify(vec![approval(3), approval(0), approval(2)]).is_ok());
+
+		// The approvals of a justification were all given in its round.
+		assert!(verify_in(1, vec![approval_in(1, 0), approval_in(1, 1), approval_in(1, 2)]).is_ok());
+		assert!(matches!(
+			verify_in(1, vec![approval_in(1, 0), approval_in(1, 1), approval(2)]),
+			Err(JustificationError::BadSignature(2))
+		));
+	}
+}
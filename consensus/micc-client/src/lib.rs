@@ -20,13 +20,67 @@
 //!
 //! Micc works by having a list of authorities A who are expected to roughly
 //! agree on the current time. Time is divided up into discrete slots of t
-//! seconds each. For each slot s, the author of that slot is A[s % |A|].
+//! seconds each. The author of each slot is picked from A by a [`SlotAuthorSelector`] supplied by
+//! the runtime, by default A[s % |A|] for slot s.
 //!
 //! The author is allowed to issue one block but not more during that slot,
 //! and it will be built upon the longest valid chain that has been seen.
 //!
 //! Blocks from future steps will be either deferred or rejected depending on how
-//! far in the future they are.
+//! far in the future they are, see [`DriftTolerance`].
+//!
+//! The drift of the local clock is estimated from the timestamps of blocks imported from peers by a
+//! [`ClockDriftMonitor`], and the node refuses to author while it is too large.
+//!
+//! Authorities, along with their selector and authorship mode, are looked up in
+//! [`SharedAuthoritySets`], kept up to date from the consensus digests of the blocks going through
+//! the [`MiccBlockImport`], before asking the runtime.
+//!
+//! Before sealing, the headers are recorded in a [`SigningHistory`], which refuses to seal two
+//! headers at the same slot with the same key.
+//!
+//! Development chains may declare the [`AuthorshipMode::Permissive`] mode in their chain spec, in
+//! which case any authority may author any slot, as with `--force-authoring`, naming itself in the
+//! pre-digest unless it owns the slot, and sibling blocks are ordered deterministically. Chains
+//! after the lowest latency may declare the [`AuthorshipMode::AnyAuthority`] mode instead, where
+//! authors name themselves in the pre-digest and siblings are ordered by slot, then by the
+//! rotation of authors. Their nodes should select the best chain through a [`MiccSelectChain`].
+//!
+//! Chains may finalize their blocks with Micc justifications instead of another finality gadget,
+//! see [`justification`]: instantly when they have a single authority, or once more than two thirds
+//! of the authorities approved them, see [`approvals`].
+//!
+//! Blocks can be sealed by an external signer through a [`RemoteKeystore`], keeping the authority
+//! keys off the node.
+//!
+//! The block proposer may order the transactions by arrival, or put those that triggered the block
+//! first, when built over an [`OrderedPool`].
+//!
+//! Event-driven blocks can be built while their transactions are collected, by the proposers of a
+//! [`SpeculativeProposerFactory`], so that they are ready to be sealed once requested. How long
+//! transactions are collected is set by a [`CollectionController`], aiming at an inclusion latency
+//! and a block fill ratio. Transactions promoted from the future queue of the pool, once the one
+//! they depend on arrives, trigger blocks as imported ones do.
+//!
+//! Instead of on demand, blocks may be authored at every slot with transactions ready, with
+//! high-priority transactions getting theirs early within the slot, see
+//! [`ProductionMode::Hybrid`].
+//!
+//! Should the event-driven trigger stream end, a [`TriggerSupervisor`] starts it again, and
+//! requests blocks at every slot with transactions ready until then.
+//!
+//! Every block authored is reported to the [`AuthoringObserver`]s of the worker, with what led to
+//! it, how long it took and whether it was imported.
+//!
+//! Blocks are also authored when the runtime needs them for work of its own, such as scheduled
+//! calls or migrations, at the time given by [`MiccApi::next_required_block_time`].
+//!
+//! A node that doesn't author can evaluate a production policy against its own pool with
+//! [`run_shadow_authoring`], which proposes the blocks an authority would, without sealing them,
+//! and reports the transactions they would have included and how long these would have waited.
+//!
+//! Blocks authored on demand back off while finality lags through a
+//! [`BackoffAuthoringOnFinalityAge`], which never holds back the heartbeats of an idle chain.
 //!
 //! NOTE: Micc itself is designed to be generic over the crypto used.
 #![forbid(missing_docs, unsafe_code)]
@@ -45,35 +99,86 @@ use sp_consensus_micc::{MICC};
 use sc_telemetry::TelemetryHandle;
 use sp_api::{Core, ProvideRuntimeApi};
 use sp_application_crypto::{AppPublic, ByteArray};
-use sp_blockchain::HeaderBackend;
+use sp_blockchain::{HeaderBackend, HeaderMetadata};
 use sp_consensus::{BlockOrigin, Environment, Error as ConsensusError, Proposer, SelectChain};
 use sp_consensus_slots::Slot;
 use sp_core::crypto::Pair;
 use sp_inherents::CreateInherentDataProviders;
 use sp_keystore::KeystorePtr;
-use sp_runtime::traits::{Block as BlockT, Header, Member, NumberFor};
-use sc_transaction_pool_api::MaintainedTransactionPool;
+use sp_runtime::traits::{Block as BlockT, Header, Member, NumberFor, One, Saturating};
+use sc_transaction_pool_api::{MaintainedTransactionPool, TxHash};
 use futures::StreamExt;
 use tokio_stream::wrappers::IntervalStream;
 use tokio::time;
 
+pub mod approvals;
+mod authority_sets;
+mod backoff;
+mod block_import;
+mod clock_drift;
+mod collection;
+mod deferred;
 mod import_queue;
+pub mod justification;
+mod ordering;
+mod promotion;
+mod required_blocks;
+mod select_chain;
+mod shadow;
+mod signing_history;
+pub mod remote_keystore;
+mod slot_schedule;
+mod speculative;
 pub mod standalone;
+mod supervisor;
 pub mod event_driven;
 
-pub use crate::standalone::{find_pre_digest, slot_duration};
-pub use sc_consensus_slots::SlotTrigger;
+pub use crate::standalone::{find_pre_digest, slot_duration, slot_duration_schedule_at};
+pub use sc_consensus_slots::{
+	AuthoredBlock, AuthoringReason, ProposalBudget, ProposalTiming, SlotTrigger,
+};
+pub use approvals::{
+	approvals_peers_set_config, approvals_protocol_name, run_approval_voter, ApprovalVoterParams,
+};
+pub use authority_sets::SharedAuthoritySets;
+pub use backoff::{BackoffAuthoringOnFinalityAge, FinalityAgeBackoffConfig};
+pub use block_import::MiccBlockImport;
+pub use justification::{MiccJustification, MiccJustificationImport, MiccWarpSyncProvider};
+pub use ordering::{OrderedPool, TransactionArrivals, TransactionOrdering};
+pub use select_chain::MiccSelectChain;
+pub use shadow::{
+	run_shadow_authoring, ShadowAuthoring, ShadowAuthoringParams, ShadowBlock, ShadowReport,
+};
+pub use clock_drift::{
+	pallet_timestamp_reader, AuthorClockDrift, ClockDriftConfig, ClockDriftError,
+	ClockDriftEstimate, ClockDriftMonitor, TimestampReader,
+};
+pub use collection::{measure_authored_blocks, CollectionController, CollectionTargets};
+pub use deferred::{DeferringImportQueue, DriftTolerance};
+pub use event_driven::ProductionMode;
 pub use import_queue::{
 	build_verifier, import_queue, BuildVerifierParams, CheckForEquivocation, ImportQueueParams,
 	MiccVerifier,
 };
 pub use sc_consensus_slots::SlotProportion;
+pub use remote_keystore::{
+	RemoteKeystore, RemoteKeystoreError, RemoteSignerEndpoint, DEFAULT_REMOTE_SIGNER_TIMEOUT,
+};
+pub use signing_history::{
+	SealedHeader, SigningHistory, SigningHistoryError, SigningHistoryInterchange,
+	INTERCHANGE_VERSION,
+};
+pub use slot_schedule::RuntimeSlotDurations;
+pub use speculative::{Speculation, SpeculativeProposer, SpeculativeProposerFactory};
 pub use sp_consensus::SyncOracle;
 pub use sp_consensus_micc::{
 	digests::CompatibleDigestItem,
 	inherents::{InherentDataProvider, InherentType as MiccInherent, INHERENT_IDENTIFIER},
-	ConsensusLog, MiccApi, SlotDuration, MICC_ENGINE_ID,
+	AuthorityIndex, AuthorshipMode, ConsensusLog, MiccApi, SlotAuthorSelector, SlotDuration,
+	SlotDurationSchedule, MICC_ENGINE_ID,
 };
+pub use standalone::SlotAuthorities;
+pub use supervisor::{TriggerStream, TriggerStreamHealth, TriggerSupervisor};
 
 const LOG_TARGET: &str = "micc";
 
@@ -117,9 +222,15 @@ impl<N> Default for CompatibilityMode<N> {
 }
 
 /// Parameters of [`start_micc`].
-pub struct StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, N> {
-	/// The duration of a slot.
+pub struct StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, N, H, A, B: BlockT, TH> {
+	/// The duration of a slot, until the runtime is asked for its slot duration schedule.
 	pub slot_duration: SlotDuration,
+	/// The slot duration schedule of the runtime, a cache of its own following
+	/// [`Self::slot_duration`] if `None`.
+	///
+	/// Should be the cache the import queue and [`Self::create_inherent_data_providers`] look the
+	/// schedule up in, so that the runtime is only asked once per change.
+	pub slot_durations: Option<Arc<RuntimeSlotDurations<B, C, A>>>,
 	/// The client to interact with the chain.
 	pub client: Arc<C>,
 	/// A select chain implementation to select the best block.
@@ -155,12 +266,57 @@ pub struct StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, N> {
 	///
 	/// If in doubt, use `Default::default()`.
 	pub compatibility_mode: CompatibilityMode<N>,
+	/// Monitor of the local clock drift, authoring is refused while it reports a drift too large.
+	pub clock_drift: Option<ClockDriftMonitor>,
+	/// Cache of the authority sets, looked up before asking the runtime.
+	///
+	/// Must be the cache of a [`MiccBlockImport`] wrapping every import of blocks, including the
+	/// blocks authored locally through `block_import`.
+	pub authority_sets: Option<SharedAuthoritySets<H, N, A>>,
+	/// History of the headers sealed by the local keys, sealing is refused if it would equivocate.
+	pub signing_history: Option<SigningHistory>,
+	/// Finalize the blocks authored while the local key is the only authority, with a Micc
+	/// justification, see [`justification::instant_justification`].
+	pub instant_finality: bool,
+	/// Where to record the transaction imports and block triggers, for an [`OrderedPool`] to order
+	/// the proposed transactions by. Only the event-driven production of [`start_micc`] records
+	/// them.
+	pub transaction_arrivals: Option<TransactionArrivals<TH>>,
+	/// Opened and sealed around the collection windows of the event-driven production of
+	/// [`start_micc`], for a [`SpeculativeProposerFactory`] to build blocks during the windows.
+	pub speculation: Option<Speculation>,
+	/// Sizes the collection windows of the event-driven production of [`start_micc`], fed by
+	/// [`measure_authored_blocks`]. Should be created from [`Self::event_config`].
+	pub collection_controller: Option<CollectionController>,
+	/// Configuration of the event-driven production of [`start_micc`].
+	pub event_config: event_driven::EventDrivenConfig,
+	/// How [`start_micc`] requests blocks: on demand, or at every slot with transactions ready.
+	pub production_mode: ProductionMode,
+	/// Restarts the event-driven trigger stream of [`start_micc`] should it end, requesting blocks
+	/// at every slot with transactions ready meanwhile.
+	pub trigger_supervisor: Option<TriggerSupervisor>,
+	/// Notified of every block authored, see [`BuildMiccWorkerParams::authoring_observers`].
+	pub authoring_observers: Vec<Arc<dyn AuthoringObserver<B>>>,
 }
 
 /// Start the micc worker with event-driven block production.
 /// This is the main function that replaces polling with transaction pool event monitoring.
 pub fn start_micc<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
-    params: StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, NumberFor<B>>,
+    params: StartMiccParams<
+        C,
+        SC,
+        I,
+        PF,
+        SO,
+        L,
+        CIDP,
+        BS,
+        NumberFor<B>,
+        B::Hash,
+        AuthorityId<P>,
+        B,
+        TxHash<TExPool>,
+    >,
     pool: Arc<TExPool>,
 ) -> Result<impl Future<Output = ()>, ConsensusError>
 where
@@ -168,7 +324,14 @@ where
     P::Public: AppPublic + Member,
     P::Signature: TryFrom<Vec<u8>> + Member + Codec,
     B: BlockT,
-    C: ProvideRuntimeApi<B> + BlockOf + AuxStore + HeaderBackend<B> + Send + Sync + 'static,
+    C: ProvideRuntimeApi<B>
+        + BlockOf
+        + AuxStore
+        + HeaderBackend<B>
+        + HeaderMetadata<B, Error = sp_blockchain::Error>
+        + Send
+        + Sync
+        + 'static,
     C::Api: MiccApi<B, AuthorityId<P>>,
     SC: SelectChain<B> + 'static,
     I: BlockImport<B> + Send + Sync + 'static,
@@ -182,20 +345,33 @@ where
     Error: std::error::Error + Send + From<ConsensusError> + 'static,
     TExPool: MaintainedTransactionPool<Block = B, Hash = <B as BlockT>::Hash> + 'static,
 {
+    if params.production_mode == ProductionMode::Hybrid {
+        info!(target: LOG_TARGET, "Starting MICC consensus with hybrid block production");
+
+        return start_micc_hybrid::<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
+            params,
+            pool,
+            None,
+        )
+        .map(future::Either::Left)
+    }
+
     info!(target: LOG_TARGET, "Starting MICC consensus with true event-driven block production");
     
     // Use the new true event-driven implementation with import notifications
     start_micc_true_event_driven::<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
-        params, 
+        params,
         pool,
-        None, // Use default configuration
+        None,
     )
+    .map(future::Either::Right)
 }
 
 /// Start the micc worker. The returned future should be run in a futures executor.
 pub fn start_micc_v2<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
     StartMiccParams {
         slot_duration,
+        slot_durations,
         client,
         select_chain,
         block_import,
@@ -210,7 +386,32 @@ pub fn start_micc_v2<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
         max_block_proposal_slot_portion,
         telemetry,
         compatibility_mode,
-    }: StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, NumberFor<B>>,
+        clock_drift,
+        authority_sets,
+        signing_history,
+        instant_finality,
+        transaction_arrivals: _,
+        speculation: _,
+        collection_controller: _,
+        event_config: _,
+        production_mode: _,
+        trigger_supervisor: _,
+        authoring_observers,
+    }: StartMiccParams<
+        C,
+        SC,
+        I,
+        PF,
+        SO,
+        L,
+        CIDP,
+        BS,
+        NumberFor<B>,
+        B::Hash,
+        AuthorityId<P>,
+        B,
+        TxHash<TExPool>,
+    >,
     pool: Arc<TExPool>, // Use reference to the transaction pool
 ) -> Result<impl Future<Output = ()>, ConsensusError>
 where
@@ -218,7 +419,13 @@ where
     P::Public: AppPublic + Member,
     P::Signature: TryFrom<Vec<u8>> + Member + Codec,
     B: BlockT,
-    C: ProvideRuntimeApi<B> + BlockOf + AuxStore + HeaderBackend<B> + Send + Sync,
+    C: ProvideRuntimeApi<B>
+        + BlockOf
+        + AuxStore
+        + HeaderBackend<B>
+        + HeaderMetadata<B, Error = sp_blockchain::Error>
+        + Send
+        + Sync,
     C::Api: MiccApi<B, AuthorityId<P>>,
     SC: SelectChain<B>,
     I: BlockImport<B> + Send + Sync + 'static,
@@ -232,6 +439,7 @@ where
     Error: std::error::Error + Send + From<ConsensusError> + 'static,
     TExPool: MaintainedTransactionPool<Block = B, Hash = <B as BlockT>::Hash> + 'static,
 {
+    let slot_durations = shared_slot_durations(slot_durations, &client, slot_duration);
     let worker = build_micc_worker::<P, _, _, _, _, _, _, _, _>(BuildMiccWorkerParams {
         client,
         block_import,
@@ -245,6 +453,11 @@ where
         block_proposal_slot_portion,
         max_block_proposal_slot_portion,
         compatibility_mode,
+        clock_drift,
+        authority_sets,
+        signing_history,
+        instant_finality,
+        authoring_observers,
     });
     
     // Check if there are any transactions in pool every 500 ms
@@ -254,7 +467,7 @@ where
 			let pool_status = pool.status();
 			if pool_status.ready > 0 {
 				info!(target: LOG_TARGET, "Transaction pool has {} ready transactions", pool_status.ready);
-				return SlotTrigger::CreateBlock;
+				return SlotTrigger::CreateBlock(ProposalBudget::BATCH);
 			}
 			SlotTrigger::NoAction
 		});
@@ -262,7 +475,7 @@ where
 	info!(target: LOG_TARGET, "Starting Micc slot worker");
 
 	Ok(sc_consensus_slots::start_slot_worker_v2(
-		slot_duration,
+		slot_durations,
 		select_chain,
 		SimpleSlotWorkerToSlotWorker(worker),
 		sync_oracle,
@@ -274,7 +487,21 @@ where
 /// Start the micc worker with true event-driven block production.
 /// This completely replaces polling with transaction pool event monitoring for optimal efficiency.
 pub fn start_micc_event_driven<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
-    params: StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, NumberFor<B>>,
+    params: StartMiccParams<
+        C,
+        SC,
+        I,
+        PF,
+        SO,
+        L,
+        CIDP,
+        BS,
+        NumberFor<B>,
+        B::Hash,
+        AuthorityId<P>,
+        B,
+        TxHash<TExPool>,
+    >,
     pool: Arc<TExPool>,
     event_config: Option<crate::event_driven::EventDrivenConfig>,
 ) -> Result<impl Future<Output = ()>, ConsensusError>
@@ -283,7 +510,14 @@ where
     P::Public: AppPublic + Member,
     P::Signature: TryFrom<Vec<u8>> + Member + Codec,
     B: BlockT,
-    C: ProvideRuntimeApi<B> + BlockOf + AuxStore + HeaderBackend<B> + Send + Sync + 'static,
+    C: ProvideRuntimeApi<B>
+        + BlockOf
+        + AuxStore
+        + HeaderBackend<B>
+        + HeaderMetadata<B, Error = sp_blockchain::Error>
+        + Send
+        + Sync
+        + 'static,
     C::Api: MiccApi<B, AuthorityId<P>>,
     SC: SelectChain<B> + 'static,
     I: BlockImport<B> + Send + Sync + 'static,
@@ -299,8 +533,13 @@ where
 {
     use crate::event_driven::create_event_driven_stream;
     
-    let config = event_config.unwrap_or_default();
+    let config = event_config.unwrap_or_else(|| params.event_config.clone());
     
+    let slot_durations = shared_slot_durations(
+        params.slot_durations.clone(),
+        &params.client,
+        params.slot_duration,
+    );
     let worker = build_micc_worker::<P, _, _, _, _, _, _, _, _>(BuildMiccWorkerParams {
         client: params.client,
         block_import: params.block_import,
@@ -314,6 +553,11 @@ where
         block_proposal_slot_portion: params.block_proposal_slot_portion,
         max_block_proposal_slot_portion: params.max_block_proposal_slot_portion,
         compatibility_mode: params.compatibility_mode,
+        clock_drift: params.clock_drift,
+        authority_sets: params.authority_sets,
+        signing_history: params.signing_history,
+        instant_finality: params.instant_finality,
+        authoring_observers: params.authoring_observers,
     });
 
     info!(target: LOG_TARGET, "Starting true event-driven Micc consensus");
@@ -323,7 +567,7 @@ where
 
     // Use the existing start_slot_worker_v2 with our event-driven stream
     Ok(sc_consensus_slots::start_slot_worker_v2(
-        params.slot_duration,
+        slot_durations,
         params.select_chain,
         SimpleSlotWorkerToSlotWorker(worker),
         params.sync_oracle,
@@ -335,7 +579,21 @@ where
 /// Start the micc worker with the new true event-driven approach using import notifications.
 /// This provides 0ms response time to transaction arrival for optimal block production.
 pub fn start_micc_true_event_driven<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
-    params: StartMiccParams<C, SC, I, PF, SO, L, CIDP, BS, NumberFor<B>>,
+    params: StartMiccParams<
+        C,
+        SC,
+        I,
+        PF,
+        SO,
+        L,
+        CIDP,
+        BS,
+        NumberFor<B>,
+        B::Hash,
+        AuthorityId<P>,
+        B,
+        TxHash<TExPool>,
+    >,
     pool: Arc<TExPool>,
     event_config: Option<crate::event_driven::EventDrivenConfig>,
 ) -> Result<impl Future<Output = ()>, ConsensusError>
@@ -344,7 +602,14 @@ where
     P::Public: AppPublic + Member,
     P::Signature: TryFrom<Vec<u8>> + Member + Codec,
     B: BlockT,
-    C: ProvideRuntimeApi<B> + BlockOf + AuxStore + HeaderBackend<B> + Send + Sync + 'static,
+    C: ProvideRuntimeApi<B>
+        + BlockOf
+        + AuxStore
+        + HeaderBackend<B>
+        + HeaderMetadata<B, Error = sp_blockchain::Error>
+        + Send
+        + Sync
+        + 'static,
     C::Api: MiccApi<B, AuthorityId<P>>,
     SC: SelectChain<B> + 'static,
     I: BlockImport<B> + Send + Sync + 'static,
@@ -358,10 +623,19 @@ where
     Error: std::error::Error + Send + From<ConsensusError> + 'static,
     TExPool: MaintainedTransactionPool<Block = B, Hash = <B as BlockT>::Hash> + 'static,
 {
-    use crate::event_driven::create_true_event_driven_stream;
+    use crate::event_driven::{create_hybrid_stream, create_true_event_driven_stream};
+    use crate::required_blocks::create_required_block_stream;
     
-    let config = event_config.unwrap_or_default();
+    let config = event_config.unwrap_or_else(|| params.event_config.clone());
     
+    let slot_durations = shared_slot_durations(
+        params.slot_durations.clone(),
+        &params.client,
+        params.slot_duration,
+    );
+    let proposal_portion = params.block_proposal_slot_portion.get();
+    let required_blocks =
+        create_required_block_stream::<B, _, AuthorityId<P>>(params.client.clone());
     let worker = build_micc_worker::<P, _, _, _, _, _, _, _, _>(BuildMiccWorkerParams {
         client: params.client,
         block_import: params.block_import,
@@ -375,16 +649,163 @@ where
         block_proposal_slot_portion: params.block_proposal_slot_portion,
         max_block_proposal_slot_portion: params.max_block_proposal_slot_portion,
         compatibility_mode: params.compatibility_mode,
+        clock_drift: params.clock_drift,
+        authority_sets: params.authority_sets,
+        signing_history: params.signing_history,
+        instant_finality: params.instant_finality,
+        authoring_observers: params.authoring_observers,
     });
 
     info!(target: LOG_TARGET, "Starting TRUE event-driven Micc consensus with import notifications");
 
     // Create true event-driven stream using transaction pool import notifications
-    let event_stream = create_true_event_driven_stream::<B, TExPool>(pool, config);
+    let arrivals = params.transaction_arrivals;
+    let speculation = params.speculation;
+    let controller = params.collection_controller;
+    let event_stream = match params.trigger_supervisor {
+        Some(supervisor) => {
+            let primary_pool = pool.clone();
+            let primary_config = config.clone();
+            let primary_arrivals = arrivals.clone();
+            let fallback_durations = slot_durations.clone();
+            supervisor.supervise(
+                move || {
+                    create_true_event_driven_stream::<B, TExPool>(
+                        primary_pool.clone(),
+                        primary_config.clone(),
+                        primary_arrivals.clone(),
+                        speculation.clone(),
+                        controller.clone(),
+                    )
+                },
+                move || {
+                    create_hybrid_stream::<B, TExPool, _>(
+                        pool.clone(),
+                        config.clone(),
+                        fallback_durations.clone(),
+                        SlotProportion::new(proposal_portion),
+                        arrivals.clone(),
+                    )
+                },
+            )
+        },
+        None => create_true_event_driven_stream::<B, TExPool>(
+            pool,
+            config,
+            arrivals,
+            speculation,
+            controller,
+        ),
+    };
+    // The runtime may need blocks for work of its own while no transaction requests any.
+    let event_stream = stream::select(event_stream, required_blocks);
 
     // Use the existing start_slot_worker_v2 with our true event-driven stream
     Ok(sc_consensus_slots::start_slot_worker_v2(
+        slot_durations,
+        params.select_chain,
+        SimpleSlotWorkerToSlotWorker(worker),
+        params.sync_oracle,
+        params.create_inherent_data_providers,
+        event_stream,
+    ))
+}
+
+/// Start the micc worker with hybrid block production: a block at every slot while transactions
+/// are ready, proposed within the [`SlotProportion`] of the slot, and an early block within the
+/// slot for high-priority transactions, see [`event_driven::create_hybrid_stream`].
+pub fn start_micc_hybrid<P, B, C, SC, I, PF, SO, L, CIDP, BS, Error, TExPool>(
+    params: StartMiccParams<
+        C,
+        SC,
+        I,
+        PF,
+        SO,
+        L,
+        CIDP,
+        BS,
+        NumberFor<B>,
+        B::Hash,
+        AuthorityId<P>,
+        B,
+        TxHash<TExPool>,
+    >,
+    pool: Arc<TExPool>,
+    event_config: Option<crate::event_driven::EventDrivenConfig>,
+) -> Result<impl Future<Output = ()>, ConsensusError>
+where
+    P: Pair,
+    P::Public: AppPublic + Member,
+    P::Signature: TryFrom<Vec<u8>> + Member + Codec,
+    B: BlockT,
+    C: ProvideRuntimeApi<B>
+        + BlockOf
+        + AuxStore
+        + HeaderBackend<B>
+        + HeaderMetadata<B, Error = sp_blockchain::Error>
+        + Send
+        + Sync
+        + 'static,
+    C::Api: MiccApi<B, AuthorityId<P>>,
+    SC: SelectChain<B> + 'static,
+    I: BlockImport<B> + Send + Sync + 'static,
+    PF: Environment<B, Error = Error> + Send + Sync + 'static,
+    PF::Proposer: Proposer<B, Error = Error>,
+    SO: SyncOracle + Send + Sync + Clone + 'static,
+    L: sc_consensus::JustificationSyncLink<B>,
+    CIDP: CreateInherentDataProviders<B, ()> + Send + 'static,
+    CIDP::InherentDataProviders: InherentDataProviderExt + Send,
+    BS: BackoffAuthoringBlocksStrategy<NumberFor<B>> + Send + Sync + 'static,
+    Error: std::error::Error + Send + From<ConsensusError> + 'static,
+    TExPool: MaintainedTransactionPool<Block = B, Hash = <B as BlockT>::Hash> + 'static,
+{
+    use crate::event_driven::create_hybrid_stream;
+    use crate::required_blocks::create_required_block_stream;
+
+    let config = event_config.unwrap_or_else(|| params.event_config.clone());
+
+    // Shared with the stream, which follows the slots as the worker refreshes their schedule.
+    let slot_durations = shared_slot_durations(
+        params.slot_durations.clone(),
+        &params.client,
         params.slot_duration,
+    );
+    let proposal_portion = params.block_proposal_slot_portion.get();
+    let required_blocks =
+        create_required_block_stream::<B, _, AuthorityId<P>>(params.client.clone());
+    let worker = build_micc_worker::<P, _, _, _, _, _, _, _, _>(BuildMiccWorkerParams {
+        client: params.client,
+        block_import: params.block_import,
+        proposer_factory: params.proposer_factory,
+        keystore: params.keystore,
+        sync_oracle: params.sync_oracle.clone(),
+        justification_sync_link: params.justification_sync_link,
+        force_authoring: params.force_authoring,
+        backoff_authoring_blocks: params.backoff_authoring_blocks,
+        telemetry: params.telemetry,
+        block_proposal_slot_portion: params.block_proposal_slot_portion,
+        max_block_proposal_slot_portion: params.max_block_proposal_slot_portion,
+        compatibility_mode: params.compatibility_mode,
+        clock_drift: params.clock_drift,
+        authority_sets: params.authority_sets,
+        signing_history: params.signing_history,
+        instant_finality: params.instant_finality,
+        authoring_observers: params.authoring_observers,
+    });
+
+    info!(target: LOG_TARGET, "Starting hybrid Micc consensus");
+
+    let event_stream = create_hybrid_stream::<B, TExPool, _>(
+        pool,
+        config,
+        slot_durations.clone(),
+        SlotProportion::new(proposal_portion),
+        params.transaction_arrivals,
+    );
+    let event_stream = stream::select(event_stream, required_blocks);
+
+    Ok(sc_consensus_slots::start_slot_worker_v2(
+        slot_durations,
         params.select_chain,
         SimpleSlotWorkerToSlotWorker(worker),
         params.sync_oracle,
@@ -393,8 +814,18 @@ where
     ))
 }
 
+/// The slot durations shared by the caller, or a cache of their own following `slot_duration`.
+fn shared_slot_durations<B: BlockT, C, A>(
+    slot_durations: Option<Arc<RuntimeSlotDurations<B, C, A>>>,
+    client: &Arc<C>,
+    slot_duration: SlotDuration,
+) -> Arc<RuntimeSlotDurations<B, C, A>> {
+    slot_durations
+        .unwrap_or_else(|| Arc::new(RuntimeSlotDurations::new(client.clone(), slot_duration)))
+}
+
 /// Parameters of [`build_micc_worker`].
-pub struct BuildMiccWorkerParams<C, I, PF, SO, L, BS, N> {
+pub struct BuildMiccWorkerParams<C, I, PF, SO, L, BS, N, H, A, B> {
 	/// The client to interact with the chain.
 	pub client: Arc<C>,
 	/// The block import.
@@ -426,6 +857,21 @@ pub struct BuildMiccWorkerParams<C, I, PF, SO, L, BS, N> {
 	///
 	/// If in doubt, use `Default::default()`.
 	pub compatibility_mode: CompatibilityMode<N>,
+	/// Monitor of the local clock drift, authoring is refused while it reports a drift too large.
+	pub clock_drift: Option<ClockDriftMonitor>,
+	/// Cache of the authority sets, looked up before asking the runtime.
+	///
+	/// Must be the cache of a [`MiccBlockImport`] wrapping every import of blocks, including the
+	/// blocks authored locally through `block_import`.
+	pub authority_sets: Option<SharedAuthoritySets<H, N, A>>,
+	/// History of the headers sealed by the local keys, sealing is refused if it would equivocate.
+	pub signing_history: Option<SigningHistory>,
+	/// Finalize the blocks authored while the local key is the only authority, with a Micc
+	/// justification, see [`justification::instant_justification`].
+	pub instant_finality: bool,
+	/// Notified of every block authored, with what led to it, how long it took and whether it
+	/// was imported.
+	pub authoring_observers: Vec<Arc<dyn AuthoringObserver<B>>>,
 }
 
 /// Build the micc worker.
@@ -445,19 +891,30 @@ pub fn build_micc_worker<P, B, C, PF, I, SO, L, BS, Error>(
 		telemetry,
 		force_authoring,
 		compatibility_mode,
-	}: BuildMiccWorkerParams<C, I, PF, SO, L, BS, NumberFor<B>>,
+		clock_drift,
+		authority_sets,
+		signing_history,
+		instant_finality,
+		authoring_observers,
+	}: BuildMiccWorkerParams<C, I, PF, SO, L, BS, NumberFor<B>, B::Hash, AuthorityId<P>, B>,
 ) -> impl sc_consensus_slots::SimpleSlotWorker<
 	B,
 	Proposer = PF::Proposer,
 	BlockImport = I,
 	SyncOracle = SO,
 	JustificationSyncLink = L,
-	Claim = P::Public,
-	AuxData = Vec<AuthorityId<P>>,
+	Claim = MiccClaim<P::Public>,
+	AuxData = SlotAuthorities<AuthorityId<P>>,
 >
 where
 	B: BlockT,
-	C: ProvideRuntimeApi<B> + BlockOf + AuxStore + HeaderBackend<B> + Send + Sync,
+	C: ProvideRuntimeApi<B>
+		+ BlockOf
+		+ AuxStore
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ Send
+		+ Sync,
 	C::Api: MiccApi<B, AuthorityId<P>>,
 	PF: Environment<B, Error = Error> + Send + Sync + 'static,
 	PF::Proposer: Proposer<B, Error = Error>,
@@ -483,11 +940,44 @@ where
 		block_proposal_slot_portion,
 		max_block_proposal_slot_portion,
 		compatibility_mode,
+		clock_drift,
+		authority_sets,
+		signing_history,
+		instant_finality,
+		authoring_observers,
+		first_claims: None,
 		_phantom: PhantomData::<fn() -> P>,
 	}
 }
 
-struct MiccWorker<C, E, I, P, SO, L, BS, N> {
+/// Notified of the blocks authored by the local node, for integrations such as indexing, latency
+/// tracking or alerting.
+///
+/// Observers are called on the authoring path, and should hand any lengthy work over to a task of
+/// their own.
+pub trait AuthoringObserver<B>: Send + Sync {
+	/// Called once a block was authored, whether its import succeeded or not.
+	fn on_block_authored(&self, authored: &AuthoredBlock<B>);
+}
+
+impl<B, F: Fn(&AuthoredBlock<B>) + Send + Sync> AuthoringObserver<B> for F {
+	fn on_block_authored(&self, authored: &AuthoredBlock<B>) {
+		self(authored)
+	}
+}
+
+/// A slot claimed by a local authority key.
+#[derive(Clone, Debug)]
+pub struct MiccClaim<Public> {
+	/// The key sealing the block.
+	pub public: Public,
+	/// The index of the key in the authority set, named in the pre-digest when authoring under
+	/// [`AuthorshipMode::AnyAuthority`], or a slot owned by another authority under
+	/// [`AuthorshipMode::Permissive`].
+	pub author_index: Option<AuthorityIndex>,
+}
+
+struct MiccWorker<C, E, I, P: Pair, SO, L, BS, N, H, B> {
 	client: Arc<C>,
 	block_import: I,
 	env: E,
@@ -500,15 +990,28 @@ struct MiccWorker<C, E, I, P, SO, L, BS, N> {
 	max_block_proposal_slot_portion: Option<SlotProportion>,
 	telemetry: Option<TelemetryHandle>,
 	compatibility_mode: CompatibilityMode<N>,
+	clock_drift: Option<ClockDriftMonitor>,
+	authority_sets: Option<SharedAuthoritySets<H, N, AuthorityId<P>>>,
+	signing_history: Option<SigningHistory>,
+	instant_finality: bool,
+	authoring_observers: Vec<Arc<dyn AuthoringObserver<B>>>,
+	/// The parent last claimed on under [`AuthorshipMode::AnyAuthority`], and the first slot it
+	/// was claimed at.
+	first_claims: Option<(H, Slot)>,
 	_phantom: PhantomData<fn() -> P>,
 }
 
 #[async_trait::async_trait]
 impl<B, C, E, I, P, Error, SO, L, BS> sc_consensus_slots::SimpleSlotWorker<B>
-	for MiccWorker<C, E, I, P, SO, L, BS, NumberFor<B>>
+	for MiccWorker<C, E, I, P, SO, L, BS, NumberFor<B>, B::Hash, B>
 where
 	B: BlockT,
-	C: ProvideRuntimeApi<B> + BlockOf + HeaderBackend<B> + Sync,
+	C: ProvideRuntimeApi<B>
+		+ BlockOf
+		+ AuxStore
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ Sync,
 	C::Api: MiccApi<B, AuthorityId<P>>,
 	E: Environment<B, Error = Error> + Send + Sync,
 	E::Proposer: Proposer<B, Error = Error>,
@@ -527,8 +1030,8 @@ where
 	type CreateProposer =
 		Pin<Box<dyn Future<Output = Result<E::Proposer, ConsensusError>> + Send + 'static>>;
 	type Proposer = E::Proposer;
-	type Claim = P::Public;
-	type AuxData = Vec<AuthorityId<P>>;
+	type Claim = MiccClaim<P::Public>;
+	type AuxData = SlotAuthorities<AuthorityId<P>>;
 
 	fn logging_target(&self) -> &'static str {
 		"micc"
@@ -544,6 +1047,7 @@ where
 			header.hash(),
 			*header.number() + 1u32.into(),
 			&self.compatibility_mode,
+			self.authority_sets.as_ref(),
 		)
 	}
 
@@ -553,29 +1057,82 @@ where
 
 	async fn claim_slot(
 		&mut self,
-		_header: &B::Header,
+		header: &B::Header,
 		slot: Slot,
 		authorities: &Self::AuxData,
 	) -> Option<Self::Claim> {
-		// For force authoring (dev mode), allow any authority in keystore to claim any slot
-		if self.force_authoring {
-			// Try to find any authority key in our keystore that can sign
-			for authority in authorities {
-				if self.keystore.has_keys(&[(authority.to_raw_vec(), MICC)]) {
-					log::info!(target: "micc", "🔧 Force authoring: claiming slot {} with available authority", slot);
-					return Some(authority.clone());
+		let claim = 'claim: {
+			// Any local authority key may claim any slot, naming itself in the pre-digest. The
+			// authorities take turns in the rotation of the first slot claimed on the parent, one
+			// more each slot, so that they don't all author siblings at once.
+			if authorities.mode == AuthorshipMode::AnyAuthority {
+				let since = match self.first_claims {
+					Some((parent, since)) if parent == header.hash() => since,
+					_ => self.first_claims.insert((header.hash(), slot)).1,
+				};
+				let waited = u64::from(slot).saturating_sub(since.into());
+				let len = authorities.len();
+				let local = authorities
+					.authorities
+					.iter()
+					.enumerate()
+					.filter(|(_, authority)| {
+						self.keystore.has_keys(&[(authority.to_raw_vec(), MICC)])
+					})
+					.filter_map(|(index, authority)| {
+						let index = index as AuthorityIndex;
+						let rank = authorities.selector.rotation_rank(since, index, len)?;
+						Some((rank, index, authority))
+					})
+					.min_by_key(|(rank, ..)| *rank)
+					.filter(|(rank, ..)| u64::from(*rank) <= waited);
+				break 'claim local.map(|(_, index, authority)| MiccClaim {
+					public: authority.clone(),
+					author_index: Some(index),
+				});
+			}
+
+			// For force authoring (dev mode), allow any authority in keystore to claim any slot
+			if self.force_authoring {
+				// Name the author unless it owns the slot, for the runtime to check it.
+				let owner = crate::standalone::slot_author::<P>(slot, authorities);
+				let permissive = authorities.mode == AuthorshipMode::Permissive;
+				// Try to find any authority key in our keystore that can sign
+				for (index, authority) in authorities.authorities.iter().enumerate() {
+					if self.keystore.has_keys(&[(authority.to_raw_vec(), MICC)]) {
+						log::info!(target: "micc", "🔧 Force authoring: claiming slot {} with available authority", slot);
+						let author_index = (permissive && owner != Some(authority))
+							.then_some(index as AuthorityIndex);
+						break 'claim Some(MiccClaim { public: authority.clone(), author_index });
+					}
 				}
+				log::debug!(target: "micc", "🔧 Force authoring: no authority keys available in keystore for slot {}", slot);
+				break 'claim None;
 			}
-			log::debug!(target: "micc", "🔧 Force authoring: no authority keys available in keystore for slot {}", slot);
-			return None;
+
+			// Normal mode: use strict slot assignment
+			crate::standalone::claim_slot::<P>(slot, authorities, &self.keystore)
+				.await
+				.map(|public| MiccClaim { public, author_index: None })
+		};
+
+		// Only complain about the clock when it actually keeps us from authoring.
+		if let (Some(_), Some(Err(e))) =
+			(&claim, self.clock_drift.as_ref().map(ClockDriftMonitor::check))
+		{
+			log::error!(target: LOG_TARGET, "Refusing to author slot {}: {}", slot, e);
+			return None
 		}
-		
-		// Normal mode: use strict slot assignment
-		crate::standalone::claim_slot::<P>(slot, authorities, &self.keystore).await
+
+		claim
 	}
 
-	fn pre_digest_data(&self, slot: Slot, _claim: &Self::Claim) -> Vec<sp_runtime::DigestItem> {
-		vec![crate::standalone::pre_digest::<P>(slot)]
+	fn pre_digest_data(&self, slot: Slot, claim: &Self::Claim) -> Vec<sp_runtime::DigestItem> {
+		match claim.author_index {
+			Some(author_index) =>
+				vec![crate::standalone::author_pre_digest::<P>(slot, author_index)],
+			None => vec![crate::standalone::pre_digest::<P>(slot)],
+		}
 	}
 
 	async fn block_import_params(
@@ -584,9 +1141,20 @@ where
 		header_hash: &B::Hash,
 		body: Vec<B::Extrinsic>,
 		storage_changes: StorageChanges<B>,
-		public: Self::Claim,
-		_authorities: Self::AuxData,
+		MiccClaim { public, .. }: Self::Claim,
+		authorities: Self::AuxData,
 	) -> Result<sc_consensus::BlockImportParams<B>, ConsensusError> {
+		if let Some(signing_history) = &self.signing_history {
+			let slot = find_pre_digest::<B, P::Signature>(&header)
+				.map_err(|e| ConsensusError::CannotSign(e.to_string()))?;
+			signing_history
+				.record_sealing(&*self.client, public.as_slice(), slot, header_hash.as_ref())
+				.map_err(|e| {
+					log::error!(target: LOG_TARGET, "Refusing to seal {:?}: {}", header_hash, e);
+					ConsensusError::CannotSign(e.to_string())
+				})?;
+		}
+
 		let signature_digest_item =
 			crate::standalone::seal::<_, P>(header_hash, &public, &self.keystore)?;
 
@@ -595,7 +1163,23 @@ where
 		import_block.body = Some(body);
 		import_block.state_action =
 			StateAction::ApplyChanges(sc_consensus::StorageChanges::Changes(storage_changes));
-		import_block.fork_choice = Some(ForkChoiceStrategy::LongestChain);
+
+		let post_hash = import_block.post_hash();
+		import_block.fork_choice = Some(crate::select_chain::fork_choice::<B, C, P>(
+			&*self.client,
+			&import_block.header,
+			post_hash,
+			authorities.mode,
+			&self.compatibility_mode,
+			self.authority_sets.as_ref(),
+		)?);
+
+		if self.instant_finality && justification::sole_authority(&authorities).is_some() {
+			let justification =
+				justification::instant_justification::<_, P>(&post_hash, &public, &self.keystore)?;
+			import_block.justifications = Some(justification.into());
+			import_block.finalized = true;
+		}
 
 		Ok(import_block)
 	}
@@ -639,6 +1223,12 @@ where
 	}
 
 	fn proposing_remaining_duration(&self, slot_info: &SlotInfo<B>) -> std::time::Duration {
+		// Blocks authored on demand skip slots as a matter of course, their trigger sets the
+		// deadline instead of the lenience over skipped slots.
+		if let Some(deadline) = slot_info.proposing_deadline {
+			return deadline.saturating_duration_since(std::time::Instant::now())
+		}
+
 		let parent_slot = find_pre_digest::<B, P::Signature>(&slot_info.chain_head).ok();
 
 		sc_consensus_slots::proposing_remaining_duration(
@@ -650,6 +1240,12 @@ where
 			self.logging_target(),
 		)
 	}
+
+	fn on_block_authored(&self, authored: &AuthoredBlock<B>) {
+		for observer in &self.authoring_observers {
+			observer.on_block_authored(authored);
+		}
+	}
 }
 
 /// Micc Errors
@@ -704,13 +1300,28 @@ fn authorities<A, B, C>(
 	parent_hash: B::Hash,
 	context_block_number: NumberFor<B>,
 	compatibility_mode: &CompatibilityMode<NumberFor<B>>,
-) -> Result<Vec<A>, ConsensusError>
+	authority_sets: Option<&SharedAuthoritySets<B::Hash, NumberFor<B>, A>>,
+) -> Result<SlotAuthorities<A>, ConsensusError>
 where
-	A: Codec + Debug,
+	A: Codec + Debug + Clone,
 	B: BlockT,
-	C: ProvideRuntimeApi<B>,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + HeaderMetadata<B, Error = sp_blockchain::Error>,
 	C::Api: MiccApi<B, A>,
 {
+	let cached = match compatibility_mode {
+		CompatibilityMode::UseInitializeBlock { until } if *until > context_block_number => None,
+		_ => authority_sets.and_then(|authority_sets| {
+			authority_sets.authorities_for_child_of(
+				&parent_hash,
+				&context_block_number.saturating_sub(One::one()),
+				&sc_client_api::utils::is_descendent_of(client, None),
+			)
+		}),
+	};
+	if let Some(authorities) = cached {
+		return Ok(authorities)
+	}
+
 	let runtime_api = client.runtime_api();
 
 	match compatibility_mode {
@@ -733,8 +1344,12 @@ where
 			},
 	}
 
-	runtime_api
+	let authorities = runtime_api
 		.authorities(parent_hash)
 		.ok()
-		.ok_or(ConsensusError::InvalidAuthoritiesSet)
+		.ok_or(ConsensusError::InvalidAuthoritiesSet)?;
+	let selector = standalone::slot_author_selector_at::<A, B, _>(&*runtime_api, parent_hash)?;
+	let mode = standalone::authorship_mode_at::<A, B, _>(&*runtime_api, parent_hash)?;
+
+	Ok(SlotAuthorities { authorities, selector, mode })
 }
@@ -0,0 +1,507 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Ordering of the transactions proposed in a block.
+//!
+//! `sc_basic_authorship::ProposerFactory` includes the ready transactions in the order the pool
+//! yields them, by priority. Built over an [`OrderedPool`] instead of the pool itself, it follows a
+//! [`TransactionOrdering`], still never proposing a transaction before those it depends on.
+
+use std::{
+	cmp::Reverse,
+	collections::{BTreeMap, BinaryHeap, HashMap, HashSet},
+	fmt,
+	hash::Hash,
+	ops::Deref,
+	pin::Pin,
+	str::FromStr,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use sc_transaction_pool_api::{
+	ImportNotificationStream, InPoolTransaction, PoolStatus, ReadyIteratorFor, ReadyTransactions,
+	TransactionFor, TransactionPool, TransactionSource, TransactionStatusStreamFor, TxHash,
+	TxInvalidityReportMap,
+};
+use sp_runtime::{traits::Block as BlockT, transaction_validity::TransactionTag};
+
+/// Maximum number of transaction arrivals remembered, the oldest one is forgotten first.
+const MAX_ARRIVALS: usize = 65_536;
+
+/// The order in which the ready transactions are proposed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TransactionOrdering {
+	/// Highest priority first, as the transaction pool yields them.
+	#[default]
+	Priority,
+	/// First come, first served, by the time the transactions were imported in the pool.
+	FirstCome,
+	/// The transactions that triggered the block first, by import time, then the others by
+	/// priority.
+	TriggeringFirst,
+}
+
+impl FromStr for TransactionOrdering {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"priority" => Ok(Self::Priority),
+			"first-come" => Ok(Self::FirstCome),
+			"triggering-first" => Ok(Self::TriggeringFirst),
+			_ => Err(format!(
+				"`{s}` is not a transaction ordering, expected `priority`, `first-come` or \
+				 `triggering-first`"
+			)),
+		}
+	}
+}
+
+impl fmt::Display for TransactionOrdering {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Priority => write!(f, "priority"),
+			Self::FirstCome => write!(f, "first-come"),
+			Self::TriggeringFirst => write!(f, "triggering-first"),
+		}
+	}
+}
+
+/// The order in which transactions were imported in the pool, and which of them triggered the
+/// block being authored.
+///
+/// Recorded by the event-driven watcher, see
+/// [`create_true_event_driven_stream`](crate::event_driven::create_true_event_driven_stream).
+pub struct TransactionArrivals<H> {
+	inner: Arc<Mutex<Arrivals<H>>>,
+}
+
+impl<H> Clone for TransactionArrivals<H> {
+	fn clone(&self) -> Self {
+		Self { inner: self.inner.clone() }
+	}
+}
+
+impl<H> Default for TransactionArrivals<H> {
+	fn default() -> Self {
+		Self {
+			inner: Arc::new(Mutex::new(Arrivals {
+				next: 0,
+				by_hash: HashMap::new(),
+				by_arrival: BTreeMap::new(),
+				triggering: 0..0,
+			})),
+		}
+	}
+}
+
+struct Arrivals<H> {
+	next: u64,
+	by_hash: HashMap<H, u64>,
+	by_arrival: BTreeMap<u64, H>,
+	/// Arrivals of the transactions that triggered the last block.
+	triggering: std::ops::Range<u64>,
+}
+
+impl<H: Hash + Eq + Clone> TransactionArrivals<H> {
+	/// Record the import of a transaction, unless it was already recorded.
+	pub fn record(&self, hash: H) {
+		let mut arrivals = self.inner.lock().expect("never poisoned; qed");
+		if arrivals.by_hash.contains_key(&hash) {
+			return
+		}
+
+		let arrival = arrivals.next;
+		arrivals.next += 1;
+		arrivals.by_hash.insert(hash.clone(), arrival);
+		arrivals.by_arrival.insert(arrival, hash);
+
+		if arrivals.by_arrival.len() > MAX_ARRIVALS {
+			if let Some((_, oldest)) = arrivals.by_arrival.pop_first() {
+				arrivals.by_hash.remove(&oldest);
+			}
+		}
+	}
+
+	/// Note that a block is about to be authored for the transactions recorded since the previous
+	/// one.
+	pub fn mark_trigger(&self) {
+		let mut arrivals = self.inner.lock().expect("never poisoned; qed");
+		arrivals.triggering = arrivals.triggering.end..arrivals.next;
+	}
+
+	/// The sort key of each transaction under `ordering`, ties broken by the order of the pool.
+	fn keys<'a>(
+		&self,
+		ordering: TransactionOrdering,
+		hashes: impl Iterator<Item = &'a H>,
+	) -> Vec<(u8, u64)>
+	where
+		H: 'a,
+	{
+		let arrivals = self.inner.lock().expect("never poisoned; qed");
+		hashes
+			.map(|hash| {
+				let arrival = arrivals.by_hash.get(hash).copied();
+				match ordering {
+					TransactionOrdering::Priority => (0, 0),
+					// Transactions imported before the watcher started come last.
+					TransactionOrdering::FirstCome => (0, arrival.unwrap_or(u64::MAX)),
+					TransactionOrdering::TriggeringFirst => match arrival {
+						Some(arrival) if arrivals.triggering.contains(&arrival) => (0, arrival),
+						_ => (1, 0),
+					},
+				}
+			})
+			.collect()
+	}
+}
+
+/// Ready transactions yielded by their sort key, each one after those it requires.
+struct OrderedReady<T> {
+	transactions: Vec<Option<T>>,
+	keys: Vec<(u8, u64)>,
+	/// Number of transactions each transaction still waits for.
+	waiting_for: Vec<usize>,
+	/// Transactions waiting for each transaction.
+	unlocks: Vec<Vec<usize>>,
+	ready: BinaryHeap<Reverse<((u8, u64), usize)>>,
+	/// Tags provided by transactions reported invalid, the transactions requiring them are skipped.
+	invalid: HashSet<TransactionTag>,
+}
+
+impl<T> OrderedReady<T>
+where
+	T: Deref,
+	T::Target: InPoolTransaction,
+{
+	/// Order `transactions`, given in the order of the pool, by `keys`.
+	fn new(transactions: Vec<T>, keys: Vec<(u8, u64)>) -> Self {
+		let mut providers = HashMap::new();
+		for (index, transaction) in transactions.iter().enumerate() {
+			for tag in transaction.provides() {
+				providers.entry(tag.clone()).or_insert(index);
+			}
+		}
+
+		let mut waiting_for = vec![0; transactions.len()];
+		let mut unlocks = vec![Vec::new(); transactions.len()];
+		for (index, transaction) in transactions.iter().enumerate() {
+			for tag in transaction.requires() {
+				if let Some(&provider) = providers.get(tag).filter(|provider| **provider != index) {
+					waiting_for[index] += 1;
+					unlocks[provider].push(index);
+				}
+			}
+		}
+
+		let ready = (0..transactions.len())
+			.filter(|index| waiting_for[*index] == 0)
+			.map(|index| Reverse((keys[index], index)))
+			.collect();
+
+		Self {
+			transactions: transactions.into_iter().map(Some).collect(),
+			keys,
+			waiting_for,
+			unlocks,
+			ready,
+			invalid: HashSet::new(),
+		}
+	}
+}
+
+impl<T> Iterator for OrderedReady<T>
+where
+	T: Deref,
+	T::Target: InPoolTransaction,
+{
+	type Item = T;
+
+	fn next(&mut self) -> Option<T> {
+		while let Some(Reverse((_, index))) = self.ready.pop() {
+			for unlocked in std::mem::take(&mut self.unlocks[index]) {
+				self.waiting_for[unlocked] -= 1;
+				if self.waiting_for[unlocked] == 0 {
+					self.ready.push(Reverse((self.keys[unlocked], unlocked)));
+				}
+			}
+
+			let transaction = self.transactions[index].take()?;
+			if transaction.requires().iter().any(|tag| self.invalid.contains(tag)) {
+				self.invalid.extend(transaction.provides().iter().cloned());
+				continue
+			}
+
+			return Some(transaction)
+		}
+
+		None
+	}
+}
+
+impl<T> ReadyTransactions for OrderedReady<T>
+where
+	T: Deref,
+	T::Target: InPoolTransaction,
+{
+	fn report_invalid(&mut self, transaction: &T) {
+		self.invalid.extend(transaction.provides().iter().cloned());
+	}
+}
+
+/// A transaction pool yielding its ready transactions in a [`TransactionOrdering`] to the block
+/// proposer, to build a `sc_basic_authorship::ProposerFactory` with.
+///
+/// Only [`TransactionPool::ready_at`], [`TransactionPool::ready_at_with_timeout`], which the
+/// proposer reads, and [`TransactionPool::ready`] are reordered. Everything else is the pool's
+/// own.
+pub struct OrderedPool<P: TransactionPool> {
+	pool: Arc<P>,
+	ordering: TransactionOrdering,
+	arrivals: TransactionArrivals<TxHash<P>>,
+}
+
+impl<P: TransactionPool> OrderedPool<P> {
+	/// Order the ready transactions of `pool` in `ordering`, following the import times recorded
+	/// in `arrivals`.
+	pub fn new(
+		pool: Arc<P>,
+		ordering: TransactionOrdering,
+		arrivals: TransactionArrivals<TxHash<P>>,
+	) -> Self {
+		Self { pool, ordering, arrivals }
+	}
+
+	fn order(&self, ready: ReadyIteratorFor<P>) -> ReadyIteratorFor<P> {
+		if self.ordering == TransactionOrdering::Priority {
+			return ready
+		}
+
+		let transactions: Vec<_> = ready.collect();
+		let keys = self
+			.arrivals
+			.keys(self.ordering, transactions.iter().map(|transaction| transaction.hash()));
+
+		Box::new(OrderedReady::new(transactions, keys))
+	}
+}
+
+#[async_trait::async_trait]
+impl<P: TransactionPool> TransactionPool for OrderedPool<P> {
+	type Block = P::Block;
+	type Hash = P::Hash;
+	type InPoolTransaction = P::InPoolTransaction;
+	type Error = P::Error;
+
+	async fn submit_at(
+		&self,
+		at: <Self::Block as BlockT>::Hash,
+		source: TransactionSource,
+		xts: Vec<TransactionFor<Self>>,
+	) -> Result<Vec<Result<TxHash<Self>, Self::Error>>, Self::Error> {
+		self.pool.submit_at(at, source, xts).await
+	}
+
+	async fn submit_one(
+		&self,
+		at: <Self::Block as BlockT>::Hash,
+		source: TransactionSource,
+		xt: TransactionFor<Self>,
+	) -> Result<TxHash<Self>, Self::Error> {
+		self.pool.submit_one(at, source, xt).await
+	}
+
+	async fn submit_and_watch(
+		&self,
+		at: <Self::Block as BlockT>::Hash,
+		source: TransactionSource,
+		xt: TransactionFor<Self>,
+	) -> Result<Pin<Box<TransactionStatusStreamFor<Self>>>, Self::Error> {
+		self.pool.submit_and_watch(at, source, xt).await
+	}
+
+	async fn ready_at(&self, at: <Self::Block as BlockT>::Hash) -> ReadyIteratorFor<Self> {
+		self.order(self.pool.ready_at(at).await)
+	}
+
+	fn ready(&self) -> ReadyIteratorFor<Self> {
+		self.order(self.pool.ready())
+	}
+
+	async fn report_invalid(
+		&self,
+		at: Option<<Self::Block as BlockT>::Hash>,
+		invalid_tx_errors: TxInvalidityReportMap<TxHash<Self>>,
+	) -> Vec<Arc<Self::InPoolTransaction>> {
+		self.pool.report_invalid(at, invalid_tx_errors).await
+	}
+
+	fn futures(&self) -> Vec<Self::InPoolTransaction> {
+		self.pool.futures()
+	}
+
+	fn status(&self) -> PoolStatus {
+		self.pool.status()
+	}
+
+	fn import_notification_stream(&self) -> ImportNotificationStream<TxHash<Self>> {
+		self.pool.import_notification_stream()
+	}
+
+	fn on_broadcasted(&self, propagations: HashMap<TxHash<Self>, Vec<String>>) {
+		self.pool.on_broadcasted(propagations)
+	}
+
+	fn hash_of(&self, xt: &TransactionFor<Self>) -> TxHash<Self> {
+		self.pool.hash_of(xt)
+	}
+
+	fn ready_transaction(&self, hash: &TxHash<Self>) -> Option<Arc<Self::InPoolTransaction>> {
+		self.pool.ready_transaction(hash)
+	}
+
+	async fn ready_at_with_timeout(
+		&self,
+		at: <Self::Block as BlockT>::Hash,
+		timeout: Duration,
+	) -> ReadyIteratorFor<Self> {
+		self.order(self.pool.ready_at_with_timeout(at, timeout).await)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[derive(Debug)]
+	struct Tx {
+		hash: u64,
+		priority: u64,
+		requires: Vec<TransactionTag>,
+		provides: Vec<TransactionTag>,
+	}
+
+	impl InPoolTransaction for Tx {
+		type Transaction = ();
+		type Hash = u64;
+
+		fn data(&self) -> &() {
+			&()
+		}
+
+		fn hash(&self) -> &u64 {
+			&self.hash
+		}
+
+		fn priority(&self) -> &u64 {
+			&self.priority
+		}
+
+		fn longevity(&self) -> &u64 {
+			&u64::MAX
+		}
+
+		fn requires(&self) -> &[TransactionTag] {
+			&self.requires
+		}
+
+		fn provides(&self) -> &[TransactionTag] {
+			&self.provides
+		}
+
+		fn is_propagable(&self) -> bool {
+			true
+		}
+	}
+
+	/// Transactions from one sender with nonces `nonces`, then one from another sender, in the
+	/// order of the pool.
+	fn pool(nonces: &[u8]) -> Vec<Arc<Tx>> {
+		let mut transactions: Vec<_> = nonces
+			.iter()
+			.map(|nonce| {
+				Arc::new(Tx {
+					hash: *nonce as u64,
+					priority: 10,
+					requires: if *nonce == 0 { vec![] } else { vec![vec![nonce - 1]] },
+					provides: vec![vec![*nonce]],
+				})
+			})
+			.collect();
+		let other = Tx { hash: 100, priority: 1, requires: vec![], provides: vec![] };
+		transactions.push(Arc::new(other));
+		transactions
+	}
+
+	fn ordered(
+		ordering: TransactionOrdering,
+		arrivals: &TransactionArrivals<u64>,
+		transactions: Vec<Arc<Tx>>,
+	) -> Vec<u64> {
+		let keys = arrivals.keys(ordering, transactions.iter().map(|tx| &tx.hash));
+		OrderedReady::new(transactions, keys).map(|tx| tx.hash).collect()
+	}
+
+	#[test]
+	fn first_come_keeps_the_transactions_after_those_they_require() {
+		let arrivals = TransactionArrivals::default();
+		for hash in [100, 1, 0, 2] {
+			arrivals.record(hash);
+		}
+
+		assert_eq!(
+			ordered(TransactionOrdering::FirstCome, &arrivals, pool(&[0, 1, 2])),
+			vec![100, 0, 1, 2],
+		);
+		assert_eq!(
+			ordered(TransactionOrdering::Priority, &arrivals, pool(&[0, 1, 2])),
+			vec![0, 1, 2, 100],
+		);
+	}
+
+	#[test]
+	fn triggering_transactions_come_first() {
+		let arrivals = TransactionArrivals::default();
+		arrivals.record(0);
+		arrivals.mark_trigger();
+		arrivals.record(100);
+		arrivals.mark_trigger();
+
+		assert_eq!(
+			ordered(TransactionOrdering::TriggeringFirst, &arrivals, pool(&[0, 1])),
+			vec![100, 0, 1],
+		);
+	}
+
+	#[test]
+	fn transactions_requiring_an_invalid_one_are_skipped() {
+		let arrivals = TransactionArrivals::default();
+		let transactions = pool(&[0, 1, 2]);
+		let keys =
+			arrivals.keys(TransactionOrdering::FirstCome, transactions.iter().map(|tx| &tx.hash));
+		let mut ready = OrderedReady::new(transactions, keys);
+
+		let first = ready.next().expect("four transactions are ready");
+		assert_eq!(first.hash, 0);
+		ready.report_invalid(&first);
+
+		assert_eq!(ready.map(|tx| tx.hash).collect::<Vec<_>>(), vec![100]);
+	}
+}
@@ -0,0 +1,319 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Following the transactions of the future queue until they are promoted to the ready one.
+//!
+//! A transaction missing one it depends on, such as one with a nonce gap, waits in the future
+//! queue of the pool. It is promoted to the ready queue once the missing transaction is imported,
+//! or once the pool is revalidated after a block, without any import notification. The pool is
+//! polled for such promotions, and the transactions promoted together that depend on one another,
+//! such as the nonce chain of a sender, are handled as a [`PromotedBundle`].
+//!
+//! The sender of a transaction is told from its tags: those of FRAME's `CheckNonce` are the
+//! encoded sender followed by the nonce, of [`NONCE_TAG_BYTES`] at most.
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	hash::Hash,
+	ops::Deref,
+	time::{Duration, Instant},
+};
+
+use sc_transaction_pool_api::InPoolTransaction;
+use sp_runtime::transaction_validity::TransactionTag;
+
+/// Maximum number of transactions of the future queue followed.
+const MAX_FUTURES: usize = 65_536;
+
+/// Number of trailing bytes of a tag left out to tell its sender, the most a nonce is encoded in.
+const NONCE_TAG_BYTES: usize = 8;
+
+/// How often the future queue is polled for promotions while nothing else changes in the pool.
+pub(crate) const PROMOTION_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Transactions promoted from the future queue to the ready one together, each depending on
+/// another one of the bundle or providing for one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PromotedBundle<H> {
+	/// The transactions, in the order of the ready queue.
+	pub transactions: Vec<H>,
+	/// When the first of them was seen in the future queue, standing for its import.
+	pub first_seen: Instant,
+}
+
+/// The transactions seen in the future queue, since when and the tags they require.
+pub(crate) struct FuturePromotions<H> {
+	seen: HashMap<H, (Instant, Vec<TransactionTag>)>,
+}
+
+impl<H> Default for FuturePromotions<H> {
+	fn default() -> Self {
+		Self { seen: HashMap::new() }
+	}
+}
+
+impl<H: Hash + Eq + Clone> FuturePromotions<H> {
+	/// Whether any transaction is followed, promotions being worth checking for.
+	pub fn is_empty(&self) -> bool {
+		self.seen.is_empty()
+	}
+
+	/// Whether a transaction seen in the future queue less than `recent` before `now` is still
+	/// waiting there, for a missing transaction likely on its way, from a sender of the `window`
+	/// tags provided by the transactions about to be included.
+	pub fn waiting_within<'a>(
+		&self,
+		now: Instant,
+		recent: Duration,
+		window: impl IntoIterator<Item = &'a TransactionTag>,
+	) -> bool {
+		let senders: HashSet<_> = window.into_iter().map(|tag| sender(tag)).collect();
+		if senders.is_empty() {
+			return false
+		}
+
+		self.seen.values().any(|(seen, requires)| {
+			now.saturating_duration_since(*seen) < recent &&
+				requires.iter().any(|tag| senders.contains(&sender(tag)))
+		})
+	}
+
+	/// Follow the transactions of the `futures` queue, first seen at `now`, and take those
+	/// followed until now that are among the `ready` ones, bundled by their dependencies.
+	///
+	/// The transactions neither in `futures` nor in `ready` left the pool, and are forgotten.
+	pub fn refresh<T, R>(
+		&mut self,
+		futures: &[T],
+		ready: impl IntoIterator<Item = R>,
+		now: Instant,
+	) -> Vec<PromotedBundle<H>>
+	where
+		T: InPoolTransaction<Hash = H>,
+		R: Deref<Target = T>,
+	{
+		let promoted: Vec<_> = ready
+			.into_iter()
+			.filter_map(|tx| {
+				let (first_seen, _) = self.seen.remove(tx.hash())?;
+				let (requires, provides) = (tx.requires().to_vec(), tx.provides().to_vec());
+				Some((tx.hash().clone(), first_seen, requires, provides))
+			})
+			.collect();
+
+		let waiting: HashSet<_> = futures.iter().map(|tx| tx.hash()).collect();
+		self.seen.retain(|hash, _| waiting.contains(hash));
+		for tx in futures {
+			if self.seen.len() >= MAX_FUTURES {
+				break
+			}
+			self.seen.entry(tx.hash().clone()).or_insert_with(|| (now, tx.requires().to_vec()));
+		}
+
+		// Link each promoted transaction to the promoted ones providing what it requires.
+		let providers: HashMap<_, _> = promoted
+			.iter()
+			.enumerate()
+			.flat_map(|(index, (_, _, _, provides))| provides.iter().map(move |tag| (tag, index)))
+			.collect();
+		let mut bundles = DisjointSets::new(promoted.len());
+		for (index, (_, _, requires, _)) in promoted.iter().enumerate() {
+			for provider in requires.iter().filter_map(|tag| providers.get(tag)) {
+				bundles.join(index, *provider);
+			}
+		}
+
+		let mut by_root = BTreeMap::<usize, PromotedBundle<H>>::new();
+		let mut roots = HashMap::new();
+		for (index, (hash, first_seen, _, _)) in promoted.into_iter().enumerate() {
+			// Bundles are keyed by their first transaction, to keep the order of the ready queue.
+			let root = bundles.root(index);
+			let key = *roots.entry(root).or_insert(index);
+			let bundle = by_root
+				.entry(key)
+				.or_insert_with(|| PromotedBundle { transactions: Vec::new(), first_seen });
+			bundle.transactions.push(hash);
+			bundle.first_seen = bundle.first_seen.min(first_seen);
+		}
+
+		by_root.into_values().collect()
+	}
+}
+
+/// The sender a tag is likely provided or required for, along with the length of the tag.
+fn sender(tag: &[u8]) -> (usize, &[u8]) {
+	(tag.len(), &tag[..tag.len().saturating_sub(NONCE_TAG_BYTES)])
+}
+
+/// Union-find over the indices of the promoted transactions.
+struct DisjointSets {
+	parents: Vec<usize>,
+}
+
+impl DisjointSets {
+	fn new(len: usize) -> Self {
+		Self { parents: (0..len).collect() }
+	}
+
+	fn root(&mut self, mut index: usize) -> usize {
+		while self.parents[index] != index {
+			self.parents[index] = self.parents[self.parents[index]];
+			index = self.parents[index];
+		}
+		index
+	}
+
+	fn join(&mut self, a: usize, b: usize) {
+		let (a, b) = (self.root(a), self.root(b));
+		self.parents[a.max(b)] = a.min(b);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::Arc;
+
+	#[derive(Debug)]
+	struct Tx {
+		hash: u64,
+		requires: Vec<TransactionTag>,
+		provides: Vec<TransactionTag>,
+	}
+
+	impl InPoolTransaction for Tx {
+		type Transaction = ();
+		type Hash = u64;
+
+		fn data(&self) -> &() {
+			&()
+		}
+
+		fn hash(&self) -> &u64 {
+			&self.hash
+		}
+
+		fn priority(&self) -> &u64 {
+			&0
+		}
+
+		fn longevity(&self) -> &u64 {
+			&u64::MAX
+		}
+
+		fn requires(&self) -> &[TransactionTag] {
+			&self.requires
+		}
+
+		fn provides(&self) -> &[TransactionTag] {
+			&self.provides
+		}
+
+		fn is_propagable(&self) -> bool {
+			true
+		}
+	}
+
+	/// The tag of nonce `nonce` of `sender`, as FRAME's `CheckNonce` provides it.
+	fn tag(sender: u8, nonce: u8) -> TransactionTag {
+		[[sender; 32].as_slice(), &(nonce as u32).to_le_bytes()].concat()
+	}
+
+	/// The transaction with nonce `nonce` of `sender`, hashed as `sender * 100 + nonce`.
+	fn tx(sender: u8, nonce: u8) -> Arc<Tx> {
+		Arc::new(Tx {
+			hash: sender as u64 * 100 + nonce as u64,
+			requires: if nonce == 0 { vec![] } else { vec![tag(sender, nonce - 1)] },
+			provides: vec![tag(sender, nonce)],
+		})
+	}
+
+	fn futures(transactions: &[Arc<Tx>]) -> Vec<Tx> {
+		transactions
+			.iter()
+			.map(|tx| Tx {
+				hash: tx.hash,
+				requires: tx.requires.clone(),
+				provides: tx.provides.clone(),
+			})
+			.collect()
+	}
+
+	#[test]
+	fn bundles_the_nonce_chains_promoted_together() {
+		let start = Instant::now();
+		let later = start + Duration::from_secs(1);
+		let mut promotions = FuturePromotions::default();
+
+		// Nonces 1 and 2 of sender 1 wait for nonce 0, and nonce 3 of sender 2 waits for nonce 2.
+		let waiting = [tx(1, 1), tx(1, 2), tx(2, 3)];
+		let nothing_ready = Vec::<Arc<Tx>>::new;
+		assert!(promotions.refresh(&futures(&waiting[..2]), nothing_ready(), start).is_empty());
+		assert!(promotions.refresh(&futures(&waiting), nothing_ready(), later).is_empty());
+		assert!(promotions.waiting_within(later, Duration::from_secs(2), &[tag(1, 0)]));
+		assert!(!promotions.waiting_within(later, Duration::from_millis(500), &[tag(1, 0)]));
+
+		// Nonce 0 of sender 1 is imported, promoting its chain, the other one still waits.
+		let ready = vec![tx(3, 0), tx(1, 0), tx(1, 1), tx(1, 2)];
+		let bundles = promotions.refresh(&futures(&waiting[2..]), ready, later);
+		assert_eq!(
+			bundles,
+			vec![PromotedBundle { transactions: vec![101, 102], first_seen: start }],
+		);
+		assert!(!promotions.is_empty());
+
+		// Chains of different senders promoted together are bundled apart.
+		let ready = vec![tx(2, 3), tx(4, 1)];
+		promotions.refresh(&futures(&[tx(2, 3), tx(4, 1)]), nothing_ready(), later);
+		let bundles = promotions.refresh(&[], ready, later);
+		assert_eq!(
+			bundles,
+			vec![
+				PromotedBundle { transactions: vec![203], first_seen: later },
+				PromotedBundle { transactions: vec![401], first_seen: later },
+			],
+		);
+		assert!(promotions.is_empty());
+	}
+
+	#[test]
+	fn only_senders_of_the_window_hold_it() {
+		let now = Instant::now();
+		let mut promotions = FuturePromotions::default();
+
+		// Nonce 2 of sender 1 waits for nonce 1, nonce 1 of sender 2 for nonce 0.
+		promotions.refresh(&futures(&[tx(1, 2), tx(2, 1)]), Vec::<Arc<Tx>>::new(), now);
+
+		let recent = Duration::from_secs(1);
+		assert!(promotions.waiting_within(now, recent, &[tag(1, 0)]));
+		assert!(promotions.waiting_within(now, recent, &[tag(3, 0), tag(2, 5)]));
+		assert!(!promotions.waiting_within(now, recent, &[tag(3, 0)]));
+		assert!(!promotions.waiting_within(now, recent, &[]));
+	}
+
+	#[test]
+	fn forgets_the_transactions_leaving_the_pool() {
+		let now = Instant::now();
+		let mut promotions = FuturePromotions::default();
+
+		promotions.refresh(&futures(&[tx(1, 1)]), Vec::<Arc<Tx>>::new(), now);
+		assert!(promotions.refresh(&[], Vec::<Arc<Tx>>::new(), now).is_empty());
+		assert!(promotions.refresh(&[], vec![tx(1, 1)], now).is_empty());
+		assert!(!promotions.waiting_within(now, Duration::from_secs(1), &[tag(1, 0)]));
+	}
+}
@@ -0,0 +1,864 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! A keystore forwarding signing requests to an external signer, so the authority keys don't have
+//! to live on the node.
+//!
+//! # Protocol
+//!
+//! Every request is a single JSON object sent over a new connection, either
+//!
+//! - to a Unix socket (`unix:/path/to/socket`), terminated by a newline, or
+//! - over HTTP (`http://host:port/path`), as the body of an `HTTP/1.0` `POST` carrying an
+//!   `Authorization: Bearer <token>` header.
+//!
+//! Access to a Unix socket is controlled by its file permissions, an HTTP signer only answers the
+//! requests carrying its token. The HTTP transport is not encrypted, so it should only be reached
+//! through a trusted network or a TLS tunnel.
+//!
+//! Byte strings are `0x` prefixed hex, key types are their four characters and `crypto` is one of
+//! `sr25519`, `ed25519` or `ecdsa`. The requests are
+//!
+//! ```json
+//! {"method": "public_keys", "key_type": "micc", "crypto": "sr25519"}
+//! {"method": "sign", "key_type": "micc", "crypto": "sr25519", "public": "0x..", "message": "0x.."}
+//! {"method": "has_keys", "keys": [["0x..", "micc"]]}
+//! ```
+//!
+//! answered with `{"result": ..}`, respectively the list of public keys, the signature or `null`
+//! if the signer doesn't hold the key, and whether it holds all of the keys. Failures are
+//! answered with `{"error": "reason"}`.
+//!
+//! Keys can't be generated or inserted through the [`RemoteKeystore`], and VRF signing isn't
+//! supported.
+//!
+//! The [`Keystore`] methods are synchronous and called from async tasks, such as the slot worker
+//! claiming a slot. The requests are sent by a dedicated thread, the callers only waiting for its
+//! answer until the timeout, and the keys held by the signer are cached for
+//! [`KEY_CACHE_DURATION`], so that the checks made at every slot don't wait for the signer.
+
+use std::{
+	collections::HashMap,
+	fmt,
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{TcpListener, TcpStream, ToSocketAddrs},
+	os::unix::net::{UnixListener, UnixStream},
+	path::PathBuf,
+	str::FromStr,
+	sync::{mpsc, Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use prometheus_endpoint::{
+	register, CounterVec, HistogramOpts, HistogramVec, Opts, PrometheusError, Registry, U64,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sp_core::{
+	crypto::{ByteArray, KeyTypeId},
+	ecdsa, ed25519, sr25519, Bytes,
+};
+use sp_keystore::{Error as KeystoreError, Keystore};
+
+use crate::LOG_TARGET;
+
+/// Default time allowed for a request to the signer.
+pub const DEFAULT_REMOTE_SIGNER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the public keys held by the signer are cached.
+pub const KEY_CACHE_DURATION: Duration = Duration::from_secs(30);
+
+/// Errors setting up a [`RemoteKeystore`].
+#[derive(Debug, thiserror::Error)]
+pub enum RemoteKeystoreError {
+	/// HTTP signers must be given a token.
+	#[error("A token is required to reach a signer over HTTP")]
+	MissingToken,
+	/// The metrics could not be registered.
+	#[error(transparent)]
+	Prometheus(#[from] PrometheusError),
+	/// The thread sending the requests could not be started.
+	#[error("Failed to start the remote signer thread: {0}")]
+	Thread(io::Error),
+}
+
+/// Where the external signer listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemoteSignerEndpoint {
+	/// A Unix socket, given as `unix:<path>`.
+	Unix(PathBuf),
+	/// An HTTP server, given as `http://<host>:<port>[/<path>]`.
+	Http {
+		/// Host name or address.
+		host: String,
+		/// TCP port.
+		port: u16,
+		/// Request path, `/` if none was given.
+		path: String,
+	},
+}
+
+impl FromStr for RemoteSignerEndpoint {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		if let Some(path) = s.strip_prefix("unix:") {
+			return Ok(Self::Unix(path.into()))
+		}
+
+		let rest = s
+			.strip_prefix("http://")
+			.ok_or_else(|| format!("`{s}` is neither a `unix:` nor an `http://` endpoint"))?;
+		let (authority, path) = match rest.find('/') {
+			Some(index) => (&rest[..index], rest[index..].to_string()),
+			None => (rest, "/".to_string()),
+		};
+		let (host, port) =
+			authority.rsplit_once(':').ok_or_else(|| format!("`{s}` is missing the port"))?;
+		let port = port.parse().map_err(|_| format!("`{port}` is not a valid port"))?;
+
+		Ok(Self::Http { host: host.to_string(), port, path })
+	}
+}
+
+impl fmt::Display for RemoteSignerEndpoint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Self::Unix(path) => write!(f, "unix:{}", path.display()),
+			Self::Http { host, port, path } => write!(f, "http://{host}:{port}{path}"),
+		}
+	}
+}
+
+/// The crypto of the keys in a [`SignerRequest`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerCrypto {
+	/// Schnorrkel over Ristretto25519.
+	Sr25519,
+	/// Ed25519.
+	Ed25519,
+	/// ECDSA over secp256k1.
+	Ecdsa,
+}
+
+/// A request to the external signer, see the [module docs](self).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum SignerRequest {
+	/// List the public keys of a key type.
+	PublicKeys {
+		/// The key type.
+		key_type: String,
+		/// The crypto of the keys.
+		crypto: SignerCrypto,
+	},
+	/// Sign a message.
+	Sign {
+		/// The key type.
+		key_type: String,
+		/// The crypto of the key.
+		crypto: SignerCrypto,
+		/// The public key to sign with.
+		public: Bytes,
+		/// The message to sign.
+		message: Bytes,
+	},
+	/// Check whether all of the keys are held.
+	HasKeys {
+		/// Public keys with their key type.
+		keys: Vec<(Bytes, String)>,
+	},
+}
+
+impl SignerRequest {
+	fn method(&self) -> &'static str {
+		match self {
+			Self::PublicKeys { .. } => "public_keys",
+			Self::Sign { .. } => "sign",
+			Self::HasKeys { .. } => "has_keys",
+		}
+	}
+}
+
+/// The answer of the external signer to a [`SignerRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignerResponse<T> {
+	/// The request succeeded.
+	Result(T),
+	/// The request failed.
+	Error(String),
+}
+
+fn key_type_name(key_type: KeyTypeId) -> String {
+	String::from_utf8_lossy(&key_type.0).into_owned()
+}
+
+fn parse_key_type(name: &str) -> Result<KeyTypeId, String> {
+	KeyTypeId::try_from(name).map_err(|_| format!("`{name}` is not a valid key type"))
+}
+
+#[derive(Clone)]
+struct Metrics {
+	requests: CounterVec<U64>,
+	duration: HistogramVec,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			requests: register(
+				CounterVec::new(
+					Opts::new(
+						"micc_remote_signer_requests_total",
+						"Number of requests to the remote signer, by method and outcome",
+					),
+					&["method", "outcome"],
+				)?,
+				registry,
+			)?,
+			duration: register(
+				HistogramVec::new(
+					HistogramOpts::new(
+						"micc_remote_signer_request_duration_seconds",
+						"Time taken by the remote signer to answer a request",
+					)
+					.buckets(vec![0.001, 0.005, 0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+					&["method"],
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// A request handed to the thread talking to the signer, with when its caller gives up and where
+/// to send the response.
+type Exchange = (Vec<u8>, Instant, mpsc::SyncSender<io::Result<Vec<u8>>>);
+
+/// A [`Keystore`] forwarding to an external signer, see the [module docs](self).
+///
+/// Requests block the calling thread for at most the configured timeout.
+pub struct RemoteKeystore {
+	endpoint: RemoteSignerEndpoint,
+	timeout: Duration,
+	exchanges: mpsc::Sender<Exchange>,
+	/// Responses to the requests other than signing, by request.
+	cache: Mutex<HashMap<Vec<u8>, (Instant, Vec<u8>)>>,
+	metrics: Option<Metrics>,
+}
+
+impl RemoteKeystore {
+	/// Forward to the signer at `endpoint`, registering the metrics if a registry is given.
+	///
+	/// Signers reached over HTTP require a `token`.
+	pub fn new(
+		endpoint: RemoteSignerEndpoint,
+		timeout: Duration,
+		token: Option<String>,
+		registry: Option<&Registry>,
+	) -> Result<Self, RemoteKeystoreError> {
+		if matches!(endpoint, RemoteSignerEndpoint::Http { .. }) && token.is_none() {
+			return Err(RemoteKeystoreError::MissingToken)
+		}
+		let metrics = registry.map(Metrics::register).transpose()?;
+
+		let (exchanges, pending) = mpsc::channel::<Exchange>();
+		let transport = endpoint.clone();
+		std::thread::Builder::new()
+			.name("micc-remote-signer".into())
+			.spawn(move || {
+				for (body, deadline, response) in pending {
+					// The caller already gave up.
+					if Instant::now() >= deadline {
+						continue
+					}
+					let timeout = deadline.saturating_duration_since(Instant::now());
+					let _ = response.send(send(&transport, token.as_deref(), timeout, &body));
+				}
+			})
+			.map_err(RemoteKeystoreError::Thread)?;
+
+		Ok(Self { endpoint, timeout, exchanges, cache: Default::default(), metrics })
+	}
+
+	fn request<T: DeserializeOwned>(&self, request: &SignerRequest) -> Result<T, KeystoreError> {
+		let body = serde_json::to_vec(request).map_err(|e| KeystoreError::Other(e.to_string()))?;
+		let cached = !matches!(request, SignerRequest::Sign { .. });
+		if cached {
+			if let Some(Ok(SignerResponse::Result(result))) = self.cached(&body) {
+				return Ok(result)
+			}
+		}
+
+		let started = Instant::now();
+		let result = self.exchange(body.clone()).and_then(|response| {
+			let parsed = serde_json::from_slice::<SignerResponse<T>>(&response)?;
+			Ok((response, parsed))
+		});
+
+		let outcome = match &result {
+			Ok((_, SignerResponse::Result(_))) => "ok",
+			Ok((_, SignerResponse::Error(_))) => "rejected",
+			Err(e)
+				if e.kind() == io::ErrorKind::TimedOut || e.kind() == io::ErrorKind::WouldBlock =>
+				"timeout",
+			Err(_) => "failed",
+		};
+		if let Some(metrics) = &self.metrics {
+			metrics.requests.with_label_values(&[request.method(), outcome]).inc();
+			metrics
+				.duration
+				.with_label_values(&[request.method()])
+				.observe(started.elapsed().as_secs_f64());
+		}
+
+		match result {
+			Ok((response, SignerResponse::Result(result))) => {
+				if cached {
+					let mut cache = self.cache.lock().expect("never poisoned; qed");
+					cache.insert(body, (Instant::now(), response));
+				}
+				Ok(result)
+			},
+			Ok((_, SignerResponse::Error(e))) => {
+				log::debug!(target: LOG_TARGET, "Remote signer rejected {}: {}", request.method(), e);
+				Err(KeystoreError::Other(e))
+			},
+			Err(e) => {
+				log::warn!(
+					target: LOG_TARGET,
+					"Request to remote signer at {} failed ({}): {}",
+					self.endpoint,
+					outcome,
+					e,
+				);
+				Err(KeystoreError::Unavailable)
+			},
+		}
+	}
+
+	/// The response to the request `body` received less than [`KEY_CACHE_DURATION`] ago.
+	fn cached<T: DeserializeOwned>(&self, body: &[u8]) -> Option<serde_json::Result<T>> {
+		let mut cache = self.cache.lock().expect("never poisoned; qed");
+		cache.retain(|_, (received, _)| received.elapsed() < KEY_CACHE_DURATION);
+		cache.get(body).map(|(_, response)| serde_json::from_slice(response))
+	}
+
+	/// Hand the request `body` to the thread talking to the signer and wait for the response, for
+	/// the timeout at most.
+	fn exchange(&self, body: Vec<u8>) -> io::Result<Vec<u8>> {
+		let (response_tx, response_rx) = mpsc::sync_channel(1);
+		self.exchanges
+			.send((body, Instant::now() + self.timeout, response_tx))
+			.map_err(|_| io::Error::other("the remote signer thread stopped"))?;
+
+		// Let the other tasks of the runtime move to another worker thread while waiting.
+		let wait = || response_rx.recv_timeout(self.timeout);
+		let response = match tokio::runtime::Handle::try_current() {
+			Ok(handle) if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread =>
+				tokio::task::block_in_place(wait),
+			_ => wait(),
+		};
+
+		response.unwrap_or_else(|_| {
+			Err(io::Error::new(io::ErrorKind::TimedOut, "the remote signer did not answer in time"))
+		})
+	}
+
+	fn public_keys<P: ByteArray>(&self, key_type: KeyTypeId, crypto: SignerCrypto) -> Vec<P> {
+		let request = SignerRequest::PublicKeys { key_type: key_type_name(key_type), crypto };
+		self.request::<Vec<Bytes>>(&request)
+			.unwrap_or_default()
+			.into_iter()
+			.filter_map(|public| P::from_slice(&public).ok())
+			.collect()
+	}
+
+	fn sign<P: ByteArray, S: for<'a> TryFrom<&'a [u8]>>(
+		&self,
+		key_type: KeyTypeId,
+		crypto: SignerCrypto,
+		public: &P,
+		message: &[u8],
+	) -> Result<Option<S>, KeystoreError> {
+		let request = SignerRequest::Sign {
+			key_type: key_type_name(key_type),
+			crypto,
+			public: public.to_raw_vec().into(),
+			message: message.to_vec().into(),
+		};
+
+		self.request::<Option<Bytes>>(&request)?
+			.map(|signature| {
+				S::try_from(&signature[..]).map_err(|_| {
+					KeystoreError::ValidationError(
+						"remote signer returned a malformed signature".into(),
+					)
+				})
+			})
+			.transpose()
+	}
+}
+
+/// Send the request `body` to the signer at `endpoint`, returning its response.
+fn send(
+	endpoint: &RemoteSignerEndpoint,
+	token: Option<&str>,
+	timeout: Duration,
+	body: &[u8],
+) -> io::Result<Vec<u8>> {
+	match endpoint {
+		RemoteSignerEndpoint::Unix(path) => {
+			let mut stream = UnixStream::connect(path)?;
+			stream.set_read_timeout(Some(timeout))?;
+			stream.set_write_timeout(Some(timeout))?;
+			stream.write_all(body)?;
+			stream.write_all(b"\n")?;
+
+			let mut response = Vec::new();
+			BufReader::new(stream).read_until(b'\n', &mut response)?;
+			Ok(response)
+		},
+		RemoteSignerEndpoint::Http { host, port, path } => {
+			let address = (host.as_str(), *port).to_socket_addrs()?.next().ok_or_else(|| {
+				io::Error::new(io::ErrorKind::NotFound, format!("{host} did not resolve"))
+			})?;
+			let mut stream = TcpStream::connect_timeout(&address, timeout)?;
+			stream.set_read_timeout(Some(timeout))?;
+			stream.set_write_timeout(Some(timeout))?;
+			write!(
+				stream,
+				"POST {path} HTTP/1.0\r\nHost: {host}:{port}\r\nContent-Type: application/json\r\n\
+				 Authorization: Bearer {}\r\nContent-Length: {}\r\n\r\n",
+				token.unwrap_or_default(),
+				body.len(),
+			)?;
+			stream.write_all(body)?;
+
+			let mut response = Vec::new();
+			stream.read_to_end(&mut response)?;
+			http_body(&response).map(<[u8]>::to_vec)
+		},
+	}
+}
+
+/// The body of an HTTP response, if its status is `200`.
+fn http_body(response: &[u8]) -> io::Result<&[u8]> {
+	let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason.to_string());
+
+	let split = response
+		.windows(4)
+		.position(|window| window == b"\r\n\r\n")
+		.ok_or_else(|| invalid("incomplete HTTP response"))?;
+	let status = response[..split].split(|b| *b == b' ').nth(1);
+	if status != Some(&b"200"[..]) {
+		return Err(invalid("HTTP response status is not 200"))
+	}
+
+	Ok(&response[split + 4..])
+}
+
+impl Keystore for RemoteKeystore {
+	fn sr25519_public_keys(&self, key_type: KeyTypeId) -> Vec<sr25519::Public> {
+		self.public_keys(key_type, SignerCrypto::Sr25519)
+	}
+
+	fn sr25519_generate_new(
+		&self,
+		_: KeyTypeId,
+		_: Option<&str>,
+	) -> Result<sr25519::Public, KeystoreError> {
+		Err(KeystoreError::Unavailable)
+	}
+
+	fn sr25519_sign(
+		&self,
+		key_type: KeyTypeId,
+		public: &sr25519::Public,
+		msg: &[u8],
+	) -> Result<Option<sr25519::Signature>, KeystoreError> {
+		self.sign(key_type, SignerCrypto::Sr25519, public, msg)
+	}
+
+	fn sr25519_vrf_sign(
+		&self,
+		_: KeyTypeId,
+		_: &sr25519::Public,
+		_: &sr25519::vrf::VrfSignData,
+	) -> Result<Option<sr25519::vrf::VrfSignature>, KeystoreError> {
+		Err(KeystoreError::Unavailable)
+	}
+
+	fn sr25519_vrf_pre_output(
+		&self,
+		_: KeyTypeId,
+		_: &sr25519::Public,
+		_: &sr25519::vrf::VrfInput,
+	) -> Result<Option<sr25519::vrf::VrfPreOutput>, KeystoreError> {
+		Err(KeystoreError::Unavailable)
+	}
+
+	fn ed25519_public_keys(&self, key_type: KeyTypeId) -> Vec<ed25519::Public> {
+		self.public_keys(key_type, SignerCrypto::Ed25519)
+	}
+
+	fn ed25519_generate_new(
+		&self,
+		_: KeyTypeId,
+		_: Option<&str>,
+	) -> Result<ed25519::Public, KeystoreError> {
+		Err(KeystoreError::Unavailable)
+	}
+
+	fn ed25519_sign(
+		&self,
+		key_type: KeyTypeId,
+		public: &ed25519::Public,
+		msg: &[u8],
+	) -> Result<Option<ed25519::Signature>, KeystoreError> {
+		self.sign(key_type, SignerCrypto::Ed25519, public, msg)
+	}
+
+	fn ecdsa_public_keys(&self, key_type: KeyTypeId) -> Vec<ecdsa::Public> {
+		self.public_keys(key_type, SignerCrypto::Ecdsa)
+	}
+
+	fn ecdsa_generate_new(
+		&self,
+		_: KeyTypeId,
+		_: Option<&str>,
+	) -> Result<ecdsa::Public, KeystoreError> {
+		Err(KeystoreError::Unavailable)
+	}
+
+	fn ecdsa_sign(
+		&self,
+		key_type: KeyTypeId,
+		public: &ecdsa::Public,
+		msg: &[u8],
+	) -> Result<Option<ecdsa::Signature>, KeystoreError> {
+		self.sign(key_type, SignerCrypto::Ecdsa, public, msg)
+	}
+
+	fn ecdsa_sign_prehashed(
+		&self,
+		_: KeyTypeId,
+		_: &ecdsa::Public,
+		_: &[u8; 32],
+	) -> Result<Option<ecdsa::Signature>, KeystoreError> {
+		Err(KeystoreError::Unavailable)
+	}
+
+	fn insert(&self, _: KeyTypeId, _: &str, _: &[u8]) -> Result<(), ()> {
+		Err(())
+	}
+
+	fn keys(&self, key_type: KeyTypeId) -> Result<Vec<Vec<u8>>, KeystoreError> {
+		let mut keys = Vec::new();
+		for crypto in [SignerCrypto::Sr25519, SignerCrypto::Ed25519, SignerCrypto::Ecdsa] {
+			let request = SignerRequest::PublicKeys { key_type: key_type_name(key_type), crypto };
+			keys.extend(self.request::<Vec<Bytes>>(&request)?.into_iter().map(|key| key.0));
+		}
+		Ok(keys)
+	}
+
+	fn has_keys(&self, public_keys: &[(Vec<u8>, KeyTypeId)]) -> bool {
+		let keys = public_keys
+			.iter()
+			.map(|(public, key_type)| (public.clone().into(), key_type_name(*key_type)))
+			.collect();
+		self.request(&SignerRequest::HasKeys { keys }).unwrap_or(false)
+	}
+}
+
+/// Answer a serialized [`SignerRequest`] from the keys in `keystore`.
+///
+/// This is the signing side of the protocol, used by the stand-in signer.
+pub fn answer_request(keystore: &dyn Keystore, request: &[u8]) -> Vec<u8> {
+	fn answer<T: Serialize>(result: Result<T, String>) -> Vec<u8> {
+		let response = match result {
+			Ok(result) => SignerResponse::Result(result),
+			Err(e) => SignerResponse::Error(e),
+		};
+		serde_json::to_vec(&response).expect("responses always serialize; qed")
+	}
+
+	let request = match serde_json::from_slice::<SignerRequest>(request) {
+		Ok(request) => request,
+		Err(e) => return answer::<()>(Err(format!("invalid request: {e}"))),
+	};
+
+	match request {
+		SignerRequest::PublicKeys { key_type, crypto } =>
+			answer(parse_key_type(&key_type).map(|key_type| -> Vec<Bytes> {
+				match crypto {
+					SignerCrypto::Sr25519 => keystore
+						.sr25519_public_keys(key_type)
+						.iter()
+						.map(|k| k.to_raw_vec().into())
+						.collect(),
+					SignerCrypto::Ed25519 => keystore
+						.ed25519_public_keys(key_type)
+						.iter()
+						.map(|k| k.to_raw_vec().into())
+						.collect(),
+					SignerCrypto::Ecdsa => keystore
+						.ecdsa_public_keys(key_type)
+						.iter()
+						.map(|k| k.to_raw_vec().into())
+						.collect(),
+				}
+			})),
+		SignerRequest::Sign { key_type, crypto, public, message } =>
+			answer(parse_key_type(&key_type).and_then(|key_type| {
+				let crypto_id = match crypto {
+					SignerCrypto::Sr25519 => sr25519::CRYPTO_ID,
+					SignerCrypto::Ed25519 => ed25519::CRYPTO_ID,
+					SignerCrypto::Ecdsa => ecdsa::CRYPTO_ID,
+				};
+				keystore
+					.sign_with(key_type, crypto_id, &public, &message)
+					.map(|signature| signature.map(Bytes))
+					.map_err(|e| e.to_string())
+			})),
+		SignerRequest::HasKeys { keys } => answer(
+			keys.into_iter()
+				.map(|(public, key_type)| {
+					parse_key_type(&key_type).map(|key_type| (public.0, key_type))
+				})
+				.collect::<Result<Vec<_>, _>>()
+				.map(|keys| keystore.has_keys(&keys)),
+		),
+	}
+}
+
+/// Serve signing requests for the keys in `keystore` at `endpoint`, one thread per connection.
+///
+/// Requests over HTTP must carry `token`, which is required for such endpoints.
+///
+/// Blocks forever unless listening fails. Meant for tests and as a stand-in for a real signer.
+pub fn serve_signer(
+	endpoint: &RemoteSignerEndpoint,
+	keystore: Arc<dyn Keystore>,
+	token: Option<String>,
+) -> io::Result<()> {
+	match endpoint {
+		RemoteSignerEndpoint::Unix(path) => {
+			let listener = UnixListener::bind(path)?;
+			for stream in listener.incoming() {
+				let mut stream = stream?;
+				let keystore = keystore.clone();
+				std::thread::spawn(move || -> io::Result<()> {
+					let mut request = Vec::new();
+					BufReader::new(&mut stream).read_until(b'\n', &mut request)?;
+					stream.write_all(&answer_request(&*keystore, &request))?;
+					stream.write_all(b"\n")
+				});
+			}
+		},
+		RemoteSignerEndpoint::Http { host, port, .. } => {
+			let token: Arc<str> = token
+				.ok_or_else(|| {
+					io::Error::new(io::ErrorKind::InvalidInput, "an HTTP signer requires a token")
+				})?
+				.into();
+			let listener = TcpListener::bind((host.as_str(), *port))?;
+			for stream in listener.incoming() {
+				let stream = stream?;
+				let keystore = keystore.clone();
+				let token = token.clone();
+				std::thread::spawn(move || serve_http(stream, &*keystore, &token));
+			}
+		},
+	}
+
+	Ok(())
+}
+
+fn serve_http(mut stream: TcpStream, keystore: &dyn Keystore, token: &str) -> io::Result<()> {
+	let mut reader = BufReader::new(&mut stream);
+	let mut content_length = 0;
+	let mut authorized = false;
+	loop {
+		let mut line = String::new();
+		if reader.read_line(&mut line)? == 0 || line == "\r\n" {
+			break;
+		}
+		if let Some((name, value)) = line.split_once(':') {
+			if name.eq_ignore_ascii_case("content-length") {
+				content_length = value.trim().parse().unwrap_or(0);
+			} else if name.eq_ignore_ascii_case("authorization") {
+				authorized = value
+					.trim()
+					.strip_prefix("Bearer ")
+					.is_some_and(|given| constant_time_eq(given.as_bytes(), token.as_bytes()));
+			}
+		}
+	}
+
+	if !authorized {
+		return stream.write_all(b"HTTP/1.0 401 Unauthorized\r\nContent-Length: 0\r\n\r\n")
+	}
+
+	let mut request = vec![0; content_length];
+	reader.read_exact(&mut request)?;
+
+	let response = answer_request(keystore, &request);
+	write!(
+		stream,
+		"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+		response.len(),
+	)?;
+	stream.write_all(&response)
+}
+
+/// Compare `a` and `b` in a time independent of where they differ.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sp_consensus_micc::MICC;
+	use sp_core::Pair;
+	use sp_keystore::testing::MemoryKeystore;
+
+	const TOKEN: &str = "secret";
+
+	fn spawn_signer(endpoint: RemoteSignerEndpoint) -> sr25519::Public {
+		let keystore = MemoryKeystore::new();
+		let public = keystore.sr25519_generate_new(MICC, Some("//Alice")).unwrap();
+		std::thread::spawn(move || serve_signer(&endpoint, Arc::new(keystore), Some(TOKEN.into())));
+		std::thread::sleep(Duration::from_millis(100));
+		public
+	}
+
+	fn check_signer(endpoint: RemoteSignerEndpoint) {
+		let public = spawn_signer(endpoint.clone());
+		let remote =
+			RemoteKeystore::new(endpoint, DEFAULT_REMOTE_SIGNER_TIMEOUT, Some(TOKEN.into()), None)
+				.unwrap();
+
+		assert_eq!(remote.sr25519_public_keys(MICC), vec![public]);
+		assert!(remote.has_keys(&[(public.to_raw_vec(), MICC)]));
+		assert!(!remote.has_keys(&[(vec![0; 32], MICC)]));
+
+		let signature = remote
+			.sign_with(MICC, sr25519::CRYPTO_ID, public.as_slice(), b"block")
+			.unwrap()
+			.expect("the signer holds the key");
+		let signature = sr25519::Signature::try_from(&signature[..]).unwrap();
+		assert!(sr25519::Pair::verify(&signature, b"block", &public));
+
+		assert_eq!(
+			remote.sr25519_sign(MICC, &sr25519::Public::from_raw([0; 32]), b"block").unwrap(),
+			None
+		);
+	}
+
+	#[test]
+	fn signs_over_unix_socket() {
+		let dir = tempfile::tempdir().unwrap();
+		check_signer(RemoteSignerEndpoint::Unix(dir.path().join("signer.sock")));
+	}
+
+	#[test]
+	fn signs_over_http() {
+		let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+		check_signer(format!("http://127.0.0.1:{port}/sign").parse().unwrap());
+	}
+
+	#[test]
+	fn http_signer_requires_its_token() {
+		let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+		let endpoint: RemoteSignerEndpoint =
+			format!("http://127.0.0.1:{port}/sign").parse().unwrap();
+		let public = spawn_signer(endpoint.clone());
+
+		assert!(matches!(
+			RemoteKeystore::new(endpoint.clone(), DEFAULT_REMOTE_SIGNER_TIMEOUT, None, None),
+			Err(RemoteKeystoreError::MissingToken),
+		));
+
+		let remote =
+			RemoteKeystore::new(endpoint, DEFAULT_REMOTE_SIGNER_TIMEOUT, Some("guess".into()), None)
+				.unwrap();
+		assert!(remote.sr25519_public_keys(MICC).is_empty());
+		assert!(remote.sr25519_sign(MICC, &public, b"block").is_err());
+	}
+
+	#[tokio::test(flavor = "multi_thread")]
+	async fn public_keys_are_cached() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("signer.sock");
+		let public = spawn_signer(RemoteSignerEndpoint::Unix(path.clone()));
+		let remote = RemoteKeystore::new(
+			RemoteSignerEndpoint::Unix(path.clone()),
+			DEFAULT_REMOTE_SIGNER_TIMEOUT,
+			None,
+			None,
+		)
+		.unwrap();
+		assert!(remote.has_keys(&[(public.to_raw_vec(), MICC)]));
+
+		// Checked at every slot without reaching the signer, gone by now.
+		std::fs::remove_file(&path).unwrap();
+		assert!(remote.has_keys(&[(public.to_raw_vec(), MICC)]));
+		assert_eq!(remote.sr25519_public_keys(MICC), vec![public]);
+		assert!(remote.sr25519_sign(MICC, &public, b"block").is_err());
+	}
+
+	#[test]
+	fn unresponsive_signer_times_out() {
+		let dir = tempfile::tempdir().unwrap();
+		let path = dir.path().join("signer.sock");
+		let _listener = UnixListener::bind(&path).unwrap();
+
+		let remote = RemoteKeystore::new(
+			RemoteSignerEndpoint::Unix(path),
+			Duration::from_millis(50),
+			None,
+			None,
+		)
+		.unwrap();
+		let started = Instant::now();
+		assert!(remote.sr25519_public_keys(MICC).is_empty());
+		assert!(remote.sr25519_sign(MICC, &sr25519::Public::from_raw([0; 32]), b"block").is_err());
+		assert!(started.elapsed() < Duration::from_secs(1));
+	}
+
+	#[test]
+	fn parses_endpoints() {
+		assert_eq!(
+			"unix:/run/signer.sock".parse(),
+			Ok(RemoteSignerEndpoint::Unix("/run/signer.sock".into()))
+		);
+		assert_eq!(
+			"http://10.0.0.1:9000".parse(),
+			Ok(RemoteSignerEndpoint::Http {
+				host: "10.0.0.1".into(),
+				port: 9000,
+				path: "/".into()
+			})
+		);
+		assert!("https://signer:443".parse::<RemoteSignerEndpoint>().is_err());
+		assert!("http://signer".parse::<RemoteSignerEndpoint>().is_err());
+	}
+}
@@ -0,0 +1,178 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Requesting the blocks the runtime needs for work of its own.
+//!
+//! Scheduled calls, the migrations of an upgraded runtime and the like are no transactions, and
+//! nothing in the pool requests a block for them. The runtime tells when it needs the next block
+//! through [`MiccApi::next_required_block_time`], asked again whenever the best block changes,
+//! and a block is requested at that time.
+
+use std::{
+	marker::PhantomData,
+	sync::Arc,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use codec::Codec;
+use futures::stream;
+use sc_consensus_slots::{ProposalBudget, SlotTrigger};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block as BlockT;
+use tokio::time::sleep;
+
+use crate::{standalone::fetch_next_required_block_time, MiccApi, TriggerStream};
+
+const LOG_TARGET: &str = "micc::required_blocks";
+
+/// How often the best block is checked for a change.
+const BEST_BLOCK_CHECK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Delay before requesting a block again on the same best block, when the previous request did
+/// not lead to one, such as when another authority owned the slot.
+const REQUEST_RETRY_DELAY: Duration = Duration::from_secs(6);
+
+/// The block the runtime needs on top of the best block, if any.
+struct RequiredBlock<H> {
+	/// The best block the runtime was asked at.
+	best: Option<H>,
+	/// When the runtime needs the next block, in milliseconds since the Unix epoch.
+	due: Option<u64>,
+	/// When the block was last requested on top of `best`.
+	requested: Option<Instant>,
+}
+
+impl<H: PartialEq> RequiredBlock<H> {
+	fn new() -> Self {
+		Self { best: None, due: None, requested: None }
+	}
+
+	/// Whether the runtime has to be asked again, `best` not being the block it was asked at.
+	fn is_stale(&self, best: &H) -> bool {
+		self.best.as_ref() != Some(best)
+	}
+
+	/// Record when the runtime needs the next block on top of `best`.
+	fn update(&mut self, best: H, due: Option<u64>) {
+		*self = Self { best: Some(best), due, requested: None };
+	}
+
+	/// Whether to request the block at `now`, `now_ms` milliseconds since the Unix epoch, or how
+	/// long to wait before checking again.
+	fn poll(&mut self, now_ms: u64, now: Instant) -> Result<(), Duration> {
+		let Some(due) = self.due else { return Err(BEST_BLOCK_CHECK_INTERVAL) };
+
+		if let Some(requested) = self.requested {
+			let retry = REQUEST_RETRY_DELAY.saturating_sub(now.duration_since(requested));
+			if !retry.is_zero() {
+				return Err(retry.min(BEST_BLOCK_CHECK_INTERVAL))
+			}
+		}
+
+		match Duration::from_millis(due.saturating_sub(now_ms)) {
+			wait if wait.is_zero() => {
+				self.requested = Some(now);
+				Ok(())
+			},
+			wait => Err(wait.min(BEST_BLOCK_CHECK_INTERVAL)),
+		}
+	}
+}
+
+/// Create a stream requesting a block whenever the runtime needs one, see
+/// [`MiccApi::next_required_block_time`].
+///
+/// The blocks are requested with [`SlotTrigger::RuntimeRequired`], authored for
+/// [`AuthoringReason::RuntimeRequired`](sc_consensus_slots::AuthoringReason::RuntimeRequired)
+/// within a [`ProposalBudget::BATCH`] budget. Runtimes without the call never need one.
+pub(crate) fn create_required_block_stream<B, C, A>(client: Arc<C>) -> TriggerStream
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + Send + Sync + 'static,
+	C::Api: MiccApi<B, A>,
+	A: Codec + Send + 'static,
+{
+	let state = (client, RequiredBlock::new(), PhantomData::<A>);
+
+	Box::pin(stream::unfold(state, |(client, mut required, authority)| async move {
+		loop {
+			let best = client.info().best_hash;
+			if required.is_stale(&best) {
+				let due = fetch_next_required_block_time::<A, B, C>(&*client, best)
+					.unwrap_or_else(|e| {
+						log::debug!(
+							target: LOG_TARGET,
+							"Failed to ask the runtime for a required block at {best:?}: {e}",
+						);
+						None
+					});
+				if let Some(due) = due {
+					log::debug!(target: LOG_TARGET, "Runtime needs a block at {due} on {best:?}");
+				}
+				required.update(best, due);
+			}
+
+			let now_ms = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.unwrap_or_default()
+				.as_millis() as u64;
+			match required.poll(now_ms, Instant::now()) {
+				Ok(()) => {
+					log::info!(target: LOG_TARGET, "⏰ Requesting a block needed by the runtime");
+					return Some((
+						SlotTrigger::RuntimeRequired(ProposalBudget::BATCH),
+						(client, required, authority),
+					))
+				},
+				Err(wait) => sleep(wait).await,
+			}
+		}
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn requests_the_block_once_due_and_retries_on_the_same_best_block() {
+		let start = Instant::now();
+		let mut required = RequiredBlock::new();
+		assert!(required.is_stale(&1));
+
+		// The runtime needs no block.
+		required.update(1, None);
+		assert!(!required.is_stale(&1));
+		assert_eq!(required.poll(1_000, start), Err(BEST_BLOCK_CHECK_INTERVAL));
+
+		// The runtime needs a block at 1_200, checked again in the meantime.
+		required.update(1, Some(1_200));
+		assert_eq!(required.poll(1_000, start), Err(Duration::from_millis(200)));
+		assert_eq!(required.poll(1_200, start), Ok(()));
+
+		// Requested once on the same best block until the retry delay passed.
+		assert_eq!(required.poll(1_300, start), Err(BEST_BLOCK_CHECK_INTERVAL));
+		assert_eq!(required.poll(7_300, start + REQUEST_RETRY_DELAY), Ok(()));
+
+		// A new best block asks the runtime again, a time already passed requesting a block now.
+		assert!(required.is_stale(&2));
+		required.update(2, Some(500));
+		assert_eq!(required.poll(7_400, start + REQUEST_RETRY_DELAY), Ok(()));
+	}
+}
@@ -0,0 +1,228 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Fork choice for chains where the slot author isn't the only one allowed to author a slot.
+//!
+//! Under [`AuthorshipMode::Permissive`] and [`AuthorshipMode::AnyAuthority`] competing blocks of
+//! the same height are the norm, and every node orders them by their [`ForkRank`] rather than by
+//! the order it happened to import them in.
+
+use std::{fmt::Debug, sync::Arc};
+
+use codec::Codec;
+use sc_consensus::ForkChoiceStrategy;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::{HeaderBackend, HeaderMetadata};
+use sp_consensus::{Error as ConsensusError, SelectChain};
+use sp_consensus_micc::{AuthorshipMode, MiccApi};
+use sp_core::crypto::Pair;
+use sp_runtime::traits::{Block as BlockT, Header, NumberFor, One};
+
+use crate::{
+	authorities,
+	authority_sets::SharedAuthoritySets,
+	standalone::{find_author_pre_digest, ForkRank},
+	AuthorityId, CompatibilityMode,
+};
+
+/// The rank of a block among its competitors under `mode`.
+pub(crate) fn fork_rank<B, C, P>(
+	client: &C,
+	header: &B::Header,
+	hash: B::Hash,
+	mode: AuthorshipMode,
+	compatibility_mode: &CompatibilityMode<NumberFor<B>>,
+	authority_sets: Option<&SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+) -> Result<ForkRank<NumberFor<B>, B::Hash>, ConsensusError>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + HeaderMetadata<B, Error = sp_blockchain::Error>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	let (slot, author_index) = find_author_pre_digest::<B, P::Signature>(header)
+		.map_err(|e| ConsensusError::ChainLookup(format!("{:?}: {}", hash, e)))?;
+
+	let rotation_rank = match (mode, author_index) {
+		(AuthorshipMode::AnyAuthority, Some(author_index)) => {
+			let authorities = authorities(
+				client,
+				*header.parent_hash(),
+				*header.number(),
+				compatibility_mode,
+				authority_sets,
+			)?;
+			authorities
+				.selector
+				.rotation_rank(slot, author_index, authorities.len())
+				.unwrap_or(u32::MAX)
+		},
+		_ => 0,
+	};
+
+	Ok(ForkRank::new(*header.number(), slot, rotation_rank, hash))
+}
+
+/// How to choose between the block `hash` and the current best block under `mode`.
+///
+/// `header` may or may not include the seal, but `hash` must.
+pub(crate) fn fork_choice<B, C, P>(
+	client: &C,
+	header: &B::Header,
+	hash: B::Hash,
+	mode: AuthorshipMode,
+	compatibility_mode: &CompatibilityMode<NumberFor<B>>,
+	authority_sets: Option<&SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+) -> Result<ForkChoiceStrategy, ConsensusError>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + HeaderMetadata<B, Error = sp_blockchain::Error>,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+{
+	if mode == AuthorshipMode::Strict {
+		return Ok(ForkChoiceStrategy::LongestChain)
+	}
+
+	let best_hash = client.info().best_hash;
+	let best_header = client
+		.header(best_hash)
+		.map_err(|e| ConsensusError::ChainLookup(e.to_string()))?
+		.ok_or_else(|| ConsensusError::ChainLookup(format!("No header for {:?}", best_hash)))?;
+
+	let rank = |header: &B::Header, hash: B::Hash| {
+		fork_rank::<B, C, P>(client, header, hash, mode, compatibility_mode, authority_sets)
+	};
+	Ok(ForkChoiceStrategy::Custom(rank(header, hash)? < rank(&best_header, best_hash)?))
+}
+
+/// A [`SelectChain`] settling on the best block by [`ForkRank`] whenever slots may be authored by
+/// others than their slot author, and deferring to the wrapped chain selection otherwise.
+///
+/// Finality targets are always left to the wrapped chain selection.
+pub struct MiccSelectChain<B: BlockT, C, P: Pair, SC> {
+	inner: SC,
+	client: Arc<C>,
+	compatibility_mode: CompatibilityMode<NumberFor<B>>,
+	authority_sets: Option<SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+}
+
+impl<B: BlockT, C, P: Pair, SC> MiccSelectChain<B, C, P, SC> {
+	/// Wrap `inner`, usually a [`sc_consensus::LongestChain`].
+	///
+	/// `compatibility_mode` and `authority_sets` should be the ones given to the import queue.
+	pub fn new(
+		inner: SC,
+		client: Arc<C>,
+		compatibility_mode: CompatibilityMode<NumberFor<B>>,
+		authority_sets: Option<SharedAuthoritySets<B::Hash, NumberFor<B>, AuthorityId<P>>>,
+	) -> Self {
+		Self { inner, client, compatibility_mode, authority_sets }
+	}
+}
+
+impl<B: BlockT, C, P: Pair, SC: Clone> Clone for MiccSelectChain<B, C, P, SC> {
+	fn clone(&self) -> Self {
+		Self {
+			inner: self.inner.clone(),
+			client: self.client.clone(),
+			compatibility_mode: self.compatibility_mode.clone(),
+			authority_sets: self.authority_sets.clone(),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl<B, C, P, SC> SelectChain<B> for MiccSelectChain<B, C, P, SC>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B>
+		+ HeaderBackend<B>
+		+ HeaderMetadata<B, Error = sp_blockchain::Error>
+		+ Send
+		+ Sync,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	P: Pair,
+	P::Public: Codec + Debug + Clone,
+	P::Signature: Codec,
+	SC: SelectChain<B>,
+{
+	async fn leaves(&self) -> Result<Vec<B::Hash>, ConsensusError> {
+		self.inner.leaves().await
+	}
+
+	async fn best_chain(&self) -> Result<B::Header, ConsensusError> {
+		let mut best = self.inner.best_chain().await?;
+		let mode = authorities(
+			&*self.client,
+			best.hash(),
+			*best.number() + One::one(),
+			&self.compatibility_mode,
+			self.authority_sets.as_ref(),
+		)?
+		.mode;
+		if mode == AuthorshipMode::Strict {
+			return Ok(best)
+		}
+
+		let leaves = self.inner.leaves().await?;
+		let rank = |header: &B::Header| {
+			fork_rank::<B, C, P>(
+				&*self.client,
+				header,
+				header.hash(),
+				mode,
+				&self.compatibility_mode,
+				self.authority_sets.as_ref(),
+			)
+		};
+
+		let mut best_rank = rank(&best)?;
+		for leaf in leaves {
+			let header = self
+				.client
+				.header(leaf)
+				.map_err(|e| ConsensusError::ChainLookup(e.to_string()))?
+				.ok_or_else(|| ConsensusError::ChainLookup(format!("No header for {:?}", leaf)))?;
+			// Lower leaves can't win.
+			if header.number() < best.number() {
+				continue
+			}
+
+			let leaf_rank = rank(&header)?;
+			if leaf_rank < best_rank {
+				best = header;
+				best_rank = leaf_rank;
+			}
+		}
+
+		Ok(best)
+	}
+
+	async fn finality_target(
+		&self,
+		base_hash: B::Hash,
+		maybe_max_number: Option<NumberFor<B>>,
+	) -> Result<B::Hash, ConsensusError> {
+		self.inner.finality_target(base_hash, maybe_max_number).await
+	}
+}
@@ -0,0 +1,780 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Evaluating a block production policy on a node that doesn't author.
+//!
+//! [`run_shadow_authoring`] follows the live transaction pool with the trigger stream of a
+//! [`ProductionMode`], and proposes a block on the best block whenever one is requested, as an
+//! authority would. The block is neither sealed nor imported, and is only measured: when it would
+//! have been authored, how many transactions it would have included and how long they would have
+//! waited for it. A [`ShadowAuthoring`] keeps the results, for the RPC and the metrics.
+//!
+//! The blocks are proposed as an authority proposes them, the proposer factory given should be set
+//! up the same way: ordering the transactions with an [`OrderedPool`](crate::OrderedPool) fed by
+//! the [`TransactionArrivals`] recorded, and building the blocks of the collection windows
+//! speculatively with a [`SpeculativeProposerFactory`](crate::SpeculativeProposerFactory) sharing
+//! the [`Speculation`]. A block announced by a [`SlotTrigger::Prepare`] is proposed right away,
+//! and serves the request that follows, as it does on an authority.
+//!
+//! Shadow blocks never leave the pool, so each transaction is counted in the first shadow block
+//! including it only, as the following ones would not have included it again.
+
+use std::{
+	collections::VecDeque,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use codec::{Codec, Encode};
+use futures::{stream, Future, Stream, StreamExt};
+use prometheus_endpoint::{
+	register, Counter, Histogram, HistogramOpts, PrometheusError, Registry, U64,
+};
+use sc_consensus_slots::{
+	InherentDataProviderExt, ProposalBudget, SlotDurationSource, SlotProportion, SlotTrigger,
+};
+use sc_transaction_pool_api::{InPoolTransaction, TransactionPool, TxHash};
+use sp_api::ProvideRuntimeApi;
+use sp_application_crypto::AppPublic;
+use sp_blockchain::HeaderBackend;
+use sp_consensus::{Environment, Error as ConsensusError, Proposer};
+use sp_core::crypto::Pair;
+use sp_inherents::{CreateInherentDataProviders, InherentDataProvider};
+use sp_runtime::{
+	traits::{Block as BlockT, Header, Member, UniqueSaturatedInto},
+	Digest,
+};
+
+use crate::{
+	collection::Arrivals,
+	event_driven::{create_hybrid_stream, create_true_event_driven_stream, EventDrivenConfig},
+	promotion::PROMOTION_CHECK_INTERVAL,
+	required_blocks::create_required_block_stream,
+	standalone::{find_pre_digest, pre_digest},
+	AuthorityId, CollectionController, MiccApi, ProductionMode, RuntimeSlotDurations,
+	SlotDuration, Speculation, TransactionArrivals,
+};
+
+const LOG_TARGET: &str = "micc::shadow";
+
+/// Number of shadow blocks kept in the report.
+const MAX_RECENT_BLOCKS: usize = 64;
+
+/// A block that would have been authored.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowBlock {
+	/// When the block would have been authored, in milliseconds since the Unix epoch.
+	pub authored_at_ms: u64,
+	/// The number of the block.
+	pub number: u64,
+	/// The slot of the block.
+	pub slot: u64,
+	/// Number of transactions the block would have included first.
+	pub transactions: u32,
+	/// Time taken to propose the block.
+	pub proposing_ms: u64,
+	/// Mean time the transactions waited in the pool for the block.
+	pub mean_latency_ms: Option<u64>,
+	/// Longest time a transaction waited in the pool for the block.
+	pub max_latency_ms: Option<u64>,
+}
+
+/// What a policy evaluated by [`run_shadow_authoring`] would have authored.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowReport {
+	/// The production mode and configuration evaluated.
+	pub policy: String,
+	/// Number of blocks requested by the trigger stream.
+	pub requested: u64,
+	/// Number of blocks that would have been authored.
+	pub blocks: u64,
+	/// Number of transactions these blocks would have included.
+	pub transactions: u64,
+	/// Number of requested blocks that couldn't be proposed.
+	pub failed: u64,
+	/// The latest blocks that would have been authored, oldest first.
+	pub recent: VecDeque<ShadowBlock>,
+}
+
+struct Metrics {
+	blocks: Counter<U64>,
+	transactions: Counter<U64>,
+	proposing: Histogram,
+	latency: Histogram,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			blocks: register(
+				Counter::new(
+					"micc_shadow_blocks_total",
+					"Number of blocks the shadow authoring would have authored",
+				)?,
+				registry,
+			)?,
+			transactions: register(
+				Counter::new(
+					"micc_shadow_transactions_total",
+					"Number of transactions the blocks of the shadow authoring would have included",
+				)?,
+				registry,
+			)?,
+			proposing: register(
+				Histogram::with_opts(
+					HistogramOpts::new(
+						"micc_shadow_proposing_seconds",
+						"Time taken to propose the blocks of the shadow authoring",
+					)
+					.buckets(vec![0.01, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 4.0]),
+				)?,
+				registry,
+			)?,
+			latency: register(
+				Histogram::with_opts(
+					HistogramOpts::new(
+						"micc_shadow_inclusion_latency_seconds",
+						"Time transactions would have waited in the pool for a block of the shadow \
+						 authoring",
+					)
+					.buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0]),
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// Results of the shadow authoring of a node, see [`run_shadow_authoring`].
+#[derive(Clone)]
+pub struct ShadowAuthoring {
+	report: Arc<Mutex<ShadowReport>>,
+	metrics: Option<Arc<Metrics>>,
+}
+
+impl ShadowAuthoring {
+	/// Create the results of a shadow authoring, registering its metrics if a registry is given.
+	pub fn new(registry: Option<&Registry>) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			report: Default::default(),
+			metrics: registry.map(Metrics::register).transpose()?.map(Arc::new),
+		})
+	}
+
+	/// What the shadow authoring would have authored so far.
+	pub fn report(&self) -> ShadowReport {
+		self.report.lock().expect("never poisoned; qed").clone()
+	}
+
+	fn set_policy(&self, policy: String) {
+		self.report.lock().expect("never poisoned; qed").policy = policy;
+	}
+
+	fn note_requested(&self) {
+		self.report.lock().expect("never poisoned; qed").requested += 1;
+	}
+
+	fn note_failed(&self) {
+		self.report.lock().expect("never poisoned; qed").failed += 1;
+	}
+
+	/// Note a block including transactions that waited `latencies` for it.
+	fn note_block(
+		&self,
+		authored_at_ms: u64,
+		number: u64,
+		slot: u64,
+		proposing: Duration,
+		latencies: &[Duration],
+	) {
+		let total: Duration = latencies.iter().sum();
+		let block = ShadowBlock {
+			authored_at_ms,
+			number,
+			slot,
+			transactions: latencies.len() as u32,
+			proposing_ms: proposing.as_millis() as u64,
+			mean_latency_ms: (!latencies.is_empty())
+				.then(|| (total / latencies.len() as u32).as_millis() as u64),
+			max_latency_ms: latencies.iter().max().map(|latency| latency.as_millis() as u64),
+		};
+
+		let mut report = self.report.lock().expect("never poisoned; qed");
+		report.blocks += 1;
+		report.transactions += latencies.len() as u64;
+		if report.recent.len() >= MAX_RECENT_BLOCKS {
+			report.recent.pop_front();
+		}
+		report.recent.push_back(block);
+
+		if let Some(metrics) = &self.metrics {
+			metrics.blocks.inc();
+			metrics.transactions.inc_by(latencies.len() as u64);
+			metrics.proposing.observe(proposing.as_secs_f64());
+			for latency in latencies {
+				metrics.latency.observe(latency.as_secs_f64());
+			}
+		}
+	}
+}
+
+/// Parameters of [`run_shadow_authoring`].
+pub struct ShadowAuthoringParams<C, PF, CIDP, Pool> {
+	/// The duration of a slot, until the runtime is asked for its slot duration schedule.
+	pub slot_duration: SlotDuration,
+	/// The client to interact with the chain.
+	pub client: Arc<C>,
+	/// The transaction pool followed.
+	pub pool: Arc<Pool>,
+	/// The proposer factory to build the shadow blocks, never sealed nor imported.
+	pub proposer_factory: PF,
+	/// Something that can create the inherent data providers.
+	pub create_inherent_data_providers: CIDP,
+	/// The production mode evaluated.
+	pub production_mode: ProductionMode,
+	/// The configuration of the event-driven production evaluated.
+	pub event_config: EventDrivenConfig,
+	/// The proportion of the slot dedicated to proposing the blocks of the hybrid production.
+	pub block_proposal_slot_portion: SlotProportion,
+	/// Sizes the collection windows from the shadow blocks, as it would from authored ones.
+	pub collection_controller: Option<CollectionController>,
+	/// Where to record the transaction imports and block triggers, for the
+	/// [`OrderedPool`](crate::OrderedPool) of the proposer factory to order the proposed
+	/// transactions by. Only the event-driven production records them.
+	pub transaction_arrivals: Option<TransactionArrivals<TxHash<Pool>>>,
+	/// Opened and sealed around the collection windows of the event-driven production, for the
+	/// proposer factory to build blocks during the windows.
+	pub speculation: Option<Speculation>,
+	/// Where the results are kept.
+	pub shadow: ShadowAuthoring,
+}
+
+/// Request blocks as an authority running `production_mode` would, and measure the blocks it would
+/// have authored without sealing them, see the [module docs](self).
+pub async fn run_shadow_authoring<P, B, C, PF, CIDP, Pool, Error>(
+	ShadowAuthoringParams {
+		slot_duration,
+		client,
+		pool,
+		mut proposer_factory,
+		create_inherent_data_providers,
+		production_mode,
+		event_config,
+		block_proposal_slot_portion,
+		collection_controller,
+		transaction_arrivals,
+		speculation,
+		shadow,
+	}: ShadowAuthoringParams<C, PF, CIDP, Pool>,
+) where
+	P: Pair,
+	P::Public: AppPublic + Member,
+	P::Signature: TryFrom<Vec<u8>> + Member + Codec,
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + Send + Sync + 'static,
+	C::Api: MiccApi<B, AuthorityId<P>>,
+	PF: Environment<B, Error = Error> + Send + Sync + 'static,
+	PF::Proposer: Proposer<B, Error = Error>,
+	CIDP: CreateInherentDataProviders<B, ()> + Send + Sync + 'static,
+	CIDP::InherentDataProviders: InherentDataProviderExt + Send,
+	Pool: TransactionPool<Block = B> + 'static,
+	Error: std::error::Error + Send + 'static,
+{
+	shadow.set_policy(format!("{production_mode}: {event_config:?}"));
+	log::info!(target: LOG_TARGET, "👥 Shadow authoring with {production_mode} production");
+
+	let slot_durations =
+		Arc::new(RuntimeSlotDurations::<B, _, AuthorityId<P>>::new(client.clone(), slot_duration));
+	let triggers = match production_mode {
+		ProductionMode::EventDriven => create_true_event_driven_stream::<B, Pool>(
+			pool.clone(),
+			event_config,
+			transaction_arrivals,
+			speculation,
+			collection_controller.clone(),
+		),
+		ProductionMode::Hybrid => create_hybrid_stream::<B, Pool, _>(
+			pool.clone(),
+			event_config,
+			slot_durations.clone(),
+			block_proposal_slot_portion,
+			transaction_arrivals,
+		),
+	};
+	let triggers = stream::select(
+		triggers,
+		create_required_block_stream::<B, _, AuthorityId<P>>(client.clone()),
+	);
+
+	let create_inherent_data_providers = Arc::new(create_inherent_data_providers);
+	follow_triggers(
+		&*pool,
+		triggers,
+		|budget| {
+			propose::<P, B, C, PF, CIDP, _, Error>(
+				&*client,
+				slot_durations.clone(),
+				&mut proposer_factory,
+				create_inherent_data_providers.clone(),
+				budget,
+			)
+		},
+		collection_controller.as_ref(),
+		&shadow,
+	)
+	.await
+}
+
+/// Follow `triggers` and the transactions of `pool`, and measure the blocks `propose` proposes
+/// within the budget of the blocks requested. Returns once `triggers` ends.
+async fn follow_triggers<B, Pool, F, Fut>(
+	pool: &Pool,
+	mut triggers: impl Stream<Item = SlotTrigger> + Unpin,
+	mut propose: F,
+	collection_controller: Option<&CollectionController>,
+	shadow: &ShadowAuthoring,
+) where
+	B: BlockT,
+	Pool: TransactionPool<Block = B>,
+	F: FnMut(ProposalBudget) -> Fut,
+	Fut: Future<Output = Result<(B, u64, Duration), ConsensusError>>,
+{
+	let mut imports = pool.import_notification_stream();
+	let mut futures_check = tokio::time::interval(PROMOTION_CHECK_INTERVAL);
+	let mut arrivals = Arrivals::default();
+
+	loop {
+		// The budget of the block requested, and whether it is only announced.
+		let mut request = tokio::select! {
+			// Note the arrival of the transactions before the blocks they trigger.
+			biased;
+			Some(hash) = imports.next() => {
+				arrivals.note(hash, Instant::now());
+				continue
+			},
+			_ = futures_check.tick(), if pool.status().future > 0 => {
+				note_futures(pool, &mut arrivals);
+				continue
+			},
+			trigger = triggers.next() => match trigger {
+				Some(SlotTrigger::CreateBlock(budget) | SlotTrigger::RuntimeRequired(budget)) =>
+					Some((budget, false)),
+				Some(SlotTrigger::Prepare(budget)) => Some((budget, true)),
+				Some(SlotTrigger::NoAction) => continue,
+				None => return,
+			},
+		};
+
+		while let Some((budget, prepared)) = request.take() {
+			shadow.note_requested();
+
+			// Keep noting the arrivals while proposing, and listening to the triggers while the
+			// block is prepared: the block requested next is served by the prepared one.
+			let proposed = {
+				let proposing = propose(budget);
+				futures::pin_mut!(proposing);
+				loop {
+					tokio::select! {
+						biased;
+						Some(hash) = imports.next() => arrivals.note(hash, Instant::now()),
+						_ = futures_check.tick(), if pool.status().future > 0 =>
+							note_futures(pool, &mut arrivals),
+						trigger = triggers.next(), if prepared && request.is_none() => {
+							match trigger {
+								Some(
+									SlotTrigger::CreateBlock(budget) |
+									SlotTrigger::RuntimeRequired(budget),
+								) => request = Some((budget, false)),
+								Some(_) => {},
+								None => break proposing.await,
+							}
+						},
+						proposed = &mut proposing => break proposed,
+					}
+				}
+			};
+
+			let (block, slot, proposing) = match proposed {
+				Ok(proposed) => proposed,
+				Err(e) => {
+					log::warn!(target: LOG_TARGET, "Failed to propose a shadow block: {e}");
+					shadow.note_failed();
+					// A block requested while this one was prepared gets a block of its own.
+					continue
+				},
+			};
+			request = None;
+
+			let now = Instant::now();
+			let mut latencies = Vec::new();
+			let mut transactions_size = 0;
+			for extrinsic in block.extrinsics() {
+				if let Some(arrived) = arrivals.take(&pool.hash_of(extrinsic)) {
+					latencies.push(now.saturating_duration_since(arrived));
+					transactions_size += extrinsic.encoded_size();
+				}
+			}
+
+			let number: u64 = (*block.header().number()).unique_saturated_into();
+			log::debug!(
+				target: LOG_TARGET,
+				"Would have authored block #{number} at slot {slot} with {} transactions",
+				latencies.len(),
+			);
+			let authored_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+			shadow.note_block(authored_at.as_millis() as u64, number, slot, proposing, &latencies);
+			if let Some(controller) = collection_controller {
+				controller.note_block(latencies, transactions_size, block.encoded_size());
+			}
+		}
+	}
+}
+
+/// Note the arrival of the transactions of the future queue of `pool` not noted yet: neither
+/// entering the queue nor leaving it for the ready one is notified.
+fn note_futures<Pool: TransactionPool>(pool: &Pool, arrivals: &mut Arrivals<TxHash<Pool>>) {
+	let now = Instant::now();
+	for tx in pool.futures() {
+		arrivals.note(tx.hash().clone(), now);
+	}
+}
+
+/// Start proposing a block on the best block within `budget`, at the first slot after the best
+/// block's, as the slot worker would. Resolves to the block, its slot and the time proposing it
+/// took.
+fn propose<P, B, C, PF, CIDP, SD, Error>(
+	client: &C,
+	slot_durations: Arc<SD>,
+	proposer_factory: &mut PF,
+	create_inherent_data_providers: Arc<CIDP>,
+	budget: ProposalBudget,
+) -> impl Future<Output = Result<(B, u64, Duration), ConsensusError>>
+where
+	P: Pair,
+	P::Signature: TryFrom<Vec<u8>> + Member + Codec,
+	B: BlockT,
+	C: HeaderBackend<B>,
+	PF: Environment<B, Error = Error>,
+	PF::Proposer: Proposer<B, Error = Error>,
+	CIDP: CreateInherentDataProviders<B, ()>,
+	CIDP::InherentDataProviders: InherentDataProviderExt,
+	SD: SlotDurationSource<B> + ?Sized,
+	Error: std::error::Error + Send + 'static,
+{
+	let best = client.info().best_hash;
+	let parent = client
+		.header(best)
+		.map_err(|e| ConsensusError::ClientImport(e.to_string()))
+		.and_then(|parent| {
+			parent.ok_or_else(|| {
+				ConsensusError::ClientImport(format!("Best block {best:?} not found"))
+			})
+		})
+		.map(|parent| (proposer_factory.init(&parent), parent));
+
+	async move {
+		let (proposer, parent) = parent?;
+		let parent_slot = find_pre_digest::<B, P::Signature>(&parent).ok();
+
+		let providers = loop {
+			let providers = create_inherent_data_providers
+				.create_inherent_data_providers(best, ())
+				.await
+				.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+			if parent_slot.is_none_or(|parent_slot| providers.slot() > parent_slot) {
+				break providers
+			}
+			tokio::time::sleep(slot_durations.time_until_next_slot()).await;
+		};
+		let slot = providers.slot();
+		// Keeps the schedule followed by the hybrid stream up to date, as the slot worker does.
+		slot_durations.slot_duration(&parent, slot);
+
+		let inherent_data = providers
+			.create_inherent_data()
+			.await
+			.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+		let proposer = proposer.await.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+
+		let started = Instant::now();
+		let proposal = proposer
+			.propose(
+				inherent_data,
+				Digest { logs: vec![pre_digest::<P>(slot)] },
+				budget.proposing,
+				budget.block_size_limit,
+			)
+			.await
+			.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+
+		Ok((proposal.block, *slot, started.elapsed()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::channel::mpsc;
+	use sc_transaction_pool_api::{
+		ImportNotificationStream, PoolStatus, ReadyTransactions, TransactionPriority,
+		TransactionSource, TransactionStatusStreamFor, TransactionTag, TxInvalidityReportMap,
+	};
+	use sp_core::H256;
+	use sp_runtime::{
+		generic::{Block, Header},
+		traits::{BlakeTwo256, Header as _},
+		OpaqueExtrinsic,
+	};
+	use std::{collections::HashMap, pin::Pin};
+
+	type TestBlock = Block<Header<u64, BlakeTwo256>, OpaqueExtrinsic>;
+
+	#[test]
+	fn reports_the_blocks_that_would_have_been_authored() {
+		let shadow = ShadowAuthoring::new(Some(&Registry::new())).unwrap();
+		shadow.note_requested();
+		shadow.note_block(1_000, 1, 10, Duration::from_millis(30), &[]);
+		shadow.note_requested();
+		shadow.note_block(
+			7_000,
+			2,
+			11,
+			Duration::from_millis(50),
+			&[Duration::from_millis(400), Duration::from_millis(800)],
+		);
+		shadow.note_requested();
+		shadow.note_failed();
+
+		let report = shadow.report();
+		assert_eq!((report.requested, report.blocks, report.failed), (3, 2, 1));
+		assert_eq!(report.transactions, 2);
+		assert_eq!(report.recent[0].mean_latency_ms, None);
+		assert_eq!(
+			report.recent[1],
+			ShadowBlock {
+				authored_at_ms: 7_000,
+				number: 2,
+				slot: 11,
+				transactions: 2,
+				proposing_ms: 50,
+				mean_latency_ms: Some(600),
+				max_latency_ms: Some(800),
+			},
+		);
+
+		for number in 3..=MAX_RECENT_BLOCKS as u64 + 2 {
+			shadow.note_block(0, number, number + 9, Duration::ZERO, &[]);
+		}
+		let report = shadow.report();
+		assert_eq!(report.recent.len(), MAX_RECENT_BLOCKS);
+		assert_eq!(report.recent[0].number, 3);
+	}
+
+	/// The transaction named `name`, made of that single byte.
+	fn extrinsic(name: u8) -> OpaqueExtrinsic {
+		OpaqueExtrinsic::from_bytes(&[4, name]).expect("a single encoded byte; qed")
+	}
+
+	/// A block proposed at slot `number + 10` with the transactions `names`.
+	fn proposed(number: u64, names: &[u8]) -> (TestBlock, u64, Duration) {
+		let header = Header::new(
+			number,
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Default::default(),
+		);
+		let block = Block { header, extrinsics: names.iter().copied().map(extrinsic).collect() };
+		(block, number + 10, Duration::from_millis(20))
+	}
+
+	#[derive(Debug)]
+	struct Tx(Arc<OpaqueExtrinsic>, u64);
+
+	impl InPoolTransaction for Tx {
+		type Transaction = Arc<OpaqueExtrinsic>;
+		type Hash = u64;
+
+		fn data(&self) -> &Arc<OpaqueExtrinsic> {
+			&self.0
+		}
+
+		fn hash(&self) -> &u64 {
+			&self.1
+		}
+
+		fn priority(&self) -> &TransactionPriority {
+			&0
+		}
+
+		fn longevity(&self) -> &u64 {
+			&u64::MAX
+		}
+
+		fn requires(&self) -> &[TransactionTag] {
+			&[]
+		}
+
+		fn provides(&self) -> &[TransactionTag] {
+			&[]
+		}
+
+		fn is_propagable(&self) -> bool {
+			true
+		}
+	}
+
+	/// A pool notifying the imports sent to it, and naming the transactions by their byte.
+	struct TestPool {
+		imports: Mutex<Option<ImportNotificationStream<u64>>>,
+	}
+
+	type Ready = Box<dyn ReadyTransactions<Item = Arc<Tx>> + Send>;
+
+	#[async_trait::async_trait]
+	impl TransactionPool for TestPool {
+		type Block = TestBlock;
+		type Hash = u64;
+		type InPoolTransaction = Tx;
+		type Error = sc_transaction_pool_api::error::Error;
+
+		async fn submit_at(
+			&self,
+			_at: H256,
+			_source: TransactionSource,
+			_xts: Vec<OpaqueExtrinsic>,
+		) -> Result<Vec<Result<u64, Self::Error>>, Self::Error> {
+			unimplemented!()
+		}
+
+		async fn submit_one(
+			&self,
+			_at: H256,
+			_source: TransactionSource,
+			_xt: OpaqueExtrinsic,
+		) -> Result<u64, Self::Error> {
+			unimplemented!()
+		}
+
+		async fn submit_and_watch(
+			&self,
+			_at: H256,
+			_source: TransactionSource,
+			_xt: OpaqueExtrinsic,
+		) -> Result<Pin<Box<TransactionStatusStreamFor<Self>>>, Self::Error> {
+			unimplemented!()
+		}
+
+		async fn ready_at(&self, _at: H256) -> Ready {
+			unimplemented!()
+		}
+
+		fn ready(&self) -> Ready {
+			unimplemented!()
+		}
+
+		fn report_invalid(
+			&self,
+			_at: Option<H256>,
+			_invalid_tx_errors: TxInvalidityReportMap<u64>,
+		) -> Vec<Arc<Tx>> {
+			unimplemented!()
+		}
+
+		fn futures(&self) -> Vec<Tx> {
+			Vec::new()
+		}
+
+		fn status(&self) -> PoolStatus {
+			PoolStatus { ready: 0, ready_bytes: 0, future: 0, future_bytes: 0 }
+		}
+
+		fn import_notification_stream(&self) -> ImportNotificationStream<u64> {
+			self.imports.lock().expect("never poisoned; qed").take().expect("subscribed once; qed")
+		}
+
+		fn on_broadcasted(&self, _propagations: HashMap<u64, Vec<String>>) {
+			unimplemented!()
+		}
+
+		fn hash_of(&self, xt: &OpaqueExtrinsic) -> u64 {
+			xt.encode()[1] as u64
+		}
+
+		fn ready_transaction(&self, _hash: &u64) -> Option<Arc<Tx>> {
+			unimplemented!()
+		}
+
+		async fn ready_at_with_timeout(&self, _at: H256, _timeout: Duration) -> Ready {
+			unimplemented!()
+		}
+	}
+
+	#[tokio::test]
+	async fn prepared_blocks_serve_the_request_that_follows() {
+		let (mut import_sink, imports) = mpsc::channel(16);
+		let pool = TestPool { imports: Mutex::new(Some(imports)) };
+		for hash in [1, 2] {
+			import_sink.try_send(hash).unwrap();
+		}
+
+		let budget = ProposalBudget::BATCH;
+		let (trigger_sink, triggers) = mpsc::unbounded();
+		for trigger in [
+			SlotTrigger::Prepare(budget),
+			SlotTrigger::CreateBlock(budget),
+			SlotTrigger::RuntimeRequired(budget),
+			SlotTrigger::Prepare(budget),
+		] {
+			trigger_sink.unbounded_send(trigger).unwrap();
+		}
+		drop(trigger_sink);
+
+		// The first prepared block fails, and the block requested meanwhile is proposed on its
+		// own. The transactions are counted in the first block including them only.
+		let mut proposals = VecDeque::from([
+			Err(ConsensusError::ClientImport("no state".into())),
+			Ok(proposed(1, &[1, 2])),
+			Ok(proposed(2, &[2])),
+			Ok(proposed(3, &[])),
+		]);
+		let shadow = ShadowAuthoring::new(None).unwrap();
+		follow_triggers(
+			&pool,
+			triggers,
+			|_| futures::future::ready(proposals.pop_front().expect("one proposal per block")),
+			None,
+			&shadow,
+		)
+		.await;
+		assert!(proposals.is_empty());
+
+		let report = shadow.report();
+		assert_eq!((report.requested, report.blocks, report.failed), (4, 3, 1));
+		assert_eq!(report.transactions, 2);
+		let included: Vec<_> = report
+			.recent
+			.iter()
+			.map(|block| (block.number, block.slot, block.transactions))
+			.collect();
+		assert_eq!(included, vec![(1, 11, 2), (2, 12, 0), (3, 13, 0)]);
+		assert!(report.recent[0].max_latency_ms.is_some());
+	}
+}
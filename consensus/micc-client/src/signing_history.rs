@@ -0,0 +1,314 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Slashing protection: the last header sealed by every local authority key.
+//!
+//! The slot worker records a header here before sealing it, and refuses to seal a header when the
+//! key already sealed a different one at the same slot, or any header at a later slot. The history
+//! is kept in the aux store, so nodes sharing a key are only protected from each other once the
+//! history moves along with the key, see the interchange format below.
+//!
+//! # Interchange format
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "genesisHash": "0x..",
+//!   "keys": [
+//!     { "publicKey": "0x..", "slot": 1234, "headerHash": "0x.." }
+//!   ]
+//! }
+//! ```
+//!
+//! Every key lists the last header it sealed, by the hash of the header before the seal was added.
+//! Importing keeps the entry with the highest slot of every key.
+
+use std::{
+	collections::BTreeMap,
+	sync::{Arc, Mutex},
+};
+
+use codec::{Decode, Encode};
+use sc_client_api::backend::AuxStore;
+use sp_blockchain::{Error as ClientError, Result as ClientResult};
+use sp_consensus_slots::Slot;
+use sp_core::{hexdisplay::HexDisplay, Bytes};
+
+const SIGNING_HISTORY_KEY: &[u8] = b"micc_signing_history";
+
+/// The version of the [`SigningHistoryInterchange`] format.
+pub const INTERCHANGE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Eq, Encode, Decode)]
+struct LastSealed {
+	slot: u64,
+	header_hash: Vec<u8>,
+}
+
+/// The last header sealed by a key, in the [`SigningHistoryInterchange`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SealedHeader {
+	/// The public key.
+	pub public_key: Bytes,
+	/// The slot of the header.
+	pub slot: u64,
+	/// The hash of the header without its seal.
+	pub header_hash: Bytes,
+}
+
+/// A [`SigningHistory`] exported to move keys between hosts.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SigningHistoryInterchange {
+	/// The version of the format, [`INTERCHANGE_VERSION`].
+	pub version: u32,
+	/// The hash of the genesis block of the chain the headers belong to.
+	pub genesis_hash: Bytes,
+	/// The last header sealed by every key.
+	pub keys: Vec<SealedHeader>,
+}
+
+/// Errors of the [`SigningHistory`].
+#[derive(Debug, thiserror::Error)]
+pub enum SigningHistoryError {
+	/// Sealing would equivocate.
+	#[error("already sealed {existing} at slot {slot}, refusing to seal {refused}")]
+	Equivocation {
+		/// The slot of both headers.
+		slot: u64,
+		/// The hash of the header sealed before.
+		existing: String,
+		/// The hash of the header refused.
+		refused: String,
+	},
+	/// The key already sealed a header at a later slot.
+	#[error("already sealed a header at slot {last}, refusing to seal at slot {slot}")]
+	SlotBehind {
+		/// The slot of the header refused.
+		slot: u64,
+		/// The slot of the last header sealed.
+		last: u64,
+	},
+	/// The interchange version isn't supported.
+	#[error("unsupported signing history version {0}, expected {INTERCHANGE_VERSION}")]
+	UnsupportedVersion(u32),
+	/// The interchange belongs to another chain.
+	#[error("signing history of the chain with genesis {found}, expected {expected}")]
+	WrongChain {
+		/// The genesis of the local chain.
+		expected: String,
+		/// The genesis of the interchange.
+		found: String,
+	},
+	/// Reading or writing the aux store failed.
+	#[error(transparent)]
+	Client(#[from] ClientError),
+}
+
+fn hex(bytes: &[u8]) -> String {
+	format!("0x{}", HexDisplay::from(&bytes))
+}
+
+/// The last header sealed by every local authority key.
+#[derive(Clone)]
+pub struct SigningHistory {
+	keys: Arc<Mutex<BTreeMap<Vec<u8>, LastSealed>>>,
+}
+
+impl SigningHistory {
+	/// Load the history from the aux store, starting empty on a fresh node.
+	pub fn load<C: AuxStore>(client: &C) -> ClientResult<Self> {
+		let keys = match client.get_aux(SIGNING_HISTORY_KEY)? {
+			None => BTreeMap::new(),
+			Some(encoded) => Decode::decode(&mut &encoded[..]).map_err(|e| {
+				ClientError::Backend(format!("Micc signing history is corrupted: {}", e))
+			})?,
+		};
+
+		Ok(Self { keys: Arc::new(Mutex::new(keys)) })
+	}
+
+	/// Record that `public` is about to seal the header `header_hash` at `slot`, unless that
+	/// would equivocate.
+	///
+	/// The record is written before returning, so the header may be sealed once this succeeds.
+	/// Sealing the same header again is allowed.
+	pub fn record_sealing<C: AuxStore>(
+		&self,
+		client: &C,
+		public: &[u8],
+		slot: Slot,
+		header_hash: &[u8],
+	) -> Result<(), SigningHistoryError> {
+		let slot = u64::from(slot);
+		let mut keys = self.keys.lock().expect("never poisoned; qed");
+
+		if let Some(last) = keys.get(public) {
+			if last.slot > slot {
+				return Err(SigningHistoryError::SlotBehind { slot, last: last.slot })
+			}
+			if last.slot == slot {
+				if last.header_hash == header_hash {
+					return Ok(())
+				}
+				return Err(SigningHistoryError::Equivocation {
+					slot,
+					existing: hex(&last.header_hash),
+					refused: hex(header_hash),
+				})
+			}
+		}
+
+		// Kept in memory even if the write fails, erring on the side of not sealing.
+		keys.insert(public.to_vec(), LastSealed { slot, header_hash: header_hash.to_vec() });
+		client.insert_aux(&[(SIGNING_HISTORY_KEY, &keys.encode()[..])], &[])?;
+
+		Ok(())
+	}
+
+	/// Export the history of the chain with `genesis_hash`.
+	pub fn export(&self, genesis_hash: &[u8]) -> SigningHistoryInterchange {
+		let keys = self.keys.lock().expect("never poisoned; qed");
+
+		SigningHistoryInterchange {
+			version: INTERCHANGE_VERSION,
+			genesis_hash: genesis_hash.to_vec().into(),
+			keys: keys
+				.iter()
+				.map(|(public, last)| SealedHeader {
+					public_key: public.clone().into(),
+					slot: last.slot,
+					header_hash: last.header_hash.clone().into(),
+				})
+				.collect(),
+		}
+	}
+
+	/// Merge an exported history into this one, keeping the latest header of every key.
+	pub fn import<C: AuxStore>(
+		&self,
+		client: &C,
+		genesis_hash: &[u8],
+		interchange: SigningHistoryInterchange,
+	) -> Result<(), SigningHistoryError> {
+		if interchange.version != INTERCHANGE_VERSION {
+			return Err(SigningHistoryError::UnsupportedVersion(interchange.version))
+		}
+		if &interchange.genesis_hash[..] != genesis_hash {
+			return Err(SigningHistoryError::WrongChain {
+				expected: hex(genesis_hash),
+				found: hex(&interchange.genesis_hash),
+			})
+		}
+
+		let mut keys = self.keys.lock().expect("never poisoned; qed");
+		for sealed in interchange.keys {
+			let imported = LastSealed { slot: sealed.slot, header_hash: sealed.header_hash.0 };
+			match keys.get(&sealed.public_key[..]) {
+				Some(last) if last.slot >= imported.slot => {},
+				_ => {
+					keys.insert(sealed.public_key.0, imported);
+				},
+			}
+		}
+		client.insert_aux(&[(SIGNING_HISTORY_KEY, &keys.encode()[..])], &[])?;
+
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::collections::HashMap;
+
+	#[derive(Default)]
+	struct Aux(Mutex<HashMap<Vec<u8>, Vec<u8>>>);
+
+	impl AuxStore for Aux {
+		fn insert_aux<
+			'a,
+			'b: 'a,
+			'c: 'a,
+			I: IntoIterator<Item = &'a (&'c [u8], &'c [u8])>,
+			D: IntoIterator<Item = &'a &'b [u8]>,
+		>(
+			&self,
+			insert: I,
+			delete: D,
+		) -> ClientResult<()> {
+			let mut aux = self.0.lock().unwrap();
+			for (key, value) in insert {
+				aux.insert(key.to_vec(), value.to_vec());
+			}
+			for key in delete {
+				aux.remove(*key);
+			}
+			Ok(())
+		}
+
+		fn get_aux(&self, key: &[u8]) -> ClientResult<Option<Vec<u8>>> {
+			Ok(self.0.lock().unwrap().get(key).cloned())
+		}
+	}
+
+	#[test]
+	fn refuses_to_equivocate_or_go_back() {
+		let aux = Aux::default();
+		let history = SigningHistory::load(&aux).unwrap();
+
+		history.record_sealing(&aux, b"alice", 5.into(), b"a").unwrap();
+		history.record_sealing(&aux, b"alice", 5.into(), b"a").unwrap();
+		assert!(matches!(
+			history.record_sealing(&aux, b"alice", 5.into(), b"b"),
+			Err(SigningHistoryError::Equivocation { slot: 5, .. })
+		));
+		assert!(matches!(
+			history.record_sealing(&aux, b"alice", 4.into(), b"c"),
+			Err(SigningHistoryError::SlotBehind { slot: 4, last: 5 })
+		));
+		history.record_sealing(&aux, b"bob", 5.into(), b"b").unwrap();
+		history.record_sealing(&aux, b"alice", 6.into(), b"b").unwrap();
+
+		// The history survives a restart.
+		let reloaded = SigningHistory::load(&aux).unwrap();
+		assert!(reloaded.record_sealing(&aux, b"alice", 6.into(), b"c").is_err());
+	}
+
+	#[test]
+	fn moves_between_hosts() {
+		let (old_host, new_host) = (Aux::default(), Aux::default());
+		let old_history = SigningHistory::load(&old_host).unwrap();
+		old_history.record_sealing(&old_host, b"alice", 7.into(), b"a").unwrap();
+
+		let exported = serde_json::to_string(&old_history.export(b"genesis")).unwrap();
+		let interchange: SigningHistoryInterchange = serde_json::from_str(&exported).unwrap();
+
+		let new_history = SigningHistory::load(&new_host).unwrap();
+		assert!(matches!(
+			new_history.import(&new_host, b"other", interchange.clone()),
+			Err(SigningHistoryError::WrongChain { .. })
+		));
+		new_history.record_sealing(&new_host, b"alice", 3.into(), b"x").unwrap();
+		new_history.import(&new_host, b"genesis", interchange).unwrap();
+
+		assert!(new_history.record_sealing(&new_host, b"alice", 7.into(), b"b").is_err());
+		new_history.record_sealing(&new_host, b"alice", 7.into(), b"a").unwrap();
+	}
+}
@@ -0,0 +1,133 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Slot timing following the slot duration changes scheduled by the runtime.
+
+use std::{
+	marker::PhantomData,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use codec::Codec;
+use sc_consensus_slots::{time_until_next_slot, SlotDurationSource};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus_micc::{
+	ConsensusLog, MiccApi, SlotDuration, SlotDurationSchedule, MICC_ENGINE_ID,
+};
+use sp_consensus_slots::Slot;
+use sp_runtime::{
+	generic::OpaqueDigestItemId,
+	traits::{Block as BlockT, Header},
+};
+
+use crate::LOG_TARGET;
+
+/// Slot durations read from the [`SlotDurationSchedule`] of the runtime at the chain head.
+///
+/// The schedule of the last chain head is kept to time the next slot, which is not tied to any
+/// block. The runtime is asked again for the chain heads carrying a
+/// [`ConsensusLog::SlotDurationChange`], and for those that aren't a child of the last one.
+pub struct RuntimeSlotDurations<B: BlockT, C, A> {
+	client: Arc<C>,
+	schedule: Mutex<(Option<B::Hash>, SlotDurationSchedule)>,
+	_phantom: PhantomData<fn() -> A>,
+}
+
+impl<B: BlockT, C, A> RuntimeSlotDurations<B, C, A> {
+	/// Follow the schedule of the runtime, assuming `slot_duration` until a block was seen.
+	pub fn new(client: Arc<C>, slot_duration: SlotDuration) -> Self {
+		Self {
+			client,
+			schedule: Mutex::new((None, SlotDurationSchedule::new(slot_duration))),
+			_phantom: PhantomData,
+		}
+	}
+}
+
+impl<B, C, A> RuntimeSlotDurations<B, C, A>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B>,
+	C::Api: MiccApi<B, A>,
+	A: Codec,
+{
+	/// The schedule of the runtime at `hash`, or the last one fetched if that fails.
+	pub fn schedule_at(&self, hash: B::Hash) -> SlotDurationSchedule {
+		let mut cached = self.schedule.lock().expect("never poisoned; qed");
+		if cached.0 == Some(hash) {
+			return cached.1.clone()
+		}
+
+		// The schedule only changes in the blocks carrying a `SlotDurationChange` log.
+		let unchanged = cached.0.is_some() &&
+			self.client.header(hash).ok().flatten().is_some_and(|header| {
+				cached.0 == Some(*header.parent_hash()) && !changes_slot_duration::<B, A>(&header)
+			});
+		if unchanged {
+			cached.0 = Some(hash);
+			return cached.1.clone()
+		}
+
+		match crate::standalone::slot_duration_schedule_at::<A, B, C>(&*self.client, hash) {
+			Ok(schedule) => *cached = (Some(hash), schedule),
+			Err(e) => log::warn!(
+				target: LOG_TARGET,
+				"Could not fetch slot duration schedule at {:?}, keeping the last one: {}",
+				hash,
+				e,
+			),
+		}
+
+		cached.1.clone()
+	}
+}
+
+/// Whether `header` changes the slot duration, see [`ConsensusLog::SlotDurationChange`].
+fn changes_slot_duration<B: BlockT, A: Codec>(header: &B::Header) -> bool {
+	header.digest().logs().iter().any(|log| {
+		matches!(
+			log.try_to::<ConsensusLog<A>>(OpaqueDigestItemId::Consensus(&MICC_ENGINE_ID)),
+			Some(ConsensusLog::SlotDurationChange { .. })
+		)
+	})
+}
+
+impl<B, C, A> SlotDurationSource<B> for RuntimeSlotDurations<B, C, A>
+where
+	B: BlockT,
+	C: ProvideRuntimeApi<B> + HeaderBackend<B> + Send + Sync,
+	C::Api: MiccApi<B, A>,
+	A: Codec,
+{
+	fn slot_duration(&self, chain_head: &B::Header, slot: Slot) -> Duration {
+		self.schedule_at(chain_head.hash()).duration_at(slot).as_duration()
+	}
+
+	fn time_until_next_slot(&self) -> Duration {
+		let schedule = self.schedule.lock().expect("never poisoned; qed").1.clone();
+		if schedule.changes().is_empty() {
+			return time_until_next_slot(schedule.genesis_duration().as_duration())
+		}
+
+		let now = sc_consensus_slots::duration_now().as_millis() as u64;
+		let next_slot = schedule.slot_at(now).saturating_add(1u64);
+		Duration::from_millis(schedule.slot_start(next_slot).saturating_sub(now))
+	}
+}
@@ -0,0 +1,504 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Building a block while its transactions are still being collected.
+//!
+//! When the event-driven production opens a collection window, it opens a [`Speculation`] and asks
+//! the slot worker to [`SlotTrigger::Prepare`](sc_consensus_slots::SlotTrigger::Prepare) a block.
+//! The proposer of a [`SpeculativeProposerFactory`] then starts the block on the best head right
+//! away and applies the transactions as they become ready. Once the window closes, the speculation
+//! is sealed and the block is finished with what was applied so far, instead of being built from
+//! scratch. The block is discarded if another block becomes the best head in the meantime.
+//!
+//! The inherent data, the timestamp above all, is created again once the speculation is sealed.
+//! While the slot claimed for the block is still in progress, the block is then started over with
+//! the fresh inherents, applying again the transactions it had accepted.
+
+use std::{
+	collections::HashSet,
+	marker::PhantomData,
+	pin::Pin,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use codec::Encode;
+use futures::{channel::oneshot, prelude::*};
+use log::{debug, warn};
+use sc_block_builder::{BlockBuilder, BlockBuilderBuilder};
+use sc_client_api::BlockchainEvents;
+use sc_consensus_slots::InherentDataProviderExt;
+use sc_transaction_pool_api::{InPoolTransaction, ReadyIteratorFor, TransactionPool, TxHash};
+use sp_api::{ApiExt, CallApiAt, ProvideRuntimeApi};
+use sp_block_builder::BlockBuilder as BlockBuilderApi;
+use sp_blockchain::{ApplyExtrinsicFailed, Error as ClientError, HeaderBackend};
+use sp_consensus::{Environment, Error as ConsensusError, Proposal, ProofRecording, Proposer};
+use sp_consensus_micc::inherents::MiccInherentData;
+use sp_consensus_slots::Slot;
+use sp_core::traits::SpawnNamed;
+use sp_inherents::{CreateInherentDataProviders, InherentData, InherentDataProvider};
+use sp_runtime::{
+	traits::{Block as BlockT, Header as HeaderT, NumberFor},
+	Digest,
+};
+
+use crate::LOG_TARGET;
+
+/// A block being built ahead of the trigger asking for it, shared between the event-driven
+/// production opening and sealing it, and the [`SpeculativeProposer`] building it.
+#[derive(Clone, Default)]
+pub struct Speculation {
+	pending: Arc<Mutex<Option<PendingSpeculation>>>,
+}
+
+struct PendingSpeculation {
+	/// Finishes the block once sent to, discards it once dropped.
+	seal: oneshot::Sender<()>,
+	/// Taken by the proposer building the block.
+	sealed: Option<oneshot::Receiver<()>>,
+}
+
+impl Speculation {
+	/// Let the next proposer build its block speculatively, until sealed. Any speculation still
+	/// open is abandoned.
+	pub fn open(&self) {
+		let (seal, sealed) = oneshot::channel();
+		*self.pending.lock().expect("never poisoned; qed") =
+			Some(PendingSpeculation { seal, sealed: Some(sealed) });
+	}
+
+	/// Finish the block being built speculatively, if any.
+	pub fn seal(&self) {
+		if let Some(pending) = self.pending.lock().expect("never poisoned; qed").take() {
+			let _ = pending.seal.send(());
+		}
+	}
+
+	/// Discard the block being built speculatively, if any.
+	pub fn abandon(&self) {
+		self.pending.lock().expect("never poisoned; qed").take();
+	}
+
+	/// Take the open speculation, resolving once it is sealed, or cancelled once abandoned.
+	fn take(&self) -> Option<oneshot::Receiver<()>> {
+		self.pending
+			.lock()
+			.expect("never poisoned; qed")
+			.as_mut()
+			.and_then(|pending| pending.sealed.take())
+	}
+}
+
+/// Proposer factory building blocks speculatively while a [`Speculation`] is open, and through the
+/// wrapped factory otherwise.
+pub struct SpeculativeProposerFactory<B, C, A, PF, CIDP> {
+	spawn_handle: Box<dyn SpawnNamed>,
+	client: Arc<C>,
+	pool: Arc<A>,
+	inner: PF,
+	speculation: Speculation,
+	create_inherent_data_providers: Arc<CIDP>,
+	_phantom: PhantomData<B>,
+}
+
+impl<B, C, A, PF, CIDP> SpeculativeProposerFactory<B, C, A, PF, CIDP> {
+	/// Build the speculative blocks on `client` with the transactions of `pool`, and the others
+	/// with `inner`.
+	///
+	/// The inherent data of a speculative block is created again with
+	/// `create_inherent_data_providers` once its speculation is sealed, it should be the one the
+	/// slot worker creates the inherent data with.
+	pub fn new(
+		spawn_handle: impl SpawnNamed + 'static,
+		client: Arc<C>,
+		pool: Arc<A>,
+		inner: PF,
+		speculation: Speculation,
+		create_inherent_data_providers: CIDP,
+	) -> Self {
+		Self {
+			spawn_handle: Box::new(spawn_handle),
+			client,
+			pool,
+			inner,
+			speculation,
+			create_inherent_data_providers: Arc::new(create_inherent_data_providers),
+			_phantom: PhantomData,
+		}
+	}
+}
+
+impl<B, C, A, PF, CIDP> Environment<B> for SpeculativeProposerFactory<B, C, A, PF, CIDP>
+where
+	B: BlockT,
+	C: HeaderBackend<B>
+		+ ProvideRuntimeApi<B>
+		+ CallApiAt<B>
+		+ BlockchainEvents<B>
+		+ Send
+		+ Sync
+		+ 'static,
+	C::Api: ApiExt<B> + BlockBuilderApi<B>,
+	A: TransactionPool<Block = B> + 'static,
+	PF: Environment<B>,
+	CIDP: CreateInherentDataProviders<B, ()> + 'static,
+	CIDP::InherentDataProviders: InherentDataProviderExt,
+{
+	type Proposer = SpeculativeProposer<B, C, A, PF::Proposer, CIDP>;
+	type CreateProposer =
+		Pin<Box<dyn Future<Output = Result<Self::Proposer, Self::Error>> + Send + 'static>>;
+	type Error = PF::Error;
+
+	fn init(&mut self, parent_header: &B::Header) -> Self::CreateProposer {
+		let inner = self.inner.init(parent_header);
+		let spawn_handle = self.spawn_handle.clone();
+		let client = self.client.clone();
+		let pool = self.pool.clone();
+		let speculation = self.speculation.clone();
+		let create_inherent_data_providers = self.create_inherent_data_providers.clone();
+		let parent_hash = parent_header.hash();
+		let parent_number = *parent_header.number();
+
+		Box::pin(async move {
+			Ok(SpeculativeProposer {
+				inner: inner.await?,
+				spawn_handle,
+				client,
+				pool,
+				speculation,
+				create_inherent_data_providers,
+				parent_hash,
+				parent_number,
+			})
+		})
+	}
+}
+
+/// Proposer of a [`SpeculativeProposerFactory`].
+pub struct SpeculativeProposer<B: BlockT, C, A, P, CIDP> {
+	inner: P,
+	spawn_handle: Box<dyn SpawnNamed>,
+	client: Arc<C>,
+	pool: Arc<A>,
+	speculation: Speculation,
+	create_inherent_data_providers: Arc<CIDP>,
+	parent_hash: B::Hash,
+	parent_number: NumberFor<B>,
+}
+
+impl<B, C, A, P, CIDP> Proposer<B> for SpeculativeProposer<B, C, A, P, CIDP>
+where
+	B: BlockT,
+	C: HeaderBackend<B>
+		+ ProvideRuntimeApi<B>
+		+ CallApiAt<B>
+		+ BlockchainEvents<B>
+		+ Send
+		+ Sync
+		+ 'static,
+	C::Api: ApiExt<B> + BlockBuilderApi<B>,
+	A: TransactionPool<Block = B> + 'static,
+	P: Proposer<B>,
+	CIDP: CreateInherentDataProviders<B, ()> + 'static,
+	CIDP::InherentDataProviders: InherentDataProviderExt,
+{
+	type Error = P::Error;
+	type Proposal = Pin<
+		Box<dyn Future<Output = Result<Proposal<B, P::Proof>, Self::Error>> + Send + 'static>,
+	>;
+	type ProofRecording = P::ProofRecording;
+	type Proof = P::Proof;
+
+	fn propose(
+		self,
+		inherent_data: InherentData,
+		inherent_digests: Digest,
+		max_duration: Duration,
+		block_size_limit: Option<usize>,
+	) -> Self::Proposal {
+		let Some(sealed) = self.speculation.take() else {
+			return Box::pin(self.inner.propose(
+				inherent_data,
+				inherent_digests,
+				max_duration,
+				block_size_limit,
+			))
+		};
+
+		// Keep a quarter of the time to finish the block once the transactions stop being applied.
+		let collect_until = Instant::now() + max_duration * 3 / 4;
+		let (tx, rx) = oneshot::channel();
+		let builder = SpeculativeBuilder::<B, C, A, CIDP, P::ProofRecording> {
+			client: self.client,
+			pool: self.pool,
+			create_inherent_data_providers: self.create_inherent_data_providers,
+			parent_hash: self.parent_hash,
+			parent_number: self.parent_number,
+			block_size_limit,
+			_phantom: PhantomData,
+		};
+
+		self.spawn_handle.spawn_blocking(
+			"micc-speculative-proposer",
+			None,
+			Box::pin(async move {
+				let result = builder.build(inherent_data, inherent_digests, sealed, collect_until);
+				let _ = tx.send(result.await);
+			}),
+		);
+
+		Box::pin(async move {
+			match rx.await {
+				Ok(result) => result.map_err(Into::into),
+				Err(_) => Err(ConsensusError::Other(
+					"Speculative block building was cancelled".into(),
+				)
+				.into()),
+			}
+		})
+	}
+}
+
+/// Builds a block incrementally until its speculation is sealed.
+struct SpeculativeBuilder<B: BlockT, C, A, CIDP, PR> {
+	client: Arc<C>,
+	pool: Arc<A>,
+	create_inherent_data_providers: Arc<CIDP>,
+	parent_hash: B::Hash,
+	parent_number: NumberFor<B>,
+	block_size_limit: Option<usize>,
+	_phantom: PhantomData<PR>,
+}
+
+impl<B, C, A, CIDP, PR> SpeculativeBuilder<B, C, A, CIDP, PR>
+where
+	B: BlockT,
+	C: HeaderBackend<B> + ProvideRuntimeApi<B> + CallApiAt<B> + BlockchainEvents<B>,
+	C::Api: ApiExt<B> + BlockBuilderApi<B>,
+	A: TransactionPool<Block = B>,
+	CIDP: CreateInherentDataProviders<B, ()>,
+	CIDP::InherentDataProviders: InherentDataProviderExt,
+	PR: ProofRecording,
+{
+	async fn build(
+		self,
+		inherent_data: InherentData,
+		inherent_digests: Digest,
+		sealed: oneshot::Receiver<()>,
+		collect_until: Instant,
+	) -> Result<Proposal<B, PR::Proof>, ConsensusError> {
+		let client_error = |e: ClientError| ConsensusError::Other(Box::new(e));
+		let discarded = |reason: &str| {
+			debug!(
+				target: LOG_TARGET,
+				"Discarding the speculative block on {:?}: {}",
+				self.parent_hash,
+				reason,
+			);
+			ConsensusError::Other(format!("Speculative block discarded: {reason}").into())
+		};
+
+		// Subscribe before looking at the pool, not to miss the transactions imported meanwhile.
+		let mut imports = self.pool.import_notification_stream();
+		let mut blocks = self.client.import_notification_stream();
+
+		let claimed_slot = inherent_data.micc_inherent_data().ok().flatten();
+		let mut block_builder = self.start_block(inherent_data, inherent_digests.clone())?;
+
+		let mut seen = HashSet::new();
+		let mut applied = Vec::new();
+		let mut full = false;
+		let ready = self.pool.ready_at(self.parent_hash).await;
+		self.apply_ready(&mut block_builder, ready, &mut seen, &mut applied, &mut full);
+
+		let mut sealed = sealed.fuse();
+		let collecting = tokio::time::sleep_until(collect_until.into());
+		futures::pin_mut!(collecting);
+		loop {
+			tokio::select! {
+				sealed = &mut sealed => match sealed {
+					Ok(()) => break,
+					Err(_) => return Err(discarded("abandoned")),
+				},
+				Some(_) = imports.next() => {
+					let ready = self.pool.ready_at(self.parent_hash).await;
+					self.apply_ready(&mut block_builder, ready, &mut seen, &mut applied, &mut full);
+				},
+				Some(block) = blocks.next() => {
+					if block.is_new_best && block.hash != self.parent_hash {
+						return Err(discarded("the best head changed"))
+					}
+				},
+				_ = &mut collecting => break,
+			}
+		}
+
+		if self.client.info().best_hash != self.parent_hash {
+			return Err(discarded("the best head changed"))
+		}
+
+		if let Some(inherent_data) = self.refresh_inherent_data(claimed_slot).await {
+			block_builder = self.start_block(inherent_data, inherent_digests)?;
+			for extrinsic in applied {
+				if let Err(e) = block_builder.push(extrinsic) {
+					debug!(
+						target: LOG_TARGET,
+						"Transaction left out of the speculative block started over: {}",
+						e,
+					);
+				}
+			}
+		}
+
+		let (block, storage_changes, proof) =
+			block_builder.build().map_err(client_error)?.into_inner();
+		let proof = PR::into_proof(proof)
+			.map_err(|e| ConsensusError::Other(format!("Proof recording failed: {e:?}").into()))?;
+
+		Ok(Proposal { block, proof, storage_changes })
+	}
+
+	/// Start the block on the parent, with the inherents of `inherent_data`.
+	fn start_block(
+		&self,
+		inherent_data: InherentData,
+		inherent_digests: Digest,
+	) -> Result<BlockBuilder<'_, B, C>, ConsensusError> {
+		let client_error = |e: ClientError| ConsensusError::Other(Box::new(e));
+		let mut block_builder = BlockBuilderBuilder::new(&*self.client)
+			.on_parent_block(self.parent_hash)
+			.with_parent_block_number(self.parent_number)
+			.with_proof_recording(PR::ENABLED)
+			.with_inherent_digests(inherent_digests)
+			.build()
+			.map_err(client_error)?;
+
+		for inherent in block_builder.create_inherents(inherent_data).map_err(client_error)? {
+			if let Err(e) = block_builder.push(inherent) {
+				warn!(target: LOG_TARGET, "Inherent extrinsic could not be applied: {}", e);
+			}
+		}
+
+		Ok(block_builder)
+	}
+
+	/// Create the inherent data again, unless the `claimed_slot` of the block is over: the
+	/// timestamp must stay within the slot.
+	async fn refresh_inherent_data(&self, claimed_slot: Option<Slot>) -> Option<InherentData> {
+		let providers = match self
+			.create_inherent_data_providers
+			.create_inherent_data_providers(self.parent_hash, ())
+			.await
+		{
+			Ok(providers) => providers,
+			Err(e) => {
+				warn!(target: LOG_TARGET, "Unable to create the inherent data providers: {}", e);
+				return None
+			},
+		};
+
+		if Some(providers.slot()) != claimed_slot {
+			debug!(
+				target: LOG_TARGET,
+				"Slot {:?} of the speculative block is over, keeping its inherent data",
+				claimed_slot,
+			);
+			return None
+		}
+
+		providers
+			.create_inherent_data()
+			.await
+			.map_err(|e| warn!(target: LOG_TARGET, "Unable to create the inherent data: {}", e))
+			.ok()
+	}
+
+	/// Apply the `ready` transactions not `seen` yet, until the block is `full`. The transactions
+	/// applied are kept in `applied`.
+	fn apply_ready(
+		&self,
+		block_builder: &mut BlockBuilder<'_, B, C>,
+		ready: ReadyIteratorFor<A>,
+		seen: &mut HashSet<TxHash<A>>,
+		applied: &mut Vec<B::Extrinsic>,
+		full: &mut bool,
+	) {
+		for pending in ready {
+			if *full {
+				return
+			}
+			if !seen.insert(pending.hash().clone()) {
+				continue
+			}
+
+			let extrinsic = (**pending.data()).clone();
+			let size = block_builder.estimate_block_size(PR::ENABLED) + extrinsic.encoded_size();
+			if self.block_size_limit.is_some_and(|limit| size > limit) {
+				*full = true;
+				return
+			}
+
+			match block_builder.push(extrinsic.clone()) {
+				Ok(()) => applied.push(extrinsic),
+				Err(ClientError::ApplyExtrinsicFailed(ApplyExtrinsicFailed::Validity(e)))
+					if e.exhausted_resources() =>
+				{
+					*full = true;
+				},
+				Err(e) => debug!(
+					target: LOG_TARGET,
+					"Transaction {:?} left out of the speculative block: {}",
+					pending.hash(),
+					e,
+				),
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use futures::executor::block_on;
+
+	#[test]
+	fn speculations_resolve_once_sealed_or_abandoned() {
+		let speculation = Speculation::default();
+		assert!(speculation.take().is_none());
+
+		speculation.open();
+		let sealed = speculation.take().expect("a speculation is open");
+		assert!(speculation.take().is_none());
+		speculation.seal();
+		assert_eq!(block_on(sealed), Ok(()));
+
+		speculation.open();
+		let abandoned = speculation.take().expect("a speculation is open");
+		speculation.abandon();
+		assert!(block_on(abandoned).is_err());
+
+		speculation.open();
+		let replaced = speculation.take().expect("a speculation is open");
+		speculation.open();
+		assert!(block_on(replaced).is_err());
+
+		// Sealing a speculation nobody took leaves nothing behind for the next proposer.
+		speculation.seal();
+		assert!(speculation.take().is_none());
+	}
+}
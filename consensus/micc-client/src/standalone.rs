@@ -22,14 +22,16 @@ use std::fmt::Debug;
 
 use log::trace;
 
-use codec::Codec;
+use codec::{Codec, Decode, Encode};
 
 use sc_client_api::UsageProvider;
-use sp_api::{Core, ProvideRuntimeApi};
+use sp_api::{ApiExt, Core, ProvideRuntimeApi};
 use sp_application_crypto::{AppCrypto, AppPublic};
 use sp_blockchain::Result as CResult;
 use sp_consensus::Error as ConsensusError;
-use sp_consensus_micc::MICC;
+use sp_consensus_micc::{
+	AuthorityIndex, AuthorshipMode, SlotAuthorSelector, SlotDurationSchedule, MICC,
+};
 use sp_consensus_slots::Slot;
 use sp_core::crypto::{ByteArray, Pair};
 use sp_keystore::KeystorePtr;
@@ -67,22 +69,66 @@ where
 	client.runtime_api().slot_duration(block_hash).map_err(|err| err.into())
 }
 
-/// Get the slot author for given block along with authorities.
-pub fn slot_author<P: Pair>(slot: Slot, authorities: &[AuthorityId<P>]) -> Option<&AuthorityId<P>> {
-	if authorities.is_empty() {
-		return None
+/// Get the history of slot durations for Micc from a runtime API at a given block's state.
+///
+/// Runtimes predating slot duration changes keep their slot duration forever.
+pub fn slot_duration_schedule_at<A, B, C>(
+	client: &C,
+	block_hash: B::Hash,
+) -> CResult<SlotDurationSchedule>
+where
+	A: Codec,
+	B: BlockT,
+	C: ProvideRuntimeApi<B>,
+	C::Api: MiccApi<B, A>,
+{
+	let runtime_api = client.runtime_api();
+	match runtime_api.api_version::<dyn MiccApi<B, A>>(block_hash)? {
+		Some(version) if version >= 3 =>
+			runtime_api.slot_duration_schedule(block_hash).map_err(|err| err.into()),
+		_ => Ok(SlotDurationSchedule::new(runtime_api.slot_duration(block_hash)?)),
 	}
+}
 
-	let idx = *slot % (authorities.len() as u64); // Use rotated index
+/// An authority set along with the rule used to select slot authors among its members.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode)]
+pub struct SlotAuthorities<A> {
+	/// The authorities, in the order known to the runtime.
+	pub authorities: Vec<A>,
+	/// The rule used to select the author of a slot among [`Self::authorities`].
+	pub selector: SlotAuthorSelector,
+	/// Which of [`Self::authorities`] may author a slot.
+	pub mode: AuthorshipMode,
+}
 
-	assert!(
-		idx <= usize::MAX as u64,
-		"It is impossible to have a vector with length beyond the address space; qed",
-	);
+impl<A> SlotAuthorities<A> {
+	/// Authorities taking turns in round robin, as with runtimes predating configurable selectors.
+	pub fn round_robin(authorities: Vec<A>) -> Self {
+		Self { authorities, selector: SlotAuthorSelector::RoundRobin, mode: AuthorshipMode::Strict }
+	}
 
-	let current_author = authorities.get(idx as usize).expect(
-		"authorities not empty; index constrained to list length;this is a valid index; qed",
-	);
+	/// Number of authorities in the set.
+	pub fn len(&self) -> usize {
+		self.authorities.len()
+	}
+
+	/// Whether the set is empty.
+	pub fn is_empty(&self) -> bool {
+		self.authorities.is_empty()
+	}
+}
+
+/// Get the slot author for given block along with authorities.
+pub fn slot_author<P: Pair>(
+	slot: Slot,
+	authorities: &SlotAuthorities<AuthorityId<P>>,
+) -> Option<&AuthorityId<P>> {
+	let idx = authorities.selector.slot_author(slot, authorities.len())?;
+
+	let current_author = authorities
+		.authorities
+		.get(idx as usize)
+		.expect("selectors return an index below the number of authorities; qed");
 
 	Some(current_author)
 }
@@ -93,7 +139,7 @@ pub fn slot_author<P: Pair>(slot: Slot, authorities: &[AuthorityId<P>]) -> Optio
 /// with the public key of the slot author.
 pub async fn claim_slot<P: Pair>(
 	slot: Slot,
-	authorities: &[AuthorityId<P>],
+	authorities: &SlotAuthorities<AuthorityId<P>>,
 	keystore: &KeystorePtr,
 ) -> Option<P::Public> {
 	let expected_author = slot_author::<P>(slot, authorities);
@@ -122,6 +168,18 @@ where
 	<DigestItem as CompatibleDigestItem<P::Signature>>::micc_pre_digest(slot)
 }
 
+/// Produce the pre-runtime digest containing the slot info and the index of its author, for
+/// slots authored under [`AuthorshipMode::AnyAuthority`].
+pub fn author_pre_digest<P: Pair>(
+	slot: Slot,
+	author_index: AuthorityIndex,
+) -> sp_runtime::DigestItem
+where
+	P::Signature: Codec,
+{
+	<DigestItem as CompatibleDigestItem<P::Signature>>::micc_author_pre_digest(slot, author_index)
+}
+
 /// Produce the seal digest item by signing the hash of a block.
 ///
 /// Note that after this is added to a block header, the hash of the block will change.
@@ -178,14 +236,24 @@ pub enum PreDigestLookupError {
 pub fn find_pre_digest<B: BlockT, Signature: Codec>(
 	header: &B::Header,
 ) -> Result<Slot, PreDigestLookupError> {
+	find_author_pre_digest::<B, Signature>(header).map(|(slot, _)| slot)
+}
+
+/// Extract a pre-digest from a block header, along with the index of the author if it names one.
+///
+/// This fails if there is no pre-digest or there are multiple.
+pub fn find_author_pre_digest<B: BlockT, Signature: Codec>(
+	header: &B::Header,
+) -> Result<(Slot, Option<AuthorityIndex>), PreDigestLookupError> {
 	if header.number().is_zero() {
-		return Ok(0.into())
+		return Ok((0.into(), None))
 	}
 
-	let mut pre_digest: Option<Slot> = None;
+	let mut pre_digest: Option<(Slot, Option<AuthorityIndex>)> = None;
 	for log in header.digest().logs() {
 		trace!(target: LOG_TARGET, "Checking log {:?}", log);
-		match (CompatibleDigestItem::<Signature>::as_micc_pre_digest(log), pre_digest.is_some()) {
+		let found = CompatibleDigestItem::<Signature>::as_micc_author_pre_digest(log);
+		match (found, pre_digest.is_some()) {
 			(Some(_), true) => return Err(PreDigestLookupError::MultipleHeaders),
 			(None, _) => trace!(target: LOG_TARGET, "Ignoring digest not meant for us"),
 			(s, false) => pre_digest = s,
@@ -239,6 +307,130 @@ where
 		.ok_or(ConsensusError::InvalidAuthoritiesSet)
 }
 
+/// Load the rule used to select slot authors from a runtime at a specific block.
+///
+/// Runtimes predating [`MiccApi::slot_author_selector`] select authors in round robin.
+pub fn fetch_slot_author_selector<A, B, C>(
+	client: &C,
+	parent_hash: B::Hash,
+) -> Result<SlotAuthorSelector, ConsensusError>
+where
+	A: Codec,
+	B: BlockT,
+	C: ProvideRuntimeApi<B>,
+	C::Api: MiccApi<B, A>,
+{
+	slot_author_selector_at::<A, B, _>(&*client.runtime_api(), parent_hash)
+}
+
+/// Load the rule used to select slot authors through an existing runtime API instance.
+pub(crate) fn slot_author_selector_at<A, B, Api>(
+	runtime_api: &Api,
+	at: B::Hash,
+) -> Result<SlotAuthorSelector, ConsensusError>
+where
+	A: Codec,
+	B: BlockT,
+	Api: ApiExt<B> + MiccApi<B, A>,
+{
+	let version = runtime_api
+		.api_version::<dyn MiccApi<B, A>>(at)
+		.map_err(|_| ConsensusError::InvalidAuthoritiesSet)?;
+
+	match version {
+		Some(version) if version >= 2 => runtime_api
+			.slot_author_selector(at)
+			.map_err(|_| ConsensusError::InvalidAuthoritiesSet),
+		_ => Ok(SlotAuthorSelector::RoundRobin),
+	}
+}
+
+/// Load whether any authority may author a slot from a runtime at a specific block.
+///
+/// Runtimes predating [`MiccApi::authorship_mode`] only accept the slot author.
+pub fn fetch_authorship_mode<A, B, C>(
+	client: &C,
+	parent_hash: B::Hash,
+) -> Result<AuthorshipMode, ConsensusError>
+where
+	A: Codec,
+	B: BlockT,
+	C: ProvideRuntimeApi<B>,
+	C::Api: MiccApi<B, A>,
+{
+	authorship_mode_at::<A, B, _>(&*client.runtime_api(), parent_hash)
+}
+
+/// Load whether any authority may author a slot through an existing runtime API instance.
+pub(crate) fn authorship_mode_at<A, B, Api>(
+	runtime_api: &Api,
+	at: B::Hash,
+) -> Result<AuthorshipMode, ConsensusError>
+where
+	A: Codec,
+	B: BlockT,
+	Api: ApiExt<B> + MiccApi<B, A>,
+{
+	let version = runtime_api
+		.api_version::<dyn MiccApi<B, A>>(at)
+		.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+
+	match version {
+		Some(version) if version >= 4 =>
+			runtime_api.authorship_mode(at).map_err(|e| ConsensusError::ClientImport(e.to_string())),
+		_ => Ok(AuthorshipMode::Strict),
+	}
+}
+
+/// Load when the runtime needs the next block for work of its own from a runtime at a specific
+/// block, in milliseconds since the Unix epoch.
+///
+/// Runtimes predating [`MiccApi::next_required_block_time`] never need one.
+pub fn fetch_next_required_block_time<A, B, C>(
+	client: &C,
+	at: B::Hash,
+) -> Result<Option<u64>, ConsensusError>
+where
+	A: Codec,
+	B: BlockT,
+	C: ProvideRuntimeApi<B>,
+	C::Api: MiccApi<B, A>,
+{
+	let runtime_api = client.runtime_api();
+	let version = runtime_api
+		.api_version::<dyn MiccApi<B, A>>(at)
+		.map_err(|e| ConsensusError::ClientImport(e.to_string()))?;
+
+	match version {
+		Some(version) if version >= 5 => runtime_api
+			.next_required_block_time(at)
+			.map_err(|e| ConsensusError::ClientImport(e.to_string())),
+		_ => Ok(None),
+	}
+}
+
+/// Where a block stands among competing blocks when any authority may author any slot, the best
+/// block ranking lowest.
+///
+/// Every node has to settle on the same block: the higher block wins, then the block at the lower
+/// slot, then the block whose author has the lower [`SlotAuthorSelector::rotation_rank`], then the
+/// block with the lower hash. The hash includes the seal.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ForkRank<N, H> {
+	number: std::cmp::Reverse<N>,
+	slot: Slot,
+	rotation_rank: u32,
+	hash: H,
+}
+
+impl<N, H> ForkRank<N, H> {
+	/// The rank of the block `hash` of height `number`, authored at `slot` by the authority at
+	/// `rotation_rank`.
+	pub fn new(number: N, slot: Slot, rotation_rank: u32, hash: H) -> Self {
+		Self { number: std::cmp::Reverse(number), slot, rotation_rank, hash }
+	}
+}
+
 /// Load the current set of authorities from a runtime at a specific block.
 pub fn fetch_authorities<A, B, C>(
 	client: &C,
@@ -295,9 +487,30 @@ pub enum SealVerificationError<Header> {
 /// This digest item will always return `Some` when used with `as_micc_seal`.
 pub fn check_header_slot_and_seal<B: BlockT, P: Pair>(
 	slot_now: Slot,
-	mut header: B::Header,
-	authorities: &[AuthorityId<P>],
+	header: B::Header,
+	authorities: &SlotAuthorities<AuthorityId<P>>,
 ) -> Result<(B::Header, Slot, DigestItem), SealVerificationError<B::Header>>
+where
+	P::Signature: Codec,
+	P::Public: Codec + PartialEq + Clone,
+{
+	let mode = AuthorshipMode::Strict;
+	check_header_slot_and_seal_with_mode::<B, P>(slot_now, header, authorities, mode)
+		.map(|(header, slot, seal, _)| (header, slot, seal))
+}
+
+/// Check a header has been signed by a key allowed to author its slot under `mode`.
+///
+/// Behaves like [`check_header_slot_and_seal`], and also returns the authority that signed the
+/// header, which may be any authority of the set unless under [`AuthorshipMode::Strict`]. Under
+/// [`AuthorshipMode::AnyAuthority`] the pre-digest must name the author, under
+/// [`AuthorshipMode::Permissive`] it names the author unless it is the slot owner.
+pub fn check_header_slot_and_seal_with_mode<B: BlockT, P: Pair>(
+	slot_now: Slot,
+	mut header: B::Header,
+	authorities: &SlotAuthorities<AuthorityId<P>>,
+	mode: AuthorshipMode,
+) -> Result<(B::Header, Slot, DigestItem, AuthorityId<P>), SealVerificationError<B::Header>>
 where
 	P::Signature: Codec,
 	P::Public: Codec + PartialEq + Clone,
@@ -312,6 +525,23 @@ where
 	if slot > slot_now {
 		header.digest_mut().push(seal);
 		return Err(SealVerificationError::Deferred(header, slot))
+	}
+
+	let (_, author_index) = find_author_pre_digest::<B, P::Signature>(&header)
+		.map_err(SealVerificationError::InvalidPreDigest)?;
+	if mode == AuthorshipMode::AnyAuthority ||
+		(mode == AuthorshipMode::Permissive && author_index.is_some())
+	{
+		// The author names itself, and may be any authority.
+		let author = author_index
+			.and_then(|index| authorities.authorities.get(index as usize))
+			.ok_or(SealVerificationError::SlotAuthorNotFound)?;
+
+		if P::verify(&sig, header.hash().as_ref(), author) {
+			Ok((header, slot, seal, author.clone()))
+		} else {
+			Err(SealVerificationError::BadSignature)
+		}
 	} else {
 		// check the signature is valid under the expected authority and
 		// chain state.
@@ -321,7 +551,7 @@ where
 		let pre_hash = header.hash();
 
 		if P::verify(&sig, pre_hash.as_ref(), expected_author) {
-			Ok((header, slot, seal))
+			Ok((header, slot, seal, expected_author.clone()))
 		} else {
 			Err(SealVerificationError::BadSignature)
 		}
@@ -331,7 +561,118 @@ where
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use sp_consensus_micc::sr25519::{AuthorityPair, AuthoritySignature};
 	use sp_keyring::sr25519::Keyring;
+	use sp_runtime::{generic, traits::BlakeTwo256, Digest, OpaqueExtrinsic};
+
+	type TestHeader = generic::Header<u64, BlakeTwo256>;
+	type TestBlock = generic::Block<TestHeader, OpaqueExtrinsic>;
+
+	fn sealed_header(number: u64, slot: u64, author: Keyring) -> TestHeader {
+		seal_header(number, pre_digest::<AuthorityPair>(slot.into()), author)
+	}
+
+	fn seal_header(number: u64, pre_digest: DigestItem, author: Keyring) -> TestHeader {
+		let mut header = TestHeader::new(
+			number,
+			Default::default(),
+			Default::default(),
+			Default::default(),
+			Digest { logs: vec![pre_digest] },
+		);
+		let signature: AuthoritySignature = author.pair().sign(header.hash().as_ref()).into();
+		header.digest_mut().push(
+			<DigestItem as CompatibleDigestItem<AuthoritySignature>>::micc_seal(signature),
+		);
+		header
+	}
+
+	#[test]
+	fn permissive_mode_accepts_any_authority() {
+		let authorities = SlotAuthorities::round_robin(vec![
+			Keyring::Alice.public().into(),
+			Keyring::Bob.public().into(),
+		]);
+		let check = |header: TestHeader, mode| {
+			check_header_slot_and_seal_with_mode::<TestBlock, AuthorityPair>(
+				2.into(),
+				header,
+				&authorities,
+				mode,
+			)
+		};
+		// Slot 2 belongs to Alice.
+		let header = seal_header(1, author_pre_digest::<AuthorityPair>(2.into(), 1), Keyring::Bob);
+
+		assert!(matches!(
+			check(header.clone(), AuthorshipMode::Strict),
+			Err(SealVerificationError::BadSignature)
+		));
+		let (_, slot, _, author) = check(header, AuthorshipMode::Permissive).unwrap();
+		assert_eq!((slot, author), (2.into(), Keyring::Bob.public().into()));
+
+		let owner = sealed_header(1, 2, Keyring::Alice);
+		let (_, _, _, author) = check(owner, AuthorshipMode::Permissive).unwrap();
+		assert_eq!(author, Keyring::Alice.public().into());
+
+		// The runtime would take the slot owner for the author.
+		let unnamed = sealed_header(1, 2, Keyring::Bob);
+		assert!(matches!(
+			check(unnamed, AuthorshipMode::Permissive),
+			Err(SealVerificationError::BadSignature)
+		));
+
+		let outsider = sealed_header(1, 2, Keyring::Charlie);
+		assert!(matches!(
+			check(outsider, AuthorshipMode::Permissive),
+			Err(SealVerificationError::BadSignature)
+		));
+	}
+
+	#[test]
+	fn any_authority_mode_checks_the_named_author() {
+		let authorities = SlotAuthorities::round_robin(vec![
+			Keyring::Alice.public().into(),
+			Keyring::Bob.public().into(),
+		]);
+		let check = |header: TestHeader| {
+			check_header_slot_and_seal_with_mode::<TestBlock, AuthorityPair>(
+				2.into(),
+				header,
+				&authorities,
+				AuthorshipMode::AnyAuthority,
+			)
+		};
+
+		let naming = |index| author_pre_digest::<AuthorityPair>(2.into(), index);
+
+		// Slot 2 belongs to Alice.
+		let named = seal_header(1, naming(1), Keyring::Bob);
+		let (_, slot, _, author) = check(named).unwrap();
+		assert_eq!((slot, author), (2.into(), Keyring::Bob.public().into()));
+
+		let misnamed = seal_header(1, naming(0), Keyring::Bob);
+		assert!(matches!(check(misnamed), Err(SealVerificationError::BadSignature)));
+
+		let unnamed = sealed_header(1, 2, Keyring::Alice);
+		assert!(matches!(check(unnamed), Err(SealVerificationError::SlotAuthorNotFound)));
+
+		let outsider = seal_header(1, naming(2), Keyring::Bob);
+		assert!(matches!(check(outsider), Err(SealVerificationError::SlotAuthorNotFound)));
+	}
+
+	#[test]
+	fn fork_ranks_are_deterministic() {
+		let rank = |number: u64, slot: u64, rotation_rank, hash: u64| {
+			ForkRank::new(number, slot.into(), rotation_rank, hash)
+		};
+
+		assert!(rank(2, 5, 1, 9) < rank(1, 3, 0, 0));
+		assert!(rank(1, 3, 1, 9) < rank(1, 4, 0, 0));
+		assert!(rank(1, 3, 0, 9) < rank(1, 3, 1, 0));
+		assert!(rank(1, 3, 0, 0) < rank(1, 3, 0, 9));
+		assert_eq!(rank(1, 3, 0, 0), rank(1, 3, 0, 0));
+	}
 
 	#[test]
 	fn authorities_call_works() {
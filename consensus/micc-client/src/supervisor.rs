@@ -0,0 +1,256 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! Keeping the trigger stream of the slot worker alive.
+//!
+//! The event-driven trigger streams follow the import notifications of the transaction pool, and
+//! end with them. A [`TriggerSupervisor`] builds such a stream again once it ended, subscribing to
+//! the pool anew, and requests blocks at the pace of the slots through a fallback stream in the
+//! meantime, so that transactions are still included while the production is degraded. The
+//! restarts back off while the restarted streams keep ending before requesting any block.
+
+use std::{
+	pin::Pin,
+	sync::{Arc, Mutex},
+	task::{Context, Poll},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::{FutureExt, Stream, StreamExt};
+use prometheus_endpoint::{register, Counter, Gauge, PrometheusError, Registry, U64};
+use sc_consensus_slots::SlotTrigger;
+use tokio::time::{sleep, Sleep};
+
+const LOG_TARGET: &str = "micc::supervisor";
+
+/// Delay before restarting a trigger stream that ended, doubled while the restarted streams end
+/// before yielding a trigger.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+/// A stream of triggers for the slot worker.
+pub type TriggerStream = Pin<Box<dyn Stream<Item = SlotTrigger> + Send + 'static>>;
+
+/// Health of a supervised trigger stream.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TriggerStreamHealth {
+	/// Whether the trigger stream ended and blocks are requested at the pace of the slots until it
+	/// is restarted.
+	pub degraded: bool,
+	/// When the production got degraded, in milliseconds since the Unix epoch.
+	pub degraded_since_ms: Option<u64>,
+	/// Number of times the trigger stream was restarted.
+	pub restarts: u64,
+}
+
+struct Metrics {
+	degraded: Gauge<U64>,
+	restarts: Counter<U64>,
+}
+
+impl Metrics {
+	fn register(registry: &Registry) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			degraded: register(
+				Gauge::new(
+					"micc_trigger_stream_degraded",
+					"Whether blocks are requested at the pace of the slots while the trigger \
+					 stream is restarted",
+				)?,
+				registry,
+			)?,
+			restarts: register(
+				Counter::new(
+					"micc_trigger_stream_restarts_total",
+					"Number of times the trigger stream of the block authoring was restarted",
+				)?,
+				registry,
+			)?,
+		})
+	}
+}
+
+/// Restarts the trigger stream of the slot worker when it ends, falling back to another stream
+/// while it does, and reports its health.
+#[derive(Clone)]
+pub struct TriggerSupervisor {
+	health: Arc<Mutex<TriggerStreamHealth>>,
+	metrics: Option<Arc<Metrics>>,
+}
+
+impl TriggerSupervisor {
+	/// Create a new supervisor, registering its metrics if a registry is given.
+	pub fn new(registry: Option<&Registry>) -> Result<Self, PrometheusError> {
+		Ok(Self {
+			health: Default::default(),
+			metrics: registry.map(Metrics::register).transpose()?.map(Arc::new),
+		})
+	}
+
+	/// The current health of the supervised stream.
+	pub fn health(&self) -> TriggerStreamHealth {
+		self.health.lock().expect("never poisoned; qed").clone()
+	}
+
+	/// Supervise the stream built by `primary`, built again whenever it ends, yielding the triggers
+	/// of the stream built by `fallback` until then.
+	pub fn supervise<P, F>(&self, mut primary: P, fallback: F) -> TriggerStream
+	where
+		P: FnMut() -> TriggerStream + Send + 'static,
+		F: FnMut() -> TriggerStream + Send + 'static,
+	{
+		Box::pin(Supervised {
+			supervisor: self.clone(),
+			primary: Some(primary()),
+			make_primary: Box::new(primary),
+			make_fallback: Box::new(fallback),
+			fallback: None,
+			restart: None,
+			delay: MIN_RESTART_DELAY,
+		})
+	}
+
+	fn note_degraded(&self) {
+		let since = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+		let mut health = self.health.lock().expect("never poisoned; qed");
+		health.degraded = true;
+		health.degraded_since_ms = Some(since.as_millis() as u64);
+
+		if let Some(metrics) = &self.metrics {
+			metrics.degraded.set(1);
+		}
+	}
+
+	fn note_restart(&self) {
+		let mut health = self.health.lock().expect("never poisoned; qed");
+		health.degraded = false;
+		health.degraded_since_ms = None;
+		health.restarts += 1;
+
+		if let Some(metrics) = &self.metrics {
+			metrics.degraded.set(0);
+			metrics.restarts.inc();
+		}
+	}
+}
+
+/// The stream returned by [`TriggerSupervisor::supervise`].
+struct Supervised {
+	supervisor: TriggerSupervisor,
+	make_primary: Box<dyn FnMut() -> TriggerStream + Send>,
+	make_fallback: Box<dyn FnMut() -> TriggerStream + Send>,
+	/// The supervised stream, `None` while waiting to restart it.
+	primary: Option<TriggerStream>,
+	/// The stream standing in for the supervised one until it is restarted.
+	fallback: Option<TriggerStream>,
+	restart: Option<Pin<Box<Sleep>>>,
+	/// Delay before the next restart.
+	delay: Duration,
+}
+
+impl Stream for Supervised {
+	type Item = SlotTrigger;
+
+	fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<SlotTrigger>> {
+		let this = &mut *self;
+
+		loop {
+			if let Some(primary) = &mut this.primary {
+				match primary.poll_next_unpin(cx) {
+					Poll::Ready(Some(trigger)) => {
+						this.delay = MIN_RESTART_DELAY;
+						return Poll::Ready(Some(trigger))
+					},
+					Poll::Ready(None) => {
+						log::warn!(
+							target: LOG_TARGET,
+							"Trigger stream ended, requesting blocks at the pace of the slots \
+							 until it is restarted in {:?}",
+							this.delay,
+						);
+						this.primary = None;
+						this.fallback = Some((this.make_fallback)());
+						this.restart = Some(Box::pin(sleep(this.delay)));
+						this.delay = (this.delay * 2).min(MAX_RESTART_DELAY);
+						this.supervisor.note_degraded();
+					},
+					Poll::Pending => return Poll::Pending,
+				}
+			}
+
+			if this.restart.as_mut().map_or(false, |restart| restart.poll_unpin(cx).is_ready()) {
+				log::info!(target: LOG_TARGET, "Restarting the trigger stream");
+				this.restart = None;
+				this.fallback = None;
+				this.primary = Some((this.make_primary)());
+				this.supervisor.note_restart();
+				continue
+			}
+
+			match this.fallback.as_mut().map(|fallback| fallback.poll_next_unpin(cx)) {
+				Some(Poll::Ready(Some(trigger))) => return Poll::Ready(Some(trigger)),
+				Some(Poll::Ready(None)) => {
+					log::warn!(target: LOG_TARGET, "Fallback trigger stream ended");
+					this.fallback = None;
+					return Poll::Pending
+				},
+				_ => return Poll::Pending,
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use sc_consensus_slots::ProposalBudget;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	#[tokio::test]
+	async fn restarts_ended_streams_and_falls_back_meanwhile() {
+		let batch = SlotTrigger::CreateBlock(ProposalBudget::BATCH);
+		let immediate = SlotTrigger::CreateBlock(ProposalBudget::IMMEDIATE);
+		let supervisor = TriggerSupervisor::new(None).unwrap();
+		let starts = Arc::new(AtomicUsize::new(0));
+
+		let primary_starts = starts.clone();
+		let mut triggers = supervisor.supervise(
+			move || {
+				primary_starts.fetch_add(1, Ordering::SeqCst);
+				Box::pin(futures::stream::iter([batch]))
+			},
+			move || Box::pin(futures::stream::iter([immediate])),
+		);
+
+		assert_eq!(triggers.next().await, Some(batch));
+		assert!(!supervisor.health().degraded);
+
+		// The primary stream ended, the fallback stands in until the restart.
+		assert_eq!(triggers.next().await, Some(immediate));
+		assert!(supervisor.health().degraded);
+		assert_eq!(starts.load(Ordering::SeqCst), 1);
+
+		assert_eq!(triggers.next().await, Some(batch));
+		assert_eq!(starts.load(Ordering::SeqCst), 2);
+		assert_eq!(
+			supervisor.health(),
+			TriggerStreamHealth { degraded: false, degraded_since_ms: None, restarts: 1 },
+		);
+	}
+}
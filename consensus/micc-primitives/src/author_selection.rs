@@ -0,0 +1,187 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Selection of the authority expected to author a given slot.
+
+use crate::AuthorityIndex;
+use alloc::vec::Vec;
+use codec::{Decode, Encode};
+use scale_info::TypeInfo;
+use sp_consensus_slots::Slot;
+
+/// Number of slots a [`SlotAuthorSelector::StakeProportional`] schedule is spread over.
+///
+/// Stakes are scaled to integer weights summing to this value, so an authority holding less than
+/// `1 / STAKE_SCHEDULE_LENGTH` of the total stake may not be assigned any slot.
+pub const STAKE_SCHEDULE_LENGTH: u32 = 10_000;
+
+/// The rule mapping a slot to the authority expected to author it.
+///
+/// The runtime and the client must agree on the author of every slot, so the rule is a plain
+/// value that the runtime hands to the client through [`crate::MiccApi`] instead of arbitrary
+/// runtime logic.
+///
+/// Weighted schedules whose length doesn't match the number of authorities, or whose weights are
+/// all zero, fall back to round robin.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub enum SlotAuthorSelector {
+	/// The author of slot `s` is `authorities[s % authorities.len()]`.
+	#[default]
+	#[codec(index = 0)]
+	RoundRobin,
+	/// Each authority authors as many slots per cycle as its weight, where a cycle is as long as
+	/// the sum of all weights. Weights are given in the order of the authority set.
+	#[codec(index = 1)]
+	WeightedRoundRobin(Vec<u32>),
+	/// Each authority authors a share of slots proportional to its stake, repeating every
+	/// [`STAKE_SCHEDULE_LENGTH`] slots. Stakes are given in the order of the authority set.
+	#[codec(index = 2)]
+	StakeProportional(Vec<u128>),
+}
+
+/// Which authorities may author a slot.
+///
+/// Declared in the chain spec and handed to the client through [`crate::MiccApi`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Encode, Decode, TypeInfo)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AuthorshipMode {
+	/// Only the authority picked by the [`SlotAuthorSelector`] may author a slot.
+	#[default]
+	#[codec(index = 0)]
+	Strict,
+	/// Any current authority may author any slot, as nodes running with `--force-authoring` do,
+	/// naming itself in the pre-digest unless it owns the slot.
+	///
+	/// Meant for development networks. Competing blocks are expected, and settled by a
+	/// deterministic fork choice so that all nodes agree on the best block.
+	#[codec(index = 1)]
+	Permissive,
+	/// Any current authority may author any slot on demand, naming itself in the pre-digest.
+	///
+	/// Meant for chains authoring as soon as transactions arrive, where waiting for the slot
+	/// author only adds latency. Competing blocks of the same height are settled by the lowest
+	/// slot, then by the lowest [`SlotAuthorSelector::rotation_rank`] of their authors.
+	#[codec(index = 2)]
+	AnyAuthority,
+}
+
+impl SlotAuthorSelector {
+	/// Returns the index of the authority expected to author `slot` out of `authorities_len`
+	/// authorities, or `None` if there are no authorities.
+	pub fn slot_author(&self, slot: Slot, authorities_len: usize) -> Option<AuthorityIndex> {
+		if authorities_len == 0 {
+			return None
+		}
+
+		let weighted = match self {
+			Self::RoundRobin => None,
+			Self::WeightedRoundRobin(weights) if weights.len() == authorities_len => {
+				let weights = weights.iter().map(|w| *w as u64).collect::<Vec<_>>();
+				weighted_slot_author(slot, &weights)
+			},
+			Self::StakeProportional(stakes) if stakes.len() == authorities_len =>
+				weighted_slot_author(slot, &stake_weights(stakes)),
+			_ => None,
+		};
+
+		let index = weighted.unwrap_or_else(|| (*slot % authorities_len as u64) as usize);
+		Some(index as AuthorityIndex)
+	}
+
+	/// Returns the position of `author` in the rotation of `authorities_len` authorities starting
+	/// at the author of `slot`, which is at position 0.
+	///
+	/// Returns `None` if `author` isn't one of the authorities.
+	pub fn rotation_rank(
+		&self,
+		slot: Slot,
+		author: AuthorityIndex,
+		authorities_len: usize,
+	) -> Option<u32> {
+		let slot_author = self.slot_author(slot, authorities_len)?;
+		let len = authorities_len as u32;
+		(author < len).then(|| (author + len - slot_author) % len)
+	}
+}
+
+/// Pick the author of `slot` from a cycle of `sum(weights)` slots in which authority `i` owns
+/// `weights[i]` slots.
+///
+/// The positions of the cycle are visited with a stride coprime to the cycle length, so the slots
+/// of an authority are spread over the cycle instead of being consecutive.
+fn weighted_slot_author(slot: Slot, weights: &[u64]) -> Option<usize> {
+	let total = weights.iter().fold(0u64, |acc, w| acc.saturating_add(*w));
+	if total == 0 {
+		return None
+	}
+
+	let stride = spreading_stride(total);
+	let position = ((*slot % total) as u128 * stride as u128 % total as u128) as u64;
+
+	let mut end = 0u64;
+	weights.iter().position(|weight| {
+		end = end.saturating_add(*weight);
+		position < end
+	})
+}
+
+/// The smallest number coprime to `total` that is at least ~0.618 times `total`.
+fn spreading_stride(total: u64) -> u64 {
+	let mut stride = ((total as u128 * 618 / 1000) as u64).max(1);
+	// `total - 1` is coprime to `total`, so this terminates before reaching `total`.
+	while gcd(stride, total) != 1 {
+		stride += 1;
+	}
+	stride
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+	while b != 0 {
+		(a, b) = (b, a % b);
+	}
+	a
+}
+
+/// Scale `stakes` to integer weights summing to [`STAKE_SCHEDULE_LENGTH`], handing out the slots
+/// lost to rounding by largest remainder, ties going to the lower index.
+///
+/// Returns an empty schedule if the total stake is zero.
+fn stake_weights(stakes: &[u128]) -> Vec<u64> {
+	let length = STAKE_SCHEDULE_LENGTH as u128;
+
+	// Scale the stakes down first so that `stake * length` cannot overflow.
+	let total = stakes.iter().fold(0u128, |acc, s| acc.saturating_add(*s));
+	let divisor = total / (u128::MAX / length) + 1;
+	let stakes = stakes.iter().map(|s| s / divisor).collect::<Vec<_>>();
+	let total = stakes.iter().sum::<u128>();
+	if total == 0 {
+		return Vec::new()
+	}
+
+	let mut weights = stakes.iter().map(|s| (s * length / total) as u64).collect::<Vec<_>>();
+
+	let mut remainders =
+		stakes.iter().enumerate().map(|(i, s)| (s * length % total, i)).collect::<Vec<_>>();
+	remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+
+	let unassigned = STAKE_SCHEDULE_LENGTH as u64 - weights.iter().sum::<u64>();
+	for (_, i) in remainders.into_iter().take(unassigned as usize) {
+		weights[i] += 1;
+	}
+
+	weights
+}
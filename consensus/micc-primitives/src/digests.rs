@@ -21,8 +21,8 @@
 //! This implements the digests for AuRa, to allow the private
 //! `CompatibleDigestItem` trait to appear in public interfaces.
 
-use crate::MICC_ENGINE_ID;
-use codec::{Codec, Encode};
+use crate::{AuthorityIndex, MICC_ENGINE_ID};
+use codec::{Codec, Decode, DecodeAll, Encode};
 use sp_consensus_slots::Slot;
 use sp_runtime::generic::DigestItem;
 
@@ -39,6 +39,14 @@ pub trait CompatibleDigestItem<Signature>: Sized {
 
 	/// If this item is an AuRa pre-digest, return the slot number
 	fn as_micc_pre_digest(&self) -> Option<Slot>;
+
+	/// Construct a digest item which contains the slot number followed by the index of the
+	/// authority authoring it.
+	fn micc_author_pre_digest(slot: Slot, author_index: AuthorityIndex) -> Self;
+
+	/// If this item is a Micc pre-digest, return the slot number, along with the index of the
+	/// author if the digest names it.
+	fn as_micc_author_pre_digest(&self) -> Option<(Slot, Option<AuthorityIndex>)>;
 }
 
 impl<Signature> CompatibleDigestItem<Signature> for DigestItem
@@ -58,6 +66,26 @@ where
 	}
 
 	fn as_micc_pre_digest(&self) -> Option<Slot> {
-		self.pre_runtime_try_to(&MICC_ENGINE_ID)
+		<Self as CompatibleDigestItem<Signature>>::as_micc_author_pre_digest(self)
+			.map(|(slot, _)| slot)
+	}
+
+	fn micc_author_pre_digest(slot: Slot, author_index: AuthorityIndex) -> Self {
+		DigestItem::PreRuntime(MICC_ENGINE_ID, (slot, author_index).encode())
+	}
+
+	fn as_micc_author_pre_digest(&self) -> Option<(Slot, Option<AuthorityIndex>)> {
+		let mut data = match self.as_pre_runtime()? {
+			(id, data) if id == MICC_ENGINE_ID => data,
+			_ => return None,
+		};
+
+		// The author index is appended, so runtimes reading only the slot keep working.
+		let slot = Slot::decode(&mut data).ok()?;
+		let author_index = match data.is_empty() {
+			true => None,
+			false => Some(AuthorityIndex::decode_all(&mut data).ok()?),
+		};
+		Some((slot, author_index))
 	}
 }
@@ -66,6 +66,15 @@ impl InherentDataProvider {
 
 		Self { slot }
 	}
+
+	/// Creates the inherent data provider by looking up the slot in progress at the given
+	/// `timestamp` in the slot duration `schedule`.
+	pub fn from_timestamp_and_schedule(
+		timestamp: sp_timestamp::Timestamp,
+		schedule: &crate::SlotDurationSchedule,
+	) -> Self {
+		Self { slot: schedule.slot_at(timestamp.as_millis()) }
+	}
 }
 
 #[cfg(feature = "std")]
@@ -25,8 +25,13 @@ use alloc::vec::Vec;
 use codec::{Codec, Decode, Encode};
 use sp_runtime::{ConsensusEngineId, KeyTypeId};
 
+pub mod author_selection;
 pub mod digests;
 pub mod inherents;
+pub mod slot_schedule;
+
+pub use author_selection::{AuthorshipMode, SlotAuthorSelector};
+pub use slot_schedule::{SlotDurationChange, SlotDurationSchedule};
 
 pub const MICC: KeyTypeId = KeyTypeId(*b"micc");
 
@@ -70,6 +75,26 @@ pub mod ed25519 {
 	pub type AuthorityId = app_ed25519::Public;
 }
 
+pub mod ecdsa {
+	mod app_ecdsa {
+		use sp_application_crypto::{app_crypto, ecdsa};
+		use crate::MICC;
+
+		app_crypto!(ecdsa, MICC);
+	}
+
+	sp_application_crypto::with_pair! {
+		/// An Micc authority keypair using ECDSA as its crypto.
+		pub type AuthorityPair = app_ecdsa::Pair;
+	}
+
+	/// An Micc authority signature using ECDSA as its crypto.
+	pub type AuthoritySignature = app_ecdsa::Signature;
+
+	/// An Micc authority identifier using ECDSA as its crypto.
+	pub type AuthorityId = app_ecdsa::Public;
+}
+
 pub use sp_consensus_slots::{Slot, SlotDuration};
 
 /// The `ConsensusEngineId` of AuRa.
@@ -87,18 +112,53 @@ pub enum ConsensusLog<AuthorityId: Codec> {
 	/// Disable the authority with given index.
 	#[codec(index = 2)]
 	OnDisabled(AuthorityIndex),
+	/// The rule used to select slot authors has changed, taking effect from the next block.
+	#[codec(index = 3)]
+	SlotAuthorSelectorChange(SlotAuthorSelector),
+	/// The slot duration changes to `duration` from `at_slot` on.
+	#[codec(index = 4)]
+	SlotDurationChange {
+		/// The first slot with the new duration.
+		at_slot: Slot,
+		/// The new slot duration.
+		duration: SlotDuration,
+	},
 }
 
 
 sp_api::decl_runtime_apis! {
 	/// API necessary for block authorship with micc.
 	pub trait MiccApi<AuthorityId: Codec> {
-		/// Returns the slot duration for Micc.
+		/// Returns the duration of the current slot.
 		///
-		/// Currently, only the value provided by this type at genesis will be used.
+		/// Nodes talking to a runtime without [`Self::slot_duration_schedule`] assume this value
+		/// never changed since genesis.
 		fn slot_duration() -> SlotDuration;
 
 		/// Return the current set of authorities.
 		fn authorities() -> Vec<AuthorityId>;
+
+		/// Return the rule used to select the author of a slot among [`Self::authorities`].
+		///
+		/// Nodes talking to a runtime without this call assume [`SlotAuthorSelector::RoundRobin`].
+		#[api_version(2)]
+		fn slot_author_selector() -> SlotAuthorSelector;
+
+		/// Return the slot durations of the chain since genesis, including scheduled changes.
+		#[api_version(3)]
+		fn slot_duration_schedule() -> SlotDurationSchedule;
+
+		/// Return which authorities may author a slot.
+		///
+		/// Nodes talking to a runtime without this call assume [`AuthorshipMode::Strict`].
+		#[api_version(4)]
+		fn authorship_mode() -> AuthorshipMode;
+
+		/// Return the time by which the runtime needs the next block for work of its own, such as
+		/// a scheduled call or a migration, in milliseconds since the Unix epoch.
+		///
+		/// Nodes talking to a runtime without this call only author blocks for transactions.
+		#[api_version(5)]
+		fn next_required_block_time() -> Option<u64>;
 	}
 }
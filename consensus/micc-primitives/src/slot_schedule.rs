@@ -0,0 +1,140 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: Apache-2.0
+
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// 	http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The history of slot durations of a chain.
+
+use alloc::vec::Vec;
+use codec::{Decode, Encode};
+use scale_info::TypeInfo;
+use sp_consensus_slots::{Slot, SlotDuration};
+
+/// A change of the slot duration, effective from `at_slot` on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub struct SlotDurationChange {
+	/// The first slot with the new duration.
+	pub at_slot: Slot,
+	/// The unix timestamp at which `at_slot` starts, in milliseconds.
+	pub at_timestamp: u64,
+	/// The new slot duration.
+	pub duration: SlotDuration,
+}
+
+/// Why a slot duration change can't be scheduled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+	/// The slot duration must not be zero.
+	ZeroDuration,
+	/// Changes must be scheduled after the last scheduled change.
+	NotAfterLastChange,
+}
+
+/// The slot durations of a chain over time.
+///
+/// Slots are numbered consecutively from genesis, but the time a slot starts at depends on the
+/// durations of all slots before it: slot `s` after the last change `c` with `c.at_slot <= s`
+/// starts at `c.at_timestamp + (s - c.at_slot) * c.duration`. Without any change, this is the
+/// usual `s * genesis_duration`.
+#[derive(Clone, Debug, PartialEq, Eq, Encode, Decode, TypeInfo)]
+pub struct SlotDurationSchedule {
+	genesis_duration: SlotDuration,
+	changes: Vec<SlotDurationChange>,
+}
+
+impl SlotDurationSchedule {
+	/// A schedule keeping `genesis_duration` forever.
+	pub fn new(genesis_duration: SlotDuration) -> Self {
+		Self { genesis_duration, changes: Vec::new() }
+	}
+
+	/// The slot duration the chain started with.
+	pub fn genesis_duration(&self) -> SlotDuration {
+		self.genesis_duration
+	}
+
+	/// The changes of the slot duration, ordered by slot.
+	pub fn changes(&self) -> &[SlotDurationChange] {
+		&self.changes
+	}
+
+	/// Schedule `duration` to take effect from `at_slot` on.
+	///
+	/// The start of `at_slot` is fixed by the schedule up to that point, so the caller must make
+	/// sure no block was built at or after `at_slot` yet.
+	pub fn schedule_change(
+		&mut self,
+		at_slot: Slot,
+		duration: SlotDuration,
+	) -> Result<SlotDurationChange, ScheduleError> {
+		if duration.as_millis() == 0 {
+			return Err(ScheduleError::ZeroDuration)
+		}
+		if self.changes.last().is_some_and(|last| last.at_slot >= at_slot) {
+			return Err(ScheduleError::NotAfterLastChange)
+		}
+
+		let change = SlotDurationChange { at_slot, at_timestamp: self.slot_start(at_slot), duration };
+		self.changes.push(change);
+		Ok(change)
+	}
+
+	/// Forget the changes superseded by a later one already in effect at `slot`.
+	///
+	/// The durations and starts of `slot` and the slots after it are kept, those of the slots
+	/// before the first change left are no longer known.
+	pub fn prune(&mut self, slot: Slot) {
+		let in_effect = self.changes.iter().filter(|change| change.at_slot <= slot).count();
+		self.changes.drain(..in_effect.saturating_sub(1));
+	}
+
+	/// The duration of `slot`.
+	pub fn duration_at(&self, slot: Slot) -> SlotDuration {
+		self.period_of_slot(slot).2
+	}
+
+	/// The unix timestamp at which `slot` starts, in milliseconds.
+	pub fn slot_start(&self, slot: Slot) -> u64 {
+		let (start_slot, start_timestamp, duration) = self.period_of_slot(slot);
+		start_timestamp.saturating_add((*slot - *start_slot).saturating_mul(duration.as_millis()))
+	}
+
+	/// The slot in progress at the unix `timestamp`, in milliseconds.
+	pub fn slot_at(&self, timestamp: u64) -> Slot {
+		let (start_slot, start_timestamp, duration) = self
+			.changes
+			.iter()
+			.rev()
+			.find(|change| change.at_timestamp <= timestamp)
+			.map_or((Slot::from(0), 0, self.genesis_duration), |change| {
+				(change.at_slot, change.at_timestamp, change.duration)
+			});
+
+		let elapsed = (timestamp - start_timestamp) / duration.as_millis().max(1);
+		start_slot.saturating_add(elapsed)
+	}
+
+	/// The first slot, its start and the duration of the period of constant duration `slot` is in.
+	fn period_of_slot(&self, slot: Slot) -> (Slot, u64, SlotDuration) {
+		self.changes
+			.iter()
+			.rev()
+			.find(|change| change.at_slot <= slot)
+			.map_or((Slot::from(0), 0, self.genesis_duration), |change| {
+				(change.at_slot, change.at_timestamp, change.duration)
+			})
+	}
+}
+
@@ -0,0 +1,126 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! RPC api for Micc.
+
+#![forbid(missing_docs, unsafe_code)]
+
+use jsonrpsee::{core::RpcResult, proc_macros::rpc};
+use sc_consensus_micc::{
+	ClockDriftEstimate, ClockDriftMonitor, ShadowAuthoring, ShadowReport, TriggerStreamHealth,
+	TriggerSupervisor,
+};
+use serde::{Deserialize, Serialize};
+
+/// Micc RPC methods.
+#[rpc(client, server)]
+pub trait MiccApi {
+	/// Returns the consensus status of the local node.
+	#[method(name = "micc_status")]
+	fn status(&self) -> RpcResult<MiccStatus>;
+}
+
+/// Consensus status of the local node.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MiccStatus {
+	/// Estimated drift of the local clock, if the node monitors it.
+	pub clock_drift: Option<ClockDriftEstimate>,
+	/// Health of the trigger stream requesting blocks, if the node authors them.
+	pub trigger_stream: Option<TriggerStreamHealth>,
+	/// What the node would have authored, if it runs the shadow authoring.
+	pub shadow: Option<ShadowReport>,
+}
+
+/// Provides RPC methods for interacting with Micc.
+#[derive(Clone, Default)]
+pub struct Micc {
+	clock_drift: Option<ClockDriftMonitor>,
+	trigger_supervisor: Option<TriggerSupervisor>,
+	shadow: Option<ShadowAuthoring>,
+}
+
+impl Micc {
+	/// Creates a new instance of the Micc Rpc handler.
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Report the estimate of `monitor` in the status.
+	pub fn with_clock_drift(mut self, monitor: ClockDriftMonitor) -> Self {
+		self.clock_drift = Some(monitor);
+		self
+	}
+
+	/// Report the health of the trigger stream restarted by `supervisor` in the status.
+	pub fn with_trigger_supervisor(mut self, supervisor: TriggerSupervisor) -> Self {
+		self.trigger_supervisor = Some(supervisor);
+		self
+	}
+
+	/// Report what the shadow authoring of `shadow` would have authored in the status.
+	pub fn with_shadow_authoring(mut self, shadow: ShadowAuthoring) -> Self {
+		self.shadow = Some(shadow);
+		self
+	}
+}
+
+impl MiccApiServer for Micc {
+	fn status(&self) -> RpcResult<MiccStatus> {
+		Ok(MiccStatus {
+			clock_drift: self.clock_drift.as_ref().map(ClockDriftMonitor::estimate),
+			trigger_stream: self.trigger_supervisor.as_ref().map(TriggerSupervisor::health),
+			shadow: self.shadow.as_ref().map(ShadowAuthoring::report),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[tokio::test]
+	async fn status_reports_clock_drift() {
+		let api = Micc::new().into_rpc();
+		let status: MiccStatus = api.call("micc_status", Vec::<()>::new()).await.unwrap();
+		assert_eq!(status, MiccStatus { clock_drift: None, trigger_stream: None, shadow: None });
+
+		let monitor = ClockDriftMonitor::new(Default::default(), None).unwrap();
+		let api = Micc::new().with_clock_drift(monitor).into_rpc();
+		let status: MiccStatus = api.call("micc_status", Vec::<()>::new()).await.unwrap();
+		let clock_drift = status.clock_drift.expect("the monitor is reported");
+		assert_eq!(clock_drift.drift_ms, None);
+		assert_eq!(clock_drift.max_drift_ms, 5_000);
+	}
+
+	#[tokio::test]
+	async fn status_reports_trigger_stream_health() {
+		let supervisor = TriggerSupervisor::new(None).unwrap();
+		let api = Micc::new().with_trigger_supervisor(supervisor).into_rpc();
+		let status: MiccStatus = api.call("micc_status", Vec::<()>::new()).await.unwrap();
+		assert_eq!(status.trigger_stream, Some(TriggerStreamHealth::default()));
+	}
+
+	#[tokio::test]
+	async fn status_reports_shadow_authoring() {
+		let shadow = ShadowAuthoring::new(None).unwrap();
+		let api = Micc::new().with_shadow_authoring(shadow).into_rpc();
+		let status: MiccStatus = api.call("micc_status", Vec::<()>::new()).await.unwrap();
+		assert_eq!(status.shadow, Some(ShadowReport::default()));
+	}
+}
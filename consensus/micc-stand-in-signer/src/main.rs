@@ -0,0 +1,85 @@
+// This file is part of Substrate.
+
+// Copyright (C) Parity Technologies (UK) Ltd.
+// SPDX-License-Identifier: GPL-3.0-or-later WITH Classpath-exception-2.0
+
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with this program. If not, see <https://www.gnu.org/licenses/>.
+
+//! A stand-in for an external Micc signer, holding its keys in memory.
+//!
+//! ```text
+//! micc-stand-in-signer --listen unix:/tmp/signer.sock [--scheme sr25519] --suri //Alice
+//! micc-stand-in-signer --listen http://127.0.0.1:9955 --token-file token --suri //Alice
+//! ```
+//!
+//! Only meant for tests and local networks, the keys are derived from the secret URIs given on
+//! the command line. Listening over HTTP requires a token, read from `--token-file`.
+
+use std::{process::exit, sync::Arc};
+
+use sc_consensus_micc::remote_keystore::{serve_signer, RemoteSignerEndpoint};
+use sp_consensus_micc::MICC;
+use sp_keystore::{testing::MemoryKeystore, Keystore};
+
+const USAGE: &str = "usage: micc-stand-in-signer --listen <unix:PATH|http://HOST:PORT> \
+	[--token-file <PATH>] [--scheme <sr25519|ed25519|ecdsa>] --suri <SURI>...";
+
+fn fail(message: impl std::fmt::Display) -> ! {
+	eprintln!("{message}\n{USAGE}");
+	exit(1)
+}
+
+fn main() {
+	let mut endpoint = None;
+	let mut token = None;
+	let mut scheme = "sr25519".to_string();
+	let mut suris = Vec::new();
+
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		let value = args.next().unwrap_or_else(|| fail(format!("missing value of {arg}")));
+		match arg.as_str() {
+			"--listen" =>
+				endpoint = Some(value.parse::<RemoteSignerEndpoint>().unwrap_or_else(|e| fail(e))),
+			"--token-file" =>
+				token = Some(
+					std::fs::read_to_string(&value)
+						.unwrap_or_else(|e| fail(format!("failed to read {value}: {e}")))
+						.trim()
+						.to_string(),
+				),
+			"--scheme" => scheme = value,
+			"--suri" => suris.push(value),
+			_ => fail(format!("unknown argument {arg}")),
+		}
+	}
+	let endpoint = endpoint.unwrap_or_else(|| fail("--listen is required"));
+
+	let keystore = MemoryKeystore::new();
+	for suri in &suris {
+		let public = match scheme.as_str() {
+			"sr25519" => keystore.sr25519_generate_new(MICC, Some(suri)).map(|k| k.to_string()),
+			"ed25519" => keystore.ed25519_generate_new(MICC, Some(suri)).map(|k| k.to_string()),
+			"ecdsa" => keystore.ecdsa_generate_new(MICC, Some(suri)).map(|k| k.to_string()),
+			_ => fail(format!("unknown scheme {scheme}")),
+		}
+		.unwrap_or_else(|e| fail(format!("invalid secret URI {suri}: {e}")));
+		eprintln!("Serving {scheme} key {public}");
+	}
+
+	eprintln!("Listening on {endpoint}");
+	if let Err(e) = serve_signer(&endpoint, Arc::new(keystore), token) {
+		fail(format!("signer stopped: {e}"))
+	}
+}
@@ -26,10 +26,17 @@
 //!
 //! ## Interface
 //!
+//! ### Dispatchable Functions
+//!
+//! - `schedule_slot_duration_change` - Change the slot duration from a future slot on.
+//!
 //! ### Public Functions
 //!
-//! - `slot_duration` - Determine the Micc slot-duration based on the Timestamp module
-//!   configuration.
+//! - `slot_duration` - Determine the duration of the current Micc slot.
+//! - `slot_duration_schedule` - Determine the Micc slot durations since genesis.
+//! - `slot_author_index` - Determine the index of the authority expected to author a slot.
+//! - `authorship_mode` - Determine which authorities may author a slot.
+//! - `next_required_block_time` - Determine when the runtime needs the next block.
 //!
 //! ## Related Modules
 //!
@@ -41,17 +48,20 @@
 extern crate alloc;
 
 use alloc::vec::Vec;
-use codec::{Decode, Encode, MaxEncodedLen};
+use codec::{Decode, DecodeAll, Encode, MaxEncodedLen};
 use frame_support::{
 	traits::{DisabledValidators, FindAuthor, Get, OnTimestampSet, OneSessionHandler},
 	BoundedSlice, BoundedVec, ConsensusEngineId, Parameter,
 };
 use frame_system::pallet_prelude::BlockNumberFor;
 use log;
-use sp_consensus_micc::{AuthorityIndex, ConsensusLog, Slot, MICC_ENGINE_ID};
+use sp_consensus_micc::{
+	slot_schedule::ScheduleError, AuthorityIndex, AuthorshipMode, ConsensusLog, Slot,
+	SlotAuthorSelector, SlotDuration, SlotDurationSchedule, MICC_ENGINE_ID,
+};
 use sp_runtime::{
 	generic::DigestItem,
-	traits::{IsMember, Member, SaturatedConversion, Saturating, Zero},
+	traits::{IsMember, Member, SaturatedConversion, Saturating},
 	RuntimeAppPublic,
 };
 
@@ -76,10 +86,58 @@ impl<T: pallet_timestamp::Config> Get<T::Moment> for MinimumPeriodTimesTwo<T> {
 	}
 }
 
+/// A slot author selector assigning slots to authorities in turn, regardless of their weight.
+///
+/// This was the only behavior of the Micc pallet before selectors were configurable.
+pub struct RoundRobin;
+
+impl Get<SlotAuthorSelector> for RoundRobin {
+	fn get() -> SlotAuthorSelector {
+		SlotAuthorSelector::RoundRobin
+	}
+}
+
+/// A source of work that only the runtime knows of, such as scheduled calls or migrations, and
+/// which needs blocks to be done.
+///
+/// Nodes producing blocks on demand author a block at the returned time, even when no transaction
+/// requests one. A time already passed requests a block at the next slot.
+pub trait RequiredBlockTime<Moment> {
+	/// Return the time by which the next block is needed, in the unit of the timestamps, or `None`
+	/// if no block is needed.
+	fn next_required_block_time() -> Option<Moment>;
+}
+
+impl<Moment> RequiredBlockTime<Moment> for () {
+	fn next_required_block_time() -> Option<Moment> {
+		None
+	}
+}
+
+/// Several sources need a block by the earliest of their times.
+macro_rules! impl_required_block_time_for_tuples {
+	($($source:ident),+) => {
+		impl<Moment: Ord, $($source: RequiredBlockTime<Moment>),+> RequiredBlockTime<Moment>
+			for ($($source,)+)
+		{
+			fn next_required_block_time() -> Option<Moment> {
+				[$($source::next_required_block_time()),+].into_iter().flatten().min()
+			}
+		}
+	};
+}
+
+impl_required_block_time_for_tuples!(A);
+impl_required_block_time_for_tuples!(A, B);
+impl_required_block_time_for_tuples!(A, B, C);
+impl_required_block_time_for_tuples!(A, B, C, D);
+impl_required_block_time_for_tuples!(A, B, C, D, E);
+
 #[frame_support::pallet]
 pub mod pallet {
 	use super::*;
 	use frame_support::pallet_prelude::*;
+	use frame_system::pallet_prelude::*;
 
 	#[pallet::config]
 	pub trait Config: pallet_timestamp::Config + frame_system::Config {
@@ -112,16 +170,44 @@ pub mod pallet {
 		type AllowMultipleBlocksPerSlot: Get<bool>;
 
 		/// The slot duration Micc should run with, expressed in milliseconds.
-		/// The effective value of this type should not change while the chain is running.
+		/// The effective value of this type should not change while the chain is running, use
+		/// [`Pallet::schedule_slot_duration_change`] instead.
 		///
 		/// For backwards compatibility either use [`MinimumPeriodTimesTwo`] or a const.
 		#[pallet::constant]
 		type SlotDuration: Get<<Self as pallet_timestamp::Config>::Moment>;
+
+		/// The origin allowed to change the slot duration.
+		type SlotDurationOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+
+		/// The rule used to select the author of each slot among the current authorities.
+		///
+		/// The value is read at the end of every block and takes effect from the next one, so it
+		/// may depend on state that changes over time, such as stakes. Weights and stakes must be
+		/// given in the order of [`Authorities`].
+		///
+		/// Use [`RoundRobin`] for the classic `slot % authorities.len()` rotation.
+		type SlotAuthorSelector: Get<SlotAuthorSelector>;
+
+		/// When the runtime needs the next block for work of its own, see [`RequiredBlockTime`].
+		///
+		/// Use `()` when all the work of the runtime is requested through transactions.
+		type RequiredBlockTime: RequiredBlockTime<<Self as pallet_timestamp::Config>::Moment>;
 	}
 
 	#[pallet::pallet]
 	pub struct Pallet<T>(core::marker::PhantomData<T>);
 
+	#[pallet::error]
+	pub enum Error<T> {
+		/// The slot duration is shorter than the minimum period between timestamps.
+		SlotDurationTooShort,
+		/// The slot duration can only be changed from a future slot on.
+		SlotNotInFuture,
+		/// A change of the slot duration is already scheduled at or after the given slot.
+		ChangeAlreadyScheduled,
+	}
+
 	#[pallet::hooks]
 	impl<T: Config> Hooks<BlockNumberFor<T>> for Pallet<T> {
 		fn on_initialize(_: BlockNumberFor<T>) -> Weight {
@@ -136,9 +222,9 @@ pub mod pallet {
 
 				CurrentSlot::<T>::put(new_slot);
 
-				if let Some(n_authorities) = <Authorities<T>>::decode_len() {
-					let authority_index = *new_slot % n_authorities as u64;
-					if T::DisabledValidators::is_disabled(authority_index as u32) {
+				let named = Self::pre_digest_from_digests().and_then(|(_, author)| author);
+				if let Some(authority_index) = Self::author_index(new_slot, named) {
+					if T::DisabledValidators::is_disabled(authority_index) {
 						panic!(
 							"Validator with index {:?} is disabled and should not be attempting to author blocks.",
 							authority_index,
@@ -149,9 +235,18 @@ pub mod pallet {
 				// TODO [#3398] Generate offence report for all authorities that skipped their
 				// slots.
 
-				T::DbWeight::get().reads_writes(2, 1)
+				// Includes `on_finalize`, which compares the configured selector with the stored one
+				// and may replace it.
+				T::DbWeight::get().reads_writes(6, 2)
 			} else {
-				T::DbWeight::get().reads(1)
+				T::DbWeight::get().reads_writes(3, 1)
+			}
+		}
+
+		fn on_finalize(_: BlockNumberFor<T>) {
+			let selector = T::SlotAuthorSelector::get();
+			if selector != SlotAuthorSelection::<T>::get() {
+				Self::change_slot_author_selector(selector);
 			}
 		}
 
@@ -172,16 +267,84 @@ pub mod pallet {
 	#[pallet::storage]
 	pub type CurrentSlot<T: Config> = StorageValue<_, Slot, ValueQuery>;
 
+	/// The rule used to select the author of a slot among [`Authorities`].
+	///
+	/// This is a snapshot of [`Config::SlotAuthorSelector`] taken at the end of the previous
+	/// block, so that the runtime and the client agree on it for the whole block.
+	#[pallet::storage]
+	#[pallet::unbounded]
+	pub type SlotAuthorSelection<T: Config> = StorageValue<_, SlotAuthorSelector, ValueQuery>;
+
+	/// The slot durations since genesis, including scheduled changes.
+	///
+	/// Empty until the slot duration is changed for the first time, see
+	/// [`Pallet::slot_duration_schedule`].
+	#[pallet::storage]
+	#[pallet::unbounded]
+	pub type SlotDurationHistory<T: Config> = StorageValue<_, SlotDurationSchedule, OptionQuery>;
+
+	/// Which authorities may author a slot, as declared in the chain spec.
+	#[pallet::storage]
+	#[pallet::unbounded]
+	pub type Authorship<T: Config> = StorageValue<_, AuthorshipMode, ValueQuery>;
+
 	#[pallet::genesis_config]
 	#[derive(frame_support::DefaultNoBound)]
 	pub struct GenesisConfig<T: Config> {
 		pub authorities: Vec<T::AuthorityId>,
+		/// Let any authority author any slot, for development networks.
+		pub authorship_mode: AuthorshipMode,
 	}
 
 	#[pallet::genesis_build]
 	impl<T: Config> BuildGenesisConfig for GenesisConfig<T> {
 		fn build(&self) {
 			Pallet::<T>::initialize_authorities(&self.authorities);
+			SlotAuthorSelection::<T>::put(T::SlotAuthorSelector::get());
+			Authorship::<T>::put(self.authorship_mode);
+		}
+	}
+
+	#[pallet::call]
+	impl<T: Config> Pallet<T> {
+		/// Change the slot duration to `duration` milliseconds from `at_slot` on.
+		///
+		/// `at_slot` must be later than the current slot and than any change scheduled before.
+		/// Nodes learn about the change from a consensus log and derive the slots of a block from
+		/// the slot durations recorded at its parent, so the change applies to syncing nodes too.
+		/// The changes superseded before the current slot are forgotten.
+		#[pallet::call_index(0)]
+		#[pallet::weight(T::DbWeight::get().reads_writes(2, 2))]
+		pub fn schedule_slot_duration_change(
+			origin: OriginFor<T>,
+			at_slot: Slot,
+			duration: T::Moment,
+		) -> DispatchResult {
+			T::SlotDurationOrigin::ensure_origin(origin)?;
+			ensure!(
+				duration >= <T as pallet_timestamp::Config>::MinimumPeriod::get(),
+				Error::<T>::SlotDurationTooShort,
+			);
+			ensure!(at_slot > CurrentSlot::<T>::get(), Error::<T>::SlotNotInFuture);
+
+			let duration = SlotDuration::from_millis(duration.saturated_into());
+			let mut schedule = Self::slot_duration_schedule();
+			// Nodes read the schedule at the parent of the blocks they check, so the blocks to come
+			// only need the changes in effect from the current slot on.
+			schedule.prune(CurrentSlot::<T>::get());
+			schedule.schedule_change(at_slot, duration).map_err(|e| match e {
+				ScheduleError::ZeroDuration => Error::<T>::SlotDurationTooShort,
+				ScheduleError::NotAfterLastChange => Error::<T>::ChangeAlreadyScheduled,
+			})?;
+			SlotDurationHistory::<T>::put(schedule);
+
+			let log = DigestItem::Consensus(
+				MICC_ENGINE_ID,
+				ConsensusLog::<T::AuthorityId>::SlotDurationChange { at_slot, duration }.encode(),
+			);
+			<frame_system::Pallet<T>>::deposit_log(log);
+
+			Ok(())
 		}
 	}
 }
@@ -209,6 +372,20 @@ impl<T: Config> Pallet<T> {
 		<frame_system::Pallet<T>>::deposit_log(log);
 	}
 
+	/// Change the rule used to select slot authors.
+	///
+	/// The storage will be applied immediately, meaning that the rule is in effect from the next
+	/// block on, and a Micc consensus log will be appended to the block's log.
+	fn change_slot_author_selector(selector: SlotAuthorSelector) {
+		SlotAuthorSelection::<T>::put(&selector);
+
+		let log = DigestItem::Consensus(
+			MICC_ENGINE_ID,
+			ConsensusLog::<T::AuthorityId>::SlotAuthorSelectorChange(selector).encode(),
+		);
+		<frame_system::Pallet<T>>::deposit_log(log);
+	}
+
 	/// Initial authorities.
 	///
 	/// The storage will be applied immediately.
@@ -228,22 +405,74 @@ impl<T: Config> Pallet<T> {
 		Authorities::<T>::decode_len().unwrap_or(0)
 	}
 
+	/// Return the rule currently used to select slot authors.
+	pub fn slot_author_selector() -> SlotAuthorSelector {
+		SlotAuthorSelection::<T>::get()
+	}
+
+	/// Return the index of the authority expected to author `slot`, or `None` if there are no
+	/// authorities.
+	pub fn slot_author_index(slot: Slot) -> Option<AuthorityIndex> {
+		Self::slot_author_selector().slot_author(slot, Self::authorities_len())
+	}
+
+	/// Return which authorities may author a slot.
+	pub fn authorship_mode() -> AuthorshipMode {
+		Authorship::<T>::get()
+	}
+
+	/// Return the index of the authority of a block at `slot`, given the author `named` in its
+	/// pre-digest, or `None` if it can't be known.
+	///
+	/// Blocks name their author unless it is the slot owner, the only one allowed to author them
+	/// under [`AuthorshipMode::Strict`].
+	pub fn author_index(slot: Slot, named: Option<AuthorityIndex>) -> Option<AuthorityIndex> {
+		match Self::authorship_mode() {
+			AuthorshipMode::Strict => Self::slot_author_index(slot),
+			AuthorshipMode::Permissive => named.or_else(|| Self::slot_author_index(slot)),
+			AuthorshipMode::AnyAuthority => named,
+		}
+	}
+
+	/// Return the time by which the runtime needs the next block, if it needs one at all.
+	pub fn next_required_block_time() -> Option<T::Moment> {
+		T::RequiredBlockTime::next_required_block_time()
+	}
+
 	/// Get the current slot from the pre-runtime digests.
 	fn current_slot_from_digests() -> Option<Slot> {
+		Self::pre_digest_from_digests().map(|(slot, _)| slot)
+	}
+
+	/// Get the slot, and the author if named, from the pre-runtime digests.
+	fn pre_digest_from_digests() -> Option<(Slot, Option<AuthorityIndex>)> {
 		let digest = frame_system::Pallet::<T>::digest();
 		let pre_runtime_digests = digest.logs.iter().filter_map(|d| d.as_pre_runtime());
-		for (id, mut data) in pre_runtime_digests {
+		for (id, data) in pre_runtime_digests {
 			if id == MICC_ENGINE_ID {
-				return Slot::decode(&mut data).ok()
+				return decode_pre_digest(data)
 			}
 		}
 
 		None
 	}
 
-	/// Determine the Micc slot-duration based on the Timestamp module configuration.
+	/// Determine the duration of the current Micc slot.
 	pub fn slot_duration() -> T::Moment {
-		T::SlotDuration::get()
+		let duration = Self::slot_duration_schedule().duration_at(CurrentSlot::<T>::get());
+		duration.as_millis().saturated_into()
+	}
+
+	/// Determine the Micc slot durations since genesis.
+	///
+	/// Until the slot duration is changed for the first time, the configured
+	/// [`Config::SlotDuration`] applies to all slots.
+	pub fn slot_duration_schedule() -> SlotDurationSchedule {
+		SlotDurationHistory::<T>::get().unwrap_or_else(|| {
+			SlotDurationSchedule::new(SlotDuration::from_millis(
+				T::SlotDuration::get().saturated_into(),
+			))
+		})
 	}
 
 	/// Ensure the correctness of the state of this pallet.
@@ -283,14 +512,21 @@ impl<T: Config> Pallet<T> {
 			<Authorities<T>>::decode_len().ok_or("Failed to decode authorities length")?;
 
 		// Check that the authorities are non-empty.
-		frame_support::ensure!(!authorities_len.is_zero(), "Authorities must be non-empty.");
+		frame_support::ensure!(authorities_len != 0, "Authorities must be non-empty.");
 
 		// Check that the current authority is not disabled.
-		let authority_index = *current_slot % authorities_len as u64;
-		frame_support::ensure!(
-			!T::DisabledValidators::is_disabled(authority_index as u32),
-			"Current validator is disabled and should not be attempting to author blocks.",
-		);
+		let named = Self::pre_digest_from_digests().and_then(|(_, author)| author);
+		if let Some(authority_index) = Self::author_index(current_slot, named) {
+			frame_support::ensure!(
+				!T::DisabledValidators::is_disabled(authority_index),
+				"Current validator is disabled and should not be attempting to author blocks.",
+			);
+		} else {
+			frame_support::ensure!(
+				Self::authorship_mode() == AuthorshipMode::AnyAuthority,
+				"Current slot has no author.",
+			);
+		}
 
 		Ok(())
 	}
@@ -348,11 +584,10 @@ impl<T: Config> FindAuthor<u32> for Pallet<T> {
 	where
 		I: 'a + IntoIterator<Item = (ConsensusEngineId, &'a [u8])>,
 	{
-		for (id, mut data) in digests.into_iter() {
+		for (id, data) in digests.into_iter() {
 			if id == MICC_ENGINE_ID {
-				let slot = Slot::decode(&mut data).ok()?;
-				let author_index = *slot % Self::authorities_len() as u64;
-				return Some(author_index as u32)
+				let (slot, author) = decode_pre_digest(data)?;
+				return Self::author_index(slot, author)
 			}
 		}
 
@@ -360,6 +595,17 @@ impl<T: Config> FindAuthor<u32> for Pallet<T> {
 	}
 }
 
+/// Decode a Micc pre-runtime digest: the slot, followed by the index of its author when any
+/// authority may author it.
+fn decode_pre_digest(mut data: &[u8]) -> Option<(Slot, Option<AuthorityIndex>)> {
+	let slot = Slot::decode(&mut data).ok()?;
+	let author = match data.is_empty() {
+		true => None,
+		false => Some(AuthorityIndex::decode_all(&mut data).ok()?),
+	};
+	Some((slot, author))
+}
+
 /// We can not implement `FindAuthor` twice, because the compiler does not know if
 /// `u32 == T::AuthorityId` and thus, prevents us to implement the trait twice.
 #[doc(hidden)]
@@ -390,11 +636,10 @@ impl<T: Config> IsMember<T::AuthorityId> for Pallet<T> {
 
 impl<T: Config> OnTimestampSet<T::Moment> for Pallet<T> {
 	fn on_timestamp_set(moment: T::Moment) {
-		let slot_duration = Self::slot_duration();
-		assert!(!slot_duration.is_zero(), "Micc slot duration cannot be zero.");
+		let schedule = Self::slot_duration_schedule();
+		assert!(schedule.genesis_duration().as_millis() != 0, "Micc slot duration cannot be zero.");
 
-		let timestamp_slot = moment / slot_duration;
-		let timestamp_slot = Slot::from(timestamp_slot.saturated_into::<u64>());
+		let timestamp_slot = schedule.slot_at(moment.saturated_into::<u64>());
 
 		assert_eq!(
 			CurrentSlot::<T>::get(),
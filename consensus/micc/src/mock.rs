@@ -24,7 +24,7 @@ use frame_support::{
 	derive_impl, parameter_types,
 	traits::{ConstU32, ConstU64, DisabledValidators},
 };
-use sp_consensus_micc::{ed25519::AuthorityId, AuthorityIndex};
+use sp_consensus_micc::{ed25519::AuthorityId, AuthorityIndex, SlotAuthorSelector};
 use sp_runtime::{testing::UintAuthorityId, BuildStorage};
 
 type Block = frame_system::mocking::MockBlock<Test>;
@@ -55,6 +55,16 @@ impl pallet_timestamp::Config for Test {
 parameter_types! {
 	static DisabledValidatorTestValue: Vec<AuthorityIndex> = Default::default();
 	pub static AllowMultipleBlocksPerSlot: bool = false;
+	pub static MockSlotAuthorSelector: SlotAuthorSelector = SlotAuthorSelector::RoundRobin;
+	pub static MockRequiredBlockTime: Option<u64> = None;
+}
+
+pub struct MockRequiredBlock;
+
+impl pallet_micc::RequiredBlockTime<u64> for MockRequiredBlock {
+	fn next_required_block_time() -> Option<u64> {
+		MockRequiredBlockTime::get()
+	}
 }
 
 pub struct MockDisabledValidators;
@@ -85,12 +95,16 @@ impl pallet_micc::Config for Test {
 	type MaxAuthorities = ConstU32<10>;
 	type AllowMultipleBlocksPerSlot = AllowMultipleBlocksPerSlot;
 	type SlotDuration = ConstU64<SLOT_DURATION>;
+	type SlotDurationOrigin = frame_system::EnsureRoot<u64>;
+	type SlotAuthorSelector = MockSlotAuthorSelector;
+	type RequiredBlockTime = (MockRequiredBlock, ());
 }
 
 fn build_ext(authorities: Vec<u64>) -> sp_io::TestExternalities {
 	let mut storage = frame_system::GenesisConfig::<Test>::default().build_storage().unwrap();
 	pallet_micc::GenesisConfig::<Test> {
 		authorities: authorities.into_iter().map(|a| UintAuthorityId(a).to_public_key()).collect(),
+		authorship_mode: Default::default(),
 	}
 	.assimilate_storage(&mut storage)
 	.unwrap();
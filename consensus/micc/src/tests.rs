@@ -19,12 +19,21 @@
 
 #![cfg(test)]
 
-use super::pallet;
-use crate::mock::{build_ext_and_execute_test, Micc, MockDisabledValidators, System, Test};
+use super::{pallet, RequiredBlockTime};
+use crate::mock::{
+	build_ext_and_execute_test, Micc, MockDisabledValidators, MockRequiredBlockTime,
+	MockSlotAuthorSelector, RuntimeOrigin, System, Test,
+};
 use codec::Encode;
-use frame_support::traits::OnInitialize;
-use sp_consensus_micc::{Slot, MICC_ENGINE_ID};
-use sp_runtime::{Digest, DigestItem};
+use frame_support::{
+	assert_noop, assert_ok,
+	traits::{FindAuthor, OnFinalize, OnInitialize, OnTimestampSet},
+};
+use sp_consensus_micc::{
+	author_selection::STAKE_SCHEDULE_LENGTH, slot_schedule::ScheduleError, AuthorshipMode,
+	ConsensusLog, Slot, SlotAuthorSelector, SlotDuration, SlotDurationSchedule, MICC_ENGINE_ID,
+};
+use sp_runtime::{Digest, DigestItem, DispatchError};
 
 #[test]
 fn initial_values() {
@@ -57,6 +66,93 @@ fn disabled_validators_cannot_author_blocks() {
 	});
 }
 
+#[test]
+fn permissive_blocks_name_their_author_unless_it_owns_the_slot() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		pallet::Authorship::<Test>::put(AuthorshipMode::Permissive);
+		assert_eq!(Micc::authorship_mode(), AuthorshipMode::Permissive);
+
+		// slot 1 is owned by validator 1, but authored by validator 3
+		let slot = Slot::from(1);
+		let pre_digest = (slot, 3u32).encode();
+		let digest =
+			Digest { logs: vec![DigestItem::PreRuntime(MICC_ENGINE_ID, pre_digest.clone())] };
+
+		System::reset_events();
+		System::initialize(&42, &System::parent_hash(), &digest);
+
+		// the owner of slot 1 is disabled, but didn't author the block
+		MockDisabledValidators::disable_validator(1);
+		Micc::on_initialize(42);
+
+		assert_eq!(Micc::find_author([(MICC_ENGINE_ID, &pre_digest[..])]), Some(3));
+		assert_eq!(Micc::find_author([(MICC_ENGINE_ID, &slot.encode()[..])]), Some(1));
+	});
+}
+
+#[test]
+#[should_panic(
+	expected = "Validator with index 3 is disabled and should not be attempting to author blocks."
+)]
+fn disabled_validators_cannot_author_permissive_blocks() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		pallet::Authorship::<Test>::put(AuthorshipMode::Permissive);
+
+		let pre_digest = (Slot::from(1), 3u32).encode();
+		let digest = Digest { logs: vec![DigestItem::PreRuntime(MICC_ENGINE_ID, pre_digest)] };
+
+		System::reset_events();
+		System::initialize(&42, &System::parent_hash(), &digest);
+
+		MockDisabledValidators::disable_validator(3);
+		Micc::on_initialize(42);
+	});
+}
+
+#[test]
+fn any_authority_blocks_name_their_author() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		pallet::Authorship::<Test>::put(AuthorshipMode::AnyAuthority);
+
+		// slot 1 is owned by validator 1, but authored by validator 3
+		let pre_digest = (Slot::from(1), 3u32).encode();
+		let digest =
+			Digest { logs: vec![DigestItem::PreRuntime(MICC_ENGINE_ID, pre_digest.clone())] };
+
+		System::reset_events();
+		System::initialize(&42, &System::parent_hash(), &digest);
+
+		MockDisabledValidators::disable_validator(1);
+		Micc::on_initialize(42);
+
+		assert_eq!(pallet::CurrentSlot::<Test>::get(), Slot::from(1));
+		assert_eq!(Micc::find_author([(MICC_ENGINE_ID, &pre_digest[..])]), Some(3));
+	});
+}
+
+#[test]
+fn pre_digests_with_trailing_bytes_name_no_author() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		pallet::Authorship::<Test>::put(AuthorshipMode::AnyAuthority);
+
+		let pre_digest = (Slot::from(1), 3u32, 0u8).encode();
+		assert_eq!(Micc::find_author([(MICC_ENGINE_ID, &pre_digest[..])]), None);
+	});
+}
+
+#[test]
+fn rotation_rank_starts_at_the_slot_author() {
+	let selector = SlotAuthorSelector::RoundRobin;
+
+	// slot 6 is owned by authority 2 out of 4
+	assert_eq!(selector.rotation_rank(6.into(), 2, 4), Some(0));
+	assert_eq!(selector.rotation_rank(6.into(), 3, 4), Some(1));
+	assert_eq!(selector.rotation_rank(6.into(), 0, 4), Some(2));
+	assert_eq!(selector.rotation_rank(6.into(), 1, 4), Some(3));
+	assert_eq!(selector.rotation_rank(6.into(), 4, 4), None);
+	assert_eq!(selector.rotation_rank(6.into(), 0, 0), None);
+}
+
 #[test]
 #[should_panic(expected = "Slot must increase")]
 fn pallet_requires_slot_to_increase_unless_allowed() {
@@ -117,3 +213,246 @@ fn pallet_always_rejects_decreasing_slot() {
 		Micc::on_initialize(43);
 	});
 }
+
+#[test]
+fn weighted_round_robin_assigns_slots_proportionally() {
+	build_ext_and_execute_test(vec![0, 1, 2], || {
+		pallet::SlotAuthorSelection::<Test>::put(SlotAuthorSelector::WeightedRoundRobin(vec![
+			3, 1, 0,
+		]));
+
+		let mut authored = [0; 3];
+		for slot in 0..40u64 {
+			authored[Micc::slot_author_index(slot.into()).unwrap() as usize] += 1;
+		}
+		assert_eq!(authored, [30, 10, 0]);
+
+		// the slots of an authority are not handed out in a single run.
+		let first_cycle =
+			(0..4u64).map(|slot| Micc::slot_author_index(slot.into()).unwrap()).collect::<Vec<_>>();
+		assert_ne!(first_cycle, vec![0, 0, 0, 1]);
+	});
+}
+
+#[test]
+fn stake_proportional_schedule_is_deterministic_and_proportional() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		// stakes this large must not overflow when scaled to the schedule length.
+		let selector = SlotAuthorSelector::StakeProportional(vec![
+			u128::MAX / 2,
+			u128::MAX / 4,
+			u128::MAX / 4,
+			0,
+		]);
+
+		let mut authored = [0; 4];
+		for slot in 0..STAKE_SCHEDULE_LENGTH as u64 {
+			let index = selector.slot_author(slot.into(), 4).unwrap();
+			assert_eq!(Some(index), selector.slot_author(slot.into(), 4));
+			authored[index as usize] += 1;
+		}
+		assert_eq!(authored, [5_000, 2_500, 2_500, 0]);
+	});
+}
+
+#[test]
+fn mismatched_or_empty_weights_fall_back_to_round_robin() {
+	let round_robin = SlotAuthorSelector::RoundRobin;
+	for selector in [
+		SlotAuthorSelector::WeightedRoundRobin(vec![1, 2]),
+		SlotAuthorSelector::WeightedRoundRobin(vec![0, 0, 0]),
+		SlotAuthorSelector::StakeProportional(vec![0, 0, 0]),
+	] {
+		for slot in 0..10u64 {
+			assert_eq!(
+				selector.slot_author(slot.into(), 3),
+				round_robin.slot_author(slot.into(), 3)
+			);
+		}
+		assert_eq!(selector.slot_author(0.into(), 0), None);
+	}
+}
+
+#[test]
+fn find_author_follows_the_selector() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		pallet::SlotAuthorSelection::<Test>::put(SlotAuthorSelector::WeightedRoundRobin(vec![
+			0, 0, 1, 0,
+		]));
+
+		for slot in [1u64, 2, 7] {
+			let slot = Slot::from(slot);
+			let author = Micc::find_author([(MICC_ENGINE_ID, &slot.encode()[..])]);
+			assert_eq!(author, Some(2));
+		}
+	});
+}
+
+#[test]
+fn selector_changes_are_snapshotted_and_logged() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		assert_eq!(Micc::slot_author_selector(), SlotAuthorSelector::RoundRobin);
+
+		let weighted = SlotAuthorSelector::WeightedRoundRobin(vec![1, 1, 1, 2]);
+		MockSlotAuthorSelector::set(weighted.clone());
+
+		System::initialize(&1, &System::parent_hash(), &Default::default());
+		Micc::on_finalize(1);
+
+		assert_eq!(Micc::slot_author_selector(), weighted);
+		let expected = DigestItem::Consensus(
+			MICC_ENGINE_ID,
+			ConsensusLog::<sp_consensus_micc::ed25519::AuthorityId>::SlotAuthorSelectorChange(
+				weighted,
+			)
+			.encode(),
+		);
+		assert_eq!(System::digest().logs, vec![expected]);
+
+		// an unchanged selector is not logged again.
+		System::initialize(&2, &System::parent_hash(), &Default::default());
+		Micc::on_finalize(2);
+		assert!(System::digest().logs.is_empty());
+	});
+}
+
+#[test]
+fn slot_duration_schedule_keeps_slots_continuous() {
+	let mut schedule = SlotDurationSchedule::new(SlotDuration::from_millis(6000));
+	assert_eq!(schedule.slot_at(59_999), Slot::from(9));
+	assert_eq!(schedule.slot_start(10.into()), 60_000);
+
+	let change = schedule.schedule_change(10.into(), SlotDuration::from_millis(2000)).unwrap();
+	assert_eq!(change.at_timestamp, 60_000);
+	schedule.schedule_change(20.into(), SlotDuration::from_millis(12_000)).unwrap();
+
+	assert_eq!(schedule.slot_at(59_999), Slot::from(9));
+	assert_eq!(schedule.slot_at(61_999), Slot::from(10));
+	assert_eq!(schedule.slot_at(62_000), Slot::from(11));
+	assert_eq!(schedule.slot_start(20.into()), 80_000);
+	assert_eq!(schedule.slot_at(92_000), Slot::from(21));
+	assert_eq!(schedule.duration_at(19.into()), SlotDuration::from_millis(2000));
+	assert_eq!(schedule.duration_at(20.into()), SlotDuration::from_millis(12_000));
+
+	for slot in 0..30u64 {
+		assert_eq!(schedule.slot_at(schedule.slot_start(slot.into())), Slot::from(slot));
+	}
+
+	assert_eq!(
+		schedule.schedule_change(20.into(), SlotDuration::from_millis(1000)),
+		Err(ScheduleError::NotAfterLastChange),
+	);
+	assert_eq!(
+		schedule.schedule_change(30.into(), SlotDuration::from_millis(0)),
+		Err(ScheduleError::ZeroDuration),
+	);
+}
+
+#[test]
+fn slot_duration_changes_are_scheduled_and_logged() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		let pre_digest =
+			Digest { logs: vec![DigestItem::PreRuntime(MICC_ENGINE_ID, Slot::from(5).encode())] };
+		System::initialize(&1, &System::parent_hash(), &pre_digest);
+		Micc::on_initialize(1);
+
+		assert_noop!(
+			Micc::schedule_slot_duration_change(RuntimeOrigin::signed(1), 10.into(), 4),
+			DispatchError::BadOrigin,
+		);
+		assert_noop!(
+			Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 5.into(), 4),
+			pallet::Error::<Test>::SlotNotInFuture,
+		);
+		assert_noop!(
+			Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 10.into(), 0),
+			pallet::Error::<Test>::SlotDurationTooShort,
+		);
+
+		assert_ok!(Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 10.into(), 4));
+		assert_noop!(
+			Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 8.into(), 4),
+			pallet::Error::<Test>::ChangeAlreadyScheduled,
+		);
+
+		let expected = DigestItem::Consensus(
+			MICC_ENGINE_ID,
+			ConsensusLog::<sp_consensus_micc::ed25519::AuthorityId>::SlotDurationChange {
+				at_slot: 10.into(),
+				duration: SlotDuration::from_millis(4),
+			}
+			.encode(),
+		);
+		assert!(System::digest().logs.contains(&expected));
+
+		// the current slot keeps its duration.
+		assert_eq!(Micc::slot_duration(), 2);
+		let schedule = Micc::slot_duration_schedule();
+		assert_eq!(schedule.slot_start(10.into()), 20);
+		assert_eq!(schedule.duration_at(10.into()), SlotDuration::from_millis(4));
+	});
+}
+
+#[test]
+fn superseded_slot_duration_changes_are_pruned() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		assert_ok!(Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 10.into(), 4));
+		assert_ok!(Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 20.into(), 6));
+
+		let pre_digest =
+			Digest { logs: vec![DigestItem::PreRuntime(MICC_ENGINE_ID, Slot::from(25).encode())] };
+		System::initialize(&1, &System::parent_hash(), &pre_digest);
+		Micc::on_initialize(1);
+		let before = Micc::slot_duration_schedule();
+
+		// the change at slot 10 is superseded by the one at slot 20, in effect at slot 25.
+		assert_ok!(Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 30.into(), 8));
+		let schedule = Micc::slot_duration_schedule();
+		let at_slots: Vec<_> = schedule.changes().iter().map(|change| change.at_slot).collect();
+		assert_eq!(at_slots, vec![Slot::from(20), Slot::from(30)]);
+		for slot in 25..30u64 {
+			assert_eq!(schedule.slot_start(slot.into()), before.slot_start(slot.into()));
+			assert_eq!(schedule.duration_at(slot.into()), before.duration_at(slot.into()));
+		}
+	});
+}
+
+#[test]
+fn timestamp_slot_follows_the_slot_duration_history() {
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		assert_ok!(Micc::schedule_slot_duration_change(RuntimeOrigin::root(), 10.into(), 4));
+
+		// slot 11 starts at 10 * 2 + 4 instead of 11 * 2.
+		let pre_digest =
+			Digest { logs: vec![DigestItem::PreRuntime(MICC_ENGINE_ID, Slot::from(11).encode())] };
+		System::initialize(&1, &System::parent_hash(), &pre_digest);
+		Micc::on_initialize(1);
+
+		Micc::on_timestamp_set(24);
+		Micc::on_timestamp_set(27);
+		assert_eq!(Micc::slot_duration(), 4);
+	});
+}
+
+#[test]
+fn the_runtime_needs_a_block_by_the_earliest_required_time() {
+	struct Later;
+
+	impl RequiredBlockTime<u64> for Later {
+		fn next_required_block_time() -> Option<u64> {
+			Some(30)
+		}
+	}
+
+	build_ext_and_execute_test(vec![0, 1, 2, 3], || {
+		assert_eq!(Micc::next_required_block_time(), None);
+
+		MockRequiredBlockTime::set(Some(20));
+		assert_eq!(Micc::next_required_block_time(), Some(20));
+
+		type Sources = (crate::mock::MockRequiredBlock, Later, ());
+		assert_eq!(Sources::next_required_block_time(), Some(20));
+		MockRequiredBlockTime::set(None);
+		assert_eq!(Sources::next_required_block_time(), Some(30));
+	});
+}
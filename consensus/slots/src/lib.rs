@@ -30,7 +30,10 @@ mod slots;
 
 pub use aux_schema::{check_equivocation, MAX_SLOT_CAPACITY, PRUNING_BOUND};
 pub use slots::Slots;
-pub use slots::{time_until_next_slot, SlotInfo};
+pub use slots::{
+	duration_now, time_until_next_slot, AuthoringReason, ProposalBudget, SlotDurationSource,
+	SlotInfo,
+};
 
 // SlotTrigger enum is defined later in this file and will be automatically public
 
@@ -38,7 +41,7 @@ use futures::{future::Either, Future, TryFutureExt};
 use futures::prelude::*;
 use futures_timer::Delay;
 use log::{debug, info, warn};
-use sc_consensus::{BlockImport, JustificationSyncLink};
+use sc_consensus::{BlockImport, ImportResult, JustificationSyncLink};
 use sc_telemetry::{telemetry, TelemetryHandle, CONSENSUS_DEBUG, CONSENSUS_INFO, CONSENSUS_WARN};
 use sp_arithmetic::traits::BaseArithmetic;
 use sp_consensus::{Proposal, Proposer, SelectChain, SyncOracle};
@@ -59,9 +62,16 @@ const LOG_TARGET: &str = "slots";
 const BLOCK_INTERVAL: u64  = 60 * 60; // 1 hour
 
 /// Slot Trigger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SlotTrigger {
-	/// Command to create block
-	CreateBlock,
+	/// Command to create block, proposed within the given budget
+	CreateBlock(ProposalBudget),
+	/// Start working on a block now, as it is about to be requested by a `CreateBlock`. The
+	/// request is served by this block if it gets authored, within the given budget
+	Prepare(ProposalBudget),
+	/// Command to create a block the runtime needs for work of its own, proposed within the given
+	/// budget
+	RuntimeRequired(ProposalBudget),
 	/// NoAction
 	NoAction,
 }
@@ -80,6 +90,34 @@ pub struct SlotResult<Block: BlockT, Proof> {
 	pub storage_proof: Proof,
 }
 
+/// How long authoring a block took, see [`AuthoredBlock`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProposalTiming {
+	/// When the worker started on the slot.
+	pub started: Instant,
+	/// Time given to propose the block.
+	pub budget: Duration,
+	/// Time taken to propose the block, creating its inherent data included.
+	pub proposing: Duration,
+	/// Time taken to import the block.
+	pub import: Duration,
+}
+
+/// A block authored by a [`SimpleSlotWorker`], given to [`SimpleSlotWorker::on_block_authored`].
+#[derive(Debug)]
+pub struct AuthoredBlock<'a, Block> {
+	/// The sealed block.
+	pub block: &'a Block,
+	/// The slot the block was authored at.
+	pub slot: Slot,
+	/// What led to authoring the block.
+	pub reason: AuthoringReason,
+	/// How long authoring the block took.
+	pub timing: ProposalTiming,
+	/// The result of importing the block.
+	pub import_result: &'a Result<ImportResult, sp_consensus::Error>,
+}
+
 /// A worker that should be invoked at every new slot.
 ///
 /// The implementation should not make any assumptions of the slot being bound to the time or
@@ -194,6 +232,11 @@ pub trait SimpleSlotWorker<B: BlockT> {
 	/// Remaining duration for proposing.
 	fn proposing_remaining_duration(&self, slot_info: &SlotInfo<B>) -> Duration;
 
+	/// Notifies that a block was authored and imported, or failed to be imported.
+	///
+	/// Called on the authoring path, it should return quickly.
+	fn on_block_authored(&self, _authored: &AuthoredBlock<B>) {}
+
 	/// Propose a block by `Proposer`.
 	async fn propose(
 		&mut self,
@@ -306,7 +349,9 @@ pub trait SimpleSlotWorker<B: BlockT> {
 	where
 		Self: Sync,
 	{
+		let started = Instant::now();
 		let slot = slot_info.slot;
+		let reason = slot_info.reason;
 		let telemetry = self.telemetry();
 		let logging_target = self.logging_target();
 
@@ -391,7 +436,9 @@ pub trait SimpleSlotWorker<B: BlockT> {
 			},
 		};
 
+		let proposing_started = Instant::now();
 		let proposal = self.propose(proposer, &claim, slot_info, end_proposing_at).await?;
+		let proposing = proposing_started.elapsed();
 
 		let (block, storage_proof) = (proposal.block, proposal.proof);
 		let (header, body) = block.deconstruct();
@@ -436,13 +483,15 @@ pub trait SimpleSlotWorker<B: BlockT> {
 		);
 
 		let header = block_import_params.post_header();
-		match self.block_import().import_block(block_import_params).await {
+		let import_started = Instant::now();
+		let import_result = match self.block_import().import_block(block_import_params).await {
 			Ok(res) => {
 				res.handle_justification(
 					&header.hash(),
 					*header.number(),
 					self.justification_sync_link(),
 				);
+				Ok(res)
 			},
 			Err(err) => {
 				warn!(
@@ -457,10 +506,25 @@ pub trait SimpleSlotWorker<B: BlockT> {
 					"hash" => ?parent_hash,
 					"err" => ?err,
 				);
+				Err(sp_consensus::Error::ClientImport(err.to_string()))
 			},
-		}
+		};
+
+		let block = B::new(header, body);
+		self.on_block_authored(&AuthoredBlock {
+			block: &block,
+			slot,
+			reason,
+			timing: ProposalTiming {
+				started,
+				budget: proposing_remaining_duration,
+				proposing,
+				import: import_started.elapsed(),
+			},
+			import_result: &import_result,
+		});
 
-		Some(SlotResult { block: B::new(header, body), storage_proof })
+		Some(SlotResult { block, storage_proof })
 	}
 }
 
@@ -519,8 +583,8 @@ impl_inherent_data_provider_ext_tuple!(S, A, B, C, D, E, F, G, H, I, J);
 ///
 /// Every time a new slot is triggered, `worker.on_slot` is called and the future it returns is
 /// polled until completion, unless we are major syncing.
-pub async fn start_slot_worker<B, C, W, SO, CIDP, Proof>(
-	slot_duration: SlotDuration,
+pub async fn start_slot_worker<B, C, W, SO, CIDP, Proof, SD>(
+	slot_duration: SD,
 	client: C,
 	mut worker: W,
 	sync_oracle: SO,
@@ -532,9 +596,10 @@ pub async fn start_slot_worker<B, C, W, SO, CIDP, Proof>(
 	SO: SyncOracle + Send,
 	CIDP: CreateInherentDataProviders<B, ()> + Send + 'static,
 	CIDP::InherentDataProviders: InherentDataProviderExt + Send,
+	SD: SlotDurationSource<B>,
 {
 	let mut slots = Slots::new(
-		slot_duration.as_duration(),
+		slot_duration,
 		create_inherent_data_providers,
 		client,
 		sync_oracle,
@@ -547,8 +612,8 @@ pub async fn start_slot_worker<B, C, W, SO, CIDP, Proof>(
 }
 
 /// Start a new slot worker.
-pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS>(
-	slot_duration: SlotDuration,
+pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS, SD>(
+	slot_duration: SD,
 	client: C,
 	mut worker: W,
 	sync_oracle: SO,
@@ -562,9 +627,10 @@ pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS>(
 	CIDP: CreateInherentDataProviders<B, ()> + Send + 'static,
 	CIDP::InherentDataProviders: InherentDataProviderExt + Send,
 	CS: Stream<Item = SlotTrigger> + Unpin + 'static, // Adjust the stream type as necessary
+	SD: SlotDurationSource<B>,
 {
 	let mut slots = Slots::new(
-		slot_duration.as_duration(),
+		slot_duration,
 		create_inherent_data_providers,
 		client,
 		sync_oracle,
@@ -572,15 +638,34 @@ pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS>(
 	
 	let one_hour = Duration::from_secs(BLOCK_INTERVAL);
 	let mut slot_interval = tokio::time::interval_at(TokioInstant::now() + one_hour, one_hour); // Start after first hour, not immediately
+	let mut command_stream_ended = false;
 
 	loop {
 		tokio::select! {
 			// Process command stream for slot triggers
-			slot_trigger_opt = command_stream.next() => {
+			slot_trigger_opt = command_stream.next(), if !command_stream_ended => {
 				match slot_trigger_opt {
-					Some(SlotTrigger::CreateBlock) => {
+					Some(SlotTrigger::CreateBlock(budget)) => {
 						info!("New block creation request received from trigger stream");
-						let slot_info = slots.next_slot_v2().await;
+						let slot_info = slots
+							.next_slot_v2()
+							.await
+							.with_budget(budget)
+							.with_reason(AuthoringReason::Requested);
+
+						info!("Creating block for trigger stream slot: {}", slot_info.slot);
+						let _ = worker.on_slot(slot_info).await;
+
+						// Reset the interval after a command stream trigger
+						slot_interval.reset();
+					},
+					Some(SlotTrigger::RuntimeRequired(budget)) => {
+						info!("Block required by the runtime received from trigger stream");
+						let slot_info = slots
+							.next_slot_v2()
+							.await
+							.with_budget(budget)
+							.with_reason(AuthoringReason::RuntimeRequired);
 
 						info!("Creating block for trigger stream slot: {}", slot_info.slot);
 						let _ = worker.on_slot(slot_info).await;
@@ -588,16 +673,61 @@ pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS>(
 						// Reset the interval after a command stream trigger
 						slot_interval.reset();
 					},
+					Some(SlotTrigger::Prepare(budget)) => {
+						let slot_info = slots
+							.next_slot_v2()
+							.await
+							.with_budget(budget)
+							.with_reason(AuthoringReason::Prepared);
+						debug!(target: LOG_TARGET, "Preparing block for slot: {}", slot_info.slot);
+
+						// Keep listening to the stream while the block is prepared, it is what
+						// tells the proposer the block is requested.
+						let mut requested = None;
+						let authored = {
+							let on_slot = worker.on_slot(slot_info);
+							futures::pin_mut!(on_slot);
+							loop {
+								tokio::select! {
+									result = &mut on_slot => break result.is_some(),
+									trigger = command_stream.next(), if requested.is_none() => {
+										match trigger {
+											Some(SlotTrigger::CreateBlock(budget)) =>
+												requested =
+													Some((budget, AuthoringReason::Requested)),
+											Some(SlotTrigger::RuntimeRequired(budget)) =>
+												requested = Some((
+													budget,
+													AuthoringReason::RuntimeRequired,
+												)),
+											Some(_) => {},
+											None => break on_slot.await.is_some(),
+										}
+									},
+								}
+							}
+						};
+
+						if let Some((budget, reason)) = requested.filter(|_| !authored) {
+							info!("Prepared block wasn't authored, creating the requested block");
+							let slot_info = slots
+								.next_slot_v2()
+								.await
+								.with_budget(budget)
+								.with_reason(reason);
+							let _ = worker.on_slot(slot_info).await;
+						}
+
+						slot_interval.reset();
+					},
 					Some(_) => {
 						// Other trigger types - do nothing for now
 					},
 					None => {
-						// Stream ended - this should not happen with a properly implemented stream
-						// but we'll log it and continue with interval-based processing
+						// Stream ended - this should not happen with a supervised stream, stop
+						// polling it and only author the interval blocks from now on
 						warn!("Event-driven command stream ended, falling back to interval-based block production");
-						
-						// Continue the loop - we'll only process the interval branch now
-						continue;
+						command_stream_ended = true;
 					}
 				}
 			},
@@ -605,7 +735,11 @@ pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS>(
 			// Run on_slot every hour if no command_stream triggers reset it
 			_ = slot_interval.tick() => {
 				info!("Interval block creation triggered");
-				let slot_info = slots.next_slot_v2().await;
+				let slot_info = slots
+					.next_slot_v2()
+					.await
+					.with_budget(ProposalBudget::BATCH)
+					.with_reason(AuthoringReason::Heartbeat);
 
 				info!("Creating block for interval slot: {}", slot_info.slot);
 				let _ = worker.on_slot(slot_info).await;
@@ -616,8 +750,8 @@ pub async fn start_slot_worker_v2<B, C, W, SO, CIDP, Proof, CS>(
 
 
 /// Start a new slot worker.
-pub async fn start_slot_worker_v3<B, C, W, SO, CIDP, Proof, TExPool>(
-	slot_duration: SlotDuration,
+pub async fn start_slot_worker_v3<B, C, W, SO, CIDP, Proof, TExPool, SD>(
+	slot_duration: SD,
 	client: C,
 	mut worker: W,
 	sync_oracle: SO,
@@ -631,9 +765,10 @@ pub async fn start_slot_worker_v3<B, C, W, SO, CIDP, Proof, TExPool>(
 	CIDP: CreateInherentDataProviders<B, ()> + Send + 'static,
 	CIDP::InherentDataProviders: InherentDataProviderExt + Send,
     TExPool: MaintainedTransactionPool<Block = B, Hash = <B as BlockT>::Hash> + 'static,
+	SD: SlotDurationSource<B>,
 {
 	let mut slots = Slots::new(
-		slot_duration.as_duration(),
+		slot_duration,
 		create_inherent_data_providers,
 		client,
 		sync_oracle,
@@ -651,7 +786,7 @@ pub async fn start_slot_worker_v3<B, C, W, SO, CIDP, Proof, TExPool>(
 			_ = sleep_until(next_100ms_trigger)  => {
 				let pool_status = pool.status();
 				if pool_status.ready > 0 {
-					let slot_info = slots.next_slot().await;
+					let slot_info = slots.next_slot().await.with_reason(AuthoringReason::Requested);
 					info!(target: LOG_TARGET, "Transaction pool check triggered with {} ready transactions", pool_status.ready);
 					let _ = worker.on_slot(slot_info).await;
 					 next_hourly_trigger = next_time_boundary(Duration::from_secs(hourly_trigger_seconds)); // Reset the hourly trigger after a transaction-based trigger
@@ -661,7 +796,7 @@ pub async fn start_slot_worker_v3<B, C, W, SO, CIDP, Proof, TExPool>(
 
 			// Hourly trigger at exact hours
 			_ = sleep_until(next_hourly_trigger) => {
-				let slot_info = slots.next_slot().await;
+				let slot_info = slots.next_slot().await.with_reason(AuthoringReason::Heartbeat);
 				info!(target: LOG_TARGET, "Hourly block creation triggered at top of the hour");
 				let _ = worker.on_slot(slot_info).await;
 				 next_hourly_trigger = next_time_boundary(Duration::from_secs(hourly_trigger_seconds)); // Schedule next exact hour trigger
@@ -980,6 +1115,8 @@ mod test {
 				Default::default(),
 			),
 			block_size_limit: None,
+			proposing_deadline: None,
+			reason: AuthoringReason::Slot,
 		}
 	}
 
@@ -1001,6 +1138,199 @@ mod test {
 		assert_eq!(super::slot_lenience_linear(1u64.into(), &slot(23)), Some(SLOT_DURATION * 20));
 	}
 
+	#[test]
+	fn proposal_budget_sets_the_deadline_and_size_limit() {
+		let budget =
+			ProposalBudget { proposing: Duration::from_millis(300), block_size_limit: Some(1024) };
+
+		let before = Instant::now();
+		let slot_info = slot(2).with_budget(budget);
+		let deadline = slot_info.proposing_deadline.expect("the budget sets a deadline; qed");
+
+		assert!(deadline >= before + budget.proposing);
+		assert!(deadline <= Instant::now() + budget.proposing);
+		assert_eq!(slot_info.block_size_limit, Some(1024));
+	}
+
+	struct TestImport {
+		fails: bool,
+	}
+
+	#[async_trait::async_trait]
+	impl BlockImport<Block> for TestImport {
+		type Error = sp_consensus::Error;
+
+		async fn check_block(
+			&self,
+			_block: sc_consensus::BlockCheckParams<Block>,
+		) -> Result<ImportResult, Self::Error> {
+			Ok(ImportResult::imported(false))
+		}
+
+		async fn import_block(
+			&self,
+			_block: sc_consensus::BlockImportParams<Block>,
+		) -> Result<ImportResult, Self::Error> {
+			if self.fails {
+				return Err(sp_consensus::Error::ClientImport("bad block".into()))
+			}
+			Ok(ImportResult::imported(true))
+		}
+	}
+
+	struct TestProposer;
+
+	impl Proposer<Block> for TestProposer {
+		type Error = sp_consensus::Error;
+		type Proposal = futures::future::Ready<Result<Proposal<Block, ()>, Self::Error>>;
+		type ProofRecording = sp_consensus::DisableProofRecording;
+		type Proof = ();
+
+		fn propose(
+			self,
+			_inherent_data: sp_inherents::InherentData,
+			inherent_digests: sp_runtime::Digest,
+			_max_duration: Duration,
+			_block_size_limit: Option<usize>,
+		) -> Self::Proposal {
+			let header = Header::new(
+				2,
+				Default::default(),
+				Default::default(),
+				Default::default(),
+				inherent_digests,
+			);
+			futures::future::ready(Ok(Proposal {
+				block: Block::new(header, Vec::new()),
+				proof: (),
+				storage_changes: Default::default(),
+			}))
+		}
+	}
+
+	/// Authors every slot it is given, keeping what it is told of the blocks it authored.
+	struct TestWorker {
+		block_import: TestImport,
+		sync_oracle: sp_consensus::NoNetwork,
+		justification_sync_link: (),
+		/// The slot, reason and import result of the blocks authored, whether they became best.
+		authored: std::sync::Mutex<Vec<(Slot, AuthoringReason, Result<bool, String>)>>,
+	}
+
+	#[async_trait::async_trait]
+	impl SimpleSlotWorker<Block> for TestWorker {
+		type BlockImport = TestImport;
+		type SyncOracle = sp_consensus::NoNetwork;
+		type JustificationSyncLink = ();
+		type CreateProposer = futures::future::Ready<Result<TestProposer, sp_consensus::Error>>;
+		type Proposer = TestProposer;
+		type Claim = ();
+		type AuxData = ();
+
+		fn logging_target(&self) -> &'static str {
+			"test"
+		}
+
+		fn block_import(&mut self) -> &mut Self::BlockImport {
+			&mut self.block_import
+		}
+
+		fn aux_data(&self, _header: &Header, _slot: Slot) -> Result<(), sp_consensus::Error> {
+			Ok(())
+		}
+
+		fn authorities_len(&self, _aux_data: &()) -> Option<usize> {
+			Some(1)
+		}
+
+		async fn claim_slot(&mut self, _header: &Header, _slot: Slot, _aux_data: &()) -> Option<()> {
+			Some(())
+		}
+
+		fn pre_digest_data(&self, _slot: Slot, _claim: &()) -> Vec<sp_runtime::DigestItem> {
+			Vec::new()
+		}
+
+		async fn block_import_params(
+			&self,
+			header: Header,
+			_header_hash: &<Block as BlockT>::Hash,
+			body: Vec<<Block as BlockT>::Extrinsic>,
+			_storage_changes: StorageChanges<Block>,
+			_claim: (),
+			_aux_data: (),
+		) -> Result<sc_consensus::BlockImportParams<Block>, sp_consensus::Error> {
+			let mut params =
+				sc_consensus::BlockImportParams::new(sp_consensus::BlockOrigin::Own, header);
+			params.body = Some(body);
+			Ok(params)
+		}
+
+		fn force_authoring(&self) -> bool {
+			true
+		}
+
+		fn sync_oracle(&mut self) -> &mut Self::SyncOracle {
+			&mut self.sync_oracle
+		}
+
+		fn justification_sync_link(&mut self) -> &mut Self::JustificationSyncLink {
+			&mut self.justification_sync_link
+		}
+
+		fn proposer(&mut self, _block: &Header) -> Self::CreateProposer {
+			futures::future::ready(Ok(TestProposer))
+		}
+
+		fn telemetry(&self) -> Option<TelemetryHandle> {
+			None
+		}
+
+		fn proposing_remaining_duration(&self, _slot_info: &SlotInfo<Block>) -> Duration {
+			SLOT_DURATION
+		}
+
+		fn on_block_authored(&self, authored: &AuthoredBlock<Block>) {
+			let import_result = authored
+				.import_result
+				.as_ref()
+				.map(|result| matches!(result, ImportResult::Imported(aux) if aux.is_new_best))
+				.map_err(|e| e.to_string());
+			self.authored.lock().expect("never poisoned; qed").push((
+				authored.slot,
+				authored.reason,
+				import_result,
+			));
+		}
+	}
+
+	#[test]
+	fn authored_blocks_are_notified_with_their_import_result() {
+		let mut worker = TestWorker {
+			block_import: TestImport { fails: false },
+			sync_oracle: sp_consensus::NoNetwork,
+			justification_sync_link: (),
+			authored: Default::default(),
+		};
+
+		let authored = futures::executor::block_on(
+			worker.on_slot(slot(2).with_reason(AuthoringReason::RuntimeRequired)),
+		);
+		assert!(authored.is_some());
+
+		worker.block_import.fails = true;
+		let authored = futures::executor::block_on(worker.on_slot(slot(3)));
+		assert!(authored.is_some(), "the block is returned though its import failed");
+
+		let authored = worker.authored.into_inner().expect("never poisoned; qed");
+		assert_eq!(authored.len(), 2);
+		assert_eq!(authored[0], (2.into(), AuthoringReason::RuntimeRequired, Ok(true)));
+		assert!(matches!(
+			&authored[1],
+			(slot, AuthoringReason::Slot, Err(e)) if *slot == 3.into() && e.contains("bad block"),
+		));
+	}
+
 	#[test]
 	fn exponential_slot_lenience() {
 		// if no slots are skipped there should be no lenience
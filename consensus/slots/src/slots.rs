@@ -20,13 +20,16 @@
 //!
 //! This is used instead of `futures_timer::Interval` because it was unreliable.
 
-use super::{InherentDataProviderExt, Slot, LOG_TARGET};
+use super::{InherentDataProviderExt, Slot, SlotDuration, LOG_TARGET};
 use sp_consensus::{SelectChain, SyncOracle};
 use sp_inherents::{CreateInherentDataProviders, InherentDataProvider};
 use sp_runtime::traits::{Block as BlockT, Header as HeaderT};
 
 use futures_timer::Delay;
-use std::time::{Duration, Instant};
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
 
 /// Returns current duration since unix epoch.
 pub fn duration_now() -> Duration {
@@ -46,6 +49,82 @@ pub fn time_until_next_slot(slot_duration: Duration) -> Duration {
 	Duration::from_millis(remaining_millis as u64)
 }
 
+/// The durations of the slots yielded by [`Slots`].
+///
+/// Implemented for [`Duration`] and [`SlotDuration`], for slots of constant duration, and for an
+/// [`Arc`] of a source shared with whoever else follows the slots.
+pub trait SlotDurationSource<B: BlockT>: Send + Sync {
+	/// The duration of `slot`, when authoring on top of `chain_head`.
+	fn slot_duration(&self, chain_head: &B::Header, slot: Slot) -> Duration;
+
+	/// The time from now until the next slot starts.
+	fn time_until_next_slot(&self) -> Duration;
+}
+
+impl<B: BlockT> SlotDurationSource<B> for Duration {
+	fn slot_duration(&self, _: &B::Header, _: Slot) -> Duration {
+		*self
+	}
+
+	fn time_until_next_slot(&self) -> Duration {
+		time_until_next_slot(*self)
+	}
+}
+
+impl<B: BlockT> SlotDurationSource<B> for SlotDuration {
+	fn slot_duration(&self, _: &B::Header, _: Slot) -> Duration {
+		self.as_duration()
+	}
+
+	fn time_until_next_slot(&self) -> Duration {
+		time_until_next_slot(self.as_duration())
+	}
+}
+
+impl<B: BlockT, T: SlotDurationSource<B> + ?Sized> SlotDurationSource<B> for Arc<T> {
+	fn slot_duration(&self, chain_head: &B::Header, slot: Slot) -> Duration {
+		(**self).slot_duration(chain_head, slot)
+	}
+
+	fn time_until_next_slot(&self) -> Duration {
+		(**self).time_until_next_slot()
+	}
+}
+
+/// How long proposing a block authored on demand may take, and how large the block may be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProposalBudget {
+	/// Time given to propose the block, counted from the moment its trigger is handled.
+	pub proposing: Duration,
+	/// Limit on the size of the block, see [`SlotInfo::block_size_limit`].
+	pub block_size_limit: Option<usize>,
+}
+
+impl ProposalBudget {
+	/// Budget of the blocks authored as soon as a transaction asks for it, such as a high priority
+	/// one.
+	pub const IMMEDIATE: Self =
+		Self { proposing: Duration::from_millis(500), block_size_limit: None };
+	/// Budget of the blocks gathering the transactions of a collection window, and of heartbeats.
+	pub const BATCH: Self = Self { proposing: Duration::from_secs(2), block_size_limit: None };
+}
+
+/// What led a slot worker to author at a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthoringReason {
+	/// The slot started, workers authoring at every slot.
+	Slot,
+	/// A [`SlotTrigger::CreateBlock`](crate::SlotTrigger::CreateBlock) requested a block.
+	Requested,
+	/// A [`SlotTrigger::Prepare`](crate::SlotTrigger::Prepare) announced a block request.
+	Prepared,
+	/// A [`SlotTrigger::RuntimeRequired`](crate::SlotTrigger::RuntimeRequired) requested a block,
+	/// the runtime needing one for work of its own.
+	RuntimeRequired,
+	/// No block was requested for long, and a block shows the chain is alive.
+	Heartbeat,
+}
+
 /// Information about a slot.
 pub struct SlotInfo<B: BlockT> {
 	/// The slot number as found in the inherent data.
@@ -62,6 +141,11 @@ pub struct SlotInfo<B: BlockT> {
 	///
 	/// For more information see [`Proposer::propose`](sp_consensus::Proposer::propose).
 	pub block_size_limit: Option<usize>,
+	/// The instant at which proposing must be over, replacing the proportion of the slot and its
+	/// lenience when set.
+	pub proposing_deadline: Option<Instant>,
+	/// What led to authoring at this slot.
+	pub reason: AuthoringReason,
 }
 
 impl<B: BlockT> SlotInfo<B> {
@@ -82,14 +166,29 @@ impl<B: BlockT> SlotInfo<B> {
 			chain_head,
 			block_size_limit,
 			ends_at: Instant::now() + time_until_next_slot(duration),
+			proposing_deadline: None,
+			reason: AuthoringReason::Slot,
 		}
 	}
+
+	/// Propose within `budget`, counted from now.
+	pub fn with_budget(mut self, budget: ProposalBudget) -> Self {
+		self.proposing_deadline = Some(Instant::now() + budget.proposing);
+		self.block_size_limit = budget.block_size_limit;
+		self
+	}
+
+	/// Author at this slot for `reason`.
+	pub fn with_reason(mut self, reason: AuthoringReason) -> Self {
+		self.reason = reason;
+		self
+	}
 }
 
 /// A stream that returns every time there is a new slot.
-pub struct Slots<Block, SC, IDP, SO> {
+pub struct Slots<Block, SC, IDP, SO, SD = Duration> {
 	last_slot: Slot,
-	slot_duration: Duration,
+	slot_duration: SD,
 	until_next_slot: Option<Delay>,
 	create_inherent_data_providers: IDP,
 	select_chain: SC,
@@ -97,10 +196,10 @@ pub struct Slots<Block, SC, IDP, SO> {
 	_phantom: std::marker::PhantomData<Block>,
 }
 
-impl<Block, SC, IDP, SO> Slots<Block, SC, IDP, SO> {
+impl<Block, SC, IDP, SO, SD> Slots<Block, SC, IDP, SO, SD> {
 	/// Create a new `Slots` stream.
 	pub fn new(
-		slot_duration: Duration,
+		slot_duration: SD,
 		create_inherent_data_providers: IDP,
 		select_chain: SC,
 		sync_oracle: SO,
@@ -117,14 +216,33 @@ impl<Block, SC, IDP, SO> Slots<Block, SC, IDP, SO> {
 	}
 }
 
-impl<Block, SC, IDP, SO> Slots<Block, SC, IDP, SO>
+impl<Block, SC, IDP, SO, SD> Slots<Block, SC, IDP, SO, SD>
 where
 	Block: BlockT,
 	SC: SelectChain<Block>,
 	IDP: CreateInherentDataProviders<Block, ()> + 'static,
 	IDP::InherentDataProviders: crate::InherentDataProviderExt,
 	SO: SyncOracle,
+	SD: SlotDurationSource<Block>,
 {
+	fn slot_info(
+		&self,
+		slot: Slot,
+		inherent_data_providers: IDP::InherentDataProviders,
+		chain_head: Block::Header,
+	) -> SlotInfo<Block> {
+		SlotInfo {
+			slot,
+			create_inherent_data: Box::new(inherent_data_providers),
+			duration: self.slot_duration.slot_duration(&chain_head, slot),
+			chain_head,
+			block_size_limit: None,
+			ends_at: Instant::now() + self.slot_duration.time_until_next_slot(),
+			proposing_deadline: None,
+			reason: AuthoringReason::Slot,
+		}
+	}
+
 /// Returns a future that fires when the next slot starts.
 	pub async fn next_slot(&mut self) -> SlotInfo<Block> {
 		loop {
@@ -133,13 +251,13 @@ where
 				.take()
 				.unwrap_or_else(|| {
 					// Schedule first timeout.
-					let wait_dur = time_until_next_slot(self.slot_duration);
+					let wait_dur = self.slot_duration.time_until_next_slot();
 					Delay::new(wait_dur)
 				})
 				.await;
 
 			// Schedule delay for next slot.
-			let wait_dur = time_until_next_slot(self.slot_duration);
+			let wait_dur = self.slot_duration.time_until_next_slot();
 			self.until_next_slot = Some(Delay::new(wait_dur));
 
 			if self.sync_oracle.is_major_syncing() {
@@ -183,13 +301,7 @@ where
 			if slot > self.last_slot {
 				self.last_slot = slot;
 
-				break SlotInfo::new(
-					slot,
-					Box::new(inherent_data_providers),
-					self.slot_duration,
-					chain_head,
-					None,
-				)
+				break self.slot_info(slot, inherent_data_providers, chain_head)
 			}
 		}
 	}
@@ -238,13 +350,7 @@ where
 			if slot > self.last_slot {
 				self.last_slot = slot;
 
-				break SlotInfo::new(
-					slot,
-					Box::new(inherent_data_providers),
-					self.slot_duration,
-					chain_head,
-					None,
-				)
+				break self.slot_info(slot, inherent_data_providers, chain_head)
 			}
 		}
 	}
@@ -254,8 +360,7 @@ where
 		loop {
 
 			// Schedule delay for next slot.
-    		let interval = Duration::from_millis(self.slot_duration.as_millis() as u64);
-			Delay::new(interval).await;
+			Delay::new(self.slot_duration.time_until_next_slot()).await;
 
 			if self.sync_oracle.is_major_syncing() {
 				log::debug!(target: LOG_TARGET, "Skipping slot: major sync is in progress.");
@@ -298,13 +403,7 @@ where
 			if slot > self.last_slot {
 				self.last_slot = slot;
 
-				break SlotInfo::new(
-					slot,
-					Box::new(inherent_data_providers),
-					self.slot_duration,
-					chain_head,
-					None,
-				)
+				break self.slot_info(slot, inherent_data_providers, chain_head)
 			}
 		}
 	}
@@ -1,4 +1,5 @@
 use sc_cli::RunCmd;
+use std::time::Duration;
 
 #[derive(Debug, clap::Parser)]
 pub struct Cli {
@@ -7,6 +8,132 @@ pub struct Cli {
 
 	#[clap(flatten)]
 	pub run: RunCmd,
+
+	#[clap(flatten)]
+	pub micc: MiccParams,
+}
+
+/// Micc block authoring options.
+#[derive(Debug, Clone, clap::Args)]
+pub struct MiccParams {
+	/// Seal blocks with an external signer instead of the local keystore.
+	///
+	/// Given as `unix:<path>` or `http://<host>:<port>[/<path>]`.
+	#[arg(long, value_name = "ENDPOINT")]
+	pub micc_remote_signer: Option<sc_consensus_micc::RemoteSignerEndpoint>,
+
+	/// Time allowed for a request to the external signer, in milliseconds.
+	#[arg(long, value_name = "MS", default_value_t = 1000)]
+	pub micc_remote_signer_timeout: u64,
+
+	/// File holding the token sent to the external signer, required for signers reached over
+	/// HTTP.
+	#[arg(long, value_name = "PATH")]
+	pub micc_remote_signer_token_file: Option<std::path::PathBuf>,
+
+	/// Let GRANDPA vote for the best block once it is this old, in seconds, instead of a couple of
+	/// blocks behind it, so that blocks authored on demand don't wait for the next ones.
+	///
+	/// 0 keeps the default voting rules.
+	#[arg(long, value_name = "SECS", default_value_t = 12)]
+	pub micc_finalize_idle_after: u64,
+
+	/// Order in which the transactions are included in authored blocks.
+	///
+	/// `priority` follows the transaction pool, `first-come` the order the transactions arrived
+	/// in, and `triggering-first` puts the transactions that triggered the block before the others.
+	#[arg(long, value_name = "ORDERING", default_value = "priority")]
+	pub micc_transaction_ordering: sc_consensus_micc::TransactionOrdering,
+
+	/// How blocks are requested.
+	///
+	/// `event-driven` authors a block once transactions are collected for it, and `hybrid` at
+	/// every owned slot with transactions ready, early within the slot for high-priority ones.
+	#[arg(long, value_name = "MODE", default_value = "event-driven")]
+	pub micc_production_mode: sc_consensus_micc::ProductionMode,
+
+	/// Follow the pool as an authority running the `--micc-shadow-*` policy would, proposing the
+	/// blocks it would author without sealing nor importing them.
+	///
+	/// What would have been authored is reported by the `micc_status` RPC and the metrics. Ignored
+	/// on authorities.
+	#[arg(long)]
+	pub micc_shadow: bool,
+
+	/// How the shadow requests blocks, `--micc-production-mode` if not given.
+	#[arg(long, value_name = "MODE", requires = "micc_shadow")]
+	pub micc_shadow_production_mode: Option<sc_consensus_micc::ProductionMode>,
+
+	/// Shortest collection window of the shadow, in milliseconds.
+	#[arg(long, value_name = "MS", requires = "micc_shadow")]
+	pub micc_shadow_min_collection: Option<u64>,
+
+	/// Longest collection window of the shadow, in milliseconds.
+	#[arg(long, value_name = "MS", requires = "micc_shadow")]
+	pub micc_shadow_max_collection: Option<u64>,
+
+	/// Number of ready transactions closing a collection window of the shadow early.
+	#[arg(long, value_name = "COUNT", requires = "micc_shadow")]
+	pub micc_shadow_max_batch_size: Option<usize>,
+
+	/// Time after which the shadow requests an empty block, in milliseconds.
+	///
+	/// 0 never requests empty blocks.
+	#[arg(long, value_name = "MS", requires = "micc_shadow")]
+	pub micc_shadow_empty_block_interval: Option<u64>,
+
+	/// Whether the shadow requests a block right away for high-priority transactions.
+	#[arg(long, value_name = "BOOL", requires = "micc_shadow")]
+	pub micc_shadow_priority_fast_track: Option<bool>,
+
+	/// Whether the collection windows of the shadow follow its measured inclusion latency and
+	/// block fill.
+	#[arg(long, value_name = "BOOL", requires = "micc_shadow")]
+	pub micc_shadow_adaptive_timing: Option<bool>,
+
+	/// 95th percentile of the inclusion latency the shadow aims for, in milliseconds.
+	#[arg(long, value_name = "MS", requires = "micc_shadow")]
+	pub micc_shadow_target_latency: Option<u64>,
+
+	/// Share of the block capacity the shadow aims to fill, between 0 and 1.
+	#[arg(long, value_name = "RATIO", requires = "micc_shadow")]
+	pub micc_shadow_target_fill_ratio: Option<f64>,
+}
+
+impl MiccParams {
+	/// The event-driven configuration evaluated by the shadow: `config` with the
+	/// `--micc-shadow-*` options applied.
+	pub fn shadow_event_config(
+		&self,
+		config: &sc_consensus_micc::event_driven::EventDrivenConfig,
+	) -> sc_consensus_micc::event_driven::EventDrivenConfig {
+		let mut config = config.clone();
+		if let Some(ms) = self.micc_shadow_min_collection {
+			config.collection.min_collection_time = Duration::from_millis(ms);
+		}
+		if let Some(ms) = self.micc_shadow_max_collection {
+			config.collection.max_collection_time = Duration::from_millis(ms);
+		}
+		if let Some(size) = self.micc_shadow_max_batch_size {
+			config.collection.max_batch_size = size;
+		}
+		if let Some(ms) = self.micc_shadow_empty_block_interval {
+			config.empty_block_interval_ms = (ms > 0).then_some(ms);
+		}
+		if let Some(fast_track) = self.micc_shadow_priority_fast_track {
+			config.enable_priority_fast_track = fast_track;
+		}
+		if let Some(adaptive) = self.micc_shadow_adaptive_timing {
+			config.collection.enable_adaptive_timing = adaptive;
+		}
+		if let Some(ms) = self.micc_shadow_target_latency {
+			config.targets.p95_inclusion_latency = Duration::from_millis(ms);
+		}
+		if let Some(ratio) = self.micc_shadow_target_fill_ratio {
+			config.targets.fill_ratio = ratio;
+		}
+		config
+	}
 }
 
 #[derive(Debug, clap::Subcommand)]
@@ -16,6 +143,15 @@ pub enum Subcommand {
 	#[command(subcommand)]
 	Key(sc_cli::KeySubcommand),
 
+	/// Insert the Micc authority key, using the crypto scheme of the runtime.
+	InsertMiccKey(crate::micc_key::InsertMiccKeyCmd),
+
+	/// Export the slashing protection history of the Micc keys.
+	ExportSigningHistory(crate::signing_history::ExportSigningHistoryCmd),
+
+	/// Import the slashing protection history of Micc keys moving to this node.
+	ImportSigningHistory(crate::signing_history::ImportSigningHistoryCmd),
+
 	/// Build a chain specification.
 	BuildSpec(sc_cli::BuildSpecCmd),
 
@@ -0,0 +1,238 @@
+//! GRANDPA settings declared in the chain spec, justifications stored on a timer, and the choice
+//! between GRANDPA and Micc justifications.
+
+use codec::{Codec, Encode};
+use futures::StreamExt;
+use sc_client_api::{Backend, BlockBackend};
+use sc_consensus::{BlockCheckParams, BlockImport, BlockImportParams, ImportResult};
+use sc_consensus_grandpa::GrandpaJustificationStream;
+use sc_consensus_micc::MiccApi;
+use sc_service::Properties;
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_consensus::Error as ConsensusError;
+use sp_consensus_grandpa::{GrandpaApi, GRANDPA_ENGINE_ID};
+use sp_runtime::traits::Block as BlockT;
+use std::{
+	sync::Arc,
+	time::{Duration, Instant},
+};
+
+/// The property setting [`GrandpaSettings::gossip_duration`], in milliseconds.
+const GOSSIP_DURATION_PROPERTY: &str = "grandpaGossipDurationMs";
+/// The property setting [`GrandpaSettings::justification_period`], in blocks.
+const JUSTIFICATION_PERIOD_PROPERTY: &str = "grandpaJustificationPeriod";
+/// The property setting [`GrandpaSettings::justification_interval`], in seconds.
+const JUSTIFICATION_INTERVAL_PROPERTY: &str = "grandpaJustificationIntervalSecs";
+/// The property choosing the finality gadget, `grandpa` or `miccApprovals`.
+const FINALITY_GADGET_PROPERTY: &str = "finalityGadget";
+
+/// GRANDPA settings a chain may declare in the properties of its chain spec.
+#[derive(Debug, Clone, Copy)]
+pub struct GrandpaSettings {
+	/// The expected duration for a message to be gossiped across the network.
+	pub gossip_duration: Duration,
+	/// A justification is stored for every block whose number is a multiple of this.
+	pub justification_period: u32,
+	/// A justification is also stored once this much time passed since the last one, if set.
+	pub justification_interval: Option<Duration>,
+}
+
+impl Default for GrandpaSettings {
+	fn default() -> Self {
+		Self {
+			gossip_duration: Duration::from_millis(333),
+			justification_period: 512,
+			justification_interval: None,
+		}
+	}
+}
+
+impl GrandpaSettings {
+	/// Read the settings from the properties of a chain spec, keeping the defaults of those
+	/// missing.
+	pub fn from_properties(properties: &Properties) -> Result<Self, String> {
+		let read = |key: &str| {
+			properties
+				.get(key)
+				.map(|value| {
+					value.as_u64().ok_or_else(|| {
+						format!("Chain spec property `{key}` must be a non-negative integer")
+					})
+				})
+				.transpose()
+		};
+		let defaults = Self::default();
+
+		let justification_period = match read(JUSTIFICATION_PERIOD_PROPERTY)? {
+			None => defaults.justification_period,
+			Some(period) => u32::try_from(period)
+				.ok()
+				.filter(|period| *period > 0)
+				.ok_or_else(|| {
+					format!("Chain spec property `{JUSTIFICATION_PERIOD_PROPERTY}` is out of range")
+				})?,
+		};
+
+		Ok(Self {
+			gossip_duration: read(GOSSIP_DURATION_PROPERTY)?
+				.map(Duration::from_millis)
+				.unwrap_or(defaults.gossip_duration),
+			justification_period,
+			justification_interval: read(JUSTIFICATION_INTERVAL_PROPERTY)?
+				.filter(|secs| *secs > 0)
+				.map(Duration::from_secs),
+		})
+	}
+}
+
+/// Store the justification of a finalized block whenever `interval` passed since the last block
+/// with a justification, on top of the blocks GRANDPA stores one for.
+///
+/// The stored justifications are served along with their blocks, so that peers syncing block by
+/// block finalize them as they go. A chain authoring a handful of blocks a day would otherwise go
+/// weeks between them. Warp sync proofs end with the justification of the latest finalized block
+/// instead, which GRANDPA keeps apart.
+pub async fn store_justifications_periodically<Block, BE, C>(
+	client: Arc<C>,
+	backend: Arc<BE>,
+	justifications: GrandpaJustificationStream<Block>,
+	interval: Duration,
+) where
+	Block: BlockT,
+	BE: Backend<Block>,
+	C: BlockBackend<Block>,
+{
+	let mut justifications = justifications.subscribe(100_000);
+	let mut last_stored = Instant::now();
+
+	while let Some(justification) = justifications.next().await {
+		let hash = justification.justification.commit.target_hash;
+		let already_stored = client
+			.justifications(hash)
+			.ok()
+			.flatten()
+			.is_some_and(|justifications| justifications.get(GRANDPA_ENGINE_ID).is_some());
+
+		if already_stored {
+			last_stored = Instant::now();
+		} else if last_stored.elapsed() >= interval {
+			match backend.append_justification(hash, (GRANDPA_ENGINE_ID, justification.encode())) {
+				Ok(()) => last_stored = Instant::now(),
+				Err(e) => log::warn!(
+					target: "grandpa",
+					"Failed to store the justification of {:?}: {}",
+					hash,
+					e,
+				),
+			}
+		}
+	}
+}
+
+/// Whether the chain spec asks for the Micc approvals rather than GRANDPA, see
+/// [`sc_consensus_micc::approvals`].
+pub fn uses_micc_approvals(properties: &Properties) -> Result<bool, String> {
+	match properties.get(FINALITY_GADGET_PROPERTY).map(|gadget| gadget.as_str()) {
+		None | Some(Some("grandpa")) => Ok(false),
+		Some(Some("miccApprovals")) => Ok(true),
+		Some(_) => Err(format!(
+			"Chain spec property `{FINALITY_GADGET_PROPERTY}` must be `grandpa` or `miccApprovals`"
+		)),
+	}
+}
+
+/// Whether the chain finalizes its blocks as they are authored, which is the case of chains
+/// starting with a single Micc authority and no GRANDPA authorities.
+pub fn is_instant_finality<Block, C, A>(client: &C) -> sp_blockchain::Result<bool>
+where
+	Block: BlockT,
+	C: ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+	C::Api: MiccApi<Block, A> + GrandpaApi<Block>,
+	A: Codec,
+{
+	let genesis_hash = client.info().genesis_hash;
+	let runtime_api = client.runtime_api();
+
+	Ok(runtime_api.authorities(genesis_hash)?.len() == 1 &&
+		runtime_api.grandpa_authorities(genesis_hash)?.is_empty())
+}
+
+/// How the blocks of the chain get finalized.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Finality {
+	/// GRANDPA votes.
+	Grandpa,
+	/// The justification of the sole authority, see [`is_instant_finality`], or the approvals of
+	/// the authorities once there are several of them.
+	Instant,
+	/// The approvals gossiped by the Micc authorities, see [`uses_micc_approvals`].
+	Approvals,
+}
+
+impl Finality {
+	/// Pick the finality of the chain from its genesis authorities and chain spec properties.
+	pub fn select<Block, C, A>(client: &C, properties: &Properties) -> Result<Self, String>
+	where
+		Block: BlockT,
+		C: ProvideRuntimeApi<Block> + HeaderBackend<Block>,
+		C::Api: MiccApi<Block, A> + GrandpaApi<Block>,
+		A: Codec,
+	{
+		if is_instant_finality::<Block, _, A>(client).map_err(|e| e.to_string())? {
+			Ok(Self::Instant)
+		} else if uses_micc_approvals(properties)? {
+			Ok(Self::Approvals)
+		} else {
+			Ok(Self::Grandpa)
+		}
+	}
+}
+
+/// The block import below the Micc one: GRANDPA's, or the client's own for chains finalized by
+/// Micc justifications.
+pub enum FinalityBlockImport<G, I> {
+	/// Blocks are finalized by GRANDPA.
+	Grandpa(G),
+	/// Blocks are finalized by Micc justifications, checked by the import queue.
+	Micc(I),
+}
+
+impl<G: Clone, I: Clone> Clone for FinalityBlockImport<G, I> {
+	fn clone(&self) -> Self {
+		match self {
+			Self::Grandpa(grandpa) => Self::Grandpa(grandpa.clone()),
+			Self::Micc(inner) => Self::Micc(inner.clone()),
+		}
+	}
+}
+
+#[async_trait::async_trait]
+impl<Block, G, I> BlockImport<Block> for FinalityBlockImport<G, I>
+where
+	Block: BlockT,
+	G: BlockImport<Block, Error = ConsensusError> + Send + Sync,
+	I: BlockImport<Block, Error = ConsensusError> + Send + Sync,
+{
+	type Error = ConsensusError;
+
+	async fn check_block(
+		&self,
+		block: BlockCheckParams<Block>,
+	) -> Result<ImportResult, Self::Error> {
+		match self {
+			Self::Grandpa(grandpa) => grandpa.check_block(block).await,
+			Self::Micc(inner) => inner.check_block(block).await,
+		}
+	}
+
+	async fn import_block(
+		&self,
+		block: BlockImportParams<Block>,
+	) -> Result<ImportResult, Self::Error> {
+		match self {
+			Self::Grandpa(grandpa) => grandpa.import_block(block).await,
+			Self::Micc(inner) => inner.import_block(block).await,
+		}
+	}
+}
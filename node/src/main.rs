@@ -5,8 +5,12 @@ mod benchmarking;
 mod chain_spec;
 mod cli;
 mod command;
+mod finality;
+mod micc_key;
 mod rpc;
 mod service;
+mod signing_history;
+mod voting_rule;
 
 fn main() -> sc_cli::Result<()> {
 	command::run()
@@ -0,0 +1,59 @@
+//! Micc authority key management.
+
+use sc_cli::{utils, Error, KeystoreParams, SharedParams, SubstrateCli};
+use sc_keystore::LocalKeystore;
+use sc_service::config::{BasePath, KeystoreConfig};
+use solochain_template_runtime::micc_crypto::AuthorityPair as MiccPair;
+use sp_consensus_micc::MICC;
+use sp_core::crypto::{ByteArray, Pair};
+use sp_keystore::Keystore;
+
+/// Insert the Micc authority key into the keystore.
+///
+/// Same as `key insert --key-type micc`, but always with the crypto scheme the runtime was built
+/// with.
+#[derive(Debug, clap::Parser)]
+pub struct InsertMiccKeyCmd {
+	/// The secret key URI.
+	/// If the value is a file, the file content is used as URI.
+	/// If not given, you will be prompted for the URI.
+	#[arg(long)]
+	suri: Option<String>,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub keystore_params: KeystoreParams,
+}
+
+impl InsertMiccKeyCmd {
+	/// Run the command.
+	pub fn run<C: SubstrateCli>(&self, cli: &C) -> Result<(), Error> {
+		let suri = utils::read_uri(self.suri.as_ref())?;
+		let base_path = self
+			.shared_params
+			.base_path()?
+			.unwrap_or_else(|| BasePath::from_project("", "", &C::executable_name()));
+		let chain_id = self.shared_params.chain_id(self.shared_params.is_dev());
+		let chain_spec = cli.load_spec(&chain_id)?;
+		let config_dir = base_path.config_dir(chain_spec.id());
+
+		let KeystoreConfig::Path { path, password } =
+			self.keystore_params.keystore_config(&config_dir)?
+		else {
+			unreachable!("keystore_config always returns path and password; qed")
+		};
+
+		// The keystore derives its keys with its password, the public key has to match.
+		let public = utils::pair_from_suri::<MiccPair>(&suri, password.clone())?.public();
+		let keystore = LocalKeystore::open(path, password)?;
+		keystore
+			.insert(MICC, &suri, &public.to_raw_vec())
+			.map_err(|_| Error::KeystoreOperation)?;
+
+		Ok(())
+	}
+}
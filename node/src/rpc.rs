@@ -8,6 +8,7 @@
 use std::sync::Arc;
 
 use jsonrpsee::RpcModule;
+use sc_consensus_micc_rpc::Micc;
 use sc_transaction_pool_api::TransactionPool;
 use solochain_template_runtime::{opaque::Block, AccountId, Nonce};
 use sp_api::ProvideRuntimeApi;
@@ -20,6 +21,8 @@ pub struct FullDeps<C, P> {
 	pub client: Arc<C>,
 	/// Transaction pool instance.
 	pub pool: Arc<P>,
+	/// Handler of the Micc consensus RPC methods.
+	pub micc: Micc,
 }
 
 /// Instantiate all full RPC extensions.
@@ -34,12 +37,14 @@ where
 	C::Api: BlockBuilder<Block>,
 	P: TransactionPool + 'static,
 {
+	use sc_consensus_micc_rpc::MiccApiServer;
 	use substrate_frame_rpc_system::{System, SystemApiServer};
 
 	let mut module = RpcModule::new(());
-	let FullDeps { client, pool } = deps;
+	let FullDeps { client, pool, micc } = deps;
 
 	module.merge(System::new(client.clone(), pool).into_rpc())?;
+	module.merge(micc.into_rpc())?;
 
 	// Extend this RPC with a custom API by using the following syntax.
 	// `YourRpcStruct` should have a reference to a client, which is needed
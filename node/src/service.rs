@@ -1,14 +1,37 @@
 //! Service and ServiceFactory implementation. Specialized wrapper over substrate service.
 
+use frame_support::{dispatch::DispatchClass, traits::PalletInfoAccess};
 use futures::FutureExt;
 use sc_client_api::{Backend, BlockBackend};
-use sc_consensus_micc::{ImportQueueParams, SlotProportion, StartMiccParams};
+use crate::{
+	cli::MiccParams,
+	finality::{
+		store_justifications_periodically, Finality, FinalityBlockImport, GrandpaSettings,
+	},
+	voting_rule::FinalizeIdleBest,
+};
+use sc_consensus_micc::{
+	event_driven::EventDrivenConfig, ApprovalVoterParams, BackoffAuthoringOnFinalityAge,
+	ClockDriftMonitor, CollectionController, CollectionTargets, DriftTolerance, ImportQueueParams,
+	MiccBlockImport, MiccJustificationImport, MiccSelectChain, MiccWarpSyncProvider, OrderedPool,
+	RemoteKeystore, RuntimeSlotDurations, ShadowAuthoring, ShadowAuthoringParams,
+	SharedAuthoritySets, SigningHistory, SlotProportion, Speculation, SpeculativeProposerFactory,
+	StartMiccParams, TransactionArrivals, TriggerSupervisor,
+};
+use sc_consensus::import_queue::BoxJustificationImport;
 use sc_consensus_grandpa::SharedVoterState;
-use sc_service::{error::Error as ServiceError, Configuration, TaskManager, WarpSyncConfig};
+use sc_service::{
+	error::Error as ServiceError, Configuration, Properties, TaskManager, WarpSyncConfig,
+};
 use sc_telemetry::{Telemetry, TelemetryWorker};
 use sc_transaction_pool_api::OffchainTransactionPoolFactory;
-use solochain_template_runtime::{self, apis::RuntimeApi, opaque::Block};
-use sp_consensus_micc::sr25519::AuthorityPair as MiccPair;
+use solochain_template_runtime::{
+	self,
+	apis::RuntimeApi,
+	configs::RuntimeBlockLength,
+	micc_crypto::{AuthorityId as MiccId, AuthorityPair as MiccPair},
+	opaque::Block,
+};
 use std::{sync::Arc, time::Duration};
 
 pub(crate) type FullClient = sc_service::TFullClient<
@@ -17,25 +40,72 @@ pub(crate) type FullClient = sc_service::TFullClient<
 	sc_executor::WasmExecutor<sp_io::SubstrateHostFunctions>,
 >;
 type FullBackend = sc_service::TFullBackend<Block>;
-type FullSelectChain = sc_consensus::LongestChain<FullBackend, Block>;
-
-/// The minimum period of blocks on which justifications will be
-/// imported and generated.
-const GRANDPA_JUSTIFICATION_PERIOD: u32 = 512;
+type FullSelectChain =
+	MiccSelectChain<Block, FullClient, MiccPair, sc_consensus::LongestChain<FullBackend, Block>>;
+type FullSlotDurations = RuntimeSlotDurations<Block, FullClient, MiccId>;
+type FullBlockImport = MiccBlockImport<
+	Block,
+	FinalityBlockImport<
+		sc_consensus_grandpa::GrandpaBlockImport<FullBackend, Block, FullClient, FullSelectChain>,
+		Arc<FullClient>,
+	>,
+	FullClient,
+	MiccId,
+>;
 
 pub type Service = sc_service::PartialComponents<
 	FullClient,
 	FullBackend,
 	FullSelectChain,
-	sc_consensus::DefaultImportQueue<Block>,
+	sc_consensus_micc::DeferringImportQueue<Block>,
 	sc_transaction_pool::TransactionPoolHandle<Block, FullClient>,
 	(
-		sc_consensus_grandpa::GrandpaBlockImport<FullBackend, Block, FullClient, FullSelectChain>,
-		sc_consensus_grandpa::LinkHalf<Block, FullClient, FullSelectChain>,
+		FullBlockImport,
+		Finality,
+		Option<sc_consensus_grandpa::LinkHalf<Block, FullClient, FullSelectChain>>,
+		GrandpaSettings,
+		ClockDriftMonitor,
+		SharedAuthoritySets<
+			<Block as sp_runtime::traits::Block>::Hash,
+			sp_runtime::traits::NumberFor<Block>,
+			MiccId,
+		>,
+		Arc<FullSlotDurations>,
 		Option<Telemetry>,
 	),
 >;
 
+/// The property setting [`DriftTolerance::accepted_slots`].
+const ACCEPTED_DRIFT_SLOTS_PROPERTY: &str = "miccAcceptedDriftSlots";
+/// The property setting [`DriftTolerance::deferred_slots`].
+const DEFERRED_DRIFT_SLOTS_PROPERTY: &str = "miccDeferredDriftSlots";
+/// The property setting [`DriftTolerance::max_deferred`].
+const MAX_DEFERRED_BLOCKS_PROPERTY: &str = "miccMaxDeferredBlocks";
+
+/// How far ahead of the local clock imported headers may be, as declared in the properties of the
+/// chain spec, keeping the defaults of those missing.
+fn drift_tolerance(properties: &Properties) -> Result<DriftTolerance, String> {
+	let read = |key: &str| {
+		properties
+			.get(key)
+			.map(|value| {
+				value.as_u64().ok_or_else(|| {
+					format!("Chain spec property `{key}` must be a non-negative integer")
+				})
+			})
+			.transpose()
+	};
+	let defaults = DriftTolerance::default();
+
+	Ok(DriftTolerance {
+		accepted_slots: read(ACCEPTED_DRIFT_SLOTS_PROPERTY)?.unwrap_or(defaults.accepted_slots),
+		deferred_slots: read(DEFERRED_DRIFT_SLOTS_PROPERTY)?.unwrap_or(defaults.deferred_slots),
+		max_deferred: read(MAX_DEFERRED_BLOCKS_PROPERTY)?
+			.map(|max| max as usize)
+			.unwrap_or(defaults.max_deferred),
+	})
+}
+
 pub fn new_partial(config: &Configuration) -> Result<Service, ServiceError> {
 	let telemetry = config
 		.telemetry_endpoints
@@ -62,7 +132,13 @@ pub fn new_partial(config: &Configuration) -> Result<Service, ServiceError> {
 		telemetry
 	});
 
-	let select_chain = sc_consensus::LongestChain::new(backend.clone());
+	let authority_sets = SharedAuthoritySets::load(&*client)?;
+	let select_chain = MiccSelectChain::new(
+		sc_consensus::LongestChain::new(backend.clone()),
+		client.clone(),
+		Default::default(),
+		Some(authority_sets.clone()),
+	);
 
 	let transaction_pool = Arc::from(
 		sc_transaction_pool::Builder::new(
@@ -75,43 +151,92 @@ pub fn new_partial(config: &Configuration) -> Result<Service, ServiceError> {
 		.build(),
 	);
 
-	let (grandpa_block_import, grandpa_link) = sc_consensus_grandpa::block_import(
+	let finality = Finality::select::<Block, _, MiccId>(&*client, &config.chain_spec.properties())
+		.map_err(ServiceError::Other)?;
+	let grandpa_settings = GrandpaSettings::from_properties(&config.chain_spec.properties())
+		.map_err(ServiceError::Other)?;
+	let (finality_block_import, justification_import, grandpa_link) = match finality {
+		Finality::Grandpa => {
+			let (grandpa_block_import, grandpa_link) = sc_consensus_grandpa::block_import(
+				client.clone(),
+				grandpa_settings.justification_period,
+				&client,
+				select_chain.clone(),
+				telemetry.as_ref().map(|x| x.handle()),
+			)?;
+			let justification_import: BoxJustificationImport<Block> =
+				Box::new(grandpa_block_import.clone());
+			let finality_block_import = FinalityBlockImport::Grandpa(grandpa_block_import);
+			(finality_block_import, justification_import, Some(grandpa_link))
+		},
+		Finality::Instant | Finality::Approvals => {
+			if finality == Finality::Instant {
+				log::info!(
+					"Single Micc authority and no GRANDPA authorities, finalizing blocks \
+					 instantly until there are several authorities"
+				);
+			} else {
+				log::info!("Finalizing blocks with the approvals of the Micc authorities");
+			}
+			let justification_import: BoxJustificationImport<Block> =
+				Box::new(MiccJustificationImport::<_, FullBackend, _, MiccPair>::new(
+					client.clone(),
+					Some(authority_sets.clone()),
+				));
+			(FinalityBlockImport::Micc(client.clone()), justification_import, None)
+		},
+	};
+
+	let clock_drift = ClockDriftMonitor::new(Default::default(), config.prometheus_registry())
+		.map_err(|e| ServiceError::Application(Box::new(e)))?;
+	let micc_block_import =
+		MiccBlockImport::new(finality_block_import, client.clone(), authority_sets.clone())
+			.with_clock_drift(
+				clock_drift.clone(),
+				sc_consensus_micc::pallet_timestamp_reader::<_, _, FullBackend>(
+					client.clone(),
+					<solochain_template_runtime::Timestamp as PalletInfoAccess>::name(),
+				),
+			);
+
+	// Shared by the import queue, the authoring and their inherent data providers, so that the
+	// runtime is only asked for the slot duration schedule when it changes.
+	let slot_durations = Arc::new(FullSlotDurations::new(
 		client.clone(),
-		GRANDPA_JUSTIFICATION_PERIOD,
-		&client,
-		select_chain.clone(),
-		telemetry.as_ref().map(|x| x.handle()),
-	)?;
+		sc_consensus_micc::slot_duration(&*client)?,
+	));
 
-	let cidp_client = client.clone();
+	let cidp_slot_durations = slot_durations.clone();
 	let import_queue =
 		sc_consensus_micc::import_queue::<MiccPair, _, _, _, _, _>(ImportQueueParams {
-			block_import: grandpa_block_import.clone(),
-			justification_import: Some(Box::new(grandpa_block_import.clone())),
+			block_import: micc_block_import.clone(),
+			justification_import: Some(justification_import),
 			client: client.clone(),
 			create_inherent_data_providers: move |parent_hash, _| {
-				let cidp_client = cidp_client.clone();
+				let schedule = cidp_slot_durations.schedule_at(parent_hash);
 				async move {
-					let slot_duration = sc_consensus_micc::standalone::slot_duration_at(
-						&*cidp_client,
-						parent_hash,
-					)?;
 					let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
 
 					let slot =
-						sp_consensus_micc::inherents::InherentDataProvider::from_timestamp_and_slot_duration(
+						sp_consensus_micc::inherents::InherentDataProvider::from_timestamp_and_schedule(
 							*timestamp,
-							slot_duration,
+							&schedule,
 						);
 
 					Ok((slot, timestamp))
 				}
 			},
+			slot_durations: Some(slot_durations.clone()),
 			spawner: &task_manager.spawn_essential_handle(),
 			registry: config.prometheus_registry(),
 			check_for_equivocation: Default::default(),
 			telemetry: telemetry.as_ref().map(|x| x.handle()),
 			compatibility_mode: Default::default(),
+			drift_tolerance: drift_tolerance(&config.chain_spec.properties())
+				.map_err(ServiceError::Other)?,
+			clock_drift: Some(clock_drift.clone()),
+			authority_sets: Some(authority_sets.clone()),
+			micc_finality: finality != Finality::Grandpa,
 		})?;
 
 	Ok(sc_service::PartialComponents {
@@ -122,7 +247,16 @@ pub fn new_partial(config: &Configuration) -> Result<Service, ServiceError> {
 		keystore_container,
 		select_chain,
 		transaction_pool,
-		other: (grandpa_block_import, grandpa_link, telemetry),
+		other: (
+			micc_block_import,
+			finality,
+			grandpa_link,
+			grandpa_settings,
+			clock_drift,
+			authority_sets,
+			slot_durations,
+			telemetry,
+		),
 	})
 }
 
@@ -131,6 +265,7 @@ pub fn new_full<
 	N: sc_network::NetworkBackend<Block, <Block as sp_runtime::traits::Block>::Hash>,
 >(
 	config: Configuration,
+	micc_params: MiccParams,
 ) -> Result<TaskManager, ServiceError> {
 	let sc_service::PartialComponents {
 		client,
@@ -140,7 +275,17 @@ pub fn new_full<
 		keystore_container,
 		select_chain,
 		transaction_pool,
-		other: (block_import, grandpa_link, mut telemetry),
+		other:
+			(
+				block_import,
+				finality,
+				grandpa_link,
+				grandpa_settings,
+				clock_drift,
+				authority_sets,
+				slot_durations,
+				mut telemetry,
+			),
 	} = new_partial(&config)?;
 
 	let mut net_config = sc_network::config::FullNetworkConfiguration::<
@@ -151,23 +296,44 @@ pub fn new_full<
 	let metrics = N::register_notification_metrics(config.prometheus_registry());
 
 	let peer_store_handle = net_config.peer_store_handle();
-	let grandpa_protocol_name = sc_consensus_grandpa::protocol_standard_name(
-		&client.block_hash(0).ok().flatten().expect("Genesis block exists; qed"),
-		&config.chain_spec,
-	);
-	let (grandpa_protocol_config, grandpa_notification_service) =
-		sc_consensus_grandpa::grandpa_peers_set_config::<_, N>(
-			grandpa_protocol_name.clone(),
-			metrics.clone(),
-			peer_store_handle,
-		);
-	net_config.add_notification_protocol(grandpa_protocol_config);
+	let genesis_hash = client.block_hash(0).ok().flatten().expect("Genesis block exists; qed");
+	let grandpa_protocol_name =
+		sc_consensus_grandpa::protocol_standard_name(&genesis_hash, &config.chain_spec);
+	let approvals_protocol_name = sc_consensus_micc::approvals_protocol_name(genesis_hash.as_ref());
+	// Chains finalizing instantly switch to approvals once they have several authorities.
+	let approvals_notification_service = (finality != Finality::Grandpa).then(|| {
+		let (approvals_protocol_config, approvals_notification_service) =
+			sc_consensus_micc::approvals_peers_set_config::<_, N>(
+				approvals_protocol_name.clone(),
+				metrics.clone(),
+				peer_store_handle.clone(),
+			);
+		net_config.add_notification_protocol(approvals_protocol_config);
+		approvals_notification_service
+	});
+	let grandpa_notification_service = grandpa_link.is_some().then(|| {
+		let (grandpa_protocol_config, grandpa_notification_service) =
+			sc_consensus_grandpa::grandpa_peers_set_config::<_, N>(
+				grandpa_protocol_name.clone(),
+				metrics.clone(),
+				peer_store_handle,
+			);
+		net_config.add_notification_protocol(grandpa_protocol_config);
+		grandpa_notification_service
+	});
 
-	let warp_sync = Arc::new(sc_consensus_grandpa::warp_proof::NetworkProvider::new(
-		backend.clone(),
-		grandpa_link.shared_authority_set().clone(),
-		Vec::default(),
-	));
+	let warp_sync = match &grandpa_link {
+		Some(grandpa_link) => WarpSyncConfig::WithProvider(Arc::new(
+			sc_consensus_grandpa::warp_proof::NetworkProvider::new(
+				backend.clone(),
+				grandpa_link.shared_authority_set().clone(),
+				Vec::default(),
+			),
+		)),
+		None => WarpSyncConfig::WithProvider(Arc::new(
+			MiccWarpSyncProvider::<Block, _, MiccPair>::new(client.clone()),
+		)),
+	};
 
 	let (network, system_rpc_tx, tx_handler_controller, sync_service) =
 		sc_service::build_network(sc_service::BuildNetworkParams {
@@ -178,7 +344,7 @@ pub fn new_full<
 			spawn_handle: task_manager.spawn_handle(),
 			import_queue,
 			block_announce_validator_builder: None,
-			warp_sync_config: Some(WarpSyncConfig::WithProvider(warp_sync)),
+			warp_sync_config: Some(warp_sync),
 			block_relay: None,
 			metrics,
 		})?;
@@ -206,17 +372,51 @@ pub fn new_full<
 
 	let role = config.role;
 	let force_authoring = config.force_authoring;
-	let backoff_authoring_blocks: Option<()> = None;
+	let backoff_authoring_blocks = Some(BackoffAuthoringOnFinalityAge::<Block, _, MiccPair, _>::new(
+		client.clone(),
+		transaction_pool.clone(),
+		Default::default(),
+	));
 	let name = config.network.node_name.clone();
 	let enable_grandpa = !config.disable_grandpa;
 	let prometheus_registry = config.prometheus_registry().cloned();
 
+	let trigger_supervisor = role
+		.is_authority()
+		.then(|| TriggerSupervisor::new(prometheus_registry.as_ref()))
+		.transpose()
+		.map_err(|e| ServiceError::Application(Box::new(e)))?;
+	let shadow = (micc_params.micc_shadow && !role.is_authority())
+		.then(|| ShadowAuthoring::new(prometheus_registry.as_ref()))
+		.transpose()
+		.map_err(|e| ServiceError::Application(Box::new(e)))?;
+
+	// Fill the event-driven blocks to the length the runtime allows normal transactions.
+	let event_config = EventDrivenConfig {
+		targets: CollectionTargets {
+			block_capacity: *RuntimeBlockLength::get().max.get(DispatchClass::Normal) as usize,
+			..Default::default()
+		},
+		..Default::default()
+	};
+
 	let rpc_extensions_builder = {
 		let client = client.clone();
 		let pool = transaction_pool.clone();
+		let mut micc = sc_consensus_micc_rpc::Micc::new().with_clock_drift(clock_drift.clone());
+		if let Some(supervisor) = &trigger_supervisor {
+			micc = micc.with_trigger_supervisor(supervisor.clone());
+		}
+		if let Some(shadow) = &shadow {
+			micc = micc.with_shadow_authoring(shadow.clone());
+		}
 
 		Box::new(move |_| {
-			let deps = crate::rpc::FullDeps { client: client.clone(), pool: pool.clone() };
+			let deps = crate::rpc::FullDeps {
+				client: client.clone(),
+				pool: pool.clone(),
+				micc: micc.clone(),
+			};
 			crate::rpc::create_full(deps).map_err(Into::into)
 		})
 	};
@@ -228,7 +428,7 @@ pub fn new_full<
 		task_manager: &mut task_manager,
 		transaction_pool: transaction_pool.clone(),
 		rpc_builder: rpc_extensions_builder,
-		backend,
+		backend: backend.clone(),
 		system_rpc_tx,
 		tx_handler_controller,
 		sync_service: sync_service.clone(),
@@ -236,44 +436,126 @@ pub fn new_full<
 		telemetry: telemetry.as_mut(),
 	})?;
 
-	if role.is_authority() {
-		let proposer_factory = sc_basic_authorship::ProposerFactory::new(
+	let cidp_slot_durations = slot_durations.clone();
+	let create_inherent_data_providers = move |parent_hash, ()| {
+		let schedule = cidp_slot_durations.schedule_at(parent_hash);
+		async move {
+			let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
+
+			let slot =
+				sp_consensus_micc::inherents::InherentDataProvider::from_timestamp_and_schedule(
+					*timestamp,
+					&schedule,
+				);
+
+			Ok((slot, timestamp))
+		}
+	};
+
+	// The shadow orders and speculates on the transactions as authorities do, so that it proposes
+	// the blocks they would.
+	let authoring_proposer_factory = || {
+		let transaction_arrivals = TransactionArrivals::default();
+		let speculation = Speculation::default();
+		let ordered_pool = Arc::new(OrderedPool::new(
+			transaction_pool.clone(),
+			micc_params.micc_transaction_ordering,
+			transaction_arrivals.clone(),
+		));
+		let proposer_factory = SpeculativeProposerFactory::new(
 			task_manager.spawn_handle(),
 			client.clone(),
-			transaction_pool.clone(),
-			prometheus_registry.as_ref(),
-			telemetry.as_ref().map(|x| x.handle()),
+			ordered_pool.clone(),
+			sc_basic_authorship::ProposerFactory::new(
+				task_manager.spawn_handle(),
+				client.clone(),
+				ordered_pool,
+				prometheus_registry.as_ref(),
+				telemetry.as_ref().map(|x| x.handle()),
+			),
+			speculation.clone(),
+			create_inherent_data_providers.clone(),
+		);
+		(proposer_factory, transaction_arrivals, speculation)
+	};
+
+	// Authorities sign their blocks and approvals with the Micc key, kept with the external signer
+	// if one is configured. GRANDPA and the other keys stay in the local keystore.
+	let micc_keystore = role
+		.is_authority()
+		.then(|| -> Result<sp_keystore::KeystorePtr, ServiceError> {
+			Ok(match &micc_params.micc_remote_signer {
+				Some(endpoint) => {
+					let token = micc_params
+						.micc_remote_signer_token_file
+						.as_ref()
+						.map(std::fs::read_to_string)
+						.transpose()
+						.map_err(|e| ServiceError::Application(Box::new(e)))?
+						.map(|token| token.trim().to_string());
+					Arc::new(
+						RemoteKeystore::new(
+							endpoint.clone(),
+							Duration::from_millis(micc_params.micc_remote_signer_timeout),
+							token,
+							prometheus_registry.as_ref(),
+						)
+						.map_err(|e| ServiceError::Application(Box::new(e)))?,
+					)
+				},
+				None => keystore_container.keystore(),
+			})
+		})
+		.transpose()?;
+
+	if let Some(micc_keystore) = micc_keystore.clone() {
+		let (proposer_factory, transaction_arrivals, speculation) = authoring_proposer_factory();
+
+		let collection_controller =
+			CollectionController::new(&event_config, prometheus_registry.as_ref())
+				.map_err(|e| ServiceError::Application(Box::new(e)))?;
+		task_manager.spawn_handle().spawn(
+			"micc-collection-controller",
+			Some("block-authoring"),
+			sc_consensus_micc::measure_authored_blocks(
+				client.clone(),
+				transaction_pool.clone(),
+				collection_controller.clone(),
+			),
 		);
 
 		let slot_duration = sc_consensus_micc::slot_duration(&*client)?;
+		let signing_history = SigningHistory::load(&*client)?;
 
 		let micc = sc_consensus_micc::start_micc::<MiccPair, _, _, _, _, _, _, _, _, _, _, _>(
 			StartMiccParams {
 				slot_duration,
-				client,
+				slot_durations: Some(slot_durations.clone()),
+				client: client.clone(),
 				select_chain,
 				block_import,
 				proposer_factory,
-				create_inherent_data_providers: move |_, ()| async move {
-					let timestamp = sp_timestamp::InherentDataProvider::from_system_time();
-
-					let slot =
-						sp_consensus_micc::inherents::InherentDataProvider::from_timestamp_and_slot_duration(
-							*timestamp,
-							slot_duration,
-						);
-
-					Ok((slot, timestamp))
-				},
+				create_inherent_data_providers: create_inherent_data_providers.clone(),
 				force_authoring,
 				backoff_authoring_blocks,
-				keystore: keystore_container.keystore(),
+				keystore: micc_keystore,
 				sync_oracle: sync_service.clone(),
 				justification_sync_link: sync_service.clone(),
 				block_proposal_slot_portion: SlotProportion::new(2f32 / 3f32),
 				max_block_proposal_slot_portion: None,
 				telemetry: telemetry.as_ref().map(|x| x.handle()),
 				compatibility_mode: Default::default(),
+				clock_drift: Some(clock_drift),
+				authority_sets: Some(authority_sets.clone()),
+				signing_history: Some(signing_history),
+				instant_finality: finality == Finality::Instant,
+				transaction_arrivals: Some(transaction_arrivals),
+				speculation: Some(speculation),
+				collection_controller: Some(collection_controller),
+				event_config: event_config.clone(),
+				production_mode: micc_params.micc_production_mode,
+				trigger_supervisor,
+				authoring_observers: Vec::new(),
 			},
 			transaction_pool.clone(),
 		)?;
@@ -285,15 +567,68 @@ pub fn new_full<
 			.spawn_blocking("micc", Some("block-authoring"), micc);
 	}
 
-	if enable_grandpa {
+	if let Some(shadow) = shadow {
+		let (proposer_factory, transaction_arrivals, speculation) = authoring_proposer_factory();
+		let event_config = micc_params.shadow_event_config(&event_config);
+		let collection_controller =
+			CollectionController::new(&event_config, prometheus_registry.as_ref())
+				.map_err(|e| ServiceError::Application(Box::new(e)))?;
+		let slot_duration = sc_consensus_micc::slot_duration(&*client)?;
+
+		let shadow_authoring =
+			sc_consensus_micc::run_shadow_authoring::<MiccPair, _, _, _, _, _, _>(
+				ShadowAuthoringParams {
+					slot_duration,
+					client: client.clone(),
+					pool: transaction_pool.clone(),
+					proposer_factory,
+					create_inherent_data_providers: create_inherent_data_providers.clone(),
+					production_mode: micc_params
+						.micc_shadow_production_mode
+						.unwrap_or(micc_params.micc_production_mode),
+					event_config,
+					block_proposal_slot_portion: SlotProportion::new(2f32 / 3f32),
+					collection_controller: Some(collection_controller),
+					transaction_arrivals: Some(transaction_arrivals),
+					speculation: Some(speculation),
+					shadow,
+				},
+			);
+		task_manager.spawn_handle().spawn("micc-shadow", Some("block-authoring"), shadow_authoring);
+	}
+
+	if let Some(notification_service) = approvals_notification_service {
+		let approval_voter =
+			sc_consensus_micc::run_approval_voter::<_, FullBackend, _, MiccPair, _, _>(
+				ApprovalVoterParams {
+					client: client.clone(),
+					authority_sets: Some(authority_sets),
+					keystore: micc_keystore,
+					network: network.clone(),
+					sync: sync_service.clone(),
+					notification_service,
+					protocol_name: approvals_protocol_name,
+					prometheus_registry: prometheus_registry.clone(),
+				},
+			)?;
+
+		// like the GRANDPA voter, the approval voter is considered infallible.
+		task_manager.spawn_essential_handle().spawn_blocking(
+			"micc-approval-voter",
+			None,
+			approval_voter,
+		);
+	}
+
+	let grandpa = grandpa_link.zip(grandpa_notification_service).filter(|_| enable_grandpa);
+	if let Some((grandpa_link, grandpa_notification_service)) = grandpa {
 		// if the node isn't actively participating in consensus then it doesn't
 		// need a keystore, regardless of which protocol we use below.
 		let keystore = if role.is_authority() { Some(keystore_container.keystore()) } else { None };
 
 		let grandpa_config = sc_consensus_grandpa::Config {
-			// FIXME #1578 make this available through chainspec
-			gossip_duration: Duration::from_millis(333),
-			justification_generation_period: GRANDPA_JUSTIFICATION_PERIOD,
+			gossip_duration: grandpa_settings.gossip_duration,
+			justification_generation_period: grandpa_settings.justification_period,
 			name: Some(name),
 			observer_enabled: false,
 			keystore,
@@ -302,6 +637,19 @@ pub fn new_full<
 			protocol_name: grandpa_protocol_name,
 		};
 
+		if let Some(interval) = grandpa_settings.justification_interval {
+			task_manager.spawn_handle().spawn(
+				"grandpa-periodic-justifications",
+				None,
+				store_justifications_periodically(
+					client.clone(),
+					backend,
+					grandpa_link.justification_stream(),
+					interval,
+				),
+			);
+		}
+
 		// start the full GRANDPA voter
 		// NOTE: non-authorities could run the GRANDPA observer protocol, but at
 		// this point the full voter should provide better guarantees of block
@@ -314,7 +662,12 @@ pub fn new_full<
 			network,
 			sync: Arc::new(sync_service),
 			notification_service: grandpa_notification_service,
-			voting_rule: sc_consensus_grandpa::VotingRulesBuilder::default().build(),
+			voting_rule: FinalizeIdleBest::new(
+				client,
+				(micc_params.micc_finalize_idle_after > 0)
+					.then(|| Duration::from_secs(micc_params.micc_finalize_idle_after)),
+				sc_consensus_grandpa::VotingRulesBuilder::default().build(),
+			),
 			prometheus_registry,
 			shared_voter_state: SharedVoterState::empty(),
 			telemetry: telemetry.as_ref().map(|x| x.handle()),
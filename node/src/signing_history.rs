@@ -0,0 +1,91 @@
+//! Moving the Micc slashing protection history between hosts.
+
+use sc_cli::{CliConfiguration, DatabaseParams, Error, SharedParams};
+use sc_client_api::backend::AuxStore;
+use sc_consensus_micc::{SigningHistory, SigningHistoryInterchange};
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::Block as BlockT;
+use std::{fs, path::PathBuf};
+
+/// Export the headers last sealed by the Micc keys of this node, to import them on the host the
+/// keys move to.
+#[derive(Debug, clap::Parser)]
+pub struct ExportSigningHistoryCmd {
+	/// The file to write to, standard output if not given.
+	#[arg(value_name = "FILE")]
+	pub output: Option<PathBuf>,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl ExportSigningHistoryCmd {
+	/// Run the command.
+	pub fn run<B: BlockT, C: AuxStore + HeaderBackend<B>>(&self, client: &C) -> Result<(), Error> {
+		let history = SigningHistory::load(client)?;
+		let interchange = history.export(client.info().genesis_hash.as_ref());
+		let json = serde_json::to_string_pretty(&interchange)
+			.map_err(|e| Error::Application(Box::new(e)))?;
+
+		match &self.output {
+			Some(path) => fs::write(path, json)?,
+			None => println!("{json}"),
+		}
+		Ok(())
+	}
+}
+
+impl CliConfiguration for ExportSigningHistoryCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
+
+/// Import the headers last sealed by Micc keys moving to this node, exported on their previous
+/// host.
+#[derive(Debug, clap::Parser)]
+pub struct ImportSigningHistoryCmd {
+	/// The file to read from.
+	#[arg(value_name = "FILE")]
+	pub input: PathBuf,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub shared_params: SharedParams,
+
+	#[allow(missing_docs)]
+	#[clap(flatten)]
+	pub database_params: DatabaseParams,
+}
+
+impl ImportSigningHistoryCmd {
+	/// Run the command.
+	pub fn run<B: BlockT, C: AuxStore + HeaderBackend<B>>(&self, client: &C) -> Result<(), Error> {
+		let interchange: SigningHistoryInterchange =
+			serde_json::from_slice(&fs::read(&self.input)?)
+				.map_err(|e| Error::Input(format!("Invalid signing history: {e}")))?;
+
+		SigningHistory::load(client)?
+			.import(client, client.info().genesis_hash.as_ref(), interchange)
+			.map_err(|e| Error::Application(Box::new(e)))
+	}
+}
+
+impl CliConfiguration for ImportSigningHistoryCmd {
+	fn shared_params(&self) -> &SharedParams {
+		&self.shared_params
+	}
+
+	fn database_params(&self) -> Option<&DatabaseParams> {
+		Some(&self.database_params)
+	}
+}
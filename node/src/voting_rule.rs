@@ -0,0 +1,84 @@
+//! GRANDPA voting on the sparse blocks of event-driven Micc authoring.
+
+use sc_consensus_grandpa::{VotingRule, VotingRuleResult};
+use sc_consensus_micc::{find_pre_digest, slot_duration_schedule_at, MiccApi};
+use solochain_template_runtime::{
+	micc_crypto::{AuthorityId as MiccId, AuthoritySignature as MiccSignature},
+	opaque::Block,
+};
+use sp_api::ProvideRuntimeApi;
+use sp_blockchain::HeaderBackend;
+use sp_runtime::traits::{Block as BlockT, Header};
+use std::{
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+type BlockHeader = <Block as BlockT>::Header;
+
+/// Votes for the best block once it is `finalize_after` old, and leaves the vote to `inner` until
+/// then.
+///
+/// The default rules vote a couple of blocks behind the best block, expecting more blocks to
+/// follow shortly. Blocks authored on demand may not be followed by any until the next heartbeat,
+/// which would leave the last transactions unfinalized meanwhile.
+pub struct FinalizeIdleBest<C, R> {
+	client: Arc<C>,
+	finalize_after: Option<Duration>,
+	inner: R,
+}
+
+impl<C, R> FinalizeIdleBest<C, R> {
+	/// Vote for idle best blocks, never if `finalize_after` is `None`.
+	pub fn new(client: Arc<C>, finalize_after: Option<Duration>, inner: R) -> Self {
+		Self { client, finalize_after, inner }
+	}
+}
+
+impl<C, R: Clone> Clone for FinalizeIdleBest<C, R> {
+	fn clone(&self) -> Self {
+		Self {
+			client: self.client.clone(),
+			finalize_after: self.finalize_after,
+			inner: self.inner.clone(),
+		}
+	}
+}
+
+impl<C, R> FinalizeIdleBest<C, R>
+where
+	C: ProvideRuntimeApi<Block>,
+	C::Api: MiccApi<Block, MiccId>,
+{
+	/// The time elapsed since the start of the slot of `header`.
+	fn age(&self, header: &BlockHeader) -> Option<Duration> {
+		let slot = find_pre_digest::<Block, MiccSignature>(header).ok()?;
+		let schedule =
+			slot_duration_schedule_at::<MiccId, Block, C>(&*self.client, header.hash()).ok()?;
+		let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+
+		Some(now.saturating_sub(Duration::from_millis(schedule.slot_start(slot))))
+	}
+}
+
+impl<C, R, B> VotingRule<Block, B> for FinalizeIdleBest<C, R>
+where
+	C: ProvideRuntimeApi<Block> + Send + Sync,
+	C::Api: MiccApi<Block, MiccId>,
+	R: VotingRule<Block, B> + Clone,
+	B: HeaderBackend<Block> + 'static,
+{
+	fn restrict_vote(
+		&self,
+		backend: Arc<B>,
+		base: &BlockHeader,
+		best_target: &BlockHeader,
+		current_target: &BlockHeader,
+	) -> VotingRuleResult<Block> {
+		match (self.finalize_after, self.age(best_target)) {
+			// Nothing to wait for, the best block can be finalized as is.
+			(Some(finalize_after), Some(age)) if age >= finalize_after => Box::pin(async { None }),
+			_ => self.inner.restrict_vote(backend, base, best_target, current_target),
+		}
+	}
+}
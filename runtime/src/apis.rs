@@ -28,7 +28,6 @@ use alloc::vec::Vec;
 use frame_support::genesis_builder_helper::{build_state, get_preset};
 use pallet_grandpa::AuthorityId as GrandpaId;
 use sp_api::impl_runtime_apis;
-use sp_consensus_micc::sr25519::AuthorityId as MiccId;
 use sp_core::{crypto::KeyTypeId, OpaqueMetadata};
 use sp_runtime::{
 	traits::{Block as BlockT, NumberFor},
@@ -42,6 +41,7 @@ use super::{
 	AccountId, Micc, Block, Executive, Grandpa, InherentDataExt, Nonce, Runtime,
 	RuntimeGenesisConfig, SessionKeys, System, VERSION,
 };
+use super::micc_crypto::AuthorityId as MiccId;
 
 impl_runtime_apis! {
 	impl sp_api::Core<Block> for Runtime {
@@ -115,6 +115,7 @@ impl_runtime_apis! {
 		}
 	}
 
+	#[api_version(5)]
 	impl sp_consensus_micc::MiccApi<Block, MiccId> for Runtime {
 		fn slot_duration() -> sp_consensus_micc::SlotDuration {
 			sp_consensus_micc::SlotDuration::from_millis(Micc::slot_duration())
@@ -123,6 +124,22 @@ impl_runtime_apis! {
 		fn authorities() -> Vec<MiccId> {
 			pallet_micc::Authorities::<Runtime>::get().into_inner()
 		}
+
+		fn slot_author_selector() -> sp_consensus_micc::SlotAuthorSelector {
+			Micc::slot_author_selector()
+		}
+
+		fn slot_duration_schedule() -> sp_consensus_micc::SlotDurationSchedule {
+			Micc::slot_duration_schedule()
+		}
+
+		fn authorship_mode() -> sp_consensus_micc::AuthorshipMode {
+			Micc::authorship_mode()
+		}
+
+		fn next_required_block_time() -> Option<u64> {
+			Micc::next_required_block_time()
+		}
 	}
 
 	impl sp_session::SessionKeys<Block> for Runtime {
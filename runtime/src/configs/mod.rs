@@ -26,23 +26,24 @@
 // Substrate and Polkadot dependencies
 use frame_support::{
 	derive_impl, parameter_types,
-	traits::{ConstBool, ConstU128, ConstU32, ConstU64, VariantCountOf},
+	migrations::MultiStepMigrator,
+	traits::{ConstBool, ConstU128, ConstU32, ConstU64, EqualPrivilegeOnly, VariantCountOf},
 	weights::{
 		constants::{RocksDbWeight, WEIGHT_REF_TIME_PER_SECOND},
 		Weight,
 	},
 };
 use frame_system::limits::{BlockLength, BlockWeights};
-use sp_consensus_micc::sr25519::AuthorityId as MiccId;
 use sp_runtime::Perbill;
 use sp_version::RuntimeVersion;
 
 // Local module imports
 use super::{
-	AccountId, Micc, Balance, Block, BlockNumber, Hash, Nonce, PalletInfo, Runtime,
+	AccountId, Micc, Balance, Block, BlockNumber, Hash, Nonce, OriginCaller, PalletInfo, Runtime,
 	RuntimeCall, RuntimeEvent, RuntimeFreezeReason, RuntimeHoldReason, RuntimeOrigin, RuntimeTask,
 	System, EXISTENTIAL_DEPOSIT, SLOT_DURATION, VERSION,
 };
+use super::micc_crypto::AuthorityId as MiccId;
 
 const NORMAL_DISPATCH_RATIO: Perbill = Perbill::from_percent(75);
 
@@ -57,6 +58,8 @@ parameter_types! {
 	);
 	pub RuntimeBlockLength: BlockLength = BlockLength::max_with_normal_ratio(5 * 1024 * 1024, NORMAL_DISPATCH_RATIO);
 	pub const SS58Prefix: u8 = 42;
+	pub MaximumSchedulerWeight: Weight =
+		Perbill::from_percent(80) * RuntimeBlockWeights::get().max_block;
 }
 
 /// The default types are being injected by [`derive_impl`](`frame_support::derive_impl`) from
@@ -95,6 +98,52 @@ impl pallet_micc::Config for Runtime {
 	type MaxAuthorities = ConstU32<32>;
 	type AllowMultipleBlocksPerSlot = ConstBool<false>;
 	type SlotDuration = pallet_micc::MinimumPeriodTimesTwo<Runtime>;
+	type SlotDurationOrigin = frame_system::EnsureRoot<AccountId>;
+	type SlotAuthorSelector = pallet_micc::RoundRobin;
+	type RequiredBlockTime = (ScheduledCalls, PendingMigrations);
+}
+
+/// Number of blocks ahead of the last one looked at for calls of the scheduler.
+const SCHEDULED_CALLS_LOOKAHEAD: BlockNumber = 64;
+
+/// Needs a block one slot after the last one while the scheduler has calls to dispatch within the
+/// next [`SCHEDULED_CALLS_LOOKAHEAD`] blocks, or agendas left incomplete.
+///
+/// Calls are scheduled at block numbers, which only advance with blocks, so the chain moves on at
+/// the pace of the slots until they are dispatched. Calls scheduled further ahead wait for the
+/// blocks of the chain to bring them within reach, rather than pinning it to the slots meanwhile.
+pub struct ScheduledCalls;
+
+impl ScheduledCalls {
+	/// The next block with calls of the scheduler to dispatch, within reach.
+	fn next_scheduled() -> Option<BlockNumber> {
+		pallet_scheduler::IncompleteSince::<Runtime>::get().or_else(|| {
+			let now = System::block_number();
+			(now.saturating_add(1)..=now.saturating_add(SCHEDULED_CALLS_LOOKAHEAD))
+				.find(|at| pallet_scheduler::Agenda::<Runtime>::contains_key(at))
+		})
+	}
+}
+
+impl pallet_micc::RequiredBlockTime<u64> for ScheduledCalls {
+	fn next_required_block_time() -> Option<u64> {
+		let last_block = pallet_timestamp::Now::<Runtime>::get();
+		Self::next_scheduled().map(|_| last_block.saturating_add(Micc::slot_duration()))
+	}
+}
+
+/// Needs a block right away once the code was upgraded, to run its migrations, and while a
+/// multi-block migration is ongoing.
+pub struct PendingMigrations;
+
+impl pallet_micc::RequiredBlockTime<u64> for PendingMigrations {
+	fn next_required_block_time() -> Option<u64> {
+		let upgraded = frame_system::LastRuntimeUpgrade::<Runtime>::get()
+			.is_some_and(|last| last.was_upgraded(&VERSION));
+		let migrating = <Runtime as frame_system::Config>::MultiBlockMigrator::ongoing();
+
+		(upgraded || migrating).then(pallet_timestamp::Now::<Runtime>::get)
+	}
 }
 
 impl pallet_grandpa::Config for Runtime {
@@ -142,3 +191,17 @@ impl pallet_sudo::Config for Runtime {
 	type RuntimeCall = RuntimeCall;
 	type WeightInfo = pallet_sudo::weights::SubstrateWeight<Runtime>;
 }
+
+impl pallet_scheduler::Config for Runtime {
+	type RuntimeEvent = RuntimeEvent;
+	type RuntimeOrigin = RuntimeOrigin;
+	type PalletsOrigin = OriginCaller;
+	type RuntimeCall = RuntimeCall;
+	type MaximumWeight = MaximumSchedulerWeight;
+	type ScheduleOrigin = frame_system::EnsureRoot<AccountId>;
+	type MaxScheduledPerBlock = ConstU32<50>;
+	type WeightInfo = pallet_scheduler::weights::SubstrateWeight<Runtime>;
+	type OriginPrivilegeCmp = EqualPrivilegeOnly;
+	type Preimages = ();
+	type BlockNumberProvider = System;
+}
@@ -15,20 +15,51 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{AccountId, BalancesConfig, RuntimeGenesisConfig, SudoConfig};
+use crate::{
+	micc_crypto::AuthorityId as MiccId, AccountId, BalancesConfig, RuntimeGenesisConfig,
+	SudoConfig,
+};
 use alloc::{vec, vec::Vec};
 use frame_support::build_struct_json_patch;
 use serde_json::Value;
-use sp_consensus_micc::sr25519::AuthorityId as MiccId;
 use sp_consensus_grandpa::AuthorityId as GrandpaId;
+use sp_consensus_micc::AuthorshipMode;
 use sp_genesis_builder::{self, PresetId};
 use sp_keyring::Sr25519Keyring;
 
+/// The Micc authority keys of `//Alice` and `//Bob`, in the crypto the runtime was built with.
+#[cfg(not(any(feature = "micc-ed25519", feature = "micc-ecdsa")))]
+fn micc_dev_keys() -> [MiccId; 2] {
+	[Sr25519Keyring::Alice.public().into(), Sr25519Keyring::Bob.public().into()]
+}
+
+#[cfg(feature = "micc-ed25519")]
+fn micc_dev_keys() -> [MiccId; 2] {
+	use sp_keyring::Ed25519Keyring;
+	[Ed25519Keyring::Alice.public().into(), Ed25519Keyring::Bob.public().into()]
+}
+
+// There is no ECDSA keyring, these are the public keys of `//Alice` and `//Bob`.
+#[cfg(feature = "micc-ecdsa")]
+fn micc_dev_keys() -> [MiccId; 2] {
+	[
+		sp_core::ecdsa::Public::from_raw(sp_core::hex2array!(
+			"020a1091341fe5664bfa1782d5e04779689068c916b04cb365ec3153755684d9a1"
+		))
+		.into(),
+		sp_core::ecdsa::Public::from_raw(sp_core::hex2array!(
+			"0390084fdbf27d2b79d26a4f13f0ccd982cb755a661969143c37cbc49ef5b91f27"
+		))
+		.into(),
+	]
+}
+
 // Returns the genesis config presets populated with given parameters.
 fn testnet_genesis(
 	initial_authorities: Vec<(MiccId, GrandpaId)>,
 	endowed_accounts: Vec<AccountId>,
 	root: AccountId,
+	authorship_mode: AuthorshipMode,
 ) -> Value {
 	build_struct_json_patch!(RuntimeGenesisConfig {
 		balances: BalancesConfig {
@@ -40,6 +71,7 @@ fn testnet_genesis(
 		},
 		micc: pallet_micc::GenesisConfig {
 			authorities: initial_authorities.iter().map(|x| (x.0.clone())).collect::<Vec<_>>(),
+			authorship_mode,
 		},
 		grandpa: pallet_grandpa::GenesisConfig {
 			authorities: initial_authorities.iter().map(|x| (x.1.clone(), 1)).collect::<Vec<_>>(),
@@ -50,11 +82,9 @@ fn testnet_genesis(
 
 /// Return the development genesis config.
 pub fn development_config_genesis() -> Value {
+	let [alice, _] = micc_dev_keys();
 	testnet_genesis(
-		vec![(
-			sp_keyring::Sr25519Keyring::Alice.public().into(),
-			sp_keyring::Ed25519Keyring::Alice.public().into(),
-		)],
+		vec![(alice, sp_keyring::Ed25519Keyring::Alice.public().into())],
 		vec![
 			Sr25519Keyring::Alice.to_account_id(),
 			Sr25519Keyring::Bob.to_account_id(),
@@ -62,27 +92,25 @@ pub fn development_config_genesis() -> Value {
 			Sr25519Keyring::BobStash.to_account_id(),
 		],
 		sp_keyring::Sr25519Keyring::Alice.to_account_id(),
+		// The development network runs with `--force-authoring`.
+		AuthorshipMode::Permissive,
 	)
 }
 
 /// Return the local genesis config preset.
 pub fn local_config_genesis() -> Value {
+	let [alice, bob] = micc_dev_keys();
 	testnet_genesis(
 		vec![
-			(
-				sp_keyring::Sr25519Keyring::Alice.public().into(),
-				sp_keyring::Ed25519Keyring::Alice.public().into(),
-			),
-			(
-				sp_keyring::Sr25519Keyring::Bob.public().into(),
-				sp_keyring::Ed25519Keyring::Bob.public().into(),
-			),
+			(alice, sp_keyring::Ed25519Keyring::Alice.public().into()),
+			(bob, sp_keyring::Ed25519Keyring::Bob.public().into()),
 		],
 		Sr25519Keyring::iter()
 			.filter(|v| v != &Sr25519Keyring::One && v != &Sr25519Keyring::Two)
 			.map(|v| v.to_account_id())
 			.collect::<Vec<_>>(),
 		Sr25519Keyring::Alice.to_account_id(),
+		AuthorshipMode::Strict,
 	)
 }
 
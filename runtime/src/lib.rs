@@ -27,6 +27,18 @@ pub use sp_runtime::BuildStorage;
 
 pub mod genesis_config_presets;
 
+#[cfg(all(feature = "micc-ed25519", feature = "micc-ecdsa"))]
+compile_error!("The `micc-ed25519` and `micc-ecdsa` features are mutually exclusive.");
+
+/// The crypto of the Micc authority keys: sr25519 by default, or ed25519 or ECDSA with the
+/// `micc-ed25519` or `micc-ecdsa` feature.
+#[cfg(not(any(feature = "micc-ed25519", feature = "micc-ecdsa")))]
+pub use sp_consensus_micc::sr25519 as micc_crypto;
+#[cfg(feature = "micc-ed25519")]
+pub use sp_consensus_micc::ed25519 as micc_crypto;
+#[cfg(feature = "micc-ecdsa")]
+pub use sp_consensus_micc::ecdsa as micc_crypto;
+
 /// Opaque types. These are used by the CLI to instantiate machinery that don't need to know
 /// the specifics of the runtime. They can then be made to be agnostic over specific formats
 /// of data like extrinsics, allowing for them to continue syncing the network through upgrades
@@ -69,7 +81,7 @@ pub const VERSION: RuntimeVersion = RuntimeVersion {
 	//   `spec_version`, and `authoring_version` are the same between Wasm and native.
 	// This value is set to 100 to notify Polkadot-JS App (https://polkadot.js.org/apps) to use
 	//   the compatible custom types.
-	spec_version: 100,
+	spec_version: 105,
 	impl_version: 1,
 	apis: apis::RUNTIME_API_VERSIONS,
 	transaction_version: 1,
@@ -85,8 +97,8 @@ mod block_times {
 	/// Change this to adjust the block time.
 	pub const MILLI_SECS_PER_BLOCK: u64 = 6000;
 
-	// NOTE: Currently it is not possible to change the slot duration after the chain has started.
-	// Attempting to do so will brick block production.
+	// NOTE: Changing this after the chain has started will brick block production. Schedule the
+	// change with `Micc::schedule_slot_duration_change` instead.
 	pub const SLOT_DURATION: u64 = MILLI_SECS_PER_BLOCK;
 }
 pub use block_times::*;
@@ -216,4 +228,7 @@ mod runtime {
 
 	#[runtime::pallet_index(5)]
 	pub type Sudo = pallet_sudo;
+
+	#[runtime::pallet_index(6)]
+	pub type Scheduler = pallet_scheduler;
 }